@@ -0,0 +1,27 @@
+// Generates include/audis.h, the C header for the `ffi` module,
+// when the `ffi` feature is enabled.
+
+#[cfg(feature = "ffi")]
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    cbindgen::Builder::new()
+        .with_config(cbindgen::Config {
+            usize_is_size_t: true,
+            ..Default::default()
+        })
+        .with_src(format!("{}/src/ffi.rs", dir))
+        .with_language(cbindgen::Language::C)
+        .with_include_guard("AUDIS_H")
+        .with_header("/* Generated by cbindgen from src/ffi.rs; do not edit. */")
+        .with_sys_include("stddef.h")
+        .with_sys_include("stdint.h")
+        .with_no_includes()
+        .with_documentation(true)
+        .generate()
+        .expect("unable to generate the C header")
+        .write_to_file(format!("{}/include/audis.h", dir));
+}
+
+#[cfg(not(feature = "ffi"))]
+fn main() {}
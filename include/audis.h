@@ -0,0 +1,114 @@
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#ifndef AUDIS_H
+#define AUDIS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * A connection to the audit log, as seen from C.
+ */
+typedef struct AudisClient AudisClient;
+
+/**
+ * An event, as retrieved from the audit log.
+ */
+typedef struct AudisEvent {
+  /**
+   * The event ID, as a NUL-terminated string.
+   */
+  char *id;
+  /**
+   * The event payload (which is not NUL-terminated).
+   */
+  uint8_t *data;
+  /**
+   * How many bytes of payload there are.
+   */
+  size_t len;
+} AudisEvent;
+
+/**
+ * Connect to the audit log at `url` (i.e.
+ * `redis://127.0.0.1:6379`).
+ *
+ * Returns `NULL` on failure.  The client must be released with
+ * `audis_free()`.
+ *
+ * # Safety
+ *
+ * `url` must be a valid, NUL-terminated string.
+ */
+struct AudisClient *audis_connect(const char *url);
+
+/**
+ * Disconnect from the audit log, releasing `client`.
+ *
+ * # Safety
+ *
+ * `client` must have come from `audis_connect()`, and must not
+ * be used again.  It may be `NULL`.
+ */
+void audis_free(struct AudisClient *client);
+
+/**
+ * Log an event, with `len` bytes of payload from `data`,
+ * against `nsubjects` subjects.  If `id` is `NULL`, a ULID is
+ * generated for it.
+ *
+ * Returns `0` on success, and `-1` on failure.
+ *
+ * # Safety
+ *
+ * `client` must have come from `audis_connect()`; `id` (unless
+ * `NULL`) and every one of the `nsubjects` strings in `subjects`
+ * must be valid, NUL-terminated strings; and `data` must point
+ * to at least `len` bytes (it may be `NULL` if `len` is `0`).
+ */
+int audis_log(struct AudisClient *client,
+              const char *id,
+              const uint8_t *data,
+              size_t len,
+              const char *const *subjects,
+              size_t nsubjects);
+
+/**
+ * Retrieve the events logged against `subject`, in order,
+ * storing a pointer to the first of them in `*events`, and how
+ * many there are in `*n`.
+ *
+ * Returns `0` on success, and `-1` on failure.  The events must
+ * be released with `audis_free_events()`.
+ *
+ * # Safety
+ *
+ * `client` must have come from `audis_connect()`, `subject` must
+ * be a valid, NUL-terminated string, and `events` and `n` must
+ * be valid pointers.
+ */
+int audis_retrieve(struct AudisClient *client,
+                   const char *subject,
+                   struct AudisEvent **events,
+                   size_t *n);
+
+/**
+ * Release the `n` events retrieved by `audis_retrieve()`.
+ *
+ * # Safety
+ *
+ * `events` and `n` must be exactly as `audis_retrieve()` left
+ * them, and the events must not be used again.
+ */
+void audis_free_events(struct AudisEvent *events, size_t n);
+
+/**
+ * Describe the last failure (on this thread), or return `NULL`
+ * if nothing has failed yet.
+ *
+ * The string belongs to audis, and is only good until the next
+ * call into audis on this thread.
+ */
+const char *audis_last_error(void);
+
+#endif  /* AUDIS_H */
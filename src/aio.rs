@@ -0,0 +1,213 @@
+//! An audit log client for asynchronous code.
+//!
+//! Services built on tokio can't call the blocking `Client`
+//! directly from their tasks without stalling the runtime's
+//! worker threads.  The asynchronous `aio::Client` wraps a
+//! blocking `Client`, and runs each operation on tokio's
+//! blocking thread pool (via `spawn_blocking()`), so that awaiting
+//! it only ties up the task awaiting it.
+//!
+//! Everything else - the keys events are stored under, payload
+//! encoding, validation, access policies and the rest - is the
+//! blocking client's, so clients of either kind can share an
+//! audit log, and an `aio::Client` is configured by configuring
+//! the `Client` it wraps.
+//!
+//! That makes this a wrapper, not a second client sharing a
+//! backend-agnostic core with the first, and there is no `sync`
+//! feature to compile the blocking client out: it *is* the core.
+//! The version of the `redis` crate that audis speaks to Redis
+//! with predates tokio 1 (its asynchronous connections run on
+//! tokio 0.1), so there's no talking to Redis natively
+//! asynchronously, and every operation in flight ties up one of
+//! tokio's blocking threads until it is done.  That will change
+//! if audis moves to a newer `redis`; until then, the one
+//! guarantee is the other way around.
+//!
+//! This requires the `async` feature, which pulls in tokio; the
+//! blocking client never does.
+//!
+//! ```rust,no_run
+//! extern crate audis;
+//!
+//! async fn handle(audit: &audis::aio::Client) -> audis::AudisResult<()> {
+//!     let e = audis::Event::builder()
+//!         .id("foo1")
+//!         .data("{\"ok\":true}")
+//!         .subjects(vec!["system", "user:42"])
+//!         .build()?;
+//!     audit.log(&e).await?;
+//!     println!("{} event(s)", audit.count("user:42").await?);
+//!     Ok(())
+//! }
+//!
+//! fn main() {
+//!     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+//!     let audit = audis::aio::Client::new(client.dedup(std::time::Duration::from_secs(60)));
+//!     tokio::runtime::Runtime::new()
+//!         .unwrap()
+//!         .block_on(handle(&audit))
+//!         .unwrap();
+//! }
+//! ```
+
+use std::panic;
+
+use tokio::task;
+
+use crate::{AudisError, AudisResult, Event, Fsck, LogOutcome};
+
+/// An asynchronous audit log client; see the `aio` module.
+///
+/// Like the blocking `Client`, this is cheap to clone, and
+/// clones share their connections to the backend.
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+}
+
+impl From<crate::Client> for Client {
+    fn from(inner: crate::Client) -> Client {
+        Client { inner }
+    }
+}
+
+impl Client {
+    /// Wrap a (fully configured) blocking client.
+    pub fn new(inner: crate::Client) -> Client {
+        Client { inner }
+    }
+
+    /// Connect to the backend at `url`, as per
+    /// `audis::Client::connect()`.
+    pub async fn connect(url: &str) -> AudisResult<Client> {
+        let url = url.to_string();
+        blocking(move || crate::Client::connect(&url))
+            .await
+            .map(Client::new)
+    }
+
+    /// The blocking client this one wraps, i.e. for operations
+    /// that don't (yet) have asynchronous counterparts, from
+    /// code that can afford to block.
+    pub fn blocking(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// Log an event; see `audis::Client::log()`.
+    pub async fn log(&self, e: &Event) -> AudisResult<()> {
+        let (c, e) = (self.inner.clone(), e.clone());
+        blocking(move || c.log(&e).map(|_| ())).await
+    }
+
+    /// Log an event, reporting which subjects it was indexed
+    /// against; see `audis::Client::log_outcome()`.
+    pub async fn log_outcome(&self, e: &Event) -> AudisResult<LogOutcome> {
+        let (c, e) = (self.inner.clone(), e.clone());
+        blocking(move || c.log_outcome(&e)).await
+    }
+
+    /// Retrieve every event logged against a subject; see
+    /// `audis::Client::retrieve()`.
+    pub async fn retrieve(&self, log: &str) -> AudisResult<Vec<Event>> {
+        let (c, log) = (self.inner.clone(), log.to_string());
+        blocking(move || c.retrieve(&log)).await
+    }
+
+    /// Retrieve part of a subject's events; see
+    /// `audis::Client::retrieve_range()`.
+    pub async fn retrieve_range(
+        &self,
+        log: &str,
+        start: i64,
+        stop: i64,
+    ) -> AudisResult<Vec<Event>> {
+        let (c, log) = (self.inner.clone(), log.to_string());
+        blocking(move || c.retrieve_range(&log, start, stop)).await
+    }
+
+    /// Retrieve every event with a given correlation ID; see
+    /// `audis::Client::retrieve_trail()`.
+    pub async fn retrieve_trail(&self, correlation_id: &str) -> AudisResult<Vec<Event>> {
+        let (c, id) = (self.inner.clone(), correlation_id.to_string());
+        blocking(move || c.retrieve_trail(&id)).await
+    }
+
+    /// Retrieve a single event, by ID; see
+    /// `audis::Client::retrieve_event()`.
+    pub async fn retrieve_event(&self, id: &str) -> AudisResult<Option<Event>> {
+        let (c, id) = (self.inner.clone(), id.to_string());
+        blocking(move || c.retrieve_event(&id)).await
+    }
+
+    /// Retrieve a batch of events, by ID; see
+    /// `audis::Client::get_events()`.
+    pub async fn get_events<S: AsRef<str>>(&self, ids: &[S]) -> AudisResult<Vec<Option<Event>>> {
+        let c = self.inner.clone();
+        let ids: Vec<String> = ids.iter().map(|id| id.as_ref().to_string()).collect();
+        blocking(move || c.get_events(&ids)).await
+    }
+
+    /// Count the events logged against a subject; see
+    /// `audis::Client::count()`.
+    pub async fn count(&self, log: &str) -> AudisResult<u64> {
+        let (c, log) = (self.inner.clone(), log.to_string());
+        blocking(move || c.count(&log)).await
+    }
+
+    /// List every subject; see `audis::Client::subjects()`.
+    pub async fn subjects(&self) -> AudisResult<Vec<String>> {
+        let c = self.inner.clone();
+        blocking(move || c.subjects()).await
+    }
+
+    /// List the subjects an event was logged against; see
+    /// `audis::Client::subjects_of()`.
+    pub async fn subjects_of(&self, id: &str) -> AudisResult<Vec<String>> {
+        let (c, id) = (self.inner.clone(), id.to_string());
+        blocking(move || c.subjects_of(&id)).await
+    }
+
+    /// Delete an event from every subject; see
+    /// `audis::Client::delete()`.
+    pub async fn delete(&self, id: &str) -> AudisResult<()> {
+        let (c, id) = (self.inner.clone(), id.to_string());
+        blocking(move || c.delete(&id).map(|_| ())).await
+    }
+
+    /// Delete all but the last `n` events of a subject; see
+    /// `audis::Client::truncate()`.
+    pub async fn truncate(&self, log: &str, n: u32) -> AudisResult<()> {
+        let (c, log) = (self.inner.clone(), log.to_string());
+        blocking(move || c.truncate(&log, n).map(|_| ())).await
+    }
+
+    /// Delete the events of a subject up to (and including)
+    /// `last`; see `audis::Client::purge()`.
+    pub async fn purge(&self, log: &str, last: &str) -> AudisResult<()> {
+        let (c, log, last) = (self.inner.clone(), log.to_string(), last.to_string());
+        blocking(move || c.purge(&log, &last).map(|_| ())).await
+    }
+
+    /// Check the audit log for consistency; see
+    /// `audis::Client::fsck()`.
+    pub async fn fsck(&self, repair: bool) -> AudisResult<Fsck> {
+        let c = self.inner.clone();
+        blocking(move || c.fsck(repair)).await
+    }
+}
+
+// Run a blocking operation on tokio's blocking thread pool.
+// Panics are passed on to whoever is awaiting the operation, as
+// if it had run on their own thread.
+async fn blocking<T, F>(f: F) -> AudisResult<T>
+where
+    F: FnOnce() -> AudisResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(r) => r,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(AudisError::Cancelled(e.to_string())),
+    }
+}
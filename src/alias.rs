@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use crate::{AudisError, AudisResult, Client, Operation};
+
+// Where aliases are kept, mapping each (stored) alias to the
+// (stored) subject it stands for.
+const ALIASES: &str = "audis:aliases";
+
+impl Client {
+    /// Resolve subject aliases (see `alias_subject()`) when
+    /// retrieving or counting a subject's events.
+    ///
+    /// This costs every retrieval an extra round trip to the
+    /// backend, to look the subject up in `audis:aliases`, so
+    /// clients that don't read via aliases don't pay for it.
+    pub fn resolve_aliases(mut self) -> Client {
+        self.aliased = true;
+        self
+    }
+
+    /// Make `alias` another name for the subject `canonical`, so
+    /// that retrieving (or counting) the events of `alias`, with
+    /// a client that `resolve_aliases()`, retrieves those of
+    /// `canonical`, i.e. so that a user's
+    /// audit log can be looked up by `email:bob@example.com` as
+    /// well as by `user:42`, without indexing every event
+    /// against both.
+    ///
+    /// Aliases are only resolved when reading a subject's events;
+    /// events logged against an alias are indexed against the
+    /// alias itself, as per usual, and stay hidden behind it
+    /// until it is removed with `unalias_subject()`.  Aliasing a
+    /// subject that already has events fails with
+    /// `AudisError::Invalid`, as does aliasing a subject to
+    /// itself.  Aliases of aliases are resolved when they are
+    /// made, so that `alias` always names a real subject, and
+    /// aliases follow their subject when it is renamed (or merged
+    /// into another; see `rename_subject()`).
+    ///
+    /// If the client has an access policy, it must allow
+    /// `Operation::Alias` on both names.  Reading through an
+    /// alias needs `Operation::Retrieve` on both the alias and
+    /// the subject it stands for, so an alias never grants access
+    /// to anything the policy wouldn't allow retrieving directly.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn alias_subject(&self, alias: &str, canonical: &str) -> AudisResult<&Client> {
+        self.instrument("alias_subject", || {
+            self.allow(Operation::Alias, alias)?;
+            self.allow(Operation::Alias, canonical)?;
+            let alias = self.subject(alias);
+            let canonical = self.resolve(&self.subject(canonical))?;
+            if alias == canonical {
+                return Err(AudisError::Invalid(format!(
+                    "cannot alias {} to itself",
+                    alias
+                )));
+            }
+            if self.llen(&alias)? > 0 {
+                return Err(AudisError::Invalid(format!(
+                    "cannot alias {} to {}: {} already has events",
+                    alias, canonical, alias
+                )));
+            }
+
+            // anything that was an alias of the new alias now
+            // stands for the subject it does.
+            for (other, _) in self.aliases()?.iter().filter(|(_, c)| **c == alias) {
+                self.query::<()>(redis::cmd("HSET").arg(ALIASES).arg(other).arg(&canonical))?;
+            }
+            self.query::<()>(
+                redis::cmd("HSET")
+                    .arg(ALIASES)
+                    .arg(alias.as_ref())
+                    .arg(&canonical),
+            )?;
+            Ok(self)
+        })
+    }
+
+    /// Remove an alias made by `alias_subject()`, returning false
+    /// if `alias` wasn't one.  The subject it stood for is left
+    /// as it was.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn unalias_subject(&self, alias: &str) -> AudisResult<bool> {
+        self.instrument("unalias_subject", || {
+            self.allow(Operation::Alias, alias)?;
+            let n: u64 = self.query(
+                redis::cmd("HDEL")
+                    .arg(ALIASES)
+                    .arg(self.subject(alias).as_ref()),
+            )?;
+            Ok(n > 0)
+        })
+    }
+
+    /// List the aliases of a subject, sorted by name.  Names are
+    /// as stored (see `subjects_matching()`), and those that the
+    /// client's access policy (if any) does not allow retrieving
+    /// are left out.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn aliases_of(&self, canonical: &str) -> AudisResult<Vec<String>> {
+        self.instrument("aliases_of", || {
+            self.allow(Operation::Retrieve, canonical)?;
+            let canonical = self.subject(canonical);
+            let mut aliases: Vec<String> = self
+                .aliases()?
+                .into_iter()
+                .filter(|(a, c)| *c == canonical && self.allows_stored(Operation::Retrieve, a))
+                .map(|(a, _)| a)
+                .collect();
+            aliases.sort();
+            Ok(aliases)
+        })
+    }
+
+    // Check that a subject can be retrieved, and find the stored
+    // name of the subject whose events retrieving it reads.
+    pub(crate) fn readable(&self, log: &str) -> AudisResult<String> {
+        self.allow(Operation::Retrieve, log)?;
+        let log = self.subject(log);
+        if !self.aliased {
+            return Ok(log.into_owned());
+        }
+        let canonical = self.resolve(&log)?;
+        if canonical != log {
+            self.allow_stored(Operation::Retrieve, &canonical)?;
+        }
+        Ok(canonical)
+    }
+
+    // Find the (stored) subject that a (stored) name stands for,
+    // which is itself, unless it is an alias.
+    pub(crate) fn resolve(&self, subject: &str) -> AudisResult<String> {
+        let canonical: Option<String> = self.query(redis::cmd("HGET").arg(ALIASES).arg(subject))?;
+        Ok(canonical.unwrap_or_else(|| subject.to_string()))
+    }
+
+    // Point the aliases of one (stored) subject at another,
+    // because the first is being renamed (or merged) into it,
+    // which is no longer an alias, if it ever was.
+    pub(crate) fn realias(&self, from: &str, to: &str) -> AudisResult<()> {
+        for (alias, _) in self.aliases()?.iter().filter(|(_, c)| *c == from) {
+            self.query::<()>(redis::cmd("HSET").arg(ALIASES).arg(alias).arg(to))?;
+        }
+        self.query::<()>(redis::cmd("HDEL").arg(ALIASES).arg(to))?;
+        Ok(())
+    }
+
+    // Drop every alias of a (stored) subject, along with the
+    // subject itself, if it is one, because it is being erased.
+    pub(crate) fn unalias_all(&self, subject: &str) -> AudisResult<()> {
+        let mut hdel = redis::cmd("HDEL");
+        hdel.arg(ALIASES).arg(subject);
+        for (alias, _) in self.aliases()?.iter().filter(|(_, c)| *c == subject) {
+            hdel.arg(alias);
+        }
+        self.query(&mut hdel)
+    }
+
+    fn aliases(&self) -> AudisResult<HashMap<String, String>> {
+        self.query(redis::cmd("HGETALL").arg(ALIASES))
+    }
+}
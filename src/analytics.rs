@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::ids::timestamp;
+use crate::{AudisError, AudisResult, Client, SubjectOrder};
+
+impl Client {
+    /// List the `n` subjects with the most events, largest first
+    /// (and then by name), along with how many events each has.
+    ///
+    /// This counts every subject, like `subject_counts()` does, so
+    /// it costs one pipelined round trip per thousand subjects;
+    /// see `stats()` for the largest subjects by memory instead.
+    /// Subjects that the client's access policy (if any) does not
+    /// allow retrieving are left out.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn top_subjects(&self, n: usize) -> AudisResult<Vec<(String, u64)>> {
+        self.instrument("top_subjects", || {
+            let mut counts = self.subject_counts("*", SubjectOrder::Count)?;
+            counts.truncate(n);
+            Ok(counts)
+        })
+    }
+
+    /// Count the events logged against a subject per `bucket` of
+    /// time (i.e. per hour, or per day), keyed by the start of
+    /// each bucket, in milliseconds since the epoch.  Buckets
+    /// without any events are left out.
+    ///
+    /// Only the event IDs are read, never their payloads.  With
+    /// `time_indexed()`, events are bucketed by their scores in
+    /// the subject's time index; without, by the timestamps of
+    /// their IDs (see `ids::timestamp()`), and events whose IDs
+    /// don't have one are left out.  Buckets are aligned to the
+    /// epoch, so daily buckets are UTC days.  A zero-length
+    /// `bucket` fails with `AudisError::Invalid`; errors are
+    /// otherwise the same as for `count()`.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///     let hour = Duration::from_secs(3600);
+    ///     for (at, n) in client.histogram("user:42", hour).unwrap() {
+    ///         println!("{} {}", at, n);
+    ///     }
+    /// }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn histogram(&self, log: &str, bucket: Duration) -> AudisResult<BTreeMap<u64, u64>> {
+        self.instrument("histogram", || {
+            let width = bucket.as_millis() as u64;
+            if width == 0 {
+                return Err(AudisError::Invalid(
+                    "histogram buckets must be at least a millisecond wide".to_string(),
+                ));
+            }
+            let log = self.readable(log)?;
+
+            let times: Vec<u64> = if self.timeline {
+                self.stamps(&log)?
+            } else {
+                self.lrange(&log, "0", "-1")?
+                    .iter()
+                    .filter_map(|id| timestamp(id))
+                    .collect()
+            };
+
+            let mut histogram = BTreeMap::new();
+            for t in times {
+                *histogram.entry(t - t % width).or_insert(0) += 1;
+            }
+            Ok(histogram)
+        })
+    }
+}
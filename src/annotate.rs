@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use crate::{AudisError, AudisResult, Client, Operation};
+
+// A subject's annotations, keyed by (stored) subject name.
+macro_rules! notes {
+    ($s:expr) => {
+        format!("audis:notes:{}", $s)
+    };
+}
+
+impl Client {
+    /// Annotate a subject, setting `key` to `value`, i.e. to
+    /// record which team owns it, how its data is classified, or
+    /// which retention class it falls under, so that the catalog
+    /// of subjects stays comprehensible as it grows.
+    ///
+    /// Annotations are kept (in `audis:notes:$subject`) apart from
+    /// the subject's events, and are left alone when they are
+    /// pruned (or the subject is removed altogether), so a
+    /// subject can be annotated before anything is logged against
+    /// it.  They follow the subject when it is renamed, but not
+    /// when it is merged into another.  Keys must not be empty.
+    ///
+    /// If the client has an access policy, it must allow
+    /// `Operation::Annotate` on the subject.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn annotate_subject(&self, subject: &str, key: &str, value: &str) -> AudisResult<&Client> {
+        self.instrument("annotate_subject", || {
+            self.allow(Operation::Annotate, subject)?;
+            if key.is_empty() {
+                return Err(AudisError::Invalid(format!(
+                    "cannot annotate {} with an empty key",
+                    subject
+                )));
+            }
+            self.query::<()>(
+                redis::cmd("HSET")
+                    .arg(notes!(self.subject(subject)))
+                    .arg(key)
+                    .arg(value),
+            )?;
+            Ok(self)
+        })
+    }
+
+    /// Remove one of a subject's annotations (see
+    /// `annotate_subject()`), returning false if it didn't have
+    /// one by that key.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn unannotate_subject(&self, subject: &str, key: &str) -> AudisResult<bool> {
+        self.instrument("unannotate_subject", || {
+            self.allow(Operation::Annotate, subject)?;
+            let n: u64 = self.query(
+                redis::cmd("HDEL")
+                    .arg(notes!(self.subject(subject)))
+                    .arg(key),
+            )?;
+            Ok(n > 0)
+        })
+    }
+
+    /// Get a subject's annotations (see `annotate_subject()`), by
+    /// key.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn annotations(&self, subject: &str) -> AudisResult<BTreeMap<String, String>> {
+        self.instrument("annotations", || {
+            self.allow(Operation::Retrieve, subject)?;
+            self.query(redis::cmd("HGETALL").arg(notes!(self.subject(subject))))
+        })
+    }
+
+    /// List the known subjects whose names match the glob
+    /// `pattern` (like `subjects_matching()` does), along with
+    /// their annotations, sorted by name.
+    ///
+    /// The annotations are fetched with pipelined `HGETALL`s, a
+    /// thousand subjects at a time.  Only subjects that have had
+    /// events logged against them are listed, annotated or not.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn annotated_subjects(
+        &self,
+        pattern: &str,
+    ) -> AudisResult<Vec<(String, BTreeMap<String, String>)>> {
+        self.instrument("annotated_subjects", || {
+            let mut subjects = self.scan("SSCAN", Some("subjects"), pattern)?;
+            subjects.retain(|s| self.allows_stored(Operation::Retrieve, s));
+            subjects.sort();
+            subjects.dedup();
+
+            let mut annotated = Vec::with_capacity(subjects.len());
+            for chunk in subjects.chunks(1000) {
+                self.cancelled()?;
+                let mut pipe = redis::pipe();
+                for s in chunk {
+                    pipe.cmd("HGETALL").arg(notes!(s));
+                }
+                let notes: Vec<BTreeMap<String, String>> = self.pipeline(&pipe)?;
+                annotated.extend(chunk.iter().cloned().zip(notes));
+            }
+            Ok(annotated)
+        })
+    }
+
+    // Drop the annotations of a (stored) subject, because it is
+    // being erased.
+    pub(crate) fn drop_notes(&self, subject: &str) -> AudisResult<()> {
+        self.query(redis::cmd("DEL").arg(notes!(subject)))
+    }
+
+    // Move the annotations of one (stored) subject to another,
+    // because the first is being renamed.
+    pub(crate) fn move_notes(&self, from: &str, to: &str) -> AudisResult<()> {
+        let notes: BTreeMap<String, String> =
+            self.query(redis::cmd("HGETALL").arg(notes!(from)))?;
+        if notes.is_empty() {
+            return Ok(());
+        }
+        let mut hset = redis::cmd("HSET");
+        hset.arg(notes!(to));
+        for (k, v) in &notes {
+            hset.arg(k).arg(v);
+        }
+        self.query::<()>(&mut hset)?;
+        self.query::<()>(redis::cmd("DEL").arg(notes!(from)))?;
+        Ok(())
+    }
+}
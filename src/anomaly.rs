@@ -0,0 +1,215 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::glob;
+use crate::intercept::sweeping;
+use crate::{AudisResult, Event, Interceptor};
+
+/// A burst of events logged against one subject, as reported by
+/// an `AnomalyDetector`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    /// The subject the events were logged against.
+    pub subject: String,
+
+    /// How many events have been logged against the subject in
+    /// the current window, so far.
+    pub events: u64,
+
+    /// How many events were logged against the subject per
+    /// window, on average, over the windows before this one.
+    pub baseline: f64,
+
+    /// How long each window is.
+    pub window: Duration,
+}
+
+/// An interceptor that watches how quickly events are logged
+/// against each subject, and calls a hook as soon as a subject's
+/// rate in the current window exceeds a multiple of its usual
+/// rate, so that i.e. security teams can be alerted to a sudden
+/// burst of audited activity as it happens.
+///
+/// ```rust,no_run
+/// extern crate audis;
+///
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .with_interceptor(Box::new(
+///             audis::AnomalyDetector::new(Duration::from_secs(60), 10.0, |a| {
+///                 eprintln!(
+///                     "{} logged {} events this minute (usually {:.1})",
+///                     a.subject, a.events, a.baseline
+///                 );
+///             })
+///             .watch("user:*"),
+///         ));
+/// }
+/// ```
+///
+/// Every subject's events are counted per `window`, starting
+/// with its first event; its baseline is the average count over
+/// the previous `history()` windows (24, by default), including
+/// those in which nothing was logged.  Once a subject has that
+/// much history, and the count for the current window exceeds
+/// `multiple` times the baseline (and `min_events()`, so that
+/// quiet subjects don't set it off with a handful of events),
+/// the hook is called, once per subject per window.
+///
+/// Events are never modified or dropped.  The hook is called on
+/// the thread logging the event that set it off, so it should
+/// hand anything slow off to another thread.  Like those of the
+/// `RateLimiter`, counts live in process memory, so each process
+/// (and each AnomalyDetector) only sees the events it logs;
+/// add the detector after any interceptors that drop events, so
+/// that it only counts the ones that are actually logged.
+/// Subjects that go quiet for longer than their history are
+/// forgotten every so often, and have to build it up again.
+pub struct AnomalyDetector {
+    patterns: Vec<String>,
+    window: Duration,
+    history: usize,
+    multiple: f64,
+    min_events: u64,
+    hook: Box<Hook>,
+    rates: Mutex<Rates>,
+}
+
+#[derive(Default)]
+struct Rates {
+    by_subject: HashMap<String, Rate>,
+    swept: Option<Instant>,
+}
+
+type Hook = dyn Fn(&Anomaly) + Send + Sync;
+
+struct Rate {
+    started: Instant,
+    events: u64,
+    past: VecDeque<u64>,
+    alerted: bool,
+}
+
+impl AnomalyDetector {
+    /// Create an AnomalyDetector that calls `hook` whenever more
+    /// than `multiple` times a subject's usual number of events
+    /// are logged against it within a `window` (of at least a
+    /// millisecond).
+    pub fn new<F>(window: Duration, multiple: f64, hook: F) -> AnomalyDetector
+    where
+        F: Fn(&Anomaly) + Send + Sync + 'static,
+    {
+        AnomalyDetector {
+            patterns: vec![],
+            window: window.max(Duration::from_millis(1)),
+            history: 24,
+            multiple,
+            min_events: 10,
+            hook: Box::new(hook),
+            rates: Mutex::new(Rates::default()),
+        }
+    }
+
+    /// Only watch subjects matching the glob `pattern` (along
+    /// with those matching any other patterns given), rather than
+    /// every subject.
+    pub fn watch(mut self, pattern: &str) -> AnomalyDetector {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Work out each subject's baseline from its last `windows`
+    /// windows (at least one), rather than the last 24.
+    pub fn history(mut self, windows: usize) -> AnomalyDetector {
+        self.history = windows.max(1);
+        self
+    }
+
+    /// Never call the hook for fewer than `n` events in a window,
+    /// rather than 10, however quiet the subject usually is.
+    pub fn min_events(mut self, n: u64) -> AnomalyDetector {
+        self.min_events = n;
+        self
+    }
+
+    // Count an event against a subject, returning the anomaly it
+    // makes, if any.
+    fn count(&self, rates: &mut HashMap<String, Rate>, s: &str, now: Instant) -> Option<Anomaly> {
+        let r = rates.entry(s.to_string()).or_insert(Rate {
+            started: now,
+            events: 0,
+            past: VecDeque::new(),
+            alerted: false,
+        });
+
+        let elapsed = now.duration_since(r.started);
+        if elapsed >= self.window {
+            let window = self.window.as_nanos();
+            let skipped = (elapsed.as_nanos() / window) as usize - 1;
+            r.past.push_back(r.events);
+            r.past
+                .extend(std::iter::repeat_n(0, skipped.min(self.history)));
+            while r.past.len() > self.history {
+                r.past.pop_front();
+            }
+            r.started = now - Duration::from_nanos((elapsed.as_nanos() % window) as u64);
+            r.events = 0;
+            r.alerted = false;
+        }
+
+        r.events += 1;
+        if r.alerted || r.past.len() < self.history || r.events < self.min_events {
+            return None;
+        }
+        let baseline = r.past.iter().sum::<u64>() as f64 / r.past.len() as f64;
+        if r.events as f64 <= baseline * self.multiple {
+            return None;
+        }
+        r.alerted = true;
+        Some(Anomaly {
+            subject: s.to_string(),
+            events: r.events,
+            baseline,
+            window: self.window,
+        })
+    }
+}
+
+impl Interceptor for AnomalyDetector {
+    fn intercept(&self, e: Event) -> AudisResult<Option<Event>> {
+        let now = Instant::now();
+        let anomalies: Vec<Anomaly> = {
+            let mut rates = self.rates.lock().unwrap();
+            let rates = &mut *rates;
+            if sweeping(&mut rates.swept, now) {
+                let windows = self.history.min(u32::MAX as usize) as u32;
+                let quiet = self.window.saturating_mul(windows.saturating_add(1));
+                rates
+                    .by_subject
+                    .retain(|_, r| now.duration_since(r.started) < quiet);
+            }
+            e.subjects
+                .iter()
+                .filter(|s| {
+                    self.patterns.is_empty()
+                        || self
+                            .patterns
+                            .iter()
+                            .any(|p| glob(p.as_bytes(), s.as_bytes()))
+                })
+                .filter_map(|s| self.count(&mut rates.by_subject, s, now))
+                .collect()
+        };
+
+        // the hook is called without holding the lock, so that it
+        // can't hold up (or deadlock) other threads' logging.
+        for a in &anomalies {
+            (self.hook)(a);
+        }
+        Ok(Some(e))
+    }
+}
@@ -0,0 +1,173 @@
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::{Event, Sender};
+
+/// A `log::Log` implementation that turns log records (sent to
+/// a particular target) into audit events, so that code already
+/// instrumented with the `log` crate can write to the audit log
+/// without any new plumbing:
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// extern crate log;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+///     let (tx, thread) = client.background(0).unwrap();
+///     audis::Appender::new(tx)
+///         .subject_key("user")
+///         .subject_key("account")
+///         .install()
+///         .unwrap();
+///
+///     // logged against user:42 and account:7
+///     log::info!(target: "audit", user = 42, account = 7; "changed the billing address");
+/// }
+/// ```
+///
+/// Records are handed to a `background()` logging thread, as
+/// events with the record's message as their payload, and a ULID
+/// for an ID.  Key-value pairs whose keys have been registered
+/// via `subject_key()` become subjects, i.e. `user:42`, as does
+/// the value of any `subject` key; the rest become metadata, as
+/// do the record's level (as `severity`) and module (as
+/// `module`).  Records with no subjects are logged against the
+/// target itself.
+///
+/// If the background thread's buffer is full, logging blocks
+/// until there is room, rather than losing audit events.
+/// Records for any other target are passed on to the `fallback()`
+/// logger, if there is one, or dropped.
+///
+/// This requires the `appender` feature.
+pub struct Appender {
+    tx: Sender,
+    target: String,
+    keys: Vec<String>,
+    fallback: Option<Box<dyn Log>>,
+}
+
+impl Appender {
+    /// Turn records for the `audit` target into events, logging
+    /// them via `tx`.
+    pub fn new(tx: Sender) -> Appender {
+        Appender {
+            tx,
+            target: "audit".to_string(),
+            keys: vec![],
+            fallback: None,
+        }
+    }
+
+    /// Turn records for `target` into events, instead.
+    pub fn target(mut self, target: &str) -> Appender {
+        self.target = target.to_string();
+        self
+    }
+
+    /// Log records with a `key` key-value pair against the
+    /// `key:value` subject.  This can be given more than once.
+    pub fn subject_key(mut self, key: &str) -> Appender {
+        self.keys.push(key.to_string());
+        self
+    }
+
+    /// Pass records for other targets on to `logger` (i.e. an
+    /// `env_logger::Logger`).
+    pub fn fallback(mut self, logger: Box<dyn Log>) -> Appender {
+        self.fallback = Some(logger);
+        self
+    }
+
+    /// Install this as the global logger, letting everything
+    /// through to it.  This fails if a global logger has already
+    /// been installed.
+    pub fn install(self) -> Result<(), SetLoggerError> {
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(LevelFilter::Trace);
+        Ok(())
+    }
+
+    fn event(&self, record: &Record) -> Result<Event, String> {
+        let mut e = Event::builder().data(record.args().to_string()).meta(
+            "severity",
+            match record.level() {
+                Level::Error => "err",
+                Level::Warn => "warning",
+                Level::Info => "info",
+                Level::Debug | Level::Trace => "debug",
+            },
+        );
+        if let Some(module) = record.module_path() {
+            e = e.meta("module", module);
+        }
+        let mut e = e.build().map_err(|e| e.to_string())?;
+
+        let mut pairs = Pairs {
+            keys: &self.keys,
+            e: &mut e,
+        };
+        record
+            .key_values()
+            .visit(&mut pairs)
+            .map_err(|e| e.to_string())?;
+        if e.subjects.is_empty() {
+            e.subjects.push(self.target.to_string());
+        }
+        Ok(e)
+    }
+}
+
+// Sorts key-value pairs into subjects and metadata.
+struct Pairs<'a> {
+    keys: &'a [String],
+    e: &'a mut Event,
+}
+
+impl<'kvs> VisitSource<'kvs> for Pairs<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let (key, value) = (key.as_str(), value.to_string());
+        if key == "subject" {
+            self.e.subjects.push(value);
+        } else if self.keys.iter().any(|k| k == key) {
+            self.e.subjects.push(format!("{}:{}", key, value));
+        } else {
+            self.e.meta.insert(key.to_string(), value);
+        }
+        Ok(())
+    }
+}
+
+impl Log for Appender {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == self.target
+            || self.fallback.as_ref().is_some_and(|f| f.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target() != self.target {
+            if let Some(f) = &self.fallback {
+                f.log(record);
+            }
+            return;
+        }
+
+        match self.event(record) {
+            Ok(e) => {
+                if self.tx.send(e).is_err() {
+                    log::error!(target: "audis", "background logger has stopped; dropping audit record");
+                }
+            }
+            Err(why) => {
+                log::error!(target: "audis", "failed to turn audit record into an event: {}", why)
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(f) = &self.fallback {
+            f.flush();
+        }
+    }
+}
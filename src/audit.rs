@@ -0,0 +1,183 @@
+use std::fmt;
+
+use crate::{AudisResult, Client, Event};
+
+/// The reserved subject that destructive operations are
+/// recorded against, when `Client::audit_changes()` is on.
+pub const SYSTEM_SUBJECT: &str = "__audis__";
+
+/// Who (or what) is carrying out administrative operations
+/// against the audit log, for the record.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Actor {
+    /// A human operator, by username.
+    User(String),
+
+    /// An automated process (a cleanup job, a retention
+    /// policy, etc.), by name.
+    Service(String),
+}
+
+impl Actor {
+    /// The name of the user or service.
+    pub fn name(&self) -> &str {
+        match self {
+            Actor::User(name) | Actor::Service(name) => name,
+        }
+    }
+
+    /// What kind of actor this is; either `user` or `service`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Actor::User(_) => "user",
+            Actor::Service(_) => "service",
+        }
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind(), self.name())
+    }
+}
+
+impl Client {
+    /// Record every destructive operation (`truncate()`,
+    /// `purge()`, `delete()`, `erase_subject()`,
+    /// `remove_subject()`, `rename_subject()`, `merge_subjects()`,
+    /// `shred_subject_key()`, `fsck()` repairs and `gc()`) as an
+    /// event in the audit log itself, against the reserved
+    /// `__audis__` subject.
+    ///
+    /// Each of these events carries the operation (`op`), the
+    /// subject it was applied to (`subject`; left out by
+    /// `erase_subject()` and `shred_subject_key()`, so that they
+    /// don't leave the name of the subject they erase behind,
+    /// unless it is a pseudonym), how many events it
+    /// removed (`events`), the IDs of the first and last of them
+    /// (`first` and `last`), the host and process it was run
+    /// from (`host` and `pid`), and the `Actor` responsible for
+    /// it (`actor` and `actor_kind`; see `acting_as()`), as
+    /// metadata.
+    ///
+    /// These events bypass interceptors and validators, but are
+    /// otherwise logged like any other; they are chained, signed
+    /// and encrypted if the client is configured to do so.
+    ///
+    pub fn audit_changes(mut self) -> Client {
+        self.audit_changes = true;
+        self
+    }
+
+    /// Carry out all administrative operations on behalf of
+    /// `actor`, recording it in the events logged by
+    /// `audit_changes()`.
+    pub fn acting_as(mut self, actor: Actor) -> Client {
+        self.actor = Some(actor);
+        self
+    }
+
+    /// Get another client, sharing this one's backend and
+    /// configuration, that acts on behalf of `actor`.
+    ///
+    /// This is handy for services that carry out operations on
+    /// behalf of different operators:
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use audis::Actor;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+    ///         .unwrap()
+    ///         .audit_changes()
+    ///         .acting_as(Actor::Service("retention".to_string()));
+    ///
+    ///     // recorded as the retention service
+    ///     client.truncate("user:42", 1000).unwrap();
+    ///
+    ///     // recorded as jhunt
+    ///     client
+    ///         .on_behalf_of(Actor::User("jhunt".to_string()))
+    ///         .purge("user:42", "ae2")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn on_behalf_of(&self, actor: Actor) -> Client {
+        self.clone().acting_as(actor)
+    }
+
+    // Log a destructive operation against the __audis__
+    // subject, if the client is configured to do so.
+    pub(crate) fn record(&self, op: &str, subject: &str, removed: &[String]) -> AudisResult<()> {
+        self.record_with(op, subject, removed, &[])
+    }
+
+    // Log the erasure of a (stored) subject, without its name,
+    // unless that is a pseudonym, which is already a keyed hash.
+    pub(crate) fn record_erasure(
+        &self,
+        op: &str,
+        subject: &str,
+        removed: &[String],
+    ) -> AudisResult<()> {
+        let subject = if self.pseudonyms.is_some() {
+            subject
+        } else {
+            ""
+        };
+        self.record(op, subject, removed)
+    }
+
+    // Log a destructive operation, along with some extra
+    // operation-specific metadata.
+    pub(crate) fn record_with(
+        &self,
+        op: &str,
+        subject: &str,
+        removed: &[String],
+        extra: &[(&str, &str)],
+    ) -> AudisResult<()> {
+        if !self.audit_changes {
+            return Ok(());
+        }
+
+        let seq: u64 = self.query(redis::cmd("INCR").arg("audis:changes"))?;
+        let what = match subject {
+            "" => op.to_string(),
+            _ => format!("{} {}", op, subject),
+        };
+        let mut e = Event {
+            id: format!("{}:{}", SYSTEM_SUBJECT, seq),
+            data: format!("{}: {} event(s) removed", what, removed.len()).into(),
+            subjects: vec![SYSTEM_SUBJECT.to_string()],
+            ..Default::default()
+        };
+        e.meta.insert("op".to_string(), op.to_string());
+        if !subject.is_empty() {
+            e.meta.insert("subject".to_string(), subject.to_string());
+        }
+        e.meta
+            .insert("events".to_string(), removed.len().to_string());
+        if let (Some(first), Some(last)) = (removed.first(), removed.last()) {
+            e.meta.insert("first".to_string(), first.to_string());
+            e.meta.insert("last".to_string(), last.to_string());
+        }
+        if let Ok(h) = hostname::get() {
+            e.meta
+                .insert("host".to_string(), h.to_string_lossy().to_string());
+        }
+        e.meta
+            .insert("pid".to_string(), std::process::id().to_string());
+        for (k, v) in extra {
+            e.meta.insert(k.to_string(), v.to_string());
+        }
+        if let Some(actor) = &self.actor {
+            e.meta.insert("actor".to_string(), actor.name().to_string());
+            e.meta
+                .insert("actor_kind".to_string(), actor.kind().to_string());
+        }
+        self.store(&e)?.ok()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1187 @@
+//! An embedded, file-based backend.
+//!
+//! The `FileBackend` keeps the entire audit log in memory,
+//! and appends every write command it executes to a journal
+//! file, in the Redis wire protocol.  When the backend is
+//! opened, the journal is replayed to rebuild the in-memory
+//! state.  The journal format is the same one Redis uses for
+//! its own append-only files, so an audis journal can be fed
+//! to `redis-server` (via `appendfilename`) to migrate a log
+//! into a real Redis instance later.
+//!
+//! Only one `FileBackend` can have a given journal open at any
+//! one time; it holds an exclusive lock on the file for as long
+//! as it is open.  Clients that share a backend (i.e. via the
+//! `background()` thread) share the same in-memory state.
+//!
+//! A write cut short by a crash leaves a partial command at the
+//! end of the journal, which is cut off (with a warning) when the
+//! journal is next opened.  Anything else that can't be replayed
+//! is an error.  Journals grow with every write, and are rewritten
+//! (compacted) to the commands needed to rebuild the current
+//! state whenever they are opened holding more than twice that
+//! many, or on demand, via `compact()`.
+
+use redis::{ErrorKind, RedisError, RedisResult, Value};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, BufWriter, Seek, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Backend;
+use crate::AudisResult;
+
+/// An audit log stored in a local, append-only journal file.
+///
+/// Clones share the same in-memory state (and journal).
+#[derive(Clone)]
+pub struct FileBackend {
+    store: Arc<Mutex<Store>>,
+}
+
+impl FileBackend {
+    /// Open (or create) a journal file, by `file:` URL.
+    ///
+    /// Both `file:/path/to/audit.aof` and `file:///path/to/audit.aof`
+    /// are understood; `file:audit.aof` is relative to the
+    /// current working directory.
+    ///
+    /// Fails if the journal can't be opened (or created), if it
+    /// is already open elsewhere, or if it holds anything but
+    /// write commands (and a partial one at the very end).
+    pub fn open(url: &str) -> AudisResult<FileBackend> {
+        let path = url
+            .strip_prefix("file://")
+            .or_else(|| url.strip_prefix("file:"))
+            .unwrap_or(url);
+        if path.is_empty() {
+            return Err(RedisError::from((
+                ErrorKind::InvalidClientConfig,
+                "missing path in file: URL",
+            ))
+            .into());
+        }
+
+        let journal = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        lock(&journal, path)?;
+
+        let mut store = Store::new(path);
+        let mut replayed = 0;
+        let mut reader = BufReader::new(&journal);
+        while !reader.fill_buf()?.is_empty() {
+            let at = reader.stream_position()?;
+            let value = match redis::Parser::new(&mut reader).parse_value() {
+                Ok(value) => value,
+
+                // a parse that ran out of journal is a write that
+                // was cut short, which never happened as far as
+                // whoever made it knows.
+                Err(_) if reader.fill_buf()?.is_empty() => {
+                    log::warn!("truncating partial command at {} in {}", at, path);
+                    journal.set_len(at)?;
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            store.exec(args(value)?)?;
+            replayed += 1;
+        }
+        store.journal = Some(journal);
+        if replayed > 2 * store.commands() {
+            store.rewrite()?;
+        }
+
+        Ok(FileBackend {
+            store: Arc::new(Mutex::new(store)),
+        })
+    }
+
+    /// Rewrite the journal to the commands needed to rebuild the
+    /// current state, and nothing else, replacing it atomically.
+    pub fn compact(&self) -> AudisResult<()> {
+        let mut store = self
+            .store
+            .lock()
+            .map_err(|_| RedisError::from((ErrorKind::IoError, "file backend lock poisoned")))?;
+        store.rewrite()?;
+        Ok(())
+    }
+}
+
+// Take an exclusive lock on a journal, failing if some other
+// backend (in this process or any other) already holds one.
+fn lock(journal: &File, path: &str) -> RedisResult<()> {
+    match journal.try_lock() {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(RedisError::from((
+            ErrorKind::IoError,
+            "journal is already open",
+            path.to_string(),
+        ))),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+impl Backend for FileBackend {
+    fn connection(&self) -> AudisResult<Box<dyn redis::ConnectionLike>> {
+        Ok(Box::new(Connection {
+            store: self.store.clone(),
+        }))
+    }
+}
+
+struct Connection {
+    store: Arc<Mutex<Store>>,
+}
+
+impl Connection {
+    fn lock(&self) -> RedisResult<std::sync::MutexGuard<'_, Store>> {
+        self.store
+            .lock()
+            .map_err(|_| RedisError::from((ErrorKind::IoError, "file backend lock poisoned")))
+    }
+}
+
+impl redis::ConnectionLike for Connection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let mut store = self.lock()?;
+        store.run(args(redis::parse_redis_value(cmd)?)?)
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let mut store = self.lock()?;
+        let mut reader = cmd;
+        let mut replies = vec![];
+        while !reader.is_empty() {
+            let value = redis::Parser::new(&mut reader).parse_value()?;
+            replies.push(store.run(args(value)?)?);
+        }
+        Ok(replies.into_iter().skip(offset).take(count).collect())
+    }
+
+    fn get_db(&self) -> i64 {
+        0
+    }
+}
+
+// Unpack a command, as parsed off the wire, into its arguments.
+fn args(v: Value) -> RedisResult<Vec<Vec<u8>>> {
+    match v {
+        Value::Bulk(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::Data(d) => Ok(d),
+                _ => Err(RedisError::from((
+                    ErrorKind::ResponseError,
+                    "malformed command",
+                ))),
+            })
+            .collect(),
+        _ => Err(RedisError::from((
+            ErrorKind::ResponseError,
+            "malformed command",
+        ))),
+    }
+}
+
+enum Item {
+    Str(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Set(BTreeSet<Vec<u8>>),
+    Hash(BTreeMap<Vec<u8>, Vec<u8>>),
+    Zset(BTreeMap<Vec<u8>, f64>),
+}
+
+impl Item {
+    // Approximate the number of bytes used by this value.
+    fn size(&self) -> usize {
+        match self {
+            Item::Str(s) => s.len(),
+            Item::List(l) => l.iter().map(|v| v.len()).sum(),
+            Item::Set(s) => s.iter().map(|v| v.len()).sum(),
+            Item::Hash(h) => h.iter().map(|(k, v)| k.len() + v.len()).sum(),
+            Item::Zset(z) => z.keys().map(|k| k.len() + 8).sum(),
+        }
+    }
+}
+
+struct Store {
+    data: HashMap<Vec<u8>, Item>,
+    expires: HashMap<Vec<u8>, i64>,
+    path: String,
+    journal: Option<File>,
+    multi: Option<Vec<Vec<Vec<u8>>>>,
+    watch: Option<(BTreeSet<Vec<u8>>, bool)>,
+}
+
+// The current time, in milliseconds since the UNIX epoch,
+// which is how key expiry deadlines are kept.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn wrongtype() -> RedisError {
+    RedisError::from((
+        ErrorKind::ResponseError,
+        "WRONGTYPE Operation against a key holding the wrong kind of value",
+    ))
+}
+
+fn arity(a: &[Vec<u8>], n: usize) -> RedisResult<()> {
+    if a.len() < n {
+        Err(RedisError::from((
+            ErrorKind::ResponseError,
+            "wrong number of arguments",
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn int(b: &[u8]) -> RedisResult<i64> {
+    std::str::from_utf8(b)?.parse().map_err(|_| {
+        RedisError::from((
+            ErrorKind::ResponseError,
+            "value is not an integer or out of range",
+        ))
+    })
+}
+
+fn score(b: &[u8]) -> RedisResult<f64> {
+    std::str::from_utf8(b)?
+        .parse()
+        .ok()
+        .filter(|f: &f64| !f.is_nan())
+        .ok_or_else(|| RedisError::from((ErrorKind::ResponseError, "value is not a valid float")))
+}
+
+// Check a score against a Redis-style ZRANGEBYSCORE bound,
+// which is exclusive if it starts with `(`.
+fn within(s: f64, min: &[u8], max: &[u8]) -> RedisResult<bool> {
+    let bound = |b: &[u8]| match b.strip_prefix(b"(") {
+        Some(b) => Ok((score(b)?, true)),
+        None => Ok::<_, RedisError>((score(b)?, false)),
+    };
+    let ((min, xmin), (max, xmax)) = (bound(min)?, bound(max)?);
+    Ok((if xmin { s > min } else { s >= min }) && (if xmax { s < max } else { s <= max }))
+}
+
+// Resolve a Redis-style (possibly negative) inclusive range
+// against a sequence of length `n`.
+fn range(n: usize, a: &[u8], b: &[u8]) -> RedisResult<Option<(usize, usize)>> {
+    let n = n as i64;
+    let (mut a, mut b) = (int(a)?, int(b)?);
+    if a < 0 {
+        a = (n + a).max(0);
+    }
+    if b < 0 {
+        b += n;
+    }
+    b = b.min(n - 1);
+    if a > b || a >= n {
+        Ok(None)
+    } else {
+        Ok(Some((a as usize, b as usize)))
+    }
+}
+
+// Match a key against a Redis-style glob pattern, supporting
+// `*`, `?`, `[...]` character classes and `\` escapes.
+pub(crate) fn glob(p: &[u8], s: &[u8]) -> bool {
+    match p.first() {
+        None => s.is_empty(),
+        Some(b'*') => (0..=s.len()).any(|i| glob(&p[1..], &s[i..])),
+        Some(b'?') => !s.is_empty() && glob(&p[1..], &s[1..]),
+        Some(b'[') => {
+            let end = match p.iter().position(|&c| c == b']') {
+                Some(end) => end,
+                None => return !s.is_empty() && s[0] == b'[' && glob(&p[1..], &s[1..]),
+            };
+            if s.is_empty() {
+                return false;
+            }
+            let (class, negate) = match p[1..end].first() {
+                Some(b'^') => (&p[2..end], true),
+                _ => (&p[1..end], false),
+            };
+            let mut hit = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    hit |= class[i] <= s[0] && s[0] <= class[i + 2];
+                    i += 3;
+                } else {
+                    hit |= class[i] == s[0];
+                    i += 1;
+                }
+            }
+            hit != negate && glob(&p[end + 1..], &s[1..])
+        }
+        Some(b'\\') if p.len() > 1 => !s.is_empty() && s[0] == p[1] && glob(&p[2..], &s[1..]),
+        Some(&c) => !s.is_empty() && s[0] == c && glob(&p[1..], &s[1..]),
+    }
+}
+
+// Implement the cursor protocol of SCAN and friends over an
+// ordered collection; the cursor is simply an offset into it.
+fn scan<'a, I: Iterator<Item = &'a Vec<u8>>>(items: I, a: &[Vec<u8>]) -> RedisResult<Value> {
+    arity(a, 1)?;
+    let cursor = int(&a[0])? as usize;
+    let (mut pattern, mut count) = (None, 10);
+    for opt in a[1..].chunks(2) {
+        arity(opt, 2)?;
+        match String::from_utf8_lossy(&opt[0]).to_uppercase().as_str() {
+            "MATCH" => pattern = Some(opt[1].clone()),
+            "COUNT" => count = int(&opt[1])?.max(1) as usize,
+            _ => return Err(RedisError::from((ErrorKind::ResponseError, "syntax error"))),
+        }
+    }
+
+    let mut items: Vec<&Vec<u8>> = items.collect();
+    items.sort();
+    let next = if cursor + count < items.len() {
+        cursor + count
+    } else {
+        0
+    };
+    let batch = items
+        .into_iter()
+        .skip(cursor)
+        .take(count)
+        .filter(|k| pattern.as_ref().is_none_or(|p| glob(p, k)))
+        .map(|k| Value::Data(k.clone()))
+        .collect();
+    Ok(Value::Bulk(vec![
+        Value::Data(next.to_string().into_bytes()),
+        Value::Bulk(batch),
+    ]))
+}
+
+fn is_write(cmd: &str) -> bool {
+    matches!(
+        cmd,
+        "SET"
+            | "SETNX"
+            | "DEL"
+            | "INCR"
+            | "DECR"
+            | "INCRBY"
+            | "SADD"
+            | "SREM"
+            | "RPUSH"
+            | "LPUSH"
+            | "LPOP"
+            | "RPOPLPUSH"
+            | "LREM"
+            | "HSET"
+            | "HSETNX"
+            | "HDEL"
+            | "HINCRBY"
+            | "ZADD"
+            | "ZREM"
+            | "PEXPIREAT"
+            | "PERSIST"
+    )
+}
+
+// Rewrite commands that set relative expiry deadlines into
+// ones that set absolute deadlines, so that replaying the
+// journal later doesn't give keys a new lease on life.
+fn absolute(mut a: Vec<Vec<u8>>, at: i64) -> Vec<Vec<u8>> {
+    let cmd = String::from_utf8_lossy(&a[0]).to_uppercase();
+    match cmd.as_str() {
+        "PEXPIRE" | "EXPIRE" => vec![
+            b"PEXPIREAT".to_vec(),
+            a[1].clone(),
+            at.to_string().into_bytes(),
+        ],
+        "SET" => {
+            for i in 3..a.len() - 1 {
+                if a[i].eq_ignore_ascii_case(b"EX") || a[i].eq_ignore_ascii_case(b"PX") {
+                    a[i] = b"PXAT".to_vec();
+                    a[i + 1] = at.to_string().into_bytes();
+                }
+            }
+            a
+        }
+        _ => a,
+    }
+}
+
+impl Store {
+    fn new(path: &str) -> Store {
+        Store {
+            data: HashMap::new(),
+            expires: HashMap::new(),
+            path: path.to_string(),
+            journal: None,
+            multi: None,
+            watch: None,
+        }
+    }
+
+    // How many commands it takes to rebuild the dataset, give
+    // or take those needed for keys with lots of members.
+    fn commands(&self) -> usize {
+        self.data.len() + self.expires.len()
+    }
+
+    // Rewrite the journal, like Redis does its append-only file,
+    // by writing the commands needed to rebuild the dataset to a
+    // new (locked) file, which then replaces the journal.
+    fn rewrite(&mut self) -> RedisResult<()> {
+        self.expire();
+        let tmp = format!("{}.rewrite", self.path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp)?;
+        lock(&file, &tmp)?;
+
+        let mut w = BufWriter::new(&file);
+        for (key, item) in &self.data {
+            let mut cmds = vec![];
+            match item {
+                Item::Str(s) => cmds.push(vec![b"SET".to_vec(), key.clone(), s.clone()]),
+                Item::List(l) => {
+                    let l: Vec<&Vec<u8>> = l.iter().collect();
+                    for chunk in l.chunks(64) {
+                        let mut a = vec![b"RPUSH".to_vec(), key.clone()];
+                        a.extend(chunk.iter().map(|v| v.to_vec()));
+                        cmds.push(a);
+                    }
+                }
+                Item::Set(s) => {
+                    let s: Vec<&Vec<u8>> = s.iter().collect();
+                    for chunk in s.chunks(64) {
+                        let mut a = vec![b"SADD".to_vec(), key.clone()];
+                        a.extend(chunk.iter().map(|v| v.to_vec()));
+                        cmds.push(a);
+                    }
+                }
+                Item::Hash(h) => {
+                    let h: Vec<(&Vec<u8>, &Vec<u8>)> = h.iter().collect();
+                    for chunk in h.chunks(64) {
+                        let mut a = vec![b"HSET".to_vec(), key.clone()];
+                        for (k, v) in chunk {
+                            a.push(k.to_vec());
+                            a.push(v.to_vec());
+                        }
+                        cmds.push(a);
+                    }
+                }
+                Item::Zset(z) => {
+                    let z: Vec<(&Vec<u8>, &f64)> = z.iter().collect();
+                    for chunk in z.chunks(64) {
+                        let mut a = vec![b"ZADD".to_vec(), key.clone()];
+                        for (m, score) in chunk {
+                            a.push(score.to_string().into_bytes());
+                            a.push(m.to_vec());
+                        }
+                        cmds.push(a);
+                    }
+                }
+            }
+            if let Some(at) = self.expires.get(key) {
+                cmds.push(vec![
+                    b"PEXPIREAT".to_vec(),
+                    key.clone(),
+                    at.to_string().into_bytes(),
+                ]);
+            }
+            for a in cmds {
+                w.write_all(&redis::pack_command(&a))?;
+            }
+        }
+        w.flush()?;
+        drop(w);
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        // the old journal (and its lock) goes once the new one,
+        // which already holds a lock, has taken its place.
+        self.journal = Some(file);
+        Ok(())
+    }
+
+    // Approximate the number of bytes used by the dataset.
+    fn used_memory(&self) -> usize {
+        self.data.iter().map(|(k, v)| k.len() + v.size()).sum()
+    }
+
+    // Remove a key, along with any expiry deadline it had.
+    fn remove(&mut self, key: &[u8]) -> Option<Item> {
+        self.expires.remove(key);
+        self.data.remove(key)
+    }
+
+    // Remove every key whose expiry deadline has passed.
+    fn expire(&mut self) {
+        let now = now();
+        let dead: Vec<Vec<u8>> = self
+            .expires
+            .iter()
+            .filter(|(_, &at)| at <= now)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for k in dead {
+            self.touch(&k);
+            self.remove(&k);
+        }
+    }
+
+    // Set (or clear) the expiry deadline of an existing key.
+    fn deadline(&mut self, key: &[u8], at: Option<i64>) -> bool {
+        if !self.data.contains_key(key) {
+            return false;
+        }
+        match at {
+            Some(at) => self.expires.insert(key.to_vec(), at),
+            None => self.expires.remove(key),
+        };
+        true
+    }
+
+    // Note that a watched key has been written to (or has
+    // expired), so that the next EXEC is aborted.
+    fn touch(&mut self, key: &[u8]) {
+        if let Some((ref keys, ref mut dirty)) = self.watch {
+            *dirty |= keys.contains(key);
+        }
+    }
+
+    // Run a command on behalf of a connection, handling
+    // WATCH / MULTI / EXEC transaction blocks and journaling writes.
+    fn run(&mut self, a: Vec<Vec<u8>>) -> RedisResult<Value> {
+        arity(&a, 1)?;
+        let cmd = String::from_utf8_lossy(&a[0]).to_uppercase();
+        match cmd.as_str() {
+            "WATCH" if self.multi.is_none() => {
+                arity(&a, 2)?;
+                self.expire();
+                let (keys, _) = self.watch.get_or_insert_with(Default::default);
+                keys.extend(a[1..].iter().cloned());
+                return Ok(Value::Okay);
+            }
+            "UNWATCH" if self.multi.is_none() => {
+                self.watch = None;
+                return Ok(Value::Okay);
+            }
+            "MULTI" => {
+                self.multi = Some(vec![]);
+                return Ok(Value::Okay);
+            }
+            "DISCARD" => {
+                self.multi = None;
+                self.watch = None;
+                return Ok(Value::Okay);
+            }
+            "EXEC" => {
+                let queued = match self.multi.take() {
+                    Some(q) => q,
+                    None => {
+                        return Err(RedisError::from((
+                            ErrorKind::ResponseError,
+                            "EXEC without MULTI",
+                        )))
+                    }
+                };
+                self.expire();
+                if let Some((_, true)) = self.watch.take() {
+                    return Ok(Value::Nil);
+                }
+                let mut replies = vec![];
+                for a in queued {
+                    replies.push(self.run(a)?);
+                }
+                return Ok(Value::Bulk(replies));
+            }
+            _ => (),
+        }
+        if let Some(ref mut q) = self.multi {
+            q.push(a);
+            return Ok(Value::Status("QUEUED".to_string()));
+        }
+
+        let v = self.exec(a.clone())?;
+        let changed = match cmd.as_str() {
+            "SETNX" => v != Value::Int(0),
+            "SET" => v != Value::Nil,
+            "PEXPIRE" | "EXPIRE" | "PEXPIREAT" | "PERSIST" => v == Value::Int(1),
+            _ => is_write(&cmd),
+        };
+        if changed {
+            for k in &a[1..] {
+                self.touch(k);
+            }
+            let at = self.expires.get(&a[1]).copied().unwrap_or(0);
+            if let Some(ref mut f) = self.journal {
+                f.write_all(&redis::pack_command(&absolute(a, at)))?;
+                f.flush()?;
+            }
+        }
+        Ok(v)
+    }
+
+    // Execute a single command against the in-memory dataset.
+    fn exec(&mut self, a: Vec<Vec<u8>>) -> RedisResult<Value> {
+        arity(&a, 1)?;
+        self.expire();
+        let cmd = String::from_utf8_lossy(&a[0]).to_uppercase();
+        match cmd.as_str() {
+            "PING" => Ok(Value::Status("PONG".to_string())),
+
+            "INFO" => Ok(Value::Data(
+                format!(
+                    "# Server\r\naudis_backend:file\r\n# Memory\r\nused_memory:{}\r\n",
+                    self.used_memory()
+                )
+                .into_bytes(),
+            )),
+
+            "DBSIZE" => Ok(Value::Int(self.data.len() as i64)),
+
+            "MEMORY" => {
+                arity(&a, 3)?;
+                if !a[1].eq_ignore_ascii_case(b"USAGE") {
+                    return Err(RedisError::from((
+                        ErrorKind::ResponseError,
+                        "unknown MEMORY subcommand",
+                    )));
+                }
+                match self.data.get(&a[2]) {
+                    None => Ok(Value::Nil),
+                    Some(v) => Ok(Value::Int((a[2].len() + v.size()) as i64)),
+                }
+            }
+
+            "SCAN" => scan(self.data.keys(), &a[1..]),
+
+            "GET" => {
+                arity(&a, 2)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Nil),
+                    Some(Item::Str(s)) => Ok(Value::Data(s.clone())),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "MGET" => Ok(Value::Bulk(
+                a[1..]
+                    .iter()
+                    .map(|k| match self.data.get(k) {
+                        Some(Item::Str(s)) => Value::Data(s.clone()),
+                        _ => Value::Nil,
+                    })
+                    .collect(),
+            )),
+
+            "SET" => {
+                arity(&a, 3)?;
+                let (mut nx, mut at) = (false, None);
+                let mut opts = a[3..].iter();
+                while let Some(opt) = opts.next() {
+                    let mut arg = || match opts.next() {
+                        Some(v) => int(v),
+                        None => Err(RedisError::from((ErrorKind::ResponseError, "syntax error"))),
+                    };
+                    match String::from_utf8_lossy(opt).to_uppercase().as_str() {
+                        "NX" => nx = true,
+                        "EX" => at = Some(now() + arg()? * 1000),
+                        "PX" => at = Some(now() + arg()?),
+                        "PXAT" => at = Some(arg()?),
+                        _ => {
+                            return Err(RedisError::from((
+                                ErrorKind::ResponseError,
+                                "syntax error",
+                            )))
+                        }
+                    }
+                }
+                if nx && self.data.contains_key(&a[1]) {
+                    return Ok(Value::Nil);
+                }
+                self.remove(&a[1]);
+                self.data.insert(a[1].clone(), Item::Str(a[2].clone()));
+                self.deadline(&a[1], at);
+                Ok(Value::Okay)
+            }
+
+            "PEXPIRE" | "EXPIRE" | "PEXPIREAT" => {
+                arity(&a, 3)?;
+                let at = match cmd.as_str() {
+                    "PEXPIRE" => now() + int(&a[2])?,
+                    "EXPIRE" => now() + int(&a[2])? * 1000,
+                    _ => int(&a[2])?,
+                };
+                let ok = self.deadline(&a[1], Some(at));
+                self.expire();
+                Ok(Value::Int(ok as i64))
+            }
+
+            "PERSIST" => {
+                arity(&a, 2)?;
+                let had = self.expires.contains_key(&a[1]);
+                Ok(Value::Int((had && self.deadline(&a[1], None)) as i64))
+            }
+
+            "PTTL" | "TTL" => {
+                arity(&a, 2)?;
+                if !self.data.contains_key(&a[1]) {
+                    return Ok(Value::Int(-2));
+                }
+                match self.expires.get(&a[1]) {
+                    None => Ok(Value::Int(-1)),
+                    Some(at) if cmd == "PTTL" => Ok(Value::Int(at - now())),
+                    Some(at) => Ok(Value::Int((at - now() + 999) / 1000)),
+                }
+            }
+
+            "SETNX" => {
+                arity(&a, 3)?;
+                if self.data.contains_key(&a[1]) {
+                    Ok(Value::Int(0))
+                } else {
+                    self.data.insert(a[1].clone(), Item::Str(a[2].clone()));
+                    Ok(Value::Int(1))
+                }
+            }
+
+            "DEL" => {
+                arity(&a, 2)?;
+                let n = a[1..].iter().filter(|k| self.remove(k).is_some()).count();
+                Ok(Value::Int(n as i64))
+            }
+
+            "TYPE" => {
+                arity(&a, 2)?;
+                Ok(Value::Status(
+                    match self.data.get(&a[1]) {
+                        None => "none",
+                        Some(Item::Str(_)) => "string",
+                        Some(Item::List(_)) => "list",
+                        Some(Item::Set(_)) => "set",
+                        Some(Item::Hash(_)) => "hash",
+                        Some(Item::Zset(_)) => "zset",
+                    }
+                    .to_string(),
+                ))
+            }
+
+            "EXISTS" => {
+                arity(&a, 2)?;
+                let n = a[1..].iter().filter(|k| self.data.contains_key(*k)).count();
+                Ok(Value::Int(n as i64))
+            }
+
+            "INCR" | "DECR" | "INCRBY" => {
+                arity(&a, if cmd == "INCRBY" { 3 } else { 2 })?;
+                let n = match self.data.get(&a[1]) {
+                    None => 0,
+                    Some(Item::Str(s)) => int(s)?,
+                    Some(_) => return Err(wrongtype()),
+                } + match cmd.as_str() {
+                    "INCR" => 1,
+                    "DECR" => -1,
+                    _ => int(&a[2])?,
+                };
+                self.data
+                    .insert(a[1].clone(), Item::Str(n.to_string().into_bytes()));
+                Ok(Value::Int(n))
+            }
+
+            "SADD" => {
+                arity(&a, 3)?;
+                let set = match self
+                    .data
+                    .entry(a[1].clone())
+                    .or_insert_with(|| Item::Set(BTreeSet::new()))
+                {
+                    Item::Set(s) => s,
+                    _ => return Err(wrongtype()),
+                };
+                let n = a[2..].iter().filter(|v| set.insert(v.to_vec())).count();
+                Ok(Value::Int(n as i64))
+            }
+
+            "SREM" => {
+                arity(&a, 3)?;
+                let (n, empty) = match self.data.get_mut(&a[1]) {
+                    None => return Ok(Value::Int(0)),
+                    Some(Item::Set(s)) => {
+                        (a[2..].iter().filter(|v| s.remove(*v)).count(), s.is_empty())
+                    }
+                    Some(_) => return Err(wrongtype()),
+                };
+                if empty {
+                    self.remove(&a[1]);
+                }
+                Ok(Value::Int(n as i64))
+            }
+
+            "SCARD" => {
+                arity(&a, 2)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Int(0)),
+                    Some(Item::Set(s)) => Ok(Value::Int(s.len() as i64)),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "SSCAN" => {
+                arity(&a, 3)?;
+                match self.data.get(&a[1]) {
+                    None => scan(std::iter::empty(), &a[2..]),
+                    Some(Item::Set(s)) => scan(s.iter(), &a[2..]),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "SMEMBERS" => {
+                arity(&a, 2)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Bulk(vec![])),
+                    Some(Item::Set(s)) => Ok(Value::Bulk(
+                        s.iter().map(|v| Value::Data(v.clone())).collect(),
+                    )),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "RPUSH" | "LPUSH" => {
+                arity(&a, 3)?;
+                let list = match self
+                    .data
+                    .entry(a[1].clone())
+                    .or_insert_with(|| Item::List(VecDeque::new()))
+                {
+                    Item::List(l) => l,
+                    _ => return Err(wrongtype()),
+                };
+                for v in &a[2..] {
+                    if cmd == "RPUSH" {
+                        list.push_back(v.clone());
+                    } else {
+                        list.push_front(v.clone());
+                    }
+                }
+                Ok(Value::Int(list.len() as i64))
+            }
+
+            "RPOPLPUSH" => {
+                arity(&a, 3)?;
+                if !matches!(self.data.get(&a[2]), None | Some(Item::List(_))) {
+                    return Err(wrongtype());
+                }
+                let (v, empty) = match self.data.get_mut(&a[1]) {
+                    None => return Ok(Value::Nil),
+                    Some(Item::List(l)) => (l.pop_back(), l.is_empty()),
+                    Some(_) => return Err(wrongtype()),
+                };
+                if empty {
+                    self.remove(&a[1]);
+                }
+                let v = match v {
+                    Some(v) => v,
+                    None => return Ok(Value::Nil),
+                };
+                if let Item::List(l) = self
+                    .data
+                    .entry(a[2].clone())
+                    .or_insert_with(|| Item::List(VecDeque::new()))
+                {
+                    l.push_front(v.clone());
+                }
+                Ok(Value::Data(v))
+            }
+
+            "LPOP" => {
+                arity(&a, 2)?;
+                let (v, empty) = match self.data.get_mut(&a[1]) {
+                    None => return Ok(Value::Nil),
+                    Some(Item::List(l)) => (l.pop_front(), l.is_empty()),
+                    Some(_) => return Err(wrongtype()),
+                };
+                if empty {
+                    self.remove(&a[1]);
+                }
+                Ok(v.map(Value::Data).unwrap_or(Value::Nil))
+            }
+
+            "LREM" => {
+                arity(&a, 4)?;
+                let count = int(&a[2])?;
+                let (removed, empty) = match self.data.get_mut(&a[1]) {
+                    None => return Ok(Value::Int(0)),
+                    Some(Item::List(l)) => {
+                        let limit = if count == 0 {
+                            l.len()
+                        } else {
+                            count.unsigned_abs() as usize
+                        };
+                        let mut hits: Vec<usize> = l
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, v)| **v == a[3])
+                            .map(|(i, _)| i)
+                            .collect();
+                        if count < 0 {
+                            hits.reverse();
+                        }
+                        hits.truncate(limit);
+                        hits.sort_unstable();
+                        for i in hits.iter().rev() {
+                            l.remove(*i);
+                        }
+                        (hits.len(), l.is_empty())
+                    }
+                    Some(_) => return Err(wrongtype()),
+                };
+                if empty {
+                    self.remove(&a[1]);
+                }
+                Ok(Value::Int(removed as i64))
+            }
+
+            "HSET" => {
+                arity(&a, 4)?;
+                if !a.len().is_multiple_of(2) {
+                    return Err(RedisError::from((
+                        ErrorKind::ResponseError,
+                        "wrong number of arguments",
+                    )));
+                }
+                let hash = match self
+                    .data
+                    .entry(a[1].clone())
+                    .or_insert_with(|| Item::Hash(BTreeMap::new()))
+                {
+                    Item::Hash(h) => h,
+                    _ => return Err(wrongtype()),
+                };
+                let n = a[2..]
+                    .chunks(2)
+                    .filter(|kv| hash.insert(kv[0].clone(), kv[1].clone()).is_none())
+                    .count();
+                Ok(Value::Int(n as i64))
+            }
+
+            "HSETNX" => {
+                arity(&a, 4)?;
+                let hash = match self
+                    .data
+                    .entry(a[1].clone())
+                    .or_insert_with(|| Item::Hash(BTreeMap::new()))
+                {
+                    Item::Hash(h) => h,
+                    _ => return Err(wrongtype()),
+                };
+                if hash.contains_key(&a[2]) {
+                    return Ok(Value::Int(0));
+                }
+                hash.insert(a[2].clone(), a[3].clone());
+                Ok(Value::Int(1))
+            }
+
+            "HGET" => {
+                arity(&a, 3)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Nil),
+                    Some(Item::Hash(h)) => {
+                        Ok(h.get(&a[2]).cloned().map_or(Value::Nil, Value::Data))
+                    }
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "HINCRBY" => {
+                arity(&a, 4)?;
+                let hash = match self
+                    .data
+                    .entry(a[1].clone())
+                    .or_insert_with(|| Item::Hash(BTreeMap::new()))
+                {
+                    Item::Hash(h) => h,
+                    _ => return Err(wrongtype()),
+                };
+                let n = match hash.get(&a[2]) {
+                    None => 0,
+                    Some(v) => int(v)?,
+                } + int(&a[3])?;
+                hash.insert(a[2].clone(), n.to_string().into_bytes());
+                Ok(Value::Int(n))
+            }
+
+            "HGETALL" => {
+                arity(&a, 2)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Bulk(vec![])),
+                    Some(Item::Hash(h)) => Ok(Value::Bulk(
+                        h.iter()
+                            .flat_map(|(k, v)| vec![Value::Data(k.clone()), Value::Data(v.clone())])
+                            .collect(),
+                    )),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "HKEYS" => {
+                arity(&a, 2)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Bulk(vec![])),
+                    Some(Item::Hash(h)) => Ok(Value::Bulk(
+                        h.keys().map(|k| Value::Data(k.clone())).collect(),
+                    )),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "HDEL" => {
+                arity(&a, 3)?;
+                let (n, empty) = match self.data.get_mut(&a[1]) {
+                    None => return Ok(Value::Int(0)),
+                    Some(Item::Hash(h)) => (
+                        a[2..].iter().filter(|k| h.remove(*k).is_some()).count(),
+                        h.is_empty(),
+                    ),
+                    Some(_) => return Err(wrongtype()),
+                };
+                if empty {
+                    self.remove(&a[1]);
+                }
+                Ok(Value::Int(n as i64))
+            }
+
+            "LLEN" => {
+                arity(&a, 2)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Int(0)),
+                    Some(Item::List(l)) => Ok(Value::Int(l.len() as i64)),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "LRANGE" => {
+                arity(&a, 4)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Bulk(vec![])),
+                    Some(Item::List(l)) => match range(l.len(), &a[2], &a[3])? {
+                        None => Ok(Value::Bulk(vec![])),
+                        Some((start, end)) => Ok(Value::Bulk(
+                            l.iter()
+                                .skip(start)
+                                .take(end - start + 1)
+                                .map(|v| Value::Data(v.clone()))
+                                .collect(),
+                        )),
+                    },
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "ZADD" => {
+                arity(&a, 4)?;
+                if !a.len().is_multiple_of(2) {
+                    return Err(RedisError::from((ErrorKind::ResponseError, "syntax error")));
+                }
+                let scored = a[2..]
+                    .chunks(2)
+                    .map(|sm| Ok((score(&sm[0])?, sm[1].clone())))
+                    .collect::<RedisResult<Vec<_>>>()?;
+                let zset = match self
+                    .data
+                    .entry(a[1].clone())
+                    .or_insert_with(|| Item::Zset(BTreeMap::new()))
+                {
+                    Item::Zset(z) => z,
+                    _ => return Err(wrongtype()),
+                };
+                let n = scored
+                    .into_iter()
+                    .filter(|(s, m)| zset.insert(m.clone(), *s).is_none())
+                    .count();
+                Ok(Value::Int(n as i64))
+            }
+
+            "ZREM" => {
+                arity(&a, 3)?;
+                let (n, empty) = match self.data.get_mut(&a[1]) {
+                    None => return Ok(Value::Int(0)),
+                    Some(Item::Zset(z)) => (
+                        a[2..].iter().filter(|m| z.remove(*m).is_some()).count(),
+                        z.is_empty(),
+                    ),
+                    Some(_) => return Err(wrongtype()),
+                };
+                if empty {
+                    self.remove(&a[1]);
+                }
+                Ok(Value::Int(n as i64))
+            }
+
+            "ZSCORE" => {
+                arity(&a, 3)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Nil),
+                    Some(Item::Zset(z)) => Ok(match z.get(&a[2]) {
+                        Some(s) => Value::Data(s.to_string().into_bytes()),
+                        None => Value::Nil,
+                    }),
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            "ZRANGEBYSCORE" => {
+                arity(&a, 4)?;
+                match self.data.get(&a[1]) {
+                    None => Ok(Value::Bulk(vec![])),
+                    Some(Item::Zset(z)) => {
+                        let mut hits = vec![];
+                        for (m, s) in z {
+                            if within(*s, &a[2], &a[3])? {
+                                hits.push((*s, m));
+                            }
+                        }
+                        hits.sort_by(|x, y| x.partial_cmp(y).unwrap());
+                        let scores = a
+                            .get(4)
+                            .is_some_and(|o| o.eq_ignore_ascii_case(b"WITHSCORES"));
+                        Ok(Value::Bulk(
+                            hits.into_iter()
+                                .flat_map(|(s, m)| {
+                                    let mut v = vec![Value::Data(m.clone())];
+                                    if scores {
+                                        v.push(Value::Data(s.to_string().into_bytes()));
+                                    }
+                                    v
+                                })
+                                .collect(),
+                        ))
+                    }
+                    Some(_) => Err(wrongtype()),
+                }
+            }
+
+            _ => Err(RedisError::from((
+                ErrorKind::ResponseError,
+                "unknown command",
+                cmd,
+            ))),
+        }
+    }
+}
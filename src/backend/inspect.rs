@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use redis::{RedisResult, Value};
+
+use super::Backend;
+use crate::AudisResult;
+
+/// A single round trip to a backend, as seen by an `Inspector`.
+#[derive(Debug, Clone)]
+pub struct RoundTrip {
+    /// The commands sent, each as a list of arguments, starting
+    /// with the command name.  Pipelines send more than one
+    /// command per round trip.
+    pub commands: Vec<Vec<Vec<u8>>>,
+
+    /// How long the backend took to reply.
+    pub elapsed: Duration,
+}
+
+impl RoundTrip {
+    /// The names of the keys that this round trip touched.
+    ///
+    /// This assumes that the first argument of each command is
+    /// a key (which holds for every command audis sends), except
+    /// for `DEL`, where all of them are.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = vec![];
+        for cmd in &self.commands {
+            let args = match cmd.first() {
+                Some(name) if name.eq_ignore_ascii_case(b"DEL") => &cmd[1..],
+                Some(_) => &cmd[1..cmd.len().min(2)],
+                None => continue,
+            };
+            keys.extend(args.iter().map(|k| String::from_utf8_lossy(k).to_string()));
+        }
+        keys
+    }
+}
+
+type Hook = dyn Fn(&RoundTrip) + Send + Sync;
+
+/// A wrapper around another backend that reports every round
+/// trip made through it to a hook, for diagnosing what audis is
+/// actually asking of its backend.
+///
+/// ```rust,no_run
+/// extern crate audis;
+///
+/// fn main() {
+///     let backend = audis::backend::open("redis://127.0.0.1:6379").unwrap();
+///     let backend = audis::backend::Inspector::new(backend, |trip| {
+///         eprintln!("{} command(s) in {:?}", trip.commands.len(), trip.elapsed);
+///     });
+///     let client = audis::Client::with_backend(Box::new(backend)).unwrap();
+///
+///     // ... every round trip is now reported ...
+/// }
+/// ```
+pub struct Inspector {
+    inner: Box<dyn Backend>,
+    hook: Arc<Hook>,
+}
+
+impl Inspector {
+    /// Wrap `inner`, reporting each round trip to `hook`.
+    pub fn new<F>(inner: Box<dyn Backend>, hook: F) -> Inspector
+    where
+        F: Fn(&RoundTrip) + Send + Sync + 'static,
+    {
+        Inspector {
+            inner,
+            hook: Arc::new(hook),
+        }
+    }
+}
+
+impl Backend for Inspector {
+    fn connection(&self) -> AudisResult<Box<dyn redis::ConnectionLike>> {
+        Ok(Box::new(Connection {
+            inner: self.inner.connection()?,
+            hook: self.hook.clone(),
+        }))
+    }
+}
+
+struct Connection {
+    inner: Box<dyn redis::ConnectionLike>,
+    hook: Arc<Hook>,
+}
+
+impl Connection {
+    fn report(&self, mut packed: &[u8], start: Instant) {
+        let mut commands = vec![];
+        while !packed.is_empty() {
+            match redis::Parser::new(&mut packed).parse_value() {
+                Ok(Value::Bulk(items)) => commands.push(
+                    items
+                        .into_iter()
+                        .filter_map(|item| match item {
+                            Value::Data(d) => Some(d),
+                            _ => None,
+                        })
+                        .collect(),
+                ),
+                _ => break,
+            }
+        }
+        (self.hook)(&RoundTrip {
+            commands,
+            elapsed: start.elapsed(),
+        });
+    }
+}
+
+impl redis::ConnectionLike for Connection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let start = Instant::now();
+        let r = self.inner.req_packed_command(cmd);
+        self.report(cmd, start);
+        r
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let start = Instant::now();
+        let r = self.inner.req_packed_commands(cmd, offset, count);
+        self.report(cmd, start);
+        r
+    }
+
+    fn get_db(&self) -> i64 {
+        self.inner.get_db()
+    }
+}
@@ -0,0 +1,158 @@
+//! Storage backends for the audit log.
+//!
+//! Audis speaks the Redis protocol to whatever is storing the
+//! audit log.  A `Backend` is anything that can hand out
+//! connections capable of executing Redis commands; the actual
+//! indexing logic lives in `Client`, and is identical no matter
+//! which backend is in play.
+//!
+//! Two backends ship with audis:
+//!
+//!  - `RedisBackend`, for talking to a real Redis instance,
+//!    selected by `redis://` and `unix:` URLs.
+//!  - `FileBackend`, an embedded store that keeps the audit
+//!    log in memory and journals every write to a local file,
+//!    selected by `file:` URLs.
+//!
+//! Any backend can be wrapped in an `Inspector`, to see every
+//! round trip that audis makes to it.
+//!
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::AudisResult;
+
+mod file;
+pub(crate) use self::file::glob;
+pub use self::file::FileBackend;
+
+mod inspect;
+pub use self::inspect::{Inspector, RoundTrip};
+
+/// A storage engine capable of housing an audit log.
+pub trait Backend: Send + Sync {
+    /// Open a new connection to the backing store.
+    fn connection(&self) -> AudisResult<Box<dyn redis::ConnectionLike>>;
+}
+
+// How many idle connections a `RedisBackend` holds on to.
+const POOL_SIZE: usize = 16;
+
+/// A real, honest-to-goodness Redis instance.
+///
+/// Connections are pooled: each one is handed back to the
+/// backend once audis is done with it (unless anything went
+/// wrong with it), and reused, keeping up to sixteen idle
+/// connections around.
+pub struct RedisBackend {
+    redis: redis::Client,
+    idle: Arc<Mutex<Vec<redis::Connection>>>,
+    timeout: Option<Duration>,
+}
+
+impl RedisBackend {
+    /// Open a Redis backend, by URL (or by anything else that
+    /// the `redis` crate can connect to, like a `ConnectionInfo`).
+    pub fn open<T: redis::IntoConnectionInfo>(info: T) -> AudisResult<RedisBackend> {
+        Ok(RedisBackend {
+            redis: redis::Client::open(info)?,
+            idle: Arc::new(Mutex::new(vec![])),
+            timeout: None,
+        })
+    }
+
+    /// Give up on reading replies from (or writing requests to)
+    /// Redis after `timeout`, failing with
+    /// `AudisError::Connection`, rather than waiting forever.
+    pub fn timeout(mut self, timeout: Duration) -> RedisBackend {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Backend for RedisBackend {
+    fn connection(&self) -> AudisResult<Box<dyn redis::ConnectionLike>> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => {
+                let conn = self.redis.get_connection()?;
+                conn.set_read_timeout(self.timeout)?;
+                conn.set_write_timeout(self.timeout)?;
+                conn
+            }
+        };
+        Ok(Box::new(Pooled {
+            conn: Some(conn),
+            idle: self.idle.clone(),
+            failed: false,
+        }))
+    }
+}
+
+// A connection borrowed from a `RedisBackend`'s pool, which it
+// goes back to when dropped, unless a request failed: pipelines
+// that fail part-way leave the rest of their replies unread.
+struct Pooled {
+    conn: Option<redis::Connection>,
+    idle: Arc<Mutex<Vec<redis::Connection>>>,
+    failed: bool,
+}
+
+impl Pooled {
+    fn conn(&mut self) -> &mut redis::Connection {
+        self.conn
+            .as_mut()
+            .expect("pooled connection already returned")
+    }
+
+    fn check<T>(&mut self, r: redis::RedisResult<T>) -> redis::RedisResult<T> {
+        self.failed |= r.is_err();
+        r
+    }
+}
+
+impl redis::ConnectionLike for Pooled {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> redis::RedisResult<redis::Value> {
+        let r = self.conn().req_packed_command(cmd);
+        self.check(r)
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> redis::RedisResult<Vec<redis::Value>> {
+        let r = self.conn().req_packed_commands(cmd, offset, count);
+        self.check(r)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.conn.as_ref().map(|c| c.get_db()).unwrap_or(0)
+    }
+}
+
+impl Drop for Pooled {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+            if !self.failed && conn.is_open() && idle.len() < POOL_SIZE {
+                idle.push(conn);
+            }
+        }
+    }
+}
+
+/// Open the appropriate backend for a URL.
+///
+/// URLs starting with `file:` get a `FileBackend`; everything
+/// else is handed off to the `redis` crate.
+pub fn open(url: &str) -> AudisResult<Box<dyn Backend>> {
+    if url.starts_with("file:") {
+        Ok(Box::new(FileBackend::open(url)?))
+    } else {
+        Ok(Box::new(RedisBackend::open(url)?))
+    }
+}
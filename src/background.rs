@@ -0,0 +1,271 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, SendError, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::{context, AudisError, AudisResult, Client, Event};
+
+// How many times to retry logging an event that failed because
+// the backend couldn't be reached, and how long to wait before
+// the first retry (doubling each time).
+const RETRIES: u32 = 3;
+const BACKOFF: Duration = Duration::from_millis(50);
+
+// How long dropping the last Sender waits for the background
+// thread to finish logging, by default.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The sending half of a `background()` logging thread.
+///
+/// Dropping every clone of the `Sender` signals the background
+/// thread to finish logging whatever remains in its buffer and
+/// exit.  Dropping the last one also waits for it to do so (up
+/// to the `flush_timeout()`), so that events still in the buffer
+/// aren't lost if the process exits right afterwards, without
+/// joining the thread.
+#[derive(Clone)]
+pub struct Sender {
+    tx: SyncSender<Event>,
+    stats: Arc<Counters>,
+    // dropped after `tx`, so that the channel is closed by the
+    // time the last Sender waits on the thread.
+    _flush: Arc<Flush>,
+}
+
+/// How a `background()` logging thread is getting on, as of
+/// `Sender::stats()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackgroundStats {
+    /// How many events are waiting in the buffer to be logged.
+    pub queued: u64,
+
+    /// How many events have been logged.
+    pub written: u64,
+
+    /// How many events could not be logged, and were dropped.
+    pub failed: u64,
+
+    /// How many times logging an event was retried, because the
+    /// backend couldn't be reached.
+    pub retried: u64,
+
+    /// The last error logging an event ran into, if any have
+    /// (even if a retry then succeeded).
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    written: AtomicU64,
+    failed: AtomicU64,
+    retried: AtomicU64,
+    last_error: Mutex<Option<String>>,
+    flush_timeout: Mutex<Option<Duration>>,
+    done: Mutex<bool>,
+    finished: Condvar,
+}
+
+impl Counters {
+    fn error(&self, err: &AudisError) {
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(err.to_string());
+    }
+}
+
+// Waits for the background thread to finish, once the last
+// Sender is dropped.
+struct Flush(Arc<Counters>);
+
+impl Drop for Flush {
+    fn drop(&mut self) {
+        let s = &self.0;
+        let timeout = s
+            .flush_timeout
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .unwrap_or(FLUSH_TIMEOUT);
+        let done = s.done.lock().unwrap_or_else(|e| e.into_inner());
+        let (done, _) = s
+            .finished
+            .wait_timeout_while(done, timeout, |done| !*done)
+            .unwrap_or_else(|e| e.into_inner());
+        let finished = *done;
+        drop(done);
+        if !finished && !timeout.is_zero() {
+            log::error!(
+                target: "audis",
+                "gave up waiting for the background logger after {:?}, with {} event(s) still queued",
+                timeout,
+                s.queued.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+// Signals that the background thread has finished (even if it
+// panicked).
+struct Done(Arc<Counters>);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        *self.0.done.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.0.finished.notify_all();
+    }
+}
+
+// Errors hand the unsent Event back, just like std's own
+// channels do, so they are necessarily about as big as it is.
+#[allow(clippy::result_large_err)]
+impl Sender {
+    /// Queue an event for logging, blocking if the buffer is full.
+    pub fn send(&self, e: Event) -> Result<(), SendError<Event>> {
+        let e = context::attach_owned(e);
+        self.queued(1);
+        let r = self.tx.send(e);
+        if r.is_err() {
+            self.queued(-1);
+        }
+        r
+    }
+
+    /// Queue an event for logging, failing if the buffer is full.
+    pub fn try_send(&self, e: Event) -> Result<(), TrySendError<Event>> {
+        let e = context::attach_owned(e);
+        self.queued(1);
+        let r = self.tx.try_send(e);
+        if r.is_err() {
+            self.queued(-1);
+        }
+        r
+    }
+
+    /// Report how the background thread is getting on, i.e. so
+    /// that a service can raise the alarm when its buffer starts
+    /// backing up, or events start failing to be logged.
+    pub fn stats(&self) -> BackgroundStats {
+        let s = &self.stats;
+        BackgroundStats {
+            queued: s.queued.load(Ordering::Relaxed),
+            written: s.written.load(Ordering::Relaxed),
+            failed: s.failed.load(Ordering::Relaxed),
+            retried: s.retried.load(Ordering::Relaxed),
+            last_error: s
+                .last_error
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        }
+    }
+
+    /// Wait at most `timeout` for the background thread to finish
+    /// logging (once every clone of this `Sender` is dropped),
+    /// rather than the default of five seconds.  A zero timeout
+    /// doesn't wait at all, like `Sender`s used to.
+    pub fn flush_timeout(self, timeout: Duration) -> Sender {
+        *self
+            .stats
+            .flush_timeout
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(timeout);
+        self
+    }
+
+    fn queued(&self, n: i64) {
+        #[cfg(feature = "metrics")]
+        metrics::queued(n);
+        if n < 0 {
+            self.stats
+                .queued
+                .fetch_sub(n.unsigned_abs(), Ordering::Relaxed);
+        } else {
+            self.stats.queued.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Client {
+    /// Delegate event logging to a background thread.
+    ///
+    /// This function spins up a new thread, with a copy of the
+    /// audis Client object, and returns a channel for sending
+    /// new audis::Event objects to be logged, and the thread
+    /// JoinHandle for waiting on the thread to finish.
+    ///
+    /// The sending channel is buffered, and will have enough
+    /// space to keep `n` Event objects in memory.  If `n` is
+    /// passed as zero, a suitable default will be used instead.
+    ///
+    /// If the background thread encounters an error while trying
+    /// to log an Event to the Redis backend, it will report the
+    /// error through the `log` crate (at the `error` level, under
+    /// the `audis` target) and attempt to recover.  Events that
+    /// failed because the backend couldn't be reached are retried
+    /// a few times (backing off in between) before they are given
+    /// up on; see `Sender::stats()` for keeping track.
+    ///
+    /// To shut down the background thread, drop the returned
+    /// Sender object and then join the thread's JoinHandle.
+    /// Dropping the Sender waits (a bounded amount of time) for
+    /// the thread to finish logging, too; see
+    /// `Sender::flush_timeout()`.
+    ///
+    pub fn background(&self, n: usize) -> AudisResult<(Sender, JoinHandle<()>)> {
+        let c = self.clone();
+        let (tx, rx) = sync_channel(if n == 0 { 100 } else { n });
+        let stats = Arc::new(Counters::default());
+
+        let counters = stats.clone();
+        let t = spawn(move || {
+            let _done = Done(counters.clone());
+            for e in rx {
+                #[cfg(feature = "metrics")]
+                metrics::queued(-1);
+                counters.queued.fetch_sub(1, Ordering::Relaxed);
+
+                let mut r = c.log(&e).map(|_| ());
+                let mut backoff = BACKOFF;
+                for _ in 0..RETRIES {
+                    match &r {
+                        Err(err @ AudisError::Connection(_)) => {
+                            log::warn!(target: "audis", "failed to log event {} (retrying): {}", e.id, err);
+                            counters.error(err);
+                        }
+                        _ => break,
+                    }
+                    sleep(backoff);
+                    backoff *= 2;
+                    counters.retried.fetch_add(1, Ordering::Relaxed);
+                    r = match c.log(&e) {
+                        // the last attempt got further than it let on.
+                        Err(AudisError::Duplicate(_)) => Ok(()),
+                        r => r.map(|_| ()),
+                    };
+                }
+
+                match r {
+                    Ok(()) => {
+                        counters.written.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(err) => {
+                        log::error!(target: "audis", "failed to log event {}: {}", e.id, err);
+                        counters.failed.fetch_add(1, Ordering::Relaxed);
+                        counters.error(&err);
+                    }
+                }
+            }
+        });
+
+        let flush = Arc::new(Flush(stats.clone()));
+        Ok((
+            Sender {
+                tx,
+                stats,
+                _flush: flush,
+            },
+            t,
+        ))
+    }
+}
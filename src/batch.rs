@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use crate::{AudisError, AudisResult, Client, Event};
+
+// How many events to look up per round trip.
+const CHUNK: usize = 1000;
+
+impl Client {
+    /// Retrieve a batch of events, by ID, in the order given, with
+    /// None in place of those that don't exist.
+    ///
+    /// This is meant for callers that keep track of event IDs
+    /// themselves (i.e. from checkpoints, or search results), and
+    /// need to fetch the events behind them in bulk.  Payloads
+    /// are looked up via `MGET`, a thousand at a time, along with
+    /// their metadata and trails in a single pipeline; events
+    /// stored in the `Hash` layout (or as RedisJSON documents)
+    /// are looked up one at a time, as per `retrieve_event()`.
+    ///
+    /// As with `retrieve_event()`, the subjects of the events are
+    /// not filled in, and if the client has an access policy,
+    /// it must allow retrieving at least one of the subjects of
+    /// every event, or this fails with `AudisError::Forbidden`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, ids), err, fields(ids = ids.len(), commands))
+    )]
+    pub fn get_events<S: AsRef<str>>(&self, ids: &[S]) -> AudisResult<Vec<Option<Event>>> {
+        self.instrument("get_events", || {
+            if let Some(allowed) = self.retrievable()? {
+                if let Some(id) = ids.iter().find(|id| !allowed.contains(id.as_ref())) {
+                    return Err(AudisError::Forbidden(format!(
+                        "retrieve of event {}",
+                        id.as_ref()
+                    )));
+                }
+            }
+
+            let mut events = Vec::with_capacity(ids.len());
+            for chunk in ids.chunks(CHUNK) {
+                self.cancelled()?;
+                let keys: Vec<String> = chunk.iter().map(|id| id!(id.as_ref())).collect();
+                let data: Vec<Option<Vec<u8>>> = self.query(redis::cmd("MGET").arg(keys))?;
+
+                let mut pipe = redis::pipe();
+                for (id, data) in chunk.iter().zip(&data) {
+                    if data.is_some() {
+                        pipe.cmd("HGETALL")
+                            .arg(idmeta!(id.as_ref()))
+                            .cmd("HGETALL")
+                            .arg(idtrail!(id.as_ref()));
+                    }
+                }
+                let found: Vec<BTreeMap<String, String>> = if data.iter().any(Option::is_some) {
+                    self.pipeline(&pipe)?
+                } else {
+                    vec![]
+                };
+                let mut found = found.into_iter();
+
+                for (id, data) in chunk.iter().zip(data) {
+                    let id = id.as_ref();
+                    // anything but a string (i.e. a Hash-layout
+                    // event) comes back from MGET as nil.
+                    let e = match data {
+                        Some(data) => {
+                            let meta = found.next().unwrap_or_default();
+                            let mut trail = found.next().unwrap_or_default();
+                            Some(Event {
+                                data: self.decode_payload(id, None, data)?,
+                                id: id.to_string(),
+                                subjects: vec![],
+                                meta,
+                                correlation_id: trail.remove("correlation"),
+                                parent_id: trail.remove("parent"),
+                            })
+                        }
+                        None => self.fetch(id, None)?,
+                    };
+                    #[cfg(feature = "crypto")]
+                    if let Some(e) = &e {
+                        self.check_seal(e)?;
+                    }
+                    events.push(e);
+                }
+            }
+            Ok(events)
+        })
+    }
+
+    /// Retrieve every event logged against each of a batch of
+    /// subjects, as per `retrieve()`, by subject, retrieving up to
+    /// `parallelism` subjects at a time.
+    ///
+    /// Each subject is retrieved on one of `parallelism` threads
+    /// (or just the one, if that's zero), each with its own
+    /// connections to the backend, which is much faster than
+    /// retrieving them one after the other when there are a lot
+    /// of them (i.e. when generating reports).  If any retrieval
+    /// fails, the rest are abandoned, and its error is returned.
+    /// Subjects listed more than once are only returned once.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, logs), err, fields(logs = logs.len(), commands))
+    )]
+    pub fn retrieve_many<S: AsRef<str> + Sync>(
+        &self,
+        logs: &[S],
+        parallelism: usize,
+    ) -> AudisResult<BTreeMap<String, Vec<Event>>> {
+        self.instrument("retrieve_many", || {
+            let (next, failed) = (AtomicUsize::new(0), AtomicBool::new(false));
+            let workers = parallelism.clamp(1, logs.len().max(1));
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..workers)
+                    .map(|_| {
+                        let c = self.clone();
+                        let (next, failed) = (&next, &failed);
+                        scope.spawn(move || {
+                            let mut retrieved = vec![];
+                            while !failed.load(Ordering::Relaxed) {
+                                let log = match logs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                                    Some(log) => log.as_ref(),
+                                    None => break,
+                                };
+                                match c.events(log, 0, -1) {
+                                    Ok(events) => retrieved.push((log.to_string(), events)),
+                                    Err(e) => {
+                                        failed.store(true, Ordering::Relaxed);
+                                        return Err(e);
+                                    }
+                                }
+                            }
+                            Ok(retrieved)
+                        })
+                    })
+                    .collect();
+
+                let mut all = BTreeMap::new();
+                let mut first = None;
+                for h in handles {
+                    match h.join().unwrap_or_else(|p| panic::resume_unwind(p)) {
+                        Ok(retrieved) => all.extend(retrieved),
+                        Err(e) => {
+                            first.get_or_insert(e);
+                        }
+                    }
+                }
+                match first {
+                    Some(e) => Err(e),
+                    None => Ok(all),
+                }
+            })
+        })
+    }
+}
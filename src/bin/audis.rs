@@ -1,76 +1,2407 @@
-use audis;
-
 #[macro_use]
 extern crate clap;
 
-use rand;
-use rand::distributions::Alphanumeric;
-use rand::{thread_rng, Rng};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
 use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-fn id() -> String {
-    thread_rng().sample_iter(&Alphanumeric).take(30).collect()
-}
+use audis::backend::{Inspector, RoundTrip};
+use axum::extract::{Path as Param, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use ratatui::crossterm::event::{self as term, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Block, List, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::{json, Value};
+use tokio_stream::wrappers::ReceiverStream;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = clap_app!(audis =>
                          (version: "0.2.1")
                          (author: "James Hunt <james@niftylogic.com>")
                          (about: "Interact with an audit log, in Redis")
-                         (@arg verbose: -v --verbose "Turn on verbose output")
+                         (@arg verbose: -v --verbose ... "Turn on verbose output (twice to dump raw commands)")
                          (@arg host: -H --host +takes_value "URL of the Redis server to connect to")
+                         (@arg profile: -P --profile +takes_value "Name of a connection profile (from the configuration file) to connect with")
+                         (@arg config: --config +takes_value "Path to the configuration file (defaults to ~/.config/audis/config.toml)")
                          (@subcommand subjects =>
-                          (about: "List known subjects"))
+                          (about: "List known subjects")
+                          (@arg format: -f --format +takes_value possible_values(&FORMATS) default_value("table") "How to format the output")
+                          (@arg pattern: -p --pattern +takes_value "Only list subjects whose names match this glob (i.e. 'user:*')")
+                          (@arg counts: -c --counts "Also print how many events each subject has")
+                          (@arg sort: -s --sort +takes_value possible_values(&["name", "count"]) "Sort subjects by name, or by how many events they have (largest first)"))
+                         (@subcommand subject =>
+                          (about: "Manage subjects / event logs")
+                          (@setting SubcommandRequiredElseHelp)
+                          (@subcommand rm =>
+                           (about: "Remove a subject, deleting events that no other subject references")
+                           (@arg name: * +takes_value "The name of the subject / event log to remove"))
+                          (@subcommand rename =>
+                           (about: "Rename a subject, keeping all of its events")
+                           (@arg old: * +takes_value "The current name of the subject")
+                           (@arg new: * +takes_value "The new name of the subject (which must not exist yet)"))
+                          (@subcommand merge =>
+                           (about: "Move all of the events of one subject onto the end of another")
+                           (@arg src: * +takes_value "The subject to merge (and then remove)")
+                           (@arg dst: * +takes_value "The subject to merge into")))
                          (@subcommand retrieve =>
                           (about: "Print out an event log for one or more subjects")
+                          (@arg format: -f --format +takes_value possible_values(&FORMATS) default_value("table") "How to format the output")
+                          (@arg last: -n --last +takes_value "Only print the last N (matching) events of each subject")
+                          (@arg since: --since +takes_value "Only print events logged at or after this time")
+                          (@arg until: --until +takes_value "Only print events logged before this time")
+                          (@arg grep: -g --grep +takes_value "Only print events whose payloads contain this (fixed) string")
                           (@arg subject: ... *))
+                         (@subcommand tail =>
+                          (about: "Print out the last few events for a subject, and (optionally) follow it")
+                          (@arg format: --format +takes_value possible_values(&FORMATS) default_value("table") "How to format the output")
+                          (@arg n: -n --lines +takes_value default_value("10") "How many of the most recent events to print")
+                          (@arg follow: -f --follow "Keep printing new events as they are logged")
+                          (@arg poll: --poll +takes_value default_value("1000") "How often to check for new events, in milliseconds")
+                          (@arg subject: * +takes_value "The name of the subject / event log to tail"))
+                         (@subcommand watch =>
+                          (about: "Keep printing new events for a subject, by polling it (i.e. where pub/sub isn't available)")
+                          (@arg format: -f --format +takes_value possible_values(&FORMATS) default_value("table") "How to format the output")
+                          (@arg interval: -i --interval +takes_value default_value("2s") "How often to check for new events (i.e. 500ms, 2s or 1m)")
+                          (@arg subject: * +takes_value "The name of the subject / event log to watch"))
                          (@subcommand log =>
                           (about: "Log an event against one or more subjects")
                           (@arg subject: -s --subject ... * +takes_value "The name of a subject to index this event against")
                           (@arg id: -i --id +takes_value "A unique ID to assign this event")
-                          (@arg data: -d --data * +takes_value "The raw data to insert into the audit log"))
+                          (@arg data: -d --data * +takes_value "The raw data to insert into the audit log ('-' to read it from standard input)"))
+                         (@subcommand load =>
+                          (about: "Log events in bulk, from a file of newline-delimited JSON objects")
+                          (@arg subject: -s --subject ... +takes_value number_of_values(1) "The name of a subject to index events without their own subjects against")
+                          (@arg file: * +takes_value "The file to load events from ('-' for standard input)"))
                          (@subcommand purge =>
                           (about: "Purge an event log, up to a last-known audit event")
                           (@arg subject: * +takes_value "The name of the subject / event log to purge")
                           (@arg to: -t --to * +takes_value "The event ID to purge up to (and including)"))
+                         (@subcommand archive =>
+                          (about: "Write out all but the most recent events of a subject, and then remove them")
+                          (@arg subject: * +takes_value "The name of the subject / event log to archive")
+                          (@arg keep: -n --keep * +takes_value "How many audit events to keep")
+                          (@arg to: -t --to * +takes_value "The NDJSON file to append archived events to ('-' for standard output), or the s3://bucket/prefix to upload them under"))
+                         (@subcommand verify =>
+                          (about: "Check the hash chains (and signatures) of subjects, exiting 2 if any have been tampered with")
+                          (@arg format: -f --format +takes_value possible_values(&FORMATS) default_value("table") "How to format the output")
+                          (@arg key: -k --("public-key") +takes_value "A hex-encoded ed25519 public key to check event signatures against (needs the crypto feature)")
+                          (@arg complete: -c --complete "Also check that no (sequence-numbered) events are missing from each subject")
+                          (@arg subject: ... "The name of a subject to verify (defaults to all of them)"))
+                         (@subcommand copy =>
+                          (about: "Copy events (and the subjects they are logged against) to another audit log")
+                          (@arg from: --from +takes_value "URL of the audit log to copy from (defaults to the one given by --host or --profile)")
+                          (@arg to: --to * +takes_value "URL of the audit log to copy to")
+                          (@arg subject: -s --subject +takes_value "Only copy subjects whose names match this glob (i.e. 'user:*')"))
+                         (@subcommand erase =>
+                          (about: "Erase one or more subjects entirely, i.e. for a right-to-erasure request")
+                          (@arg subject: -s --subject ... * +takes_value number_of_values(1) "The name of a subject to erase")
+                          (@arg fields: --("redact-fields") +takes_value "Comma-separated (dotted) JSON fields to mask in events shared with other subjects, instead of masking the whole payload")
+                          (@arg yes: -y --yes "Really erase the subject(s); this cannot be undone")
+                          (@arg dry: -n --("dry-run") "Print out what would be deleted or redacted, without changing anything"))
+                         (@subcommand browse =>
+                          (about: "Browse subjects and their events, full-screen"))
+                         (@subcommand shell =>
+                          (about: "Explore the audit log interactively, with history and completion of subject names"))
+                         (@subcommand prune =>
+                          (about: "Remove (and optionally archive) old events from every subject, according to a retention policy")
+                          (@arg policy: -p --policy * +takes_value "The retention policy (TOML) file to apply")
+                          (@arg dry: -n --("dry-run") "Print out what would be removed, without changing anything"))
+                         (@subcommand serve =>
+                          (about: "Serve up a small HTTP API for logging and retrieving events, requiring the bearer token in $AUDIS_API_TOKEN (if set)")
+                          (@arg listen: -l --listen +takes_value default_value("127.0.0.1:8080") "The address (and port) to listen on; anything but loopback requires $AUDIS_API_TOKEN")
+                          (@arg scope: -s --scope ... +takes_value number_of_values(1) "Only log and retrieve events of subjects starting with this prefix")
+                          (@arg streams: --("max-streams") +takes_value default_value("16") "How many subjects can be streamed at once"))
+                         (@subcommand stats =>
+                          (about: "Print statistics about the audit log as a whole")
+                          (@arg top: -n --top +takes_value default_value("10") "How many of the largest subjects to list"))
+                         (@subcommand info =>
+                          (about: "Print information about the backend, and how well it is responding"))
+                         (@subcommand fsck =>
+                          (about: "Check the audit log for consistency problems")
+                          (@arg repair: -r --repair "Fix whatever problems are found"))
+                         (@subcommand gc =>
+                          (about: "Delete events that no subject references any more"))
+                         (@subcommand expire =>
+                          (about: "Remove (and optionally archive) every subject that nothing has been logged against in a while")
+                          (@arg idle: -i --idle * +takes_value "How long a subject has to have been idle for (i.e. 12h or 30d)")
+                          (@arg to: -t --to +takes_value "An NDJSON file to append the events of expired subjects to ('-' for standard output), or an s3://bucket/prefix to upload them under"))
+                         (@subcommand reap =>
+                          (about: "Remove events that Redis has expired from the subjects (and trails) that reference them"))
+                         (@subcommand event =>
+                          (about: "Work with individual events, by ID")
+                          (@setting SubcommandRequiredElseHelp)
+                          (@subcommand get =>
+                           (about: "Print out a single event")
+                           (@arg format: -f --format +takes_value possible_values(&FORMATS) default_value("table") "How to format the output")
+                           (@arg id: * +takes_value "The ID of the event"))
+                          (@subcommand rm =>
+                           (about: "Delete a single event, from every subject it is logged against")
+                           (@arg id: * +takes_value "The ID of the event"))
+                          (@subcommand subjects =>
+                           (about: "List the subjects an event is logged against")
+                           (@arg id: * +takes_value "The ID of the event")))
                          (@subcommand truncate =>
                           (about: "Truncate an event log such that it only includes a set number of events")
                           (@arg subject: * +takes_value "The name of the subject / event log to truncate")
                           (@arg n: -n --keep * +takes_value "How many audit events to keep")))
         .get_matches();
 
-    let default_host = match env::var("AUDIS_HOST") {
-        Ok(v) => v,
-        Err(_) => "redis://127.0.0.1:6379".to_string(),
+    let start = Instant::now();
+    let verbose = args.occurrences_of("verbose");
+    let trips = Arc::new(Mutex::new(vec![]));
+    let config = Config::load(args.value_of("config"))?;
+    let backend = match (
+        args.value_of("host"),
+        args.value_of("profile"),
+        env::var("AUDIS_HOST"),
+    ) {
+        (Some(host), _, _) => audis::backend::open(host)?,
+        (None, Some(profile), _) => config.open(profile)?,
+        (None, None, Ok(host)) => audis::backend::open(&host)?,
+        (None, None, Err(_)) => match &config.default {
+            Some(profile) => config.open(profile)?,
+            None => audis::backend::open("redis://127.0.0.1:6379")?,
+        },
+    };
+    let c = if verbose > 0 {
+        let trips = trips.clone();
+        audis::Client::with_backend(Box::new(Inspector::new(backend, move |trip| {
+            if verbose > 1 {
+                dump(trip);
+            }
+            trips.lock().unwrap().push(trip.clone());
+        })))?
+    } else {
+        audis::Client::with_backend(backend)?
     };
-    let c = audis::Client::connect(args.value_of("host").unwrap_or(&default_host))?;
 
-    if let Some(_) = args.subcommand_matches("subjects") {
-        for s in &c.subjects()? {
-            println!("{}", s);
+    if let Some(args) = args.subcommand_matches("subjects") {
+        let counts = args.is_present("counts");
+        let header: &[&str] = if counts {
+            &["SUBJECT", "COUNT"]
+        } else {
+            &["SUBJECT"]
+        };
+        let pattern = args.value_of("pattern").unwrap_or("*");
+        let mut out = Output::new(args.value_of("format").unwrap(), header);
+        let order = match args.value_of("sort") {
+            Some("count") => Some(audis::SubjectOrder::Count),
+            Some(_) => Some(audis::SubjectOrder::Name),
+            None => None,
+        };
+        if counts || order.is_some() {
+            let order = order.unwrap_or(audis::SubjectOrder::Name);
+            for (s, n) in c.subject_counts(pattern, order)? {
+                if counts {
+                    out.row(json!({ "subject": s, "count": n }), vec![s, n.to_string()]);
+                } else {
+                    out.row(json!({ "subject": s }), vec![s]);
+                }
+            }
+        } else {
+            // SSCAN can turn up the same subject more than once.
+            let mut seen = HashSet::new();
+            for s in c.scan_subjects(pattern, 1000) {
+                let s = s?;
+                if seen.insert(s.to_string()) {
+                    out.row(json!({ "subject": s }), vec![s]);
+                }
+            }
+        }
+        out.finish();
+    } else if let Some(args) = args.subcommand_matches("subject") {
+        if let Some(args) = args.subcommand_matches("rm") {
+            let s = args.value_of("name").unwrap();
+            let n = c.count(s)?;
+            c.remove_subject(s)?;
+            println!("removed {} ({} event(s))", s, n);
+        } else if let Some(args) = args.subcommand_matches("rename") {
+            let (old, new) = (args.value_of("old").unwrap(), args.value_of("new").unwrap());
+            c.rename_subject(old, new)?;
+            println!("renamed {} to {}", old, new);
+        } else if let Some(args) = args.subcommand_matches("merge") {
+            let (src, dst) = (args.value_of("src").unwrap(), args.value_of("dst").unwrap());
+            let before = c.count(dst)?;
+            let after = c.merge_subjects(src, dst)?.count(dst)?;
+            println!(
+                "merged {} into {}: added {} event(s), now {}",
+                src,
+                dst,
+                after - before,
+                after
+            );
         }
     } else if let Some(args) = args.subcommand_matches("retrieve") {
+        let last = args.value_of("last").map(str::parse::<usize>).transpose()?;
+        let since = args.value_of("since").map(parse_time).transpose()?;
+        let until = args.value_of("until").map(parse_time).transpose()?;
+        let grep = args.value_of("grep").map(str::as_bytes);
+        // events are timestamped by their IDs, so only those
+        // with ULIDs (or the like) for IDs can be filtered by time.
+        let keep = |e: &audis::Event| {
+            if since.is_some() || until.is_some() {
+                let t = match audis::ids::timestamp(&e.id) {
+                    Some(t) => t,
+                    None => return false,
+                };
+                if since.is_some_and(|since| t < since) || until.is_some_and(|until| t >= until) {
+                    return false;
+                }
+            }
+            grep.is_none_or(|g| g.is_empty() || e.data.windows(g.len()).any(|w| w == g))
+        };
+
+        let mut out = Output::new(args.value_of("format").unwrap(), &["SUBJECT", "ID", "DATA"]);
         for s in args.values_of("subject").unwrap() {
-            for e in c.retrieve(s)? {
-                println!("{}: [{}] {}", s, e.id, e.data);
+            let mut events = match last {
+                Some(n) if since.is_none() && until.is_none() && grep.is_none() => {
+                    c.retrieve_range(s, -(n as i64), -1)?
+                }
+                _ => c.retrieve(s)?,
+            };
+            events.retain(|e| keep(e));
+            let skip = events.len().saturating_sub(last.unwrap_or(events.len()));
+            for e in events.into_iter().skip(skip) {
+                let cells = vec![s.to_string(), e.id.to_string(), text(&e.data)];
+                out.row(event(s, &e)?, cells);
             }
         }
+        out.finish();
+    } else if let Some(args) = args.subcommand_matches("tail") {
+        let s = args.value_of("subject").unwrap();
+        let n: i64 = args.value_of("n").unwrap().parse()?;
+        let format = args.value_of("format").unwrap();
+        if args.is_present("follow") && format == "json" {
+            return Err("--format json can't be used with --follow; try ndjson".into());
+        }
+
+        let mut out = Output::new(format, &["SUBJECT", "ID", "DATA"]);
+        if n > 0 {
+            for e in c.retrieve_range(s, -n, -1)? {
+                let cells = vec![s.to_string(), e.id.to_string(), text(&e.data)];
+                out.row(event(s, &e)?, cells);
+            }
+        }
+        if args.is_present("follow") {
+            out.flush();
+            let poll = Duration::from_millis(args.value_of("poll").unwrap().parse()?);
+            for e in c.follow(s, poll)? {
+                let e = e?;
+                let cells = vec![s.to_string(), e.id.to_string(), text(&e.data)];
+                out.row(event(s, &e)?, cells);
+                out.flush();
+            }
+        }
+        out.finish();
+    } else if let Some(args) = args.subcommand_matches("watch") {
+        let s = args.value_of("subject").unwrap();
+        let format = args.value_of("format").unwrap();
+        if format == "json" {
+            return Err("--format json can't be used with watch; try ndjson".into());
+        }
+        let interval = parse_duration(args.value_of("interval").unwrap())?;
+
+        let mut out = Output::new(format, &["SUBJECT", "ID", "DATA"]);
+        for e in c.follow(s, interval)? {
+            let e = e?;
+            let cells = vec![s.to_string(), e.id.to_string(), text(&e.data)];
+            out.row(event(s, &e)?, cells);
+            out.flush();
+        }
     } else if let Some(args) = args.subcommand_matches("log") {
-        let id = match args.value_of("id") {
-            Some(id) => id.to_string(),
-            _ => id(),
-        };
-        c.log(&audis::Event {
-            id: id,
-            subjects: args.values_of_lossy("subject").unwrap(),
-            data: args.value_of("data").unwrap().to_string(),
-        })?;
+        let data = match args.value_of("data").unwrap() {
+            "-" => {
+                let mut data = vec![];
+                io::stdin().read_to_end(&mut data)?;
+                data
+            }
+            data => data.into(),
+        };
+        let mut e = audis::Event::builder()
+            .subjects(args.values_of("subject").unwrap())
+            .data(data);
+        if let Some(id) = args.value_of("id") {
+            e = e.id(id);
+        }
+        c.log(&e.build()?)?;
+    } else if let Some(args) = args.subcommand_matches("load") {
+        let input: Box<dyn BufRead> = match args.value_of("file").unwrap() {
+            "-" => Box::new(BufReader::new(io::stdin())),
+            file => Box::new(BufReader::new(File::open(file)?)),
+        };
+        let subjects: Vec<&str> = args.values_of("subject").into_iter().flatten().collect();
+        let mut n = 0;
+        for (i, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let e = parse_event(&line, &subjects).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            c.log(&e).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            n += 1;
+        }
+        println!("loaded {} event(s)", n);
     } else if let Some(args) = args.subcommand_matches("purge") {
         c.purge(
-            &args.value_of("subject").unwrap().to_string(),
-            &args.value_of("to").unwrap().to_string(),
+            args.value_of("subject").unwrap(),
+            args.value_of("to").unwrap(),
         )?;
+    } else if let Some(args) = args.subcommand_matches("archive") {
+        let s = args.value_of("subject").unwrap();
+        let keep: i64 = args.value_of("keep").unwrap().parse()?;
+        let to = args.value_of("to").unwrap();
+        check_archive(to)?;
+        let last = match c.retrieve_range(s, 0, -(keep + 1))?.last() {
+            Some(e) => e.id.to_string(),
+            None => {
+                eprintln!("nothing to archive from {}", s);
+                return Ok(());
+            }
+        };
+
+        // we purge (rather than truncate), so that nothing logged
+        // in the meantime gets removed without being archived, and
+        // do so under the subject's lock, so that nothing pruned
+        // in the meantime gets archived twice.
+        let mut n = 0;
+        c.purge_archiving(s, &last, |events| {
+            n = events.len();
+            archiving(s, archive(to, s, events))
+        })?;
+        eprintln!("archived {} event(s) from {} to {}", n, s, to);
+    } else if let Some(args) = args.subcommand_matches("prune") {
+        let dry = args.is_present("dry");
+        let policy = Retention::load(args.value_of("policy").unwrap())?;
+
+        let mut seen = HashSet::new();
+        let (mut pruned, mut total) = (0, 0);
+        for rule in &policy.rules {
+            for s in c.scan_subjects(&rule.pattern, 1000) {
+                let s = s?;
+                // each subject answers to the first rule it matches.
+                if !seen.insert(s.to_string()) {
+                    continue;
+                }
+                let count = c.count(&s)? as usize;
+                let mut n = rule
+                    .keep
+                    .map(|keep| count.saturating_sub(keep))
+                    .unwrap_or(0);
+                if let Some(cutoff) = rule.cutoff {
+                    // only events with ULIDs (or the like) for IDs
+                    // have an age; the rest are as good as new.
+                    let mut old = 0;
+                    while old < count {
+                        let ids = c.event_ids(&s, old as i64, old as i64 + PAGE - 1)?;
+                        let young = ids
+                            .iter()
+                            .position(|id| audis::ids::timestamp(id).is_none_or(|t| t >= cutoff));
+                        old += young.unwrap_or(ids.len());
+                        if young.is_some() || (ids.len() as i64) < PAGE {
+                            break;
+                        }
+                    }
+                    n = n.max(old.min(count));
+                }
+                let last = match n {
+                    0 => continue,
+                    n => match c.event_ids(&s, n as i64 - 1, n as i64 - 1)?.pop() {
+                        Some(id) => id,
+                        None => continue,
+                    },
+                };
+
+                let to = match &rule.archive {
+                    Some(to) => format!(", archiving them to {}", to),
+                    None => String::new(),
+                };
+                if dry {
+                    println!(
+                        "{}: would remove {} event(s), keeping {}{}",
+                        s,
+                        n,
+                        count - n,
+                        to
+                    );
+                } else {
+                    match &rule.archive {
+                        Some(to) => {
+                            c.purge_archiving(&s, &last, |old| archiving(&s, archive(to, &s, old)))?
+                        }
+                        None => c.purge(&s, &last)?,
+                    };
+                    println!("{}: removed {} event(s), keeping {}{}", s, n, count - n, to);
+                }
+                pruned += 1;
+                total += n;
+            }
+        }
+        println!(
+            "{} {} event(s) from {} subject(s)",
+            if dry { "would prune" } else { "pruned" },
+            total,
+            pruned
+        );
+    } else if let Some(args) = args.subcommand_matches("verify") {
+        #[cfg(feature = "crypto")]
+        let c = match args.value_of("key") {
+            Some(key) => {
+                let key: [u8; 32] = unhex(key)
+                    .and_then(|key| std::convert::TryInto::try_into(key).ok())
+                    .ok_or("--public-key must be 32 hex-encoded bytes")?;
+                c.verify_signatures(audis::crypto::VerifyingKey::from_bytes(&key)?)
+            }
+            None => c,
+        };
+        #[cfg(not(feature = "crypto"))]
+        if args.is_present("key") {
+            return Err("--public-key needs audis to be built with the crypto feature".into());
+        }
+
+        let subjects = match args.values_of("subject") {
+            Some(subjects) => subjects.map(String::from).collect(),
+            None => c.subjects()?,
+        };
+        let mut out = Output::new(
+            args.value_of("format").unwrap(),
+            &["SUBJECT", "STATUS", "DETAIL"],
+        );
+        let (mut broken, mut failed) = (0, 0);
+        for s in subjects {
+            let verified = match c.verify(&s) {
+                Ok(None) if args.is_present("complete") => {
+                    c.verify_completeness(&s).map(|report| (None, report))
+                }
+                Ok(b) => Ok((b, audis::Completeness::default())),
+                Err(e) => Err(e),
+            };
+            match verified {
+                Ok((None, report)) if !report.is_complete() => {
+                    broken += 1;
+                    let mut detail: Vec<String> = report
+                        .missing
+                        .iter()
+                        .map(|(a, b)| {
+                            if a == b {
+                                format!("#{}", a)
+                            } else {
+                                format!("#{}-{}", a, b)
+                            }
+                        })
+                        .collect();
+                    if !detail.is_empty() {
+                        detail = vec![format!("missing {}", detail.join(", "))];
+                    }
+                    if !report.unnumbered.is_empty() {
+                        detail.push(format!("{} unnumbered event(s)", report.unnumbered.len()));
+                    }
+                    out.row(
+                        json!({ "subject": s, "status": "incomplete", "last": report.last, "missing": report.missing, "unnumbered": report.unnumbered }),
+                        vec![s, "INCOMPLETE".to_string(), detail.join("; ")],
+                    );
+                }
+                Ok((None, _)) => out.row(
+                    json!({ "subject": s, "status": "ok" }),
+                    vec![s, "ok".to_string(), String::new()],
+                ),
+                Ok((Some(b), _)) => {
+                    broken += 1;
+                    let detail = format!("event #{} ({}): {}", b.index, b.id, b.reason);
+                    out.row(
+                        json!({ "subject": s, "status": "broken", "index": b.index, "id": b.id, "reason": b.reason }),
+                        vec![s, "BROKEN".to_string(), detail],
+                    );
+                }
+                Err(e) => {
+                    failed += 1;
+                    out.row(
+                        json!({ "subject": s, "status": "error", "reason": e.to_string() }),
+                        vec![s, "ERROR".to_string(), e.to_string()],
+                    );
+                }
+            }
+        }
+        out.finish();
+        if broken > 0 {
+            std::process::exit(2);
+        }
+        if failed > 0 {
+            std::process::exit(1);
+        }
+    } else if let Some(args) = args.subcommand_matches("copy") {
+        let from = match args.value_of("from") {
+            Some(url) => audis::Client::connect(url)?,
+            None => c,
+        };
+        let to = audis::Client::connect(args.value_of("to").unwrap())?;
+        let mut subjects = match args.value_of("subject") {
+            Some(pattern) => from.subjects_matching(pattern)?,
+            None => from.subjects()?,
+        };
+        // the changes recorded about one audit log (see `audis::
+        // Client::audit_changes()`) don't belong in another.
+        subjects.retain(|s| s != audis::SYSTEM_SUBJECT);
+
+        // log each event (once, against all of its subjects) as
+        // soon as everything before it in each of those subjects
+        // has been logged, so that every subject keeps its order.
+        let n = subjects.len();
+        let mut merge = Merge::new(&from, subjects)?;
+        let (mut copied, mut skipped) = (0, 0);
+        while let Some((id, subjects)) = merge.next()? {
+            let mut e = match from.retrieve_event(&id)? {
+                Some(e) => e,
+                None => continue,
+            };
+            e.subjects = subjects;
+            match to.log(&e) {
+                Ok(_) => copied += 1,
+                Err(audis::AudisError::Duplicate(_)) => skipped += 1,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        println!(
+            "copied {} event(s) from {} subject(s); skipped {} that were already there",
+            copied, n, skipped
+        );
+    } else if let Some(args) = args.subcommand_matches("erase") {
+        let dry = args.is_present("dry");
+        if !dry && !args.is_present("yes") {
+            return Err("erasing subjects cannot be undone; re-run with --yes to confirm (or --dry-run to see what would be affected)".into());
+        }
+        let fields: Vec<&str> = match args.value_of("fields") {
+            Some(fields) => fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .collect(),
+            None => vec![],
+        };
+
+        for s in args.values_of("subject").unwrap() {
+            let (mut deleted, mut redacted) = (0, 0);
+            for e in c.retrieve(s)? {
+                let others: Vec<String> = c
+                    .subjects_of(&e.id)?
+                    .into_iter()
+                    .filter(|other| other != s)
+                    .collect();
+                if others.is_empty() {
+                    deleted += 1;
+                    if dry {
+                        println!("{}: would delete {}", s, e.id);
+                    }
+                } else {
+                    redacted += 1;
+                    if dry {
+                        let (_, masked) = mask(&e.data, &fields);
+                        println!(
+                            "{}: would redact {} (also logged against {}), masking {}",
+                            s,
+                            e.id,
+                            others.join(", "),
+                            if masked.is_empty() {
+                                "nothing".to_string()
+                            } else {
+                                masked.join(", ")
+                            }
+                        );
+                    }
+                }
+            }
+
+            if dry {
+                println!(
+                    "{}: would delete {} event(s), and redact {} shared event(s)",
+                    s, deleted, redacted
+                );
+            } else {
+                c.erase_subject(s, |e| mask(&e.data, &fields).0)?;
+                println!(
+                    "erased {}: deleted {} event(s), redacted {} shared event(s)",
+                    s, deleted, redacted
+                );
+            }
+        }
+    } else if args.subcommand_matches("browse").is_some() {
+        browse(&c)?;
+    } else if args.subcommand_matches("shell").is_some() {
+        shell(&c)?;
+    } else if let Some(args) = args.subcommand_matches("serve") {
+        let scope: Vec<String> = args
+            .values_of("scope")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect();
+        let api = Api {
+            client: Arc::new(scoped(c, &scope)),
+            token: env::var("AUDIS_API_TOKEN").ok().filter(|t| !t.is_empty()),
+            scope,
+            streams: Arc::new(AtomicUsize::new(0)),
+            max_streams: args.value_of("streams").unwrap().parse()?,
+        };
+        serve(api, args.value_of("listen").unwrap())?;
+    } else if let Some(args) = args.subcommand_matches("stats") {
+        let stats = c.stats(args.value_of("top").unwrap().parse()?)?;
+        println!("events:   {}", stats.events);
+        println!("subjects: {}", stats.subjects);
+        println!("orphans:  {}", stats.orphans);
+        println!();
+        println!("largest subjects, by event count:");
+        for (s, n) in &stats.by_count {
+            println!("  {:>10}  {}", n, s);
+        }
+        println!();
+        println!("largest subjects, by memory usage (bytes):");
+        for (s, n) in &stats.by_memory {
+            println!("  {:>10}  {}", n, s);
+        }
+        if !stats.by_day.is_empty() {
+            println!();
+            println!("events logged, by day (starting at, in ms since the epoch):");
+            for (day, n) in &stats.by_day {
+                println!("  {:>10}  {}", n, day);
+            }
+        }
+    } else if args.subcommand_matches("info").is_some() {
+        let h = c.health()?;
+        let unknown = || "unknown".to_string();
+        println!(
+            "server version: {}",
+            h.server_version.unwrap_or_else(unknown)
+        );
+        println!(
+            "schema version: {} (this is audis {}, on schema version {})",
+            h.schema_version
+                .map(|v| v.to_string())
+                .unwrap_or_else(unknown),
+            crate_version!(),
+            audis::SCHEMA_VERSION
+        );
+        println!("latency:        {:?}", h.latency);
+        println!("keys:           {}", h.keys);
+        println!("subjects:       {}", h.subjects);
+        println!(
+            "memory:         {}",
+            h.memory
+                .map(|m| format!("{} bytes", m))
+                .unwrap_or_else(unknown)
+        );
+    } else if let Some(args) = args.subcommand_matches("fsck") {
+        let report = c.fsck(args.is_present("repair"))?;
+        for (id, recorded, actual) in &report.miscounted {
+            println!(
+                "miscounted: event {} has {} reference(s), but claims {}",
+                id, actual, recorded
+            );
+        }
+        for (s, id) in &report.dangling {
+            println!("dangling:   subject {} references missing event {}", s, id);
+        }
+        for id in &report.orphans {
+            println!("orphaned:   event {} is not referenced by any subject", id);
+        }
+        for k in &report.unknown {
+            println!("unknown:    key {} does not belong to the audit log", k);
+        }
+
+        let problems = report.miscounted.len()
+            + report.dangling.len()
+            + report.orphans.len()
+            + report.unknown.len();
+        if report.is_clean() {
+            println!("no problems found");
+        } else if report.repaired {
+            println!(
+                "found {} problem(s); repaired all but {} unknown key(s)",
+                problems,
+                report.unknown.len()
+            );
+        } else {
+            println!(
+                "found {} problem(s); run with --repair to fix them",
+                problems
+            );
+            std::process::exit(1);
+        }
+    } else if args.subcommand_matches("gc").is_some() {
+        println!("deleted {} orphaned event(s)", c.gc()?);
+    } else if let Some(args) = args.subcommand_matches("expire") {
+        let idle = parse_duration(args.value_of("idle").unwrap())?;
+        let to = args.value_of("to");
+        if let Some(to) = to {
+            check_archive(to)?;
+        }
+        let expired = c.expire_idle(idle, |s, events| match to {
+            Some(to) => archiving(s, archive(to, s, events)),
+            None => Ok(()),
+        })?;
+        for s in &expired {
+            eprintln!("expired {}", s);
+        }
+        println!("expired {} idle subject(s)", expired.len());
+    } else if args.subcommand_matches("reap").is_some() {
+        println!("reaped {} expired event(s)", c.reap()?);
+    } else if let Some(args) = args.subcommand_matches("event") {
+        if let Some(args) = args.subcommand_matches("get") {
+            let id = args.value_of("id").unwrap();
+            let mut e = c
+                .retrieve_event(id)?
+                .ok_or(audis::AudisError::NotFound(id.to_string()))?;
+            e.subjects = c.subjects_of(id)?;
+            let mut out = Output::new(
+                args.value_of("format").unwrap(),
+                &["ID", "SUBJECTS", "DATA"],
+            );
+            let cells = vec![e.id.to_string(), e.subjects.join(","), text(&e.data)];
+            out.row(serde_json::to_value(&e)?, cells);
+            out.finish();
+        } else if let Some(args) = args.subcommand_matches("rm") {
+            let id = args.value_of("id").unwrap();
+            if c.retrieve_event(id)?.is_none() {
+                return Err(audis::AudisError::NotFound(id.to_string()).into());
+            }
+            c.delete(id)?;
+            println!("deleted event {}", id);
+        } else if let Some(args) = args.subcommand_matches("subjects") {
+            for s in c.subjects_of(args.value_of("id").unwrap())? {
+                println!("{}", s);
+            }
+        }
+    } else if let Some(args) = args.subcommand_matches("truncate") {
+        let s = args.value_of("subject").unwrap();
+        let n: u32 = args.value_of("n").unwrap().parse()?;
+        let before = c.count(s)?;
+        let after = c.truncate(s, n)?.count(s)?;
+        println!(
+            "truncated {}: removed {} event(s), kept {}",
+            s,
+            before - after,
+            after
+        );
     }
 
+    if verbose > 0 {
+        diagnose(
+            args.subcommand_name().unwrap_or("connect"),
+            start.elapsed(),
+            &trips.lock().unwrap(),
+        );
+    }
     Ok(())
 }
+
+// How many event IDs `audis copy` and `audis prune` read from a
+// subject at a time.
+const PAGE: i64 = 100;
+
+// The events of the subjects being copied by `audis copy`, in an
+// order that keeps the order of every subject: each subject is
+// read a page of event IDs at a time, and an event is ready to be
+// copied once it is at the front of every one of its subjects.
+// If the subjects disagree on the order, no event is ever ready;
+// we just take the one at the front of the first subject, and
+// carry on.
+struct Merge<'a> {
+    from: &'a audis::Client,
+    pages: Vec<Page>,
+    index: HashMap<String, usize>,
+
+    // the subjects (by index) of each event at the front of one,
+    // and those it is at the front of.
+    owners: HashMap<String, Vec<usize>>,
+    fronts: HashMap<String, Vec<usize>>,
+    ready: VecDeque<String>,
+
+    // events taken out of order, which their other subjects skip
+    // when they get to them.
+    taken: HashSet<String>,
+}
+
+// A subject being copied, and the page of its event IDs that is
+// being worked through.
+struct Page {
+    subject: String,
+    ids: VecDeque<String>,
+    next: i64,
+    done: bool,
+}
+
+impl<'a> Merge<'a> {
+    fn new(from: &'a audis::Client, subjects: Vec<String>) -> audis::AudisResult<Merge<'a>> {
+        let mut merge = Merge {
+            from,
+            index: subjects
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (s.to_string(), i))
+                .collect(),
+            pages: subjects
+                .into_iter()
+                .map(|subject| Page {
+                    subject,
+                    ids: VecDeque::new(),
+                    next: 0,
+                    done: false,
+                })
+                .collect(),
+            owners: HashMap::new(),
+            fronts: HashMap::new(),
+            ready: VecDeque::new(),
+            taken: HashSet::new(),
+        };
+        for i in 0..merge.pages.len() {
+            merge.advance(i)?;
+        }
+        Ok(merge)
+    }
+
+    // The ID of the next event to copy, and the subjects to copy
+    // it to.
+    fn next(&mut self) -> audis::AudisResult<Option<(String, Vec<String>)>> {
+        let id = match self.ready.pop_front() {
+            Some(id) => id,
+            None => match self.pages.iter().find_map(|p| p.ids.front()) {
+                Some(id) => {
+                    self.taken.insert(id.to_string());
+                    id.to_string()
+                }
+                None => return Ok(None),
+            },
+        };
+
+        let mut owners = self.owners.remove(&id).unwrap_or_default();
+        owners.sort_unstable();
+        for i in self.fronts.remove(&id).unwrap_or_default() {
+            self.pages[i].ids.pop_front();
+            self.advance(i)?;
+        }
+        let subjects = owners
+            .iter()
+            .map(|&i| self.pages[i].subject.to_string())
+            .collect();
+        Ok(Some((id, subjects)))
+    }
+
+    // Move on to the next event in a subject that is still to be
+    // copied, reading its next page of IDs if need be.
+    fn advance(&mut self, i: usize) -> audis::AudisResult<()> {
+        let id = loop {
+            let p = &mut self.pages[i];
+            match p.ids.front() {
+                Some(id) if self.taken.contains(id) => {
+                    p.ids.pop_front();
+                }
+                Some(id) => break id.to_string(),
+                None if p.done => return Ok(()),
+                None => {
+                    let events = self
+                        .from
+                        .retrieve_range(&p.subject, p.next, p.next + PAGE - 1)?;
+                    p.done = (events.len() as i64) < PAGE;
+                    p.next += PAGE;
+                    p.ids.extend(events.into_iter().map(|e| e.id.to_string()));
+                }
+            }
+        };
+
+        if !self.owners.contains_key(&id) {
+            let owners = self
+                .from
+                .subjects_of(&id)?
+                .iter()
+                .filter_map(|s| self.index.get(s).copied())
+                .collect();
+            self.owners.insert(id.to_string(), owners);
+        }
+        let owners = self.owners.get_mut(&id).unwrap();
+        if !owners.contains(&i) {
+            owners.push(i);
+        }
+        let fronts = self.fronts.entry(id.to_string()).or_default();
+        fronts.push(i);
+        if owners.iter().all(|o| fronts.contains(o)) {
+            self.ready.push_back(id);
+        }
+        Ok(())
+    }
+}
+
+// The output formats that listing subcommands understand.
+const FORMATS: [&str; 3] = ["json", "ndjson", "table"];
+
+// A retention policy, for `audis prune`:
+//
+//   [[rule]]
+//   pattern = "session:*"
+//   keep = 100
+//
+//   [[rule]]
+//   pattern = "user:*"
+//   max_age = "365d"
+//   archive = "/var/archive/users.ndjson"
+//
+//   [[rule]]
+//   pattern = "order:*"
+//   max_age = "90d"
+//   archive = "s3://audit-archive/orders"
+//
+// Each subject answers to the first rule whose pattern matches
+// it, which can limit the number of events kept, or how old
+// they can get (or both); subjects that don't match any rules
+// are left alone.
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Retention {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    pattern: String,
+    keep: Option<usize>,
+    max_age: Option<String>,
+    archive: Option<String>,
+    #[serde(skip)]
+    cutoff: Option<u64>,
+}
+
+impl Retention {
+    fn load(path: &str) -> Result<Retention, String> {
+        let policy = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut policy: Retention =
+            toml::from_str(&policy).map_err(|e| format!("{}: {}", path, e))?;
+        for rule in &mut policy.rules {
+            if rule.keep.is_none() && rule.max_age.is_none() {
+                return Err(format!(
+                    "{}: rule for '{}' needs a keep or a max_age",
+                    path, rule.pattern
+                ));
+            }
+            if let Some(to) = &rule.archive {
+                check_archive(to)?;
+            }
+            rule.cutoff = rule.max_age.as_deref().map(parse_time).transpose()?;
+        }
+        Ok(policy)
+    }
+}
+
+// The configuration file, holding named connection profiles:
+//
+//   default = "local"
+//
+//   [profiles.local]
+//   url = "redis://127.0.0.1:6379"
+//
+//   [profiles.staging]
+//   url = "redis://redis.staging.example.com:6379"
+//   db = 2
+//   password_env = "STAGING_REDIS_PASSWORD"
+//
+// The default profile is used when neither --host, --profile
+// nor $AUDIS_HOST are given.
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    default: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    url: String,
+    db: Option<i64>,
+    password: Option<String>,
+    password_env: Option<String>,
+    #[serde(default)]
+    tls: bool,
+    namespace: Option<String>,
+}
+
+impl Config {
+    // Load the configuration file at `path`, or the default one
+    // (if it exists) under $XDG_CONFIG_HOME or ~/.config.
+    fn load(path: Option<&str>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (PathBuf::from(path), true),
+            None => match env::var("XDG_CONFIG_HOME")
+                .ok()
+                .filter(|dir| !dir.is_empty())
+                .or_else(|| {
+                    env::var("HOME")
+                        .ok()
+                        .map(|home| format!("{}/.config", home))
+                }) {
+                Some(dir) => (Path::new(&dir).join("audis/config.toml"), false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(config) => toml::from_str(&config).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if !required && e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    // Open a backend, as described by the named profile.
+    fn open(&self, name: &str) -> Result<Box<dyn audis::backend::Backend>, String> {
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| format!("no such profile '{}'", name))?;
+        profile
+            .open()
+            .map_err(|e| format!("profile '{}': {}", name, e))
+    }
+}
+
+impl Profile {
+    fn open(&self) -> Result<Box<dyn audis::backend::Backend>, Box<dyn std::error::Error>> {
+        if self.tls {
+            return Err("TLS connections are not supported (yet)".into());
+        }
+        if self.namespace.is_some() {
+            return Err("namespaces are not supported (yet)".into());
+        }
+        let password = match (&self.password, &self.password_env) {
+            (Some(_), Some(_)) => {
+                return Err("only one of password and password_env can be given".into())
+            }
+            (Some(password), None) => Some(password.to_string()),
+            (None, Some(var)) => Some(env::var(var).map_err(|_| format!("${} is not set", var))?),
+            (None, None) => None,
+        };
+        if password.is_none() && self.db.is_none() {
+            return Ok(audis::backend::open(&self.url)?);
+        }
+        if self.url.starts_with("file:") {
+            return Err("a password or db can only be given for Redis URLs".into());
+        }
+
+        // going through a ConnectionInfo spares us from having to
+        // URL-encode passwords.
+        let mut info = redis::IntoConnectionInfo::into_connection_info(self.url.as_str())?;
+        if password.is_some() {
+            info.passwd = password;
+        }
+        if let Some(db) = self.db {
+            info.db = db;
+        }
+        Ok(Box::new(audis::backend::RedisBackend::open(info)?))
+    }
+}
+
+// Formats the output of listing subcommands, as JSON (all at
+// once, when finished), NDJSON (a line at a time) or a table
+// (with aligned columns, so it too waits until finished, or
+// flushed).
+struct Output {
+    format: String,
+    header: Vec<String>,
+    headed: bool,
+    json: Vec<Value>,
+    table: Vec<Vec<String>>,
+}
+
+impl Output {
+    fn new(format: &str, header: &[&str]) -> Output {
+        Output {
+            format: format.to_string(),
+            header: header.iter().map(|h| h.to_string()).collect(),
+            headed: false,
+            json: vec![],
+            table: vec![],
+        }
+    }
+
+    // Add a row to the output, given both as a JSON object,
+    // and as the cells of a table.
+    fn row(&mut self, obj: Value, cells: Vec<String>) {
+        match self.format.as_str() {
+            "json" => self.json.push(obj),
+            "ndjson" => println!("{}", obj),
+            _ => self.table.push(cells),
+        }
+    }
+
+    // Print out what's in the table so far (along with the
+    // header, the first time), for output that can't wait
+    // until the end.
+    fn flush(&mut self) {
+        if self.format != "table" {
+            return;
+        }
+        if self.header.len() == 1 {
+            // a single column needs no header, and is easier to
+            // loop over in shell scripts without one.
+            for row in &self.table {
+                println!("{}", row[0]);
+            }
+            self.table.clear();
+            return;
+        }
+
+        let mut rows = vec![];
+        if !self.headed {
+            rows.push(self.header.clone());
+            self.headed = true;
+        }
+        rows.append(&mut self.table);
+
+        let mut widths = vec![0; self.header.len()];
+        for row in &rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+        for row in &rows {
+            let last = row.len() - 1;
+            let line: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    if i == last {
+                        cell.to_string()
+                    } else {
+                        format!("{:w$}", cell, w = widths[i])
+                    }
+                })
+                .collect();
+            println!("{}", line.join("  "));
+        }
+    }
+
+    fn finish(mut self) {
+        match self.format.as_str() {
+            "json" => println!("{}", Value::Array(self.json)),
+            "ndjson" => (),
+            _ => self.flush(),
+        }
+    }
+}
+
+// Render an event as a JSON object, as retrieved via the
+// subject `s`.
+fn event(s: &str, e: &audis::Event) -> Result<Value, serde_json::Error> {
+    let mut v = serde_json::to_value(e)?;
+    if let Some(obj) = v.as_object_mut() {
+        obj.remove("subjects");
+        obj.insert("subject".to_string(), json!(s));
+    }
+    Ok(v)
+}
+
+// Make sure we can archive events to `to`.
+fn check_archive(to: &str) -> Result<(), String> {
+    if let Some(bucket) = to.strip_prefix("s3://") {
+        if bucket.split('/').next().unwrap_or("").is_empty() {
+            return Err(format!("missing bucket in archive destination '{}'", to));
+        }
+    } else if to.contains("://") {
+        return Err(format!(
+            "unsupported archive destination '{}' (only local files and s3:// are supported)",
+            to
+        ));
+    }
+    Ok(())
+}
+
+// Write events out to an archive (an NDJSON file, standard
+// output for "-", or an S3 bucket), making sure that they are
+// safely stored before the caller goes on to remove them.
+fn archive(to: &str, s: &str, events: &[audis::Event]) -> Result<(), Box<dyn std::error::Error>> {
+    check_archive(to)?;
+    let mut ndjson = vec![];
+    for e in events {
+        writeln!(ndjson, "{}", event(s, e)?)?;
+    }
+    if to == "-" {
+        io::stdout().write_all(&ndjson)?;
+    } else if let Some(dest) = to.strip_prefix("s3://") {
+        if let (Some(first), Some(last)) = (events.first(), events.last()) {
+            let (bucket, prefix) = dest.split_once('/').unwrap_or((dest, ""));
+            let prefix = prefix.trim_end_matches('/');
+            let name = format!("{}/{}-{}.ndjson", s, first.id, last.id);
+            let key = match prefix {
+                "" => name,
+                _ => format!("{}/{}", prefix, name),
+            };
+            upload(bucket, &key, &ndjson)?;
+        }
+    } else {
+        let mut f = OpenOptions::new().create(true).append(true).open(to)?;
+        f.write_all(&ndjson)?;
+        f.sync_all()?;
+    }
+    Ok(())
+}
+
+// Hand an archiving failure back to the library, which will
+// then leave the events that couldn't be archived alone.
+fn archiving(s: &str, r: Result<(), Box<dyn std::error::Error>>) -> audis::AudisResult<()> {
+    r.map_err(|e| audis::AudisError::Invalid(format!("archiving {}: {}", s, e)))
+}
+
+// Upload an object to S3 (or anything else that speaks its API,
+// at $AWS_ENDPOINT_URL), since S3 objects can't be appended to,
+// signing the request (with AWS Signature Version 4) using the
+// credentials in $AWS_ACCESS_KEY_ID, $AWS_SECRET_ACCESS_KEY and
+// (optionally) $AWS_SESSION_TOKEN, for the region in $AWS_REGION
+// (us-east-1, by default).
+fn upload(bucket: &str, key: &str, body: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let aws = |var: &str| env::var(var).map_err(|_| format!("archiving to S3 requires ${}", var));
+    let (id, secret) = (aws("AWS_ACCESS_KEY_ID")?, aws("AWS_SECRET_ACCESS_KEY")?);
+    let region = aws("AWS_REGION")
+        .or_else(|_| aws("AWS_DEFAULT_REGION"))
+        .unwrap_or_else(|_| "us-east-1".to_string());
+
+    // custom endpoints get path-style URLs, since they don't all
+    // do virtual hosting of buckets.
+    let (base, path) = match env::var("AWS_ENDPOINT_URL") {
+        Ok(endpoint) => (
+            endpoint.trim_end_matches('/').to_string(),
+            format!("/{}/{}", uri_encode(bucket), uri_encode(key)),
+        ),
+        Err(_) => (
+            format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+            format!("/{}", uri_encode(key)),
+        ),
+    };
+    let host = base
+        .split_once("://")
+        .map_or(base.as_str(), |(_, h)| h)
+        .to_string();
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let date = amz_date(now);
+    let mut headers = vec![
+        ("host", host),
+        ("x-amz-content-sha256", sha256_hex(body)),
+        ("x-amz-date", date.to_string()),
+    ];
+    if let Ok(token) = env::var("AWS_SESSION_TOKEN") {
+        headers.push(("x-amz-security-token", token));
+    }
+    let auth = sigv4(&id, &secret, &region, &date, "PUT", &path, &headers);
+
+    let mut req = attohttpc::put(format!("{}{}", base, path));
+    for (name, value) in &headers[1..] {
+        req = req.header(*name, value.as_str());
+    }
+    let resp = req.header("authorization", auth).bytes(body).send()?;
+    if !resp.is_success() {
+        let status = resp.status();
+        return Err(format!(
+            "uploading s3://{}/{}: {} {}",
+            bucket,
+            key,
+            status,
+            resp.text()?
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// Sign a request to S3, returning its Authorization header.
+// Headers must be named in lowercase, and sorted by name.
+fn sigv4(
+    id: &str,
+    secret: &str,
+    region: &str,
+    date: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, String)],
+) -> String {
+    use hmac::{Hmac, Mac};
+    let hmac = |key: &[u8], data: &str| {
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    };
+
+    let signed: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+    let signed = signed.join(";");
+    let mut canonical = format!("{}\n{}\n\n", method, path);
+    for (name, value) in headers {
+        canonical.push_str(&format!("{}:{}\n", name, value.trim()));
+    }
+    let payload = headers
+        .iter()
+        .find(|(name, _)| *name == "x-amz-content-sha256")
+        .map_or("UNSIGNED-PAYLOAD", |(_, v)| v.as_str());
+    canonical.push_str(&format!("\n{}\n{}", signed, payload));
+
+    let scope = format!("{}/{}/s3/aws4_request", &date[..8], region);
+    let to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        date,
+        scope,
+        sha256_hex(canonical.as_bytes())
+    );
+    let key = hmac(format!("AWS4{}", secret).as_bytes(), &date[..8]);
+    let key = hmac(&key, region);
+    let key = hmac(&key, "s3");
+    let key = hmac(&key, "aws4_request");
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        id,
+        scope,
+        signed,
+        hex(&hmac(&key, &to_sign))
+    )
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    use sha2::Digest;
+    hex(&sha2::Sha256::digest(data))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Percent-encode (part of) an S3 object path, as SigV4 wants it.
+fn uri_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Format a UNIX timestamp the way SigV4 wants it, i.e.
+// 20130524T000000Z.
+fn amz_date(secs: u64) -> String {
+    // civil_from_days(), from Howard Hinnant's date algorithms.
+    let days = (secs / 86400) as i64 + 719468;
+    let (era, doe) = (days.div_euclid(146097), days.rem_euclid(146097));
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let t = secs % 86400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        t / 3600,
+        t / 60 % 60,
+        t % 60
+    )
+}
+
+// Decode a string of hex digits.
+#[cfg(feature = "crypto")]
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Render a payload for a table cell, on a single line.
+fn text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+// Mask the (dotted) `fields` of a JSON payload, returning the
+// new payload and the fields that were actually masked.  With
+// no fields, the whole payload is masked; payloads that aren't
+// JSON, or don't have any of the fields, are left alone.
+fn mask(data: &[u8], fields: &[&str]) -> (Vec<u8>, Vec<String>) {
+    const MASK: &str = "[REDACTED]";
+    if fields.is_empty() {
+        return (
+            MASK.as_bytes().to_vec(),
+            vec!["the whole payload".to_string()],
+        );
+    }
+    let mut doc: Value = match serde_json::from_slice(data) {
+        Ok(doc) => doc,
+        Err(_) => return (data.to_vec(), vec![]),
+    };
+
+    let mut masked = vec![];
+    for f in fields {
+        if let Some(v) = doc.pointer_mut(&format!("/{}", f.replace('.', "/"))) {
+            *v = json!(MASK);
+            masked.push(f.to_string());
+        }
+    }
+    match serde_json::to_vec(&doc) {
+        Ok(data) if !masked.is_empty() => (data, masked),
+        _ => (data.to_vec(), vec![]),
+    }
+}
+
+// Render an event as pretty-printed JSON, with JSON payloads
+// pretty-printed along with it (and without subjects, unless
+// they've been looked up).
+fn pretty(e: &audis::Event) -> Result<String, serde_json::Error> {
+    let mut v = serde_json::to_value(e)?;
+    if let Ok(data) = serde_json::from_slice::<Value>(&e.data) {
+        v["data"] = data;
+    }
+    if let (true, Some(obj)) = (e.subjects.is_empty(), v.as_object_mut()) {
+        obj.remove("subjects");
+    }
+    serde_json::to_string_pretty(&v)
+}
+
+// Parse an event out of a single line of NDJSON, i.e.
+//
+//   {"id":"ae2","subjects":["user:42"],"data":"..."}
+//
+// The ID is optional (one is generated if missing), as are
+// the subjects (`subjects` is used if missing).  Payloads that
+// aren't strings are stored as JSON.  Metadata, correlation
+// IDs and parent IDs can be given as well.
+fn parse_event(line: &str, subjects: &[&str]) -> Result<audis::Event, Box<dyn std::error::Error>> {
+    let mut v: Value = serde_json::from_str(line)?;
+    let obj = v.as_object_mut().ok_or("not a JSON object")?;
+
+    let mut e = audis::Event::builder();
+    if let Some(id) = obj.remove("id") {
+        e = e.id(id.as_str().ok_or("id is not a string")?);
+    }
+    e = match obj.remove("data") {
+        Some(Value::String(data)) => e.data(data),
+        Some(data) => e.data(data.to_string()),
+        None => return Err("event has no data".into()),
+    };
+    match obj.remove("subjects") {
+        Some(Value::Array(list)) => {
+            for s in list {
+                e = e.subject(s.as_str().ok_or("subjects are not all strings")?);
+            }
+        }
+        Some(_) => return Err("subjects is not a list".into()),
+        None => e = e.subjects(subjects.iter().copied()),
+    }
+    if let Some(meta) = obj.remove("meta") {
+        for (k, v) in meta.as_object().ok_or("meta is not an object")? {
+            e = match v {
+                Value::String(v) => e.meta(k.as_str(), v.as_str()),
+                v => e.meta(k.as_str(), v.to_string()),
+            };
+        }
+    }
+    if let Some(cid) = obj.remove("correlation_id") {
+        e = e.correlation_id(cid.as_str().ok_or("correlation_id is not a string")?);
+    }
+    if let Some(parent) = obj.remove("parent_id") {
+        e = e.parent_id(parent.as_str().ok_or("parent_id is not a string")?);
+    }
+
+    let e = e.build()?;
+    if e.subjects.is_empty() {
+        return Err("event has no subjects (and no --subject was given)".into());
+    }
+    Ok(e)
+}
+
+// Parse a point in time, given either as a number of seconds
+// since the UNIX epoch, a span of time ago (i.e. 30s, 15m, 2h
+// or 7d), or an ISO-8601 / RFC 3339 date (and optional time)
+// in UTC (i.e. 2024-01-31 or 2024-01-31T13:45:00Z), into
+// milliseconds since the epoch.
+fn parse_time(t: &str) -> Result<u64, String> {
+    let bad = || format!("unrecognized time '{}'", t);
+
+    if let Ok(secs) = t.parse::<u64>() {
+        return Ok(secs * 1000);
+    }
+
+    let unit = match t.chars().last() {
+        Some('s') => Some(1),
+        Some('m') => Some(60),
+        Some('h') => Some(3600),
+        Some('d') => Some(86400),
+        _ => None,
+    };
+    if let (Some(unit), Ok(n)) = (unit, t[..t.len() - 1].parse::<u64>()) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| bad())?
+            .as_millis() as u64;
+        return Ok(now.saturating_sub(n * unit * 1000));
+    }
+
+    let t = t.trim_end_matches('Z');
+    let (date, time) = match t.split_once('T').or_else(|| t.split_once(' ')) {
+        Some((date, time)) => (date, time),
+        None => (t, "00:00:00"),
+    };
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|n| n.parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    let time: Vec<i64> = time
+        .split(':')
+        .map(|n| n.parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    if date.len() != 3 || time.len() < 2 || time.len() > 3 {
+        return Err(bad());
+    }
+
+    // days since the epoch, from the proleptic Gregorian
+    // calendar, as per Howard Hinnant's days_from_civil().
+    let (y, m, d) = (date[0] - (date[1] <= 2) as i64, date[1], date[2]);
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + time[0] * 3600 + time[1] * 60 + time.get(2).unwrap_or(&0);
+    if secs < 0 {
+        return Err(bad());
+    }
+    Ok(secs as u64 * 1000)
+}
+
+// Parse a (positive) interval, given either in seconds, or with
+// a unit of ms, s, m, h or d (i.e. 500ms or 2s).
+fn parse_duration(t: &str) -> Result<Duration, String> {
+    let bad = || format!("unrecognized interval '{}'", t);
+
+    let (n, unit) = match t.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => t.split_at(i),
+        None => (t, "s"),
+    };
+    let n: u64 = n.parse().map_err(|_| bad())?;
+    let d = match unit {
+        "ms" => Duration::from_millis(n),
+        "s" => Duration::from_secs(n),
+        "m" => Duration::from_secs(n * 60),
+        "h" => Duration::from_secs(n * 3600),
+        "d" => Duration::from_secs(n * 86400),
+        _ => return Err(bad()),
+    };
+    if d.is_zero() {
+        return Err(bad());
+    }
+    Ok(d)
+}
+
+// The commands understood by `audis shell`, and their usage.
+const SHELL_COMMANDS: [(&str, &str, &str); 8] = [
+    (
+        "subjects",
+        "[PATTERN]",
+        "list known subjects (matching a glob)",
+    ),
+    ("cd", "[SUBJECT]", "switch to another subject"),
+    (
+        "ls",
+        "[-n N] [SUBJECT]",
+        "list the (last N) events of a subject",
+    ),
+    (
+        "grep",
+        "TEXT",
+        "list the events of the current subject that contain TEXT",
+    ),
+    ("show", "ID", "print out a single event, in full"),
+    ("count", "[SUBJECT]", "count the events of a subject"),
+    ("help", "", "print out this help"),
+    ("exit", "", "leave the shell"),
+];
+
+// Completion for `audis shell`, of command names, and of the
+// names of known subjects.
+struct Shell {
+    subjects: Vec<String>,
+}
+
+impl Completer for Shell {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let word = &line[start..];
+        let candidates: Vec<String> = if start == 0 {
+            SHELL_COMMANDS
+                .iter()
+                .map(|(cmd, _, _)| cmd.to_string())
+                .filter(|cmd| cmd.starts_with(word))
+                .collect()
+        } else {
+            self.subjects
+                .iter()
+                .filter(|s| s.starts_with(word))
+                .cloned()
+                .collect()
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Shell {
+    type Hint = String;
+}
+impl Highlighter for Shell {}
+impl Validator for Shell {}
+impl Helper for Shell {}
+
+// Run an interactive shell, until the user exits it.  History
+// is kept in ~/.audis_history.
+fn shell(c: &audis::Client) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rl: Editor<Shell, DefaultHistory> = Editor::new()?;
+    rl.set_helper(Some(Shell {
+        subjects: c.subjects()?,
+    }));
+    let history = env::var("HOME")
+        .ok()
+        .map(|home| format!("{}/.audis_history", home));
+    if let Some(history) = &history {
+        // there won't be any history the first time around.
+        let _ = rl.load_history(history);
+    }
+
+    let mut current: Option<String> = None;
+    loop {
+        let prompt = match &current {
+            Some(s) => format!("audis {}> ", s),
+            None => "audis> ".to_string(),
+        };
+        let line = match rl.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        rl.add_history_entry(line)?;
+
+        let known = &mut rl.helper_mut().unwrap().subjects;
+        match shell_command(c, &mut current, known, line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+
+    if let Some(history) = &history {
+        rl.save_history(history)?;
+    }
+    Ok(())
+}
+
+// Run a single `audis shell` command line, returning false if
+// it's time to leave the shell.
+fn shell_command(
+    c: &audis::Client,
+    current: &mut Option<String>,
+    known: &mut Vec<String>,
+    line: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let (cmd, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+    let mut words: Vec<&str> = rest.split_whitespace().collect();
+    let subject = |words: &[&str]| match (words.first(), current.as_ref()) {
+        (Some(s), _) => Ok(s.to_string()),
+        (None, Some(s)) => Ok(s.to_string()),
+        (None, None) => Err("no subject given; try `cd SUBJECT` first"),
+    };
+
+    match cmd {
+        "subjects" => {
+            let subjects = match words.first() {
+                Some(pattern) => c.subjects_matching(pattern)?,
+                None => {
+                    *known = c.subjects()?;
+                    known.clone()
+                }
+            };
+            for s in subjects {
+                println!("{}", s);
+            }
+        }
+        "cd" => {
+            *current = words.first().map(|s| s.to_string());
+            if let Some(s) = current {
+                if !known.contains(s) {
+                    eprintln!("(note: {} has no events yet)", s);
+                }
+            }
+        }
+        "ls" | "grep" => {
+            let (s, n, grep) = if cmd == "grep" {
+                if rest.is_empty() {
+                    return Err("usage: grep TEXT".into());
+                }
+                (subject(&[])?, None, Some(rest))
+            } else {
+                let mut n = None;
+                if words.first() == Some(&"-n") {
+                    n = Some(
+                        words
+                            .get(1)
+                            .ok_or("usage: ls [-n N] [SUBJECT]")?
+                            .parse::<i64>()?,
+                    );
+                    words.drain(..2);
+                }
+                (subject(&words)?, n, None)
+            };
+            let events = match n {
+                Some(n) => c.retrieve_range(&s, -n, -1)?,
+                None => c.retrieve(&s)?,
+            };
+
+            let mut out = Output::new("table", &["ID", "DATA"]);
+            for e in events {
+                let data = text(&e.data);
+                if grep.is_none_or(|g| data.contains(g)) {
+                    out.row(Value::Null, vec![e.id.to_string(), data]);
+                }
+            }
+            out.finish();
+        }
+        "show" => {
+            let id = words.first().ok_or("usage: show ID")?;
+            let mut e = c
+                .retrieve_event(id)?
+                .ok_or(audis::AudisError::NotFound(id.to_string()))?;
+            e.subjects = c.subjects_of(id)?;
+            println!("{}", pretty(&e)?);
+        }
+        "count" => println!("{}", c.count(&subject(&words)?)?),
+        "help" | "?" => {
+            for (cmd, usage, about) in &SHELL_COMMANDS {
+                println!("  {:24} {}", format!("{} {}", cmd, usage), about);
+            }
+        }
+        "exit" | "quit" => return Ok(false),
+        _ => return Err(format!("unrecognized command '{}'; try `help`", cmd).into()),
+    }
+    Ok(true)
+}
+
+// Serve up the HTTP API, until interrupted:
+//
+//   POST /events                  log an event, given as a JSON
+//                                 object (like `audis load`)
+//   GET  /subjects                list (matching) subjects
+//        ?pattern=user:*
+//   GET  /subjects/{s}/events     retrieve the events of a
+//        ?offset=0&limit=100      subject, a page at a time
+//   GET  /subjects/{s}/stream     stream new events logged to a
+//        ?poll=1000               subject, as server-sent events,
+//                                 checking every `poll` ms
+//
+// Errors come back as {"error": "..."}, with a fitting status.
+// With $AUDIS_API_TOKEN set, every request has to carry it, as
+// `Authorization: Bearer $AUDIS_API_TOKEN`; without it, the API
+// only listens on loopback addresses.  With --scope, only the
+// subjects starting with one of the given prefixes can be read
+// (or have events logged against them).
+fn serve(api: Api, listen: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let api = Arc::new(api);
+    let app = axum::Router::new()
+        .route("/events", post(post_event))
+        .route("/subjects", get(get_subjects))
+        .route("/subjects/:subject/events", get(get_events))
+        .route("/subjects/:subject/stream", get(get_stream))
+        .layer(axum::middleware::from_fn_with_state(
+            api.clone(),
+            authenticate,
+        ))
+        .with_state(api.clone());
+
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let listener = tokio::net::TcpListener::bind(listen).await?;
+        let addr = listener.local_addr()?;
+        if api.token.is_none() && !addr.ip().is_loopback() {
+            return Err(
+                format!("refusing to serve on {} without $AUDIS_API_TOKEN set", addr).into(),
+            );
+        }
+        eprintln!("audis: listening on http://{}", addr);
+        axum::serve(listener, app).await?;
+        Ok::<(), Box<dyn std::error::Error>>(())
+    })?;
+    Ok(())
+}
+
+// What the HTTP API serves, and to whom.
+struct Api {
+    client: Arc<audis::Client>,
+    token: Option<String>,
+    scope: Vec<String>,
+    streams: Arc<AtomicUsize>,
+    max_streams: usize,
+}
+
+impl Api {
+    fn in_scope(&self, subject: &str) -> bool {
+        in_scope(&self.scope, subject)
+    }
+}
+
+fn in_scope(scope: &[String], subject: &str) -> bool {
+    scope.is_empty() || scope.iter().any(|p| subject.starts_with(p.as_str()))
+}
+
+// Restrict a client to reading the subjects in scope (if any).
+fn scoped(c: audis::Client, scope: &[String]) -> audis::Client {
+    if scope.is_empty() {
+        return c;
+    }
+    let scope = scope.to_vec();
+    c.authorize(move |op, s| *op == audis::Operation::Retrieve && in_scope(&scope, s))
+}
+
+// Turn away requests that don't carry the API token (if any),
+// comparing it in constant time.
+async fn authenticate(
+    State(api): State<Arc<Api>>,
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Result<Response, ApiError> {
+    if let Some(token) = &api.token {
+        let given = req
+            .headers()
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .unwrap_or("");
+        let differs = given.len() != token.len()
+            || given
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |d, (a, b)| d | (a ^ b))
+                != 0;
+        if differs {
+            return Err(ApiError(
+                StatusCode::UNAUTHORIZED,
+                "missing or incorrect API token".to_string(),
+            ));
+        }
+    }
+    Ok(next.run(req).await)
+}
+
+// A stream's hold on one of the API's streaming slots, which it
+// gives back when it ends.
+struct Streaming(Arc<AtomicUsize>);
+
+impl Streaming {
+    fn take(api: &Api) -> Option<Streaming> {
+        api.streams
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < api.max_streams).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Streaming(api.streams.clone()))
+    }
+}
+
+impl Drop for Streaming {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// An error, as returned by the HTTP API.
+struct ApiError(StatusCode, String);
+
+impl From<audis::AudisError> for ApiError {
+    fn from(e: audis::AudisError) -> ApiError {
+        let status = match &e {
+            audis::AudisError::Duplicate(_) => StatusCode::CONFLICT,
+            audis::AudisError::NotFound(_) => StatusCode::NOT_FOUND,
+            audis::AudisError::Invalid(_) | audis::AudisError::Codec(_) => StatusCode::BAD_REQUEST,
+            audis::AudisError::Forbidden(_) => StatusCode::FORBIDDEN,
+            audis::AudisError::Connection(_) | audis::AudisError::Locked(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            audis::AudisError::Cancelled(_) => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+// Run a (blocking) library call off of the async runtime.
+async fn blocking<T, F>(c: Arc<audis::Client>, f: F) -> Result<T, ApiError>
+where
+    F: FnOnce(&audis::Client) -> Result<T, ApiError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&c))
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+}
+
+async fn post_event(
+    State(api): State<Arc<Api>>,
+    body: String,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let e =
+        parse_event(&body, &[]).map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    if let Some(s) = e.subjects.iter().find(|s| !api.in_scope(s)) {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            format!("subject {} is out of scope", s),
+        ));
+    }
+    blocking(api.client.clone(), move |c| {
+        c.log(&e)?;
+        Ok((StatusCode::CREATED, Json(json!({ "id": e.id }))))
+    })
+    .await
+}
+
+#[derive(serde::Deserialize)]
+struct SubjectsQuery {
+    pattern: Option<String>,
+}
+
+async fn get_subjects(
+    State(api): State<Arc<Api>>,
+    Query(q): Query<SubjectsQuery>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    blocking(api.client.clone(), move |c| {
+        Ok(Json(match q.pattern {
+            Some(pattern) => c.subjects_matching(&pattern)?,
+            None => c.subjects()?,
+        }))
+    })
+    .await
+}
+
+#[derive(serde::Deserialize)]
+struct EventsQuery {
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+async fn get_events(
+    State(api): State<Arc<Api>>,
+    Param(s): Param<String>,
+    Query(q): Query<EventsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let offset = q.offset.unwrap_or(0);
+    let limit = q.limit.unwrap_or(100).clamp(1, 1000);
+    blocking(api.client.clone(), move |c| {
+        let total = c.count(&s)?;
+        let start = offset as i64;
+        let events = c
+            .retrieve_range(&s, start, start + limit as i64 - 1)?
+            .iter()
+            .map(|e| event(&s, e))
+            .collect::<Result<Vec<Value>, _>>()
+            .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let next = (offset + limit < total).then_some(offset + limit);
+        Ok(Json(json!({
+            "subject": s,
+            "total": total,
+            "offset": offset,
+            "limit": limit,
+            "next": next,
+            "events": events,
+        })))
+    })
+    .await
+}
+
+#[derive(serde::Deserialize)]
+struct StreamQuery {
+    poll: Option<u64>,
+}
+
+async fn get_stream(
+    State(api): State<Arc<Api>>,
+    Param(s): Param<String>,
+    Query(q): Query<StreamQuery>,
+) -> Result<Sse<ReceiverStream<Result<SseEvent, Infallible>>>, ApiError> {
+    let c = api.client.clone();
+    let poll = Duration::from_millis(q.poll.unwrap_or(1000).max(100));
+    // find out about bad subjects (or policies) up front, while
+    // we can still respond with an error.
+    let subject = s.to_string();
+    blocking(c.clone(), move |c| {
+        Ok(c.follow(&subject, poll).map(|_| ())?)
+    })
+    .await?;
+
+    // each stream gets its own thread, which notices that the
+    // client has gone away within a poll or so, and then exits;
+    // only so many of them can run at once.
+    let slot = Streaming::take(&api).ok_or_else(|| {
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("already streaming {} subjects", api.max_streams),
+        )
+    })?;
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    std::thread::spawn(move || {
+        let _slot = slot;
+        let mut follow = match c.follow(&s, poll) {
+            Ok(follow) => follow,
+            Err(_) => return,
+        };
+        while !tx.is_closed() {
+            let sent = match follow.try_next() {
+                Some(Ok(e)) => match event(&s, &e) {
+                    Ok(v) => tx.blocking_send(Ok(SseEvent::default()
+                        .id(&e.id)
+                        .event("event")
+                        .data(v.to_string()))),
+                    Err(_) => Ok(()),
+                },
+                Some(Err(e)) => {
+                    let sent = tx
+                        .blocking_send(Ok(SseEvent::default().event("error").data(e.to_string())));
+                    std::thread::sleep(poll);
+                    sent
+                }
+                None => {
+                    std::thread::sleep(poll);
+                    Ok(())
+                }
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+    });
+    Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
+// Which pane of `audis browse` has the focus.
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Subjects,
+    Events,
+}
+
+// The state of `audis browse`: every subject, the events of the
+// selected one (and which of those match the search), and what
+// the user is doing with them.
+struct Browser<'a> {
+    client: &'a audis::Client,
+    subjects: Vec<String>,
+    subject: ListState,
+    events: Vec<audis::Event>,
+    shown: Vec<usize>,
+    event: ListState,
+    focus: Pane,
+    search: String,
+    searching: bool,
+    scroll: u16,
+    status: String,
+}
+
+impl Browser<'_> {
+    // (Re-)load the events of the selected subject.
+    fn load(&mut self) {
+        self.events.clear();
+        if let Some(s) = self.subject.selected().and_then(|i| self.subjects.get(i)) {
+            match self.client.retrieve(s) {
+                Ok(events) => {
+                    self.status = format!("{}: {} event(s)", s, events.len());
+                    self.events = events;
+                }
+                Err(e) => self.status = format!("error: {}", e),
+            }
+        }
+        self.filter();
+    }
+
+    // Work out which events match the search (if any).
+    fn filter(&mut self) {
+        let search = self.search.as_bytes();
+        self.shown = (0..self.events.len())
+            .filter(|&i| {
+                search.is_empty()
+                    || self.events[i]
+                        .data
+                        .windows(search.len())
+                        .any(|w| w == search)
+            })
+            .collect();
+        self.event
+            .select(if self.shown.is_empty() { None } else { Some(0) });
+        self.scroll = 0;
+    }
+
+    fn selected(&self) -> Option<&audis::Event> {
+        self.event
+            .selected()
+            .and_then(|i| self.shown.get(i))
+            .map(|&i| &self.events[i])
+    }
+
+    // Move the selection of the focused pane up or down.
+    fn step(&mut self, by: i64) {
+        let (state, len) = match self.focus {
+            Pane::Subjects => (&mut self.subject, self.subjects.len()),
+            Pane::Events => (&mut self.event, self.shown.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let was = state.selected();
+        let i = match was {
+            Some(i) => (i as i64 + by).clamp(0, len as i64 - 1) as usize,
+            None => 0,
+        };
+        state.select(Some(i));
+        if was != Some(i) {
+            match self.focus {
+                Pane::Subjects => self.load(),
+                Pane::Events => self.scroll = 0,
+            }
+        }
+    }
+
+    // Handle a key press, returning false if it's time to quit.
+    fn key(&mut self, key: KeyEvent) -> bool {
+        if self.searching {
+            match key.code {
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.search.clear();
+                    self.filter();
+                }
+                KeyCode::Backspace => {
+                    self.search.pop();
+                    self.filter();
+                }
+                KeyCode::Char(ch) => {
+                    self.search.push(ch);
+                    self.filter();
+                }
+                _ => (),
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Left | KeyCode::Right => {
+                self.focus = match self.focus {
+                    Pane::Subjects => Pane::Events,
+                    Pane::Events => Pane::Subjects,
+                }
+            }
+            KeyCode::Enter => self.focus = Pane::Events,
+            KeyCode::Char('/') => {
+                self.searching = true;
+                self.focus = Pane::Events;
+            }
+            KeyCode::Char('r') => match self.client.subjects() {
+                Ok(subjects) => {
+                    self.subjects = subjects;
+                    let n = self.subjects.len();
+                    self.subject.select(match self.subject.selected() {
+                        _ if n == 0 => None,
+                        Some(i) => Some(i.min(n - 1)),
+                        None => Some(0),
+                    });
+                    self.load();
+                }
+                Err(e) => self.status = format!("error: {}", e),
+            },
+            KeyCode::Down | KeyCode::Char('j') => self.step(1),
+            KeyCode::Up | KeyCode::Char('k') => self.step(-1),
+            KeyCode::Home | KeyCode::Char('g') => self.step(i64::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.step(i64::MAX / 2),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            _ => (),
+        }
+        true
+    }
+
+    fn draw(&mut self, f: &mut Frame) {
+        let [main, bar] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(f.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+        let [top, bottom] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+        let highlight = |pane| {
+            if self.focus == pane {
+                Style::new().reversed()
+            } else {
+                Style::new().bold()
+            }
+        };
+
+        let subjects = List::new(self.subjects.iter().map(String::as_str))
+            .block(Block::bordered().title(" Subjects "))
+            .highlight_style(highlight(Pane::Subjects));
+        let title = if self.search.is_empty() {
+            " Events ".to_string()
+        } else {
+            format!(" Events containing '{}' ", self.search)
+        };
+        let events = List::new(self.shown.iter().map(|&i| {
+            let e = &self.events[i];
+            format!("{}  {}", e.id, text(&e.data))
+        }))
+        .block(Block::bordered().title(title))
+        .highlight_style(highlight(Pane::Events));
+        let detail = match self.selected().map(pretty) {
+            Some(Ok(detail)) => detail,
+            Some(Err(e)) => format!("error: {}", e),
+            None => String::new(),
+        };
+        let detail = Paragraph::new(detail)
+            .block(Block::bordered().title(" Event "))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        let status = if self.searching {
+            format!("/{}", self.search)
+        } else {
+            format!(
+                "{}  (tab: switch panes, /: search, pgup/pgdn: scroll event, r: reload, q: quit)",
+                self.status
+            )
+        };
+
+        f.render_stateful_widget(subjects, left, &mut self.subject);
+        f.render_stateful_widget(events, top, &mut self.event);
+        f.render_widget(detail, bottom);
+        f.render_widget(Paragraph::new(status), bar);
+    }
+}
+
+// Run the full-screen browser, until the user quits it.
+fn browse(c: &audis::Client) -> Result<(), Box<dyn std::error::Error>> {
+    let mut b = Browser {
+        client: c,
+        subjects: c.subjects()?,
+        subject: ListState::default(),
+        events: vec![],
+        shown: vec![],
+        event: ListState::default(),
+        focus: Pane::Subjects,
+        search: String::new(),
+        searching: false,
+        scroll: 0,
+        status: "no subjects".to_string(),
+    };
+    if !b.subjects.is_empty() {
+        b.subject.select(Some(0));
+    }
+    b.load();
+
+    let mut terminal = ratatui::try_init()?;
+    let mut run = || -> io::Result<()> {
+        loop {
+            terminal.draw(|f| b.draw(f))?;
+            if let term::Event::Key(key) = term::read()? {
+                if key.kind == KeyEventKind::Press && !b.key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    };
+    let r = run();
+    ratatui::try_restore()?;
+    Ok(r?)
+}
+
+// Print a summary of the round trips a subcommand needed.
+fn diagnose(op: &str, took: Duration, trips: &[RoundTrip]) {
+    let waiting: Duration = trips.iter().map(|t| t.elapsed).sum();
+    let commands: usize = trips.iter().map(|t| t.commands.len()).sum();
+    eprintln!(
+        "audis: {} took {:?}, over {} round trip(s) ({} command(s), {:?} waiting on the backend)",
+        op,
+        took,
+        trips.len(),
+        commands,
+        waiting
+    );
+
+    let mut keys: Vec<String> = trips.iter().flat_map(|t| t.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    eprintln!("audis: touched {} key(s):", keys.len());
+    for k in keys {
+        eprintln!("audis:   {}", k);
+    }
+}
+
+// Print out the raw commands of a single round trip, as it
+// happens.
+fn dump(trip: &RoundTrip) {
+    for cmd in &trip.commands {
+        let args: Vec<String> = cmd
+            .iter()
+            .map(|a| {
+                let a = String::from_utf8_lossy(a);
+                if a.chars().count() > 64 {
+                    format!("{:?}...", a.chars().take(64).collect::<String>())
+                } else {
+                    format!("{:?}", a)
+                }
+            })
+            .collect();
+        eprintln!("audis: > {}", args.join(" "));
+    }
+    eprintln!("audis: < {:?}", trip.elapsed);
+}
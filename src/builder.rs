@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+
+use crate::ids::IdGenerator;
+use crate::{AudisResult, Event};
+
+/// A step-by-step constructor for `Event` objects.
+///
+/// ```rust
+/// extern crate audis;
+///
+/// fn main() {
+///     let e = audis::Event::builder()
+///         .id("foo1")
+///         .data("{\"some\":\"data\"}")
+///         .subject("system")
+///         .subject("user:42")
+///         .build()
+///         .unwrap();
+///
+///     assert_eq!(e.subjects.len(), 2);
+/// }
+/// ```
+///
+/// With the `id-gen` feature enabled, the call to `id()` can
+/// be omitted, and a ULID will be generated for the event.
+/// ULIDs sort lexically in the order they were generated; use
+/// `id_from()` to generate some other kind of ID (see the `ids`
+/// module).
+#[derive(Clone, Debug, Default)]
+pub struct EventBuilder {
+    id: Option<String>,
+    data: Vec<u8>,
+    subjects: Vec<String>,
+    meta: BTreeMap<String, String>,
+    correlation_id: Option<String>,
+    parent_id: Option<String>,
+}
+
+impl Event {
+    /// Start building a new Event.
+    pub fn builder() -> EventBuilder {
+        EventBuilder::default()
+    }
+}
+
+impl EventBuilder {
+    /// Set the (globally unique) ID of the event.
+    pub fn id<S: Into<String>>(mut self, id: S) -> EventBuilder {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set the ID of the event to one newly generated by `ids`.
+    pub fn id_from(mut self, ids: &dyn IdGenerator) -> EventBuilder {
+        self.id = Some(ids.generate());
+        self
+    }
+
+    /// Set the event payload.
+    pub fn data<D: Into<Vec<u8>>>(mut self, data: D) -> EventBuilder {
+        self.data = data.into();
+        self
+    }
+
+    /// Index the event against another subject.
+    pub fn subject<S: Into<String>>(mut self, subject: S) -> EventBuilder {
+        self.subjects.push(subject.into());
+        self
+    }
+
+    /// Index the event against several more subjects.
+    pub fn subjects<I, S>(mut self, subjects: I) -> EventBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.subjects.extend(subjects.into_iter().map(Into::into));
+        self
+    }
+
+    /// Attach a metadata attribute to the event.
+    pub fn meta<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> EventBuilder {
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+
+    /// Tie the event to a business transaction, for later
+    /// retrieval via `Client::retrieve_trail()`.
+    pub fn correlation_id<S: Into<String>>(mut self, id: S) -> EventBuilder {
+        self.correlation_id = Some(id.into());
+        self
+    }
+
+    /// Record the ID of the event that caused this one.
+    pub fn parent_id<S: Into<String>>(mut self, id: S) -> EventBuilder {
+        self.parent_id = Some(id.into());
+        self
+    }
+
+    /// Finish building the event.
+    ///
+    /// If no ID was given, and the `id-gen` feature is not
+    /// enabled, this fails with `AudisError::Invalid`.
+    pub fn build(self) -> AudisResult<Event> {
+        Ok(Event {
+            id: match self.id {
+                Some(id) => id,
+                None => generate()?,
+            },
+            data: self.data,
+            subjects: self.subjects,
+            meta: self.meta,
+            correlation_id: self.correlation_id,
+            parent_id: self.parent_id,
+        })
+    }
+}
+
+#[cfg(feature = "id-gen")]
+fn generate() -> AudisResult<String> {
+    Ok(crate::ids::generate())
+}
+
+#[cfg(not(feature = "id-gen"))]
+fn generate() -> AudisResult<String> {
+    Err(crate::AudisError::Invalid(
+        "event has no ID (and the id-gen feature is disabled)".to_string(),
+    ))
+}
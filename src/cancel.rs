@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{AudisError, AudisResult, Client};
+
+/// A signal to abandon long-running operations (i.e. retrieving
+/// huge subjects, exports and `fsck()`), either on request, or
+/// once a deadline passes; see `Client::cancellable()`.
+///
+/// Tokens are cheap to clone, and every clone is cancelled
+/// along with the original, so one can be handed to another
+/// thread (or a request handler's timeout) to cancel with.
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// use audis::CancellationToken;
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+///     let token = CancellationToken::with_timeout(Duration::from_secs(5));
+///     match client.cancellable(&token).retrieve("user:42") {
+///         Ok(events) => println!("{} event(s)", events.len()),
+///         Err(audis::AudisError::Cancelled(why)) => eprintln!("gave up: {}", why),
+///         Err(e) => eprintln!("failed: {}", e),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Create a token that is only cancelled via `cancel()`.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Create a token that cancels itself at `deadline`.
+    pub fn with_deadline(deadline: Instant) -> CancellationToken {
+        CancellationToken {
+            deadline: Some(deadline),
+            ..Default::default()
+        }
+    }
+
+    /// Create a token that cancels itself once `timeout` has
+    /// passed, from now.
+    pub fn with_timeout(timeout: Duration) -> CancellationToken {
+        CancellationToken::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Cancel whatever operations are using this token (or any
+    /// of its clones).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the token has been cancelled, or its
+    /// deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.check().is_err()
+    }
+
+    fn check(&self) -> AudisResult<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(AudisError::Cancelled("cancelled".to_string()));
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                Err(AudisError::Cancelled("deadline exceeded".to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Client {
+    /// Get a copy of this client whose long-running operations
+    /// (retrieving subjects, exports, `retrieve_many()`,
+    /// `get_events()`, `stats()`, `fsck()` and `gc()`) give up
+    /// with `AudisError::Cancelled` as soon as `token` is
+    /// cancelled, or its deadline passes.
+    ///
+    /// Operations check the token between backend commands, as
+    /// they read, so cancelling one never leaves the audit log
+    /// half-modified: logging, pruning, and the repairs made by
+    /// `fsck()` (once it has finished checking) run to
+    /// completion regardless.
+    pub fn cancellable(&self, token: &CancellationToken) -> Client {
+        let mut c = self.clone();
+        c.cancel = Some(token.clone());
+        c
+    }
+
+    // Fail with `AudisError::Cancelled` if the client's
+    // cancellation token (if any) has been cancelled.
+    pub(crate) fn cancelled(&self) -> AudisResult<()> {
+        match &self.cancel {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{AudisError, AudisResult, Client};
+
+/// A record of the head of every subject's hash chain, at a
+/// single point in time.
+///
+/// Checkpoints are meant to be exported out of the backend
+/// (to object storage, a ticketing system, a printout in a
+/// safe, etc.), so that the state of the audit log can be
+/// attested to later, via `Client::attest()`, even if the
+/// backend itself is compromised.  Their `Display` format is
+/// plain text, and can be read back in with `parse()`.
+///
+/// With the `crypto` feature, and a signing key configured,
+/// checkpoints are signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// When the checkpoint was taken, in seconds since the
+    /// UNIX epoch.
+    pub taken: u64,
+
+    /// The latest chain hash of each (chained) subject.
+    pub heads: BTreeMap<String, String>,
+
+    /// The hex-encoded ed25519 signature of the checkpoint,
+    /// if it was signed.
+    pub signature: Option<String>,
+}
+
+impl Checkpoint {
+    // The part of the checkpoint covered by its signature.
+    fn body(&self) -> String {
+        let mut s = format!("audis-checkpoint v1\ntaken {}\n", self.taken);
+        for (subject, hash) in &self.heads {
+            s.push_str(&format!("head {} {}\n", hash, subject));
+        }
+        s
+    }
+
+    /// Check the checkpoint's signature against `key`.
+    #[cfg(feature = "crypto")]
+    pub fn verify(&self, key: &crate::crypto::VerifyingKey) -> bool {
+        match &self.signature {
+            Some(sig) => crate::crypto::verify(key, self.body().as_bytes(), sig),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.body())?;
+        if let Some(sig) = &self.signature {
+            writeln!(f, "sig {}", sig)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = AudisError;
+
+    fn from_str(s: &str) -> AudisResult<Checkpoint> {
+        let bad = |why: &str| AudisError::Invalid(format!("malformed checkpoint: {}", why));
+
+        let mut lines = s.lines();
+        if lines.next() != Some("audis-checkpoint v1") {
+            return Err(bad("missing header"));
+        }
+        let mut cp = Checkpoint {
+            taken: 0,
+            heads: BTreeMap::new(),
+            signature: None,
+        };
+        for line in lines {
+            match line.split_once(' ') {
+                Some(("taken", t)) => cp.taken = t.parse().map_err(|_| bad("bad timestamp"))?,
+                Some(("head", h)) => {
+                    let (hash, subject) = h.split_once(' ').ok_or_else(|| bad("bad head"))?;
+                    cp.heads.insert(subject.to_string(), hash.to_string());
+                }
+                Some(("sig", sig)) => cp.signature = Some(sig.to_string()),
+                _ if line.is_empty() => (),
+                _ => return Err(bad(line)),
+            }
+        }
+        Ok(cp)
+    }
+}
+
+// Automatic checkpointing state, shared by clones of a Client.
+pub(crate) struct Checkpointer {
+    every: u64,
+    interval: Option<Duration>,
+    logged: AtomicU64,
+    last: Mutex<Instant>,
+    export: Box<Exporter>,
+}
+
+type Exporter = dyn Fn(&Checkpoint) + Send + Sync;
+
+impl Client {
+    /// Take a checkpoint of every subject's hash chain (see
+    /// `chain()`), and record it in the `audis:checkpoints`
+    /// list.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn checkpoint(&self) -> AudisResult<Checkpoint> {
+        self.instrument("checkpoint", || {
+            let mut heads = BTreeMap::new();
+            for key in self.scan("SCAN", None, "audis:chain:*")? {
+                if let Some(hash) = self.get(&key)? {
+                    heads.insert(key["audis:chain:".len()..].to_string(), hash);
+                }
+            }
+
+            let mut cp = Checkpoint {
+                taken: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                heads,
+                signature: None,
+            };
+            cp.signature = self.keys.sign(cp.body().as_bytes());
+
+            self.rpush("audis:checkpoints", &cp.to_string())?;
+            Ok(cp)
+        })
+    }
+
+    /// Retrieve every checkpoint recorded in the backend, from
+    /// oldest to newest.
+    pub fn checkpoints(&self) -> AudisResult<Vec<Checkpoint>> {
+        self.lrange("audis:checkpoints", "0", "-1")?
+            .iter()
+            .map(|cp| cp.parse())
+            .collect()
+    }
+
+    /// Take a checkpoint after every `events` events logged by
+    /// this client (or its clones), or when `interval` has
+    /// passed since the last one, whichever comes first, and
+    /// hand it to `export` for safekeeping.
+    ///
+    /// The interval is only checked as events are logged.  An
+    /// `events` count of zero disables count-based checkpoints.
+    /// Failure to take a checkpoint does not fail the `log()`
+    /// call that triggered it; it is reported via the `log`
+    /// crate, like `background()` failures are.
+    ///
+    pub fn checkpoint_every<F>(
+        mut self,
+        events: u64,
+        interval: Option<Duration>,
+        export: F,
+    ) -> Client
+    where
+        F: Fn(&Checkpoint) + Send + Sync + 'static,
+    {
+        self.checkpointer = Some(Arc::new(Checkpointer {
+            every: events,
+            interval,
+            logged: AtomicU64::new(0),
+            last: Mutex::new(Instant::now()),
+            export: Box::new(export),
+        }));
+        self
+    }
+
+    /// Check that the audit log still agrees with a checkpoint,
+    /// returning the subjects that don't.
+    ///
+    /// A subject agrees with a checkpoint if the hash recorded
+    /// for it can still be found in its chain, and the chain
+    /// verifies.  Subjects whose checkpointed events have all
+    /// since been pruned cannot be attested to, and so will be
+    /// returned as well.
+    ///
+    pub fn attest(&self, cp: &Checkpoint) -> AudisResult<Vec<String>> {
+        let mut failed = vec![];
+        for (subject, head) in &cp.heads {
+            let mut found = false;
+            for id in self.lrange(subject, "0", "-1")? {
+                let link: Option<String> =
+                    self.query(redis::cmd("HGET").arg(idchain!(id)).arg(subject))?;
+                if link.as_deref().and_then(|l| l.split(' ').nth(1)) == Some(head) {
+                    found = true;
+                    break;
+                }
+            }
+            if !found || self.verify(subject)?.is_some() {
+                failed.push(subject.to_string());
+            }
+        }
+        Ok(failed)
+    }
+
+    // Count a logged event, taking a checkpoint if it's time.
+    pub(crate) fn tick(&self) {
+        let cp = match &self.checkpointer {
+            Some(cp) => cp,
+            None => return,
+        };
+
+        let n = cp.logged.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut due = cp.every > 0 && n.is_multiple_of(cp.every);
+        {
+            let mut last = cp.last.lock().unwrap();
+            if let Some(interval) = cp.interval {
+                due |= last.elapsed() >= interval;
+            }
+            if !due {
+                return;
+            }
+            *last = Instant::now();
+        }
+
+        match self.checkpoint() {
+            Ok(checkpoint) => (cp.export)(&checkpoint),
+            Err(err) => log::error!(target: "audis", "failed to take checkpoint: {}", err),
+        }
+    }
+}
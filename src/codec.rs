@@ -0,0 +1,85 @@
+//! Payload codecs for typed audit records.
+//!
+//! A `Codec` turns typed records into event payloads, and
+//! back again.  JSON is always available (with the `typed`
+//! feature); the binary formats are each behind their own
+//! feature, and can substantially cut down the memory used
+//! by high-volume audit streams:
+//!
+//!  - `Json` - human-readable, and the default.
+//!  - `MessagePack` - compact binary, via the `msgpack` feature.
+//!  - `Cbor` - compact binary, via the `cbor` feature.
+//!
+//! Whichever codec logs a record must also be used to read
+//! it back; payloads carry no indication of how they were
+//! encoded.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{AudisError, AudisResult};
+
+/// A serialization format for event payloads.
+pub trait Codec {
+    /// Encode a record into an event payload.
+    fn encode<T: Serialize>(&self, v: &T) -> AudisResult<Vec<u8>>;
+
+    /// Decode an event payload into a record.
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> AudisResult<T>;
+}
+
+fn codec_error<E: std::fmt::Display>(e: E) -> AudisError {
+    AudisError::Codec(e.to_string())
+}
+
+/// JSON payloads.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize>(&self, v: &T) -> AudisResult<Vec<u8>> {
+        serde_json::to_vec(v).map_err(codec_error)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> AudisResult<T> {
+        serde_json::from_slice(data).map_err(codec_error)
+    }
+}
+
+/// MessagePack payloads.
+///
+/// Structs are encoded as maps (with field names), so that
+/// records can gain fields over time without breaking the
+/// decoding of older events.
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    fn encode<T: Serialize>(&self, v: &T) -> AudisResult<Vec<u8>> {
+        rmp_serde::to_vec_named(v).map_err(codec_error)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> AudisResult<T> {
+        rmp_serde::from_slice(data).map_err(codec_error)
+    }
+}
+
+/// CBOR (RFC 8949) payloads.
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn encode<T: Serialize>(&self, v: &T) -> AudisResult<Vec<u8>> {
+        let mut buf = vec![];
+        ciborium::ser::into_writer(v, &mut buf).map_err(codec_error)?;
+        Ok(buf)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> AudisResult<T> {
+        ciborium::de::from_reader(data).map_err(codec_error)
+    }
+}
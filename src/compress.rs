@@ -0,0 +1,114 @@
+//! Transparent compression of large event payloads.
+//!
+//! Compressed payloads are stored behind a small header: the
+//! four bytes `\0AZ\x01`, followed by a single byte naming the
+//! algorithm used.  Payloads without the header are stored (and
+//! returned) verbatim, so audit logs written before compression
+//! was turned on remain readable, and clients without it turned
+//! on can still write to logs that have compressed events.
+//!
+//! The same header marks encrypted payloads (see `audis::crypto`),
+//! which wrap a compressed (or raw) payload of their own.
+
+use crate::{AudisError, AudisResult};
+
+pub(crate) const MAGIC: &[u8] = b"\0AZ\x01";
+
+const RAW: u8 = b'-';
+pub(crate) const ENCRYPTED: u8 = b'e';
+pub(crate) const ENVELOPED: u8 = b's';
+#[cfg(feature = "zstd")]
+const ZSTD: u8 = b'z';
+#[cfg(feature = "gzip")]
+const GZIP: u8 = b'g';
+
+/// A compression algorithm for event payloads.
+///
+/// Each algorithm is only available if the corresponding
+/// feature (`zstd` or `gzip`) is enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Zstandard, at its default level.
+    #[cfg(feature = "zstd")]
+    Zstd,
+
+    /// DEFLATE, in a gzip wrapper, at its default level.
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+// Encode a payload for storage, compressing it with `algo`
+// if it is larger than `threshold` bytes, and if compression
+// actually saves space.
+#[cfg_attr(not(any(feature = "zstd", feature = "gzip")), allow(unused_variables))]
+pub(crate) fn encode(data: &[u8], algo: Option<(Compression, usize)>) -> AudisResult<Vec<u8>> {
+    #[cfg(any(feature = "zstd", feature = "gzip"))]
+    if let Some((algo, threshold)) = algo {
+        if data.len() > threshold {
+            let (tag, packed) = match algo {
+                #[cfg(feature = "zstd")]
+                Compression::Zstd => (ZSTD, zstd::encode_all(data, 0)?),
+                #[cfg(feature = "gzip")]
+                Compression::Gzip => {
+                    use std::io::Write;
+                    let mut gz =
+                        flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                    gz.write_all(data)?;
+                    (GZIP, gz.finish()?)
+                }
+            };
+            if packed.len() + MAGIC.len() + 1 < data.len() {
+                return Ok(framed(tag, &packed));
+            }
+        }
+    }
+
+    // payloads that just happen to start with our header
+    // have to be framed, so that they aren't mistaken for
+    // compressed payloads on the way back out.
+    if data.starts_with(MAGIC) {
+        return Ok(framed(RAW, data));
+    }
+    Ok(data.to_vec())
+}
+
+// Decode a stored payload, decompressing it if need be.
+pub(crate) fn decode(id: &str, data: Vec<u8>) -> AudisResult<Vec<u8>> {
+    if !data.starts_with(MAGIC) || data.len() == MAGIC.len() {
+        return Ok(data);
+    }
+
+    let body = &data[MAGIC.len() + 1..];
+    match data[MAGIC.len()] {
+        RAW => Ok(body.to_vec()),
+        #[cfg(feature = "zstd")]
+        ZSTD => {
+            zstd::decode_all(body).map_err(|e| AudisError::Codec(format!("event {}: {}", id, e)))
+        }
+        #[cfg(feature = "gzip")]
+        GZIP => {
+            use std::io::Read;
+            let mut out = vec![];
+            flate2::read::GzDecoder::new(body)
+                .read_to_end(&mut out)
+                .map_err(|e| AudisError::Codec(format!("event {}: {}", id, e)))?;
+            Ok(out)
+        }
+        ENCRYPTED | ENVELOPED => Err(AudisError::Codec(format!(
+            "event {}: payload is encrypted, and cannot be decrypted",
+            id
+        ))),
+        tag => Err(AudisError::Codec(format!(
+            "event {}: payload compressed with unsupported algorithm '{}'",
+            id, tag as char
+        ))),
+    }
+}
+
+pub(crate) fn framed(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(MAGIC.len() + 1 + body.len());
+    out.extend_from_slice(MAGIC);
+    out.push(tag);
+    out.extend_from_slice(body);
+    out
+}
@@ -0,0 +1,117 @@
+//! Thread-local audit context.
+//!
+//! Values set here are attached to every event logged from
+//! the current thread, without having to be passed down to
+//! each call site.  This is most useful for per-request
+//! correlation, in servers that handle each request on a
+//! single thread:
+//!
+//! ```rust,no_run
+//! extern crate audis;
+//!
+//! fn main() {
+//!     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+//!
+//!     audis::context::set("request_id", "req-7a2f");
+//!     audis::context::subject("tenant:acme");
+//!
+//!     // this event will carry request_id=req-7a2f in its
+//!     // metadata, and be logged against tenant:acme too.
+//!     client.log(&audis::Event {
+//!         id: "e1".to_string(),
+//!         data: "{}".into(),
+//!         subjects: vec!["user:42".to_string()],
+//!         ..Default::default()
+//!     }).unwrap();
+//!
+//!     audis::context::clear();
+//! }
+//! ```
+//!
+//! Context is applied when an event is logged, or when it is
+//! handed to a `background()` Sender, whichever comes first.
+//! Metadata and subjects that an event already carries are
+//! left alone.
+//!
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::Event;
+
+#[derive(Default)]
+struct Context {
+    meta: BTreeMap<String, String>,
+    subjects: Vec<String>,
+}
+
+thread_local!(static CONTEXT: RefCell<Context> = RefCell::new(Context::default()));
+
+/// Attach a metadata field to every event logged from this
+/// thread, replacing any previous value for `key`.
+pub fn set(key: &str, value: &str) {
+    CONTEXT.with(|c| {
+        c.borrow_mut()
+            .meta
+            .insert(key.to_string(), value.to_string())
+    });
+}
+
+/// Look up a metadata field in this thread's context.
+pub fn get(key: &str) -> Option<String> {
+    CONTEXT.with(|c| c.borrow().meta.get(key).cloned())
+}
+
+/// Stop attaching a metadata field to events.
+pub fn remove(key: &str) {
+    CONTEXT.with(|c| c.borrow_mut().meta.remove(key));
+}
+
+/// Log every event logged from this thread against `subject`,
+/// in addition to its own subjects.
+pub fn subject(subject: &str) {
+    CONTEXT.with(|c| {
+        let mut c = c.borrow_mut();
+        if !c.subjects.iter().any(|s| s == subject) {
+            c.subjects.push(subject.to_string());
+        }
+    });
+}
+
+/// Forget everything in this thread's context.
+pub fn clear() {
+    CONTEXT.with(|c| *c.borrow_mut() = Context::default());
+}
+
+// Attach this thread's context to an event, copying it only
+// if there is context to attach.
+pub(crate) fn attach(e: &Event) -> Cow<'_, Event> {
+    CONTEXT.with(|c| {
+        let c = c.borrow();
+        let missing = |s: &String| !e.subjects.contains(s);
+        if c.meta.keys().all(|k| e.meta.contains_key(k)) && !c.subjects.iter().any(missing) {
+            return Cow::Borrowed(e);
+        }
+
+        let mut e = e.clone();
+        for (k, v) in &c.meta {
+            e.meta.entry(k.to_string()).or_insert_with(|| v.to_string());
+        }
+        for s in &c.subjects {
+            if !e.subjects.contains(s) {
+                e.subjects.push(s.to_string());
+            }
+        }
+        Cow::Owned(e)
+    })
+}
+
+// Attach this thread's context to an event we already own.
+pub(crate) fn attach_owned(e: Event) -> Event {
+    let attached = match attach(&e) {
+        Cow::Owned(attached) => Some(attached),
+        Cow::Borrowed(_) => None,
+    };
+    attached.unwrap_or(e)
+}
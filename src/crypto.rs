@@ -0,0 +1,474 @@
+//! Cryptographic protection of events, enabled by the `crypto`
+//! feature.
+//!
+//! With a signing key configured, every event is signed (with
+//! ed25519) as it is logged; with the corresponding verifying
+//! key configured, `retrieve()` refuses to return events whose
+//! signatures don't check out, and `verify()` reports them as
+//! breaks.  This proves that events weren't forged or altered
+//! by anyone with raw access to the backend, who won't have the
+//! signing key.
+//!
+//! Signatures cover everything stored about the event: its ID,
+//! payload, metadata (except the count of repeats kept by
+//! `dedup()`), correlation ID and parent.  They are stored,
+//! hex-encoded, under a parallel key ending in `:sig`, after the
+//! version of the scheme that signed them.  (Events signed by
+//! older versions of audis, which only signed IDs and payloads,
+//! still verify as they were.)
+//!
+//! Payloads can also be encrypted at rest, with AES-256-GCM,
+//! using keys handed out by a `KeyProvider`, so that audit data
+//! containing personal information can't be read by anyone
+//! with a copy of the backend's data files.  `retrieve()`
+//! decrypts payloads transparently.  Each encrypted payload
+//! records the ID of the key it was encrypted with, so keys
+//! can be rotated without re-encrypting older events.
+//!
+//! With `per_subject_keys()`, each subject gets its own data
+//! key instead (stored under `audis:keys:$subject`, encrypted
+//! with the provider's key).  Every payload is encrypted with
+//! a fresh key of its own, which is in turn encrypted with the
+//! data key of each of the event's subjects, and stored under
+//! a parallel key ending in `:keys`.  Destroying a subject's
+//! data key, via `shred_subject_key()`, then renders its events
+//! unreadable through that subject, while leaving them readable
+//! through any other subjects they were logged against.
+
+#[cfg(feature = "crypto")]
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+#[cfg(feature = "crypto")]
+use aes_gcm::{Aes256Gcm, Nonce};
+#[cfg(feature = "crypto")]
+use ed25519_dalek::{Signature, Signer, Verifier};
+#[cfg(feature = "crypto")]
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+#[cfg(feature = "crypto")]
+use std::convert::TryFrom;
+#[cfg(feature = "crypto")]
+use std::sync::Arc;
+
+use crate::compress::{self, ENCRYPTED, MAGIC};
+#[cfg(feature = "crypto")]
+use crate::compress::{framed, ENVELOPED};
+use crate::{AudisError, AudisResult, Client, Event};
+
+/// A source of 256-bit payload encryption keys.
+#[cfg(feature = "crypto")]
+pub trait KeyProvider: Send + Sync {
+    /// The key to encrypt new payloads with, and its ID.
+    fn current(&self) -> AudisResult<(String, [u8; 32])>;
+
+    /// Look up a key, by ID, to decrypt a payload with.
+    fn key(&self, id: &str) -> AudisResult<Option<[u8; 32]>>;
+}
+
+/// A `KeyProvider` with a single, fixed key.
+#[cfg(feature = "crypto")]
+pub struct StaticKey {
+    id: String,
+    key: [u8; 32],
+}
+
+#[cfg(feature = "crypto")]
+impl StaticKey {
+    /// Use `key` (identified as `id`) for all encryption.
+    pub fn new(id: &str, key: [u8; 32]) -> StaticKey {
+        StaticKey {
+            id: id.to_string(),
+            key,
+        }
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl KeyProvider for StaticKey {
+    fn current(&self) -> AudisResult<(String, [u8; 32])> {
+        Ok((self.id.to_string(), self.key))
+    }
+
+    fn key(&self, id: &str) -> AudisResult<Option<[u8; 32]>> {
+        Ok(if id == self.id { Some(self.key) } else { None })
+    }
+}
+
+// The key material a Client has been configured with.
+#[derive(Clone, Default)]
+pub(crate) struct Keys {
+    #[cfg(feature = "crypto")]
+    signer: Option<SigningKey>,
+    #[cfg(feature = "crypto")]
+    verifier: Option<VerifyingKey>,
+    #[cfg(feature = "crypto")]
+    payloads: Option<Arc<dyn KeyProvider>>,
+    #[cfg(feature = "crypto")]
+    per_subject: bool,
+}
+
+#[cfg(feature = "crypto")]
+impl Client {
+    /// Sign every event logged by this client with `key`.
+    pub fn sign(mut self, key: SigningKey) -> Client {
+        self.keys.signer = Some(key);
+        self
+    }
+
+    /// Encrypt the payload of every event logged by this client
+    /// with keys from `keys`, and decrypt payloads on retrieval.
+    pub fn encrypt(mut self, keys: Box<dyn KeyProvider>) -> Client {
+        self.keys.payloads = Some(keys.into());
+        self
+    }
+
+    /// Encrypt payloads with per-subject data keys, so that
+    /// they can be crypto-shredded one subject at a time.
+    ///
+    /// This requires a key provider (see `encrypt()`), which
+    /// is used to protect the subject data keys themselves.
+    pub fn per_subject_keys(mut self) -> Client {
+        self.keys.per_subject = true;
+        self
+    }
+
+    /// Destroy the data key of a subject, rendering every event
+    /// logged against it (with `per_subject_keys()`) unreadable
+    /// through that subject, forever.
+    ///
+    /// Events logged against the subject after this get a new
+    /// data key.  Like `erase_subject()`, shredding is recorded
+    /// by `audit_changes()` without the subject's name.
+    pub fn shred_subject_key(&self, subject: &str) -> AudisResult<&Client> {
+        self.allow(crate::Operation::Shred, subject)?;
+        let subject = self.subject(subject);
+        self.query::<()>(redis::cmd("DEL").arg(format!("audis:keys:{}", subject)))?;
+        self.record_erasure("shred", &subject, &[])?;
+        Ok(self)
+    }
+
+    // Look up (or create) the data key of a subject.
+    fn subject_key(&self, subject: &str, create: bool) -> AudisResult<Option<[u8; 32]>> {
+        let name = format!("audis:keys:{}", subject);
+        loop {
+            let wrapped: Option<Vec<u8>> = self.query(redis::cmd("GET").arg(&name))?;
+            if let Some(wrapped) = wrapped {
+                let key = self.keys.decrypt(&name, wrapped)?;
+                return match <[u8; 32]>::try_from(key.as_slice()) {
+                    Ok(key) => Ok(Some(key)),
+                    Err(_) => Err(AudisError::Codec(format!(
+                        "malformed data key for {}",
+                        subject
+                    ))),
+                };
+            }
+            if !create {
+                return Ok(None);
+            }
+            let key = Aes256Gcm::generate_key(&mut OsRng);
+            let wrapped = self.keys.encrypt(&name, key.to_vec())?;
+            if self.setnx(&name, &wrapped)? {
+                return Ok(Some(key.into()));
+            }
+            // someone else beat us to it; use theirs.
+        }
+    }
+
+    // Hand an event's data key over from one subject to
+    // another, re-wrapping it in the other subject's key, so
+    // that the event can be read through that subject instead.
+    pub(crate) fn rewrap(&self, id: &str, from: &str, to: &str) -> AudisResult<()> {
+        let wrapped: Option<Vec<u8>> = self.query(redis::cmd("HGET").arg(idkeys!(id)).arg(from))?;
+        let (wrapped, key) = match (wrapped, self.subject_key(from, false)?) {
+            (Some(wrapped), Some(key)) => (wrapped, key),
+            _ => return Ok(()),
+        };
+        if let Some(dek) = unseal(&key, id, &wrapped) {
+            let key = self.created_key(to)?;
+            self.query::<()>(
+                redis::cmd("HSET")
+                    .arg(idkeys!(id))
+                    .arg(to)
+                    .arg(seal(&key, id, &dek)?),
+            )?;
+        }
+        self.query::<()>(redis::cmd("HDEL").arg(idkeys!(id)).arg(from))?;
+        Ok(())
+    }
+
+    // Look up the data key of a subject, creating it if need be.
+    fn created_key(&self, subject: &str) -> AudisResult<[u8; 32]> {
+        self.subject_key(subject, true)?
+            .ok_or_else(|| AudisError::Codec(format!("cannot create a data key for {}", subject)))
+    }
+
+    // Encode a payload for storage, compressing and encrypting
+    // it, as configured, along with the command that stores its
+    // wrapped data keys (with `per_subject_keys()`), which must
+    // only be run once the payload itself has been stored, lest
+    // a duplicate event clobber the keys of the original.
+    pub(crate) fn encode_payload(&self, e: &Event) -> AudisResult<(Vec<u8>, Option<redis::Cmd>)> {
+        let data = compress::encode(&e.data, self.compression)?;
+        if !self.keys.per_subject || self.keys.payloads.is_none() {
+            return Ok((self.keys.encrypt(&e.id, data)?, None));
+        }
+
+        let dek = Aes256Gcm::generate_key(&mut OsRng);
+        let mut hset = redis::cmd("HSET");
+        hset.arg(idkeys!(e.id));
+        for s in &e.subjects {
+            let key = self.created_key(s)?;
+            hset.arg(s).arg(seal(&key, &e.id, &dek)?);
+        }
+        let data = framed(ENVELOPED, &seal(&dek.into(), &e.id, &data)?);
+        Ok((data, Some(hset).filter(|_| !e.subjects.is_empty())))
+    }
+
+    // Decode a stored payload, decrypting and decompressing it
+    // as necessary.  Payloads encrypted with per-subject keys
+    // can only be decrypted through one of their subjects;
+    // if `subject` isn't given, they all get tried.
+    pub(crate) fn decode_payload(
+        &self,
+        id: &str,
+        subject: Option<&str>,
+        data: Vec<u8>,
+    ) -> AudisResult<Vec<u8>> {
+        if !data.starts_with(MAGIC) || data.get(MAGIC.len()) != Some(&ENVELOPED) {
+            return compress::decode(id, self.keys.decrypt(id, data)?);
+        }
+
+        let subjects: Vec<String> = match subject {
+            Some(s) => vec![s.to_string()],
+            None => self.query(redis::cmd("HKEYS").arg(idkeys!(id)))?,
+        };
+        for s in subjects {
+            let wrapped: Option<Vec<u8>> =
+                self.query(redis::cmd("HGET").arg(idkeys!(id)).arg(&s))?;
+            let (key, wrapped) = match (self.subject_key(&s, false)?, wrapped) {
+                (Some(key), Some(wrapped)) => (key, wrapped),
+                _ => continue,
+            };
+            let dek = match unseal(&key, id, &wrapped)
+                .and_then(|dek| <[u8; 32]>::try_from(dek.as_slice()).ok())
+            {
+                Some(dek) => dek,
+                None => continue,
+            };
+            if let Some(data) = unseal(&dek, id, &data[MAGIC.len() + 1..]) {
+                return compress::decode(id, data);
+            }
+        }
+        Err(AudisError::Codec(format!(
+            "event {}: cannot decrypt payload (has its subject key been shredded?)",
+            id
+        )))
+    }
+
+    /// Check the signature of every event retrieved by this
+    /// client against `key`.
+    ///
+    /// Events that are unsigned, or whose signatures don't
+    /// match, cause `retrieve()` to fail with
+    /// `AudisError::Tampered`.
+    pub fn verify_signatures(mut self, key: VerifyingKey) -> Client {
+        self.keys.verifier = Some(key);
+        self
+    }
+
+    // Sign an event (if we have a signing key), storing the
+    // signature alongside it.
+    pub(crate) fn seal(&self, e: &Event) -> AudisResult<&Client> {
+        if let Some(sig) = self.keys.sign(&message(SIGNED, e)) {
+            self.query::<()>(
+                redis::cmd("SET")
+                    .arg(idsig!(e.id))
+                    .arg(format!("{}:{}", SIGNED, sig)),
+            )?;
+        }
+        Ok(self)
+    }
+
+    // Check the signature of an event (if we have a verifying
+    // key), returning what's wrong with it, if anything.
+    pub(crate) fn unsealed(&self, e: &Event) -> AudisResult<Option<String>> {
+        let key = match &self.keys.verifier {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let sig = match self.get(&idsig!(e.id))? {
+            Some(sig) => sig,
+            None => return Ok(Some("event is not signed".to_string())),
+        };
+        let (version, sig) = sig.split_once(':').unwrap_or((LEGACY, &sig));
+        if (version == LEGACY || version == SIGNED) && verify(key, &message(version, e), sig) {
+            Ok(None)
+        } else {
+            Ok(Some("event signature does not match".to_string()))
+        }
+    }
+
+    // Fail if an event's signature doesn't check out.
+    pub(crate) fn check_seal(&self, e: &Event) -> AudisResult<()> {
+        match self.unsealed(e)? {
+            Some(why) => Err(AudisError::Tampered(format!("{}: {}", e.id, why))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Keys {
+    // Sign an arbitrary message, if we have a signing key.
+    #[cfg(feature = "crypto")]
+    pub(crate) fn sign(&self, msg: &[u8]) -> Option<String> {
+        self.signer
+            .as_ref()
+            .map(|key| hex(&key.sign(msg).to_bytes()))
+    }
+
+    #[cfg(not(feature = "crypto"))]
+    pub(crate) fn sign(&self, _: &[u8]) -> Option<String> {
+        None
+    }
+
+    // Encrypt a (stored) payload, if we have a key provider.
+    #[cfg(feature = "crypto")]
+    pub(crate) fn encrypt(&self, id: &str, data: Vec<u8>) -> AudisResult<Vec<u8>> {
+        let provider = match &self.payloads {
+            Some(p) => p,
+            None => return Ok(data),
+        };
+        let (kid, key) = provider.current()?;
+        if kid.len() > 255 {
+            return Err(AudisError::Invalid(format!("key ID '{}' is too long", kid)));
+        }
+
+        let mut body = vec![kid.len() as u8];
+        body.extend_from_slice(kid.as_bytes());
+        body.extend_from_slice(&seal(&key, id, &data)?);
+        Ok(framed(ENCRYPTED, &body))
+    }
+
+    // Decrypt a stored payload, if it was encrypted.
+    pub(crate) fn decrypt(&self, id: &str, data: Vec<u8>) -> AudisResult<Vec<u8>> {
+        if !data.starts_with(MAGIC) || data.get(MAGIC.len()) != Some(&ENCRYPTED) {
+            return Ok(data);
+        }
+        self.open(id, &data[MAGIC.len() + 1..])
+            .ok_or_else(|| AudisError::Codec(format!("event {}: cannot decrypt payload", id)))?
+    }
+
+    #[cfg(feature = "crypto")]
+    fn open(&self, id: &str, body: &[u8]) -> Option<AudisResult<Vec<u8>>> {
+        let n = *body.first()? as usize;
+        let kid = std::str::from_utf8(body.get(1..1 + n)?).ok()?;
+        let key = match self.payloads.as_ref()?.key(kid) {
+            Ok(key) => key?,
+            Err(e) => return Some(Err(e)),
+        };
+        unseal(&key, id, body.get(1 + n..)?).map(Ok)
+    }
+
+    #[cfg(not(feature = "crypto"))]
+    fn open(&self, _: &str, _: &[u8]) -> Option<AudisResult<Vec<u8>>> {
+        None
+    }
+}
+
+// Check a hex-encoded signature of an arbitrary message.
+#[cfg(feature = "crypto")]
+pub(crate) fn verify(key: &VerifyingKey, msg: &[u8], sig: &str) -> bool {
+    match unhex(sig).and_then(|b| Signature::from_slice(&b).ok()) {
+        Some(sig) => key.verify(msg, &sig).is_ok(),
+        None => false,
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+impl Client {
+    pub(crate) fn rewrap(&self, _: &str, _: &str, _: &str) -> AudisResult<()> {
+        Ok(())
+    }
+
+    pub(crate) fn encode_payload(&self, e: &Event) -> AudisResult<(Vec<u8>, Option<redis::Cmd>)> {
+        Ok((compress::encode(&e.data, self.compression)?, None))
+    }
+
+    pub(crate) fn decode_payload(
+        &self,
+        id: &str,
+        _: Option<&str>,
+        data: Vec<u8>,
+    ) -> AudisResult<Vec<u8>> {
+        compress::decode(id, self.keys.decrypt(id, data)?)
+    }
+}
+
+// Encrypt `msg` with `key`, bound to the given event ID, as
+// a nonce followed by the ciphertext.
+#[cfg(feature = "crypto")]
+fn seal(key: &[u8; 32], id: &str, msg: &[u8]) -> AudisResult<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let sealed = Aes256Gcm::new(key.into())
+        .encrypt(
+            &nonce,
+            Payload {
+                msg,
+                aad: id.as_bytes(),
+            },
+        )
+        .map_err(|_| AudisError::Codec(format!("event {}: encryption failed", id)))?;
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+#[cfg(feature = "crypto")]
+fn unseal(key: &[u8; 32], id: &str, sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < 12 {
+        return None;
+    }
+    Aes256Gcm::new(key.into())
+        .decrypt(
+            Nonce::from_slice(&sealed[..12]),
+            Payload {
+                msg: &sealed[12..],
+                aad: id.as_bytes(),
+            },
+        )
+        .ok()
+}
+
+// Signatures record the version of `message()` that they sign,
+// except for those made before there was more than one.
+#[cfg(feature = "crypto")]
+const LEGACY: &str = "1";
+#[cfg(feature = "crypto")]
+const SIGNED: &str = "2";
+
+#[cfg(feature = "crypto")]
+fn message(version: &str, e: &Event) -> Vec<u8> {
+    if version != LEGACY {
+        return crate::chain::canonical(e, false);
+    }
+    let mut m = Vec::with_capacity(e.id.len() + 1 + e.data.len());
+    m.extend_from_slice(e.id.as_bytes());
+    m.push(0);
+    m.extend_from_slice(&e.data);
+    m
+}
+
+#[cfg(feature = "crypto")]
+fn hex(b: &[u8]) -> String {
+    b.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "crypto")]
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
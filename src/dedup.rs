@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::hierarchy::glob_escape;
+use crate::{AudisResult, Client, Event};
+
+// The metadata field that counts an event's repeats.
+pub(crate) const REPEATS: &str = "repeats";
+
+impl Client {
+    /// Suppress events whose payloads exactly match that of an
+    /// event logged against the same subject within the last
+    /// `window`.
+    ///
+    /// Instead of logging a suppressed event against a subject,
+    /// the `repeats` metadata field of the original event is
+    /// incremented, so that retry storms and the like are still
+    /// visible in the audit log, without filling it up.
+    ///
+    /// Recently-seen payloads are tracked by SHA-256 hash, in
+    /// self-expiring `audis:dedup:$subject:$hash` keys, so
+    /// deduplication works across every client sharing the
+    /// backend.
+    ///
+    pub fn dedup(mut self, window: Duration) -> Client {
+        self.dedup = Some(window);
+        self
+    }
+
+    /// Quietly skip logging events whose IDs have already been
+    /// logged, rather than failing with `AudisError::Duplicate`,
+    /// so that producers that deliver events at least once can
+    /// simply log them again when in doubt.
+    ///
+    /// The event already logged is left as it was, even if the
+    /// new one differs from it; `log_outcome()` reports that the
+    /// duplicate was indexed against none of its subjects.
+    pub fn ignore_duplicates(mut self) -> Client {
+        self.duplicates_ok = true;
+        self
+    }
+
+    // Drop the recently-seen payload hashes of a (stored) subject,
+    // because it is being erased.
+    pub(crate) fn drop_dedup(&self, subject: &str) -> AudisResult<()> {
+        let keys = self.scan(
+            "SCAN",
+            None,
+            &format!("audis:dedup:{}:*", glob_escape(subject)),
+        )?;
+        for chunk in keys.chunks(1000) {
+            self.query::<()>(redis::cmd("DEL").arg(chunk))?;
+        }
+        Ok(())
+    }
+
+    // Figure out which of an event's subjects it is not a
+    // duplicate for; it has to be logged against those.
+    pub(crate) fn novel<'a>(&self, e: &'a Event) -> AudisResult<Vec<&'a String>> {
+        let window = match self.dedup {
+            Some(window) => window.as_millis().max(1) as u64,
+            None => return Ok(e.subjects.iter().collect()),
+        };
+
+        let hash: String = Sha256::digest(&e.data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let mut novel = vec![];
+        for s in &e.subjects {
+            let key = format!("audis:dedup:{}:{}", s, hash);
+            let fresh: Option<String> = self.query(
+                redis::cmd("SET")
+                    .arg(&key)
+                    .arg(&e.id)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(window),
+            )?;
+            if fresh.is_some() {
+                novel.push(s);
+                continue;
+            }
+            match self.get(&key)? {
+                Some(original) => self.bump(&original, REPEATS)?,
+                // expired in the meantime; it's not a repeat anymore
+                None => novel.push(s),
+            }
+        }
+        Ok(novel)
+    }
+}
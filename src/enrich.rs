@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use crate::{AudisResult, Event, Interceptor};
+
+/// An interceptor that stamps ambient details about the
+/// running process onto the metadata of every event, so
+/// that it's always clear which instance logged what.
+///
+/// ```rust,no_run
+/// extern crate audis;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .with_interceptor(Box::new(
+///             audis::Enricher::new()
+///                 .hostname()
+///                 .pid()
+///                 .service("billing")
+///                 .environment("prod"),
+///         ));
+///
+///     // ... every event logged now carries host, pid,
+///     //     service and env metadata ...
+/// }
+/// ```
+///
+/// Metadata that an event already carries is never
+/// overwritten.
+#[derive(Clone, Debug, Default)]
+pub struct Enricher {
+    fields: BTreeMap<String, String>,
+}
+
+impl Enricher {
+    /// Create an Enricher that doesn't (yet) add anything.
+    pub fn new() -> Enricher {
+        Enricher::default()
+    }
+
+    /// Stamp events with the name of this host, as `host`.
+    ///
+    /// The hostname is looked up once, here; if it can't be
+    /// determined, no `host` field is added.
+    pub fn hostname(self) -> Enricher {
+        match hostname::get() {
+            Ok(h) => self.field("host", &h.to_string_lossy()),
+            Err(_) => self,
+        }
+    }
+
+    /// Stamp events with the ID of this process, as `pid`.
+    pub fn pid(self) -> Enricher {
+        self.field("pid", &std::process::id().to_string())
+    }
+
+    /// Stamp events with the name of the service (or app)
+    /// logging them, as `service`.
+    pub fn service(self, name: &str) -> Enricher {
+        self.field("service", name)
+    }
+
+    /// Stamp events with the deployment environment (i.e.
+    /// `prod` or `staging`), as `env`.
+    pub fn environment(self, env: &str) -> Enricher {
+        self.field("env", env)
+    }
+
+    /// Stamp events with an arbitrary metadata field.
+    pub fn field(mut self, key: &str, value: &str) -> Enricher {
+        self.fields.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+impl Interceptor for Enricher {
+    fn intercept(&self, mut e: Event) -> AudisResult<Option<Event>> {
+        for (k, v) in &self.fields {
+            e.meta.entry(k.to_string()).or_insert_with(|| v.to_string());
+        }
+        Ok(Some(e))
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{AudisResult, Client, Event, Operation};
+
+impl Client {
+    /// Erase a subject from the audit log entirely, i.e. to
+    /// satisfy a right-to-erasure request.
+    ///
+    /// The subject's index is removed, and each of its events is
+    /// dereferenced.  Events that belonged only to this subject
+    /// are deleted outright; events that are shared with other
+    /// subjects are kept (for those subjects), but have their
+    /// payloads rewritten by `redact`, which is given the event
+    /// as it stands, and returns the new payload.
+    ///
+    /// Everything else that names the subject goes too: its
+    /// per-subject data key (see `audis::crypto`), hash chain
+    /// head and links, sequence counter and numbers, annotations,
+    /// aliases (and those pointing at it), and the hashes of its
+    /// recently-seen payloads (see `dedup()`).  Since
+    /// rewriting payloads changes history, the hash chains of
+    /// the other subjects of redacted events will no longer
+    /// verify past them.  With `audit_changes()`, the erasure is
+    /// recorded without the subject's name (unless the client
+    /// pseudonymizes subjects, and it is a pseudonym).
+    ///
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, redact), err, fields(commands))
+    )]
+    pub fn erase_subject<F>(&self, subject: &str, redact: F) -> AudisResult<&Client>
+    where
+        F: Fn(&Event) -> Vec<u8>,
+    {
+        self.instrument("erase_subject", || {
+            self.allow(Operation::Erase, subject)?;
+            let subject = self.subject(subject);
+            let subject = subject.as_ref();
+            let removed = self.locked(subject, || {
+                let removed = self.lrange(subject, "0", "-1")?;
+                for id in &removed {
+                    let refs = self.refcounts(std::slice::from_ref(id))?[0];
+                    if refs.unwrap_or(0) > 1 {
+                        if let Some(mut e) = self.fetch(id, Some(subject))? {
+                            e.data = redact(&e);
+                            e.subjects =
+                                self.query::<Vec<String>>(redis::cmd("HKEYS").arg(idkeys!(id)))?;
+                            e.subjects.retain(|s| s != subject);
+                            self.query::<()>(redis::cmd("DEL").arg(idkeys!(id)))?;
+                            let (data, keys) = self.encode_payload(&e)?;
+                            if let Some(mut keys) = keys {
+                                self.query::<()>(&mut keys)?;
+                            }
+                            self.set_payload(id, &data)?;
+                            #[cfg(feature = "crypto")]
+                            self.seal(&e)?;
+                            #[cfg(feature = "search")]
+                            self.unindex(&e, subject)?;
+                        }
+                    }
+                    self.unlink(subject, id)?
+                        .unexpiring_in(id, subject)?
+                        .deref(id)?;
+                }
+
+                self.forget(subject)?;
+                self.drop_counter(subject)?;
+                self.drop_notes(subject)?;
+                self.drop_dedup(subject)?;
+                self.unalias_all(subject)?;
+                Ok(removed)
+            })?;
+            self.record_erasure("erase", subject, &removed)?;
+            Ok(self)
+        })
+    }
+}
@@ -0,0 +1,115 @@
+use std::error;
+use std::fmt;
+
+/// Everything that can go wrong while dealing with an audit log.
+pub enum AudisError {
+    /// An event with the given ID has already been logged.
+    Duplicate(String),
+
+    /// An event with the given ID could not be found.
+    NotFound(String),
+
+    /// An event (or a request) was malformed, for the given
+    /// reason.
+    Invalid(String),
+
+    /// A typed record could not be encoded into (or decoded
+    /// from) an event payload.
+    Codec(String),
+
+    /// An event failed an integrity check (i.e. its signature
+    /// did not match), for the given reason.
+    Tampered(String),
+
+    /// The access policy of the client (see
+    /// `Client::authorize()`) did not allow the given operation.
+    Forbidden(String),
+
+    /// The given subject was locked (see
+    /// `Client::snapshot_reads()`) for longer than we were
+    /// willing to wait.
+    Locked(String),
+
+    /// An operation was abandoned because its cancellation token
+    /// (see `Client::cancellable()`) was cancelled, or its
+    /// deadline passed, for the given reason.
+    Cancelled(String),
+
+    /// An event could not be delivered to a forwarding sink
+    /// (see `audis::forward`), for the given reason.
+    Forward(String),
+
+    /// The backend could not be reached, or the connection
+    /// to it was lost.
+    Connection(redis::RedisError),
+
+    /// The backend was reachable, but failed to carry out
+    /// a request.
+    Backend(redis::RedisError),
+}
+
+pub type AudisResult<T> = Result<T, AudisError>;
+
+impl AudisError {
+    /// Returns true if the error was caused by the backend
+    /// refusing our connection attempt.
+    pub fn is_connection_refusal(&self) -> bool {
+        match self {
+            AudisError::Connection(e) => e.is_connection_refusal(),
+            _ => false,
+        }
+    }
+}
+
+impl From<redis::RedisError> for AudisError {
+    fn from(e: redis::RedisError) -> AudisError {
+        if e.is_io_error()
+            || e.is_connection_refusal()
+            || e.is_connection_dropped()
+            || e.is_timeout()
+        {
+            AudisError::Connection(e)
+        } else {
+            AudisError::Backend(e)
+        }
+    }
+}
+
+impl From<std::io::Error> for AudisError {
+    fn from(e: std::io::Error) -> AudisError {
+        AudisError::Connection(e.into())
+    }
+}
+
+impl fmt::Display for AudisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudisError::Duplicate(id) => write!(f, "duplicate key detected: {}", id),
+            AudisError::NotFound(id) => write!(f, "event {} not found", id),
+            AudisError::Invalid(why) => write!(f, "invalid: {}", why),
+            AudisError::Codec(why) => write!(f, "codec error: {}", why),
+            AudisError::Tampered(why) => write!(f, "tampering detected: {}", why),
+            AudisError::Forbidden(what) => write!(f, "forbidden: {}", what),
+            AudisError::Locked(subject) => write!(f, "subject {} is locked", subject),
+            AudisError::Cancelled(why) => write!(f, "operation abandoned: {}", why),
+            AudisError::Forward(why) => write!(f, "forwarding failed: {}", why),
+            AudisError::Connection(e) => write!(f, "connection failed: {}", e),
+            AudisError::Backend(e) => write!(f, "backend error: {}", e),
+        }
+    }
+}
+
+impl fmt::Debug for AudisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl error::Error for AudisError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AudisError::Connection(e) | AudisError::Backend(e) => Some(e),
+            _ => None,
+        }
+    }
+}
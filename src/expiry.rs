@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::timeline::now;
+use crate::{AudisResult, Client, Event};
+
+// The sorted set of expiring event IDs, scored by when they
+// expire (in milliseconds since the epoch).
+const EXPIRING: &str = "audis:expiring";
+
+// The set of lists (subjects and trails) that reference an
+// expiring event, i.e. what to clean up once it's gone.
+macro_rules! referenced {
+    ($id:expr) => {
+        format!("audis:expiring:{}", $id)
+    };
+}
+
+impl Client {
+    /// Have Redis expire each newly-logged event `ttl` after it
+    /// was logged, via `PEXPIRE` on its keys.
+    ///
+    /// Redis only gets rid of the event itself; its ID lingers
+    /// in the subjects (and trail) it was logged against, where
+    /// `retrieve()` would trip over it with `AudisError::NotFound`,
+    /// until `reap()` cleans it up.  To that end, each expiring
+    /// event is also tracked in the `audis:expiring` sorted set,
+    /// and the lists that reference it in an
+    /// `audis:expiring:$id` set.  Events logged by clients that
+    /// don't `expire_after()` never expire.
+    ///
+    pub fn expire_after(mut self, ttl: Duration) -> Client {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Remove the IDs of events that Redis has expired (see
+    /// `expire_after()`) from every subject and trail that
+    /// references them, returning how many events were reaped.
+    ///
+    /// This should be run regularly (i.e. from cron, via `audis
+    /// reap`), and as often as dangling references to expired
+    /// events can be tolerated.  Events that are due, but still
+    /// around (because Redis hasn't got to them yet), are left for
+    /// next time; those that no longer have a TTL at all are no
+    /// longer tracked.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn reap(&self) -> AudisResult<u64> {
+        self.instrument("reap", || {
+            let due: Vec<String> = self.query(
+                redis::cmd("ZRANGEBYSCORE")
+                    .arg(EXPIRING)
+                    .arg("-inf")
+                    .arg(now()),
+            )?;
+            let mut reaped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            let mut n = 0;
+            for id in &due {
+                let ttl: i64 = self.query(redis::cmd("PTTL").arg(id!(id)))?;
+                if ttl >= 0 {
+                    continue;
+                }
+                if ttl == -2 {
+                    for list in self.smembers(&referenced!(id))? {
+                        if list.starts_with("audis:trail:") {
+                            self.query::<()>(redis::cmd("LREM").arg(&list).arg(0).arg(id))?;
+                            continue;
+                        }
+                        let removed = self.locked(&list, || {
+                            let removed: u64 =
+                                self.query(redis::cmd("LREM").arg(&list).arg(0).arg(id))?;
+                            if removed > 0 {
+                                self.unstamp(&list, &[id.to_string()])?;
+                            }
+                            Ok(removed)
+                        })?;
+                        if removed > 0 {
+                            reaped.entry(list).or_default().push(id.to_string());
+                        }
+                    }
+                    self.del(id)?;
+                    n += 1;
+                }
+                self.query::<()>(redis::cmd("DEL").arg(referenced!(id)))?;
+                self.query::<()>(redis::cmd("ZREM").arg(EXPIRING).arg(id))?;
+            }
+            for (subject, removed) in &reaped {
+                self.record("reap", subject, removed)?;
+            }
+            Ok(n)
+        })
+    }
+
+    // Set the TTL on a newly-logged event, and track it (and the
+    // lists it was just indexed into) for `reap()`.
+    pub(crate) fn expire(&self, e: &Event, subjects: &[&String]) -> AudisResult<()> {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl.as_millis().max(1) as u64,
+            None => return Ok(()),
+        };
+
+        let mut pipe = redis::pipe();
+        for key in &[
+            id!(e.id),
+            idref!(e.id),
+            idmeta!(e.id),
+            idtrail!(e.id),
+            idchain!(e.id),
+            idsig!(e.id),
+            idkeys!(e.id),
+            idseq!(e.id),
+            #[cfg(feature = "search")]
+            format!("audis:search:{}", e.id),
+        ] {
+            pipe.cmd("PEXPIRE").arg(key).arg(ttl).ignore();
+        }
+        pipe.cmd("ZADD")
+            .arg(EXPIRING)
+            .arg(now() + ttl)
+            .arg(&e.id)
+            .ignore();
+        let mut lists: Vec<String> = subjects.iter().map(|s| s.to_string()).collect();
+        if let Some(cid) = &e.correlation_id {
+            lists.push(trail!(cid));
+        }
+        if !lists.is_empty() {
+            pipe.cmd("SADD").arg(referenced!(e.id)).arg(lists).ignore();
+        }
+        self.pipeline::<()>(&pipe)
+    }
+
+    // Note that an (expiring) event is no longer referenced by a
+    // subject, because it is being erased.
+    pub(crate) fn unexpiring_in(&self, id: &str, subject: &str) -> AudisResult<&Client> {
+        self.query::<()>(redis::cmd("SREM").arg(referenced!(id)).arg(subject))?;
+        Ok(self)
+    }
+
+    // Note that an (expiring) event is now referenced by another
+    // subject, too.
+    pub(crate) fn expiring_in(&self, id: &str, subject: &str) -> AudisResult<&Client> {
+        let tracked: bool = self.query(redis::cmd("EXISTS").arg(referenced!(id)))?;
+        if tracked {
+            self.sadd(&referenced!(id), subject)?;
+        }
+        Ok(self)
+    }
+}
@@ -0,0 +1,526 @@
+//! Formats for exporting events to other systems, i.e. SIEMs
+//! and log collectors, one line per event.
+//!
+//! Each format renders an event (as logged, or as retrieved) as
+//! a single line of text, ready to be written to a file (whole
+//! subjects at a time, by `Client::export_to()`), or sent along
+//! to a collector by one of the sinks in `audis::forward`:
+//!
+//! ```rust
+//! extern crate audis;
+//! use audis::export::{Format, Syslog};
+//!
+//! fn main() {
+//!     let e = audis::Event {
+//!         id: "01ARZ3NDEKTSV4RRFFQ69G5FAV".to_string(),
+//!         data: b"user logged in".to_vec(),
+//!         subjects: vec!["user:42".to_string()],
+//!         ..Default::default()
+//!     };
+//!     println!("{}", Syslog::new("billing").hostname("web1").render(&e));
+//! }
+//! ```
+//!
+//! Event payloads are rendered as (lossy) UTF-8, so binary
+//! payloads don't export well.
+//!
+
+use std::collections::HashSet;
+use std::io::Write;
+
+pub(crate) use crate::ids::timestamp;
+use crate::{AudisError, AudisResult, Client, Event};
+
+// How many events to retrieve at a time, in `export_to()`.
+const PAGE: usize = 1000;
+
+/// A way of rendering events as text, for other systems to
+/// consume.
+pub trait Format: Send + Sync {
+    /// Render a single event, as a single line of text (without
+    /// a trailing newline).
+    fn render(&self, e: &Event) -> String;
+}
+
+/// RFC 5424 syslog messages, i.e.
+///
+/// ```text
+/// <110>1 2016-07-30T23:54:10.259Z web1 billing - audit [audis@32473 id="01ARZ3NDEKTSV4RRFFQ69G5FAV" subject="user:42"] user logged in
+/// ```
+///
+/// The event ID and subjects (one `subject` parameter apiece),
+/// and correlation and parent IDs (if any) are carried in an
+/// `audis@32473` structured data element, and metadata fields in
+/// a `meta@32473` element.  Metadata keys that can't be used as
+/// syslog parameter names are left out.  The payload is the
+/// message, with any line breaks escaped (as `\n`), so that each
+/// message stays on one line.
+///
+/// Timestamps are taken from event IDs that carry one (ULIDs,
+/// KSUIDs and UUIDv7s; see `ids::timestamp()`); other events are
+/// sent without one.
+/// Severity is taken from a `severity` metadata field holding one
+/// of the syslog severity names (`emerg`, `alert`, `crit`, `err`,
+/// `warning`, `notice`, `info` or `debug`), if there is one.
+#[derive(Clone, Debug)]
+pub struct Syslog {
+    facility: u8,
+    severity: u8,
+    hostname: String,
+    app: String,
+    msgid: String,
+}
+
+const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+impl Syslog {
+    /// Render events as logged by `app`, from this host, with the
+    /// `log audit` facility (13), `info` severity (6), and a
+    /// message ID of `audit`.
+    pub fn new(app: &str) -> Syslog {
+        Syslog {
+            facility: 13,
+            severity: 6,
+            hostname: hostname::get()
+                .map(|h| h.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            app: app.to_string(),
+            msgid: "audit".to_string(),
+        }
+    }
+
+    /// Use a different facility (0 - 23).
+    pub fn facility(mut self, facility: u8) -> Syslog {
+        self.facility = facility.min(23);
+        self
+    }
+
+    /// Use a different severity (0 - 7) for events that don't
+    /// set their own.
+    pub fn severity(mut self, severity: u8) -> Syslog {
+        self.severity = severity.min(7);
+        self
+    }
+
+    /// Claim to be from a different host.
+    pub fn hostname(mut self, hostname: &str) -> Syslog {
+        self.hostname = hostname.to_string();
+        self
+    }
+
+    /// Use a different message ID.
+    pub fn msgid(mut self, msgid: &str) -> Syslog {
+        self.msgid = msgid.to_string();
+        self
+    }
+}
+
+impl Format for Syslog {
+    fn render(&self, e: &Event) -> String {
+        let severity = e
+            .meta
+            .get("severity")
+            .and_then(|s| SEVERITIES.iter().position(|n| n.eq_ignore_ascii_case(s)))
+            .map(|n| n as u8)
+            .unwrap_or(self.severity);
+
+        let mut line = format!(
+            "<{}>1 {} {} {} - {} [audis@32473 id=\"{}\"",
+            self.facility as u16 * 8 + severity as u16,
+            timestamp(&e.id)
+                .map(rfc3339)
+                .unwrap_or_else(|| "-".to_string()),
+            header(&self.hostname, 255),
+            header(&self.app, 48),
+            header(&self.msgid, 32),
+            param(&e.id),
+        );
+        for s in &e.subjects {
+            line.push_str(&format!(" subject=\"{}\"", param(s)));
+        }
+        if let Some(id) = &e.correlation_id {
+            line.push_str(&format!(" correlation=\"{}\"", param(id)));
+        }
+        if let Some(id) = &e.parent_id {
+            line.push_str(&format!(" parent=\"{}\"", param(id)));
+        }
+        line.push(']');
+
+        let meta: Vec<String> = e
+            .meta
+            .iter()
+            .filter(|(k, _)| {
+                !k.is_empty()
+                    && k.len() <= 32
+                    && k.bytes()
+                        .all(|b| b.is_ascii_graphic() && !b"=]\"".contains(&b))
+            })
+            .map(|(k, v)| format!(" {}=\"{}\"", k, param(v)))
+            .collect();
+        if !meta.is_empty() {
+            line.push_str(&format!("[meta@32473{}]", meta.concat()));
+        }
+
+        if !e.data.is_empty() {
+            line.push(' ');
+            line.push_str(&oneline(&String::from_utf8_lossy(&e.data)));
+        }
+        line
+    }
+}
+
+/// ArcSight Common Event Format (CEF) messages, i.e.
+///
+/// ```text
+/// CEF:0|Acme|billing|1.0|audit|audit|3|rt=1469922850259 externalId=01ARZ3NDEKTSV4RRFFQ69G5FAV cs1Label=subjects cs1=user:42 msg=user logged in
+/// ```
+///
+/// The event class ID and name are taken from an `action`
+/// metadata field (or are `audit`, if there isn't one), and the
+/// severity from a `severity` field, either as a number (0 - 10)
+/// or as one of the syslog severity names (see `Syslog`).
+///
+/// The event ID is sent as `externalId`, its ULID timestamp (if
+/// any) as `rt`, the payload as `msg`, and subjects (comma-
+/// separated), correlation ID and parent ID as the custom string
+/// fields `cs1`, `cs2` and `cs3`.  Other metadata fields with
+/// alphanumeric keys are sent as extensions of the same name,
+/// which ArcSight files as additional data.
+#[derive(Clone, Debug)]
+pub struct Cef {
+    vendor: String,
+    product: String,
+    version: String,
+    severity: u8,
+}
+
+impl Cef {
+    /// Render events as coming from version `version` of
+    /// `vendor`'s `product`, with a severity of 3 (low).
+    pub fn new(vendor: &str, product: &str, version: &str) -> Cef {
+        Cef {
+            vendor: vendor.to_string(),
+            product: product.to_string(),
+            version: version.to_string(),
+            severity: 3,
+        }
+    }
+
+    /// Use a different severity (0 - 10) for events that don't
+    /// set their own.
+    pub fn severity(mut self, severity: u8) -> Cef {
+        self.severity = severity.min(10);
+        self
+    }
+}
+
+impl Format for Cef {
+    fn render(&self, e: &Event) -> String {
+        let action = e.meta.get("action").map(String::as_str).unwrap_or("audit");
+        let mut line = format!(
+            "CEF:0|{}|{}|{}|{}|{}|{}|",
+            pipes(&self.vendor),
+            pipes(&self.product),
+            pipes(&self.version),
+            pipes(action),
+            pipes(action),
+            siem_severity(e).unwrap_or(self.severity),
+        );
+
+        let mut ext = vec![];
+        if let Some(ms) = timestamp(&e.id) {
+            ext.push(("rt".to_string(), ms.to_string()));
+        }
+        ext.push(("externalId".to_string(), e.id.to_string()));
+        let custom = [
+            ("subjects", Some(e.subjects.join(","))),
+            ("correlation", e.correlation_id.clone()),
+            ("parent", e.parent_id.clone()),
+        ];
+        for (i, (label, value)) in custom.iter().enumerate() {
+            if let Some(v) = value.as_ref().filter(|v| !v.is_empty()) {
+                ext.push((format!("cs{}Label", i + 1), label.to_string()));
+                ext.push((format!("cs{}", i + 1), v.to_string()));
+            }
+        }
+        for (k, v) in &e.meta {
+            if !k.is_empty()
+                && k.chars().all(|c| c.is_ascii_alphanumeric())
+                && !ext.iter().any(|(x, _)| x == k)
+                && k != "msg"
+            {
+                ext.push((k.to_string(), v.to_string()));
+            }
+        }
+        if !e.data.is_empty() {
+            ext.push((
+                "msg".to_string(),
+                String::from_utf8_lossy(&e.data).to_string(),
+            ));
+        }
+
+        let ext: Vec<String> = ext
+            .iter()
+            .map(|(k, v)| {
+                let v = v.replace('\\', "\\\\").replace('=', "\\=");
+                format!("{}={}", k, oneline(&v))
+            })
+            .collect();
+        line.push_str(&ext.join(" "));
+        line
+    }
+}
+
+/// IBM QRadar Log Event Extended Format (LEEF 1.0) messages, i.e.
+///
+/// ```text
+/// LEEF:1.0|Acme|billing|1.0|audit|devTime=2016-07-30T23:54:10.259Z<TAB>devTimeFormat=yyyy-MM-dd'T'HH:mm:ss.SSSX<TAB>sev=3<TAB>id=01ARZ3NDEKTSV4RRFFQ69G5FAV<TAB>subjects=user:42<TAB>msg=user logged in
+/// ```
+///
+/// The event ID (in the header) is taken from an `action`
+/// metadata field (or is `audit`, if there isn't one), and the
+/// severity (`sev`) from a `severity` field, as for `Cef`.
+///
+/// Attributes are tab-separated; the audis event ID is sent as
+/// `id`, its ULID timestamp (if any) as `devTime`, the payload
+/// as `msg`, subjects (comma-separated) as `subjects`, and the
+/// correlation and parent IDs as `correlation` and `parent`.
+/// Other metadata fields are sent as attributes of the same name.
+#[derive(Clone, Debug)]
+pub struct Leef {
+    vendor: String,
+    product: String,
+    version: String,
+    severity: u8,
+}
+
+impl Leef {
+    /// Render events as coming from version `version` of
+    /// `vendor`'s `product`, with a severity of 3.
+    pub fn new(vendor: &str, product: &str, version: &str) -> Leef {
+        Leef {
+            vendor: vendor.to_string(),
+            product: product.to_string(),
+            version: version.to_string(),
+            severity: 3,
+        }
+    }
+
+    /// Use a different severity (0 - 10) for events that don't
+    /// set their own.
+    pub fn severity(mut self, severity: u8) -> Leef {
+        self.severity = severity.min(10);
+        self
+    }
+}
+
+impl Format for Leef {
+    fn render(&self, e: &Event) -> String {
+        let action = e.meta.get("action").map(String::as_str).unwrap_or("audit");
+        let mut line = format!(
+            "LEEF:1.0|{}|{}|{}|{}|",
+            pipes(&self.vendor),
+            pipes(&self.product),
+            pipes(&self.version),
+            pipes(action),
+        );
+
+        let mut attrs = vec![];
+        if let Some(ms) = timestamp(&e.id) {
+            attrs.push(("devTime".to_string(), rfc3339(ms)));
+            attrs.push((
+                "devTimeFormat".to_string(),
+                "yyyy-MM-dd'T'HH:mm:ss.SSSX".to_string(),
+            ));
+        }
+        attrs.push((
+            "sev".to_string(),
+            siem_severity(e).unwrap_or(self.severity).to_string(),
+        ));
+        attrs.push(("id".to_string(), e.id.to_string()));
+        if !e.subjects.is_empty() {
+            attrs.push(("subjects".to_string(), e.subjects.join(",")));
+        }
+        if let Some(id) = &e.correlation_id {
+            attrs.push(("correlation".to_string(), id.to_string()));
+        }
+        if let Some(id) = &e.parent_id {
+            attrs.push(("parent".to_string(), id.to_string()));
+        }
+        for (k, v) in &e.meta {
+            if !k.is_empty()
+                && !k.contains(|c: char| c == '=' || c.is_whitespace())
+                && !attrs.iter().any(|(x, _)| x == k)
+                && k != "msg"
+            {
+                attrs.push((k.to_string(), v.to_string()));
+            }
+        }
+        if !e.data.is_empty() {
+            attrs.push((
+                "msg".to_string(),
+                String::from_utf8_lossy(&e.data).to_string(),
+            ));
+        }
+
+        let attrs: Vec<String> = attrs
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, oneline(v).replace('\t', "\\t")))
+            .collect();
+        line.push_str(&attrs.join("\t"));
+        line
+    }
+}
+
+impl Client {
+    /// Write every event logged against a subject to `out`, in
+    /// order, rendered by `format`, one line apiece, returning
+    /// how many were written.
+    ///
+    /// The subject's event IDs are listed up front, and its
+    /// events retrieved (and written) a thousand at a time, so
+    /// that exporting even the largest subjects takes little more
+    /// memory than its IDs; `out` should be buffered (i.e. with a
+    /// `BufWriter`) if it's a file or a socket.  Since audis
+    /// doesn't keep track of the other subjects of each event,
+    /// each is rendered with the subject being exported as its
+    /// only subject.  Events logged while the export is running
+    /// are left out, as are those pruned before they are reached;
+    /// no others are skipped.  Errors are the same as for
+    /// `retrieve()`.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use std::io::BufWriter;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///     let format = audis::export::Syslog::new("billing");
+    ///     let mut out = BufWriter::new(std::fs::File::create("user-42.log").unwrap());
+    ///     client.export_to("user:42", &mut out, &format).unwrap();
+    /// }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, out, format), err, fields(commands))
+    )]
+    pub fn export_to<W: Write + ?Sized>(
+        &self,
+        log: &str,
+        out: &mut W,
+        format: &dyn Format,
+    ) -> AudisResult<u64> {
+        self.instrument("export_to", || {
+            let stored = self.readable(log)?;
+            let stored = stored.as_str();
+            let ids = self.snapshot(stored, || self.lrange(stored, "0", "-1"))?;
+            let mut n = 0;
+            for page in ids.chunks(PAGE) {
+                let mut gone = vec![];
+                for id in page {
+                    self.cancelled()?;
+                    let mut e = match self.fetch(id, Some(stored))? {
+                        Some(e) => e,
+                        None => {
+                            gone.push(id);
+                            continue;
+                        }
+                    };
+                    #[cfg(feature = "crypto")]
+                    self.check_seal(&e)?;
+                    if e.subjects.is_empty() {
+                        e.subjects.push(log.to_string());
+                    }
+                    writeln!(out, "{}", format.render(&e))?;
+                    n += 1;
+                }
+
+                // events pruned since their IDs were listed are
+                // skipped, but dangling references are not.
+                if !gone.is_empty() {
+                    let now: HashSet<String> =
+                        self.lrange(stored, "0", "-1")?.into_iter().collect();
+                    if let Some(id) = gone.into_iter().find(|id| now.contains(*id)) {
+                        return Err(AudisError::NotFound(id.to_string()));
+                    }
+                }
+            }
+            out.flush()?;
+            Ok(n)
+        })
+    }
+}
+
+// The severity of an event on the 0 - 10 scale used by CEF and
+// LEEF, from its `severity` metadata field (if any).
+fn siem_severity(e: &Event) -> Option<u8> {
+    const SCALE: [u8; 8] = [10, 9, 8, 7, 5, 4, 3, 1];
+    let s = e.meta.get("severity")?;
+    match s.parse::<u8>() {
+        Ok(n) => Some(n.min(10)),
+        Err(_) => SEVERITIES
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(s))
+            .map(|n| SCALE[n]),
+    }
+}
+
+// CEF and LEEF header fields escape `\` and `|`.
+fn pipes(s: &str) -> String {
+    oneline(&s.replace('\\', "\\\\").replace('|', "\\|"))
+}
+
+// Syslog header fields are printable ASCII, without spaces, and
+// are limited in length; empty ones are NILVALUE (`-`).
+fn header(s: &str, max: usize) -> String {
+    if s.is_empty() {
+        return "-".to_string();
+    }
+    s.chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max)
+        .collect()
+}
+
+// Structured data parameter values escape `"`, `\` and `]`.
+fn param(s: &str) -> String {
+    oneline(s)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+fn oneline(s: &str) -> String {
+    s.replace('\r', "\\r").replace('\n', "\\n")
+}
+
+// Milliseconds since the epoch, as an RFC 3339 UTC timestamp
+// (i.e. `2016-07-30T23:54:10.259Z`).
+pub(crate) fn rfc3339(ms: u64) -> String {
+    let (days, rem) = ((ms / 86_400_000) as i64, ms % 86_400_000);
+
+    // days since the epoch to a civil date; see
+    // http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3_600_000,
+        rem / 60_000 % 60,
+        rem / 1000 % 60,
+        rem % 1000
+    )
+}
@@ -0,0 +1,255 @@
+//! A C ABI, for logging to (and retrieving from) the audit log
+//! from C and C++ programs.
+//!
+//! The `ffi` feature compiles this in, and (re)generates
+//! `include/audis.h`.  Cargo only builds audis as a Rust library
+//! by default, so that Rust users don't pay for (or trip over)
+//! C libraries they don't need; the shared (or static) library
+//! for C is built on request:
+//!
+//! ```sh
+//! cargo rustc --release --lib --features ffi --crate-type cdylib
+//! cargo rustc --release --lib --features ffi --crate-type staticlib
+//! ```
+//!
+//! and then used like so:
+//!
+//! ```c
+//! #include <audis.h>
+//!
+//! AudisClient *c = audis_connect("redis://127.0.0.1:6379");
+//! if (!c) {
+//!     fprintf(stderr, "audis: %s\n", audis_last_error());
+//!     exit(1);
+//! }
+//!
+//! const char *subjects[] = { "system", "user:42" };
+//! if (audis_log(c, NULL, (const uint8_t *)"{\"ok\":true}", 11, subjects, 2) != 0)
+//!     fprintf(stderr, "audis: %s\n", audis_last_error());
+//!
+//! AudisEvent *events;
+//! size_t n;
+//! if (audis_retrieve(c, "user:42", &events, &n) == 0) {
+//!     for (size_t i = 0; i < n; i++)
+//!         printf("%s: %.*s\n", events[i].id, (int)events[i].len, events[i].data);
+//!     audis_free_events(events, n);
+//! }
+//! audis_free(c);
+//! ```
+//!
+//! Functions that can fail return `NULL` (or `-1`), and leave a
+//! description of what went wrong for `audis_last_error()`.  Any
+//! memory handed back to the caller must be released with the
+//! matching `audis_free*()` function, not with `free(3)`.
+//!
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::{Client, Event};
+
+/// A connection to the audit log, as seen from C.
+pub struct AudisClient {
+    client: Client,
+}
+
+/// An event, as retrieved from the audit log.
+#[repr(C)]
+pub struct AudisEvent {
+    /// The event ID, as a NUL-terminated string.
+    pub id: *mut c_char,
+    /// The event payload (which is not NUL-terminated).
+    pub data: *mut u8,
+    /// How many bytes of payload there are.
+    pub len: usize,
+}
+
+thread_local!(static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) });
+
+// Run `f`, stashing whatever error it fails with (or panics
+// with) for `audis_last_error()`.
+fn guard<T, F: FnOnce() -> Result<T, String>>(f: F) -> Option<T> {
+    let why = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => return Some(v),
+        Ok(Err(why)) => why,
+        Err(_) => "audis panicked".to_string(),
+    };
+    let why = CString::new(why.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(why));
+    None
+}
+
+unsafe fn string(s: *const c_char, what: &str) -> Result<String, String> {
+    if s.is_null() {
+        return Err(format!("{} is NULL", what));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map(String::from)
+        .map_err(|_| format!("{} is not valid UTF-8", what))
+}
+
+/// Connect to the audit log at `url` (i.e.
+/// `redis://127.0.0.1:6379`).
+///
+/// Returns `NULL` on failure.  The client must be released with
+/// `audis_free()`.
+///
+/// # Safety
+///
+/// `url` must be a valid, NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn audis_connect(url: *const c_char) -> *mut AudisClient {
+    guard(|| {
+        let url = string(url, "url")?;
+        let client = Client::connect(&url).map_err(|e| e.to_string())?;
+        Ok(Box::into_raw(Box::new(AudisClient { client })))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Disconnect from the audit log, releasing `client`.
+///
+/// # Safety
+///
+/// `client` must have come from `audis_connect()`, and must not
+/// be used again.  It may be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn audis_free(client: *mut AudisClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Log an event, with `len` bytes of payload from `data`,
+/// against `nsubjects` subjects.  If `id` is `NULL`, a ULID is
+/// generated for it.
+///
+/// Returns `0` on success, and `-1` on failure.
+///
+/// # Safety
+///
+/// `client` must have come from `audis_connect()`; `id` (unless
+/// `NULL`) and every one of the `nsubjects` strings in `subjects`
+/// must be valid, NUL-terminated strings; and `data` must point
+/// to at least `len` bytes (it may be `NULL` if `len` is `0`).
+#[no_mangle]
+pub unsafe extern "C" fn audis_log(
+    client: *mut AudisClient,
+    id: *const c_char,
+    data: *const u8,
+    len: usize,
+    subjects: *const *const c_char,
+    nsubjects: usize,
+) -> c_int {
+    guard(|| {
+        let client = client.as_ref().ok_or("client is NULL")?;
+        let mut e = Event::builder();
+        if !id.is_null() {
+            e = e.id(string(id, "id")?);
+        }
+        if len > 0 {
+            if data.is_null() {
+                return Err("data is NULL".to_string());
+            }
+            e = e.data(std::slice::from_raw_parts(data, len));
+        }
+        if nsubjects > 0 && subjects.is_null() {
+            return Err("subjects is NULL".to_string());
+        }
+        for i in 0..nsubjects {
+            e = e.subject(string(*subjects.add(i), "subject")?);
+        }
+        let e = e.build().map_err(|e| e.to_string())?;
+        client.client.log(&e).map_err(|e| e.to_string())?;
+        Ok(0)
+    })
+    .unwrap_or(-1)
+}
+
+/// Retrieve the events logged against `subject`, in order,
+/// storing a pointer to the first of them in `*events`, and how
+/// many there are in `*n`.
+///
+/// Returns `0` on success, and `-1` on failure.  The events must
+/// be released with `audis_free_events()`.
+///
+/// # Safety
+///
+/// `client` must have come from `audis_connect()`, `subject` must
+/// be a valid, NUL-terminated string, and `events` and `n` must
+/// be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn audis_retrieve(
+    client: *mut AudisClient,
+    subject: *const c_char,
+    events: *mut *mut AudisEvent,
+    n: *mut usize,
+) -> c_int {
+    guard(|| {
+        let client = client.as_ref().ok_or("client is NULL")?;
+        if events.is_null() || n.is_null() {
+            return Err("events (or n) is NULL".to_string());
+        }
+        let subject = string(subject, "subject")?;
+        let found = client
+            .client
+            .retrieve(&subject)
+            .map_err(|e| e.to_string())?;
+
+        let cstring = |s: String| {
+            CString::new(s.replace('\0', ""))
+                .unwrap_or_default()
+                .into_raw()
+        };
+        let found: Box<[AudisEvent]> = found
+            .into_iter()
+            .map(|e| {
+                let data = e.data.into_boxed_slice();
+                AudisEvent {
+                    id: cstring(e.id),
+                    len: data.len(),
+                    data: Box::into_raw(data) as *mut u8,
+                }
+            })
+            .collect();
+        *n = found.len();
+        *events = Box::into_raw(found) as *mut AudisEvent;
+        Ok(0)
+    })
+    .unwrap_or(-1)
+}
+
+/// Release the `n` events retrieved by `audis_retrieve()`.
+///
+/// # Safety
+///
+/// `events` and `n` must be exactly as `audis_retrieve()` left
+/// them, and the events must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn audis_free_events(events: *mut AudisEvent, n: usize) {
+    if events.is_null() {
+        return;
+    }
+    let events = Box::from_raw(ptr::slice_from_raw_parts_mut(events, n));
+    for e in events.iter() {
+        drop(CString::from_raw(e.id));
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(e.data, e.len)));
+    }
+}
+
+/// Describe the last failure (on this thread), or return `NULL`
+/// if nothing has failed yet.
+///
+/// The string belongs to audis, and is only good until the next
+/// call into audis on this thread.
+#[no_mangle]
+pub extern "C" fn audis_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(why) => why.as_ptr(),
+        None => ptr::null(),
+    })
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::{AudisResult, Client, Event, Operation};
+
+/// An endless iterator over the events logged against a
+/// subject, as they arrive; see `Client::follow()`.
+pub struct Follow<'a> {
+    client: &'a Client,
+    subject: String,
+    poll: Duration,
+    last: Option<String>,
+    queue: VecDeque<Event>,
+}
+
+impl Client {
+    /// Follow a subject, yielding each event logged against it
+    /// from now on, as it arrives (like `tail -f`).
+    ///
+    /// The subject is polled for new events every `poll`; the
+    /// iterator blocks between polls, and never ends on its own.
+    /// Errors are yielded as they happen, and following carries
+    /// on after them.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///
+    ///     for e in client.follow("user:42", Duration::from_secs(1)).unwrap() {
+    ///         println!("{}", String::from_utf8_lossy(&e.unwrap().data));
+    ///     }
+    /// }
+    /// ```
+    ///
+    pub fn follow(&self, subject: &str, poll: Duration) -> AudisResult<Follow<'_>> {
+        self.allow(Operation::Retrieve, subject)?;
+        let subject = self.subject(subject).into_owned();
+        let last = self.lrange(&subject, "-1", "-1")?.pop();
+        Ok(Follow {
+            client: self,
+            subject,
+            poll,
+            last,
+            queue: VecDeque::new(),
+        })
+    }
+}
+
+impl Follow<'_> {
+    /// Return the next event logged against the subject, if
+    /// there is one yet, without waiting for it.
+    ///
+    /// This is for callers that have other things to do between
+    /// polls (like noticing that nobody is listening anymore);
+    /// they are then responsible for pacing their own polling.
+    pub fn try_next(&mut self) -> Option<AudisResult<Event>> {
+        if self.queue.is_empty() {
+            if let Err(e) = self.poll() {
+                return Some(Err(e));
+            }
+        }
+        self.queue.pop_front().map(Ok)
+    }
+
+    // Queue up everything logged since the last event we saw.
+    fn poll(&mut self) -> AudisResult<()> {
+        let c = self.client;
+
+        // look back through ever-larger windows at the end of
+        // the subject, until we find the last event we saw, or
+        // run out of subject (if it was truncated or purged).
+        let mut window = 64;
+        let fresh = loop {
+            let ids = c.lrange(&self.subject, &format!("-{}", window), "-1")?;
+            match &self.last {
+                Some(last) => match ids.iter().position(|id| id == last) {
+                    Some(i) => break ids[i + 1..].to_vec(),
+                    None if ids.len() < window => break ids,
+                    None => window *= 2,
+                },
+                None => break ids,
+            }
+        };
+
+        for id in fresh {
+            self.last = Some(id.to_string());
+            if let Some(e) = c.fetch(&id, Some(&self.subject))? {
+                #[cfg(feature = "crypto")]
+                c.check_seal(&e)?;
+                self.queue.push_back(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Follow<'_> {
+    type Item = AudisResult<Event>;
+
+    fn next(&mut self) -> Option<AudisResult<Event>> {
+        loop {
+            match self.try_next() {
+                Some(r) => return Some(r),
+                None => sleep(self.poll),
+            }
+        }
+    }
+}
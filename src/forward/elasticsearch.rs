@@ -0,0 +1,152 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::Sink;
+use crate::export::{rfc3339, timestamp};
+use crate::{AudisError, AudisResult, Event};
+
+/// A sink that indexes events into Elasticsearch (or
+/// OpenSearch), via the bulk API, so that their payloads can be
+/// searched.
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// use audis::forward::{Elasticsearch, Forwarder, Sink};
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+///
+///     // index what's already been logged...
+///     let mut es = Elasticsearch::new("https://es.example.com:9200", "audit")
+///         .basic_auth("audis", "s3cr3t");
+///     es.deliver(&client.retrieve("user:42").unwrap()).unwrap();
+///
+///     // ... and everything logged from now on
+///     let client = client.forward(Forwarder::new(es));
+/// }
+/// ```
+///
+/// Events go into daily indexes, named for the day they were
+/// logged on, i.e. `audit-2016.07.30`, going by the timestamps of
+/// their ULID IDs; events with other IDs go into the index for
+/// the day they are indexed on.  Each document is the event (as
+/// JSON, with its payload as a string, so that payloads of
+/// different shapes don't clash in the index mapping) plus an
+/// `@timestamp`, and has the event ID as its `_id`, so indexing
+/// the same event twice (i.e. when a failed batch is retried)
+/// just overwrites it.
+///
+/// A batch fails if the request does, or if any event in it
+/// can't be indexed.
+///
+/// This requires the `elasticsearch` feature.
+pub struct Elasticsearch {
+    url: String,
+    index: String,
+    auth: Option<Auth>,
+    timeout: Duration,
+}
+
+enum Auth {
+    Basic(String, String),
+    ApiKey(String),
+}
+
+impl Elasticsearch {
+    /// Index events into the cluster at `url` (i.e.
+    /// `https://es.example.com:9200`), in daily indexes named
+    /// after `index`, giving up on requests that take longer
+    /// than 30 seconds.
+    pub fn new(url: &str, index: &str) -> Elasticsearch {
+        Elasticsearch {
+            url: format!("{}/_bulk", url.trim_end_matches('/')),
+            index: index.to_string(),
+            auth: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Authenticate with a username and password.
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Elasticsearch {
+        self.auth = Some(Auth::Basic(username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Authenticate with an (encoded) API key.
+    pub fn api_key(mut self, key: &str) -> Elasticsearch {
+        self.auth = Some(Auth::ApiKey(key.to_string()));
+        self
+    }
+
+    /// Give up on requests that take longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Elasticsearch {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Sink for Elasticsearch {
+    fn deliver(&mut self, events: &[Event]) -> AudisResult<()> {
+        let codec = |e: serde_json::Error| AudisError::Codec(e.to_string());
+        let failed = |why: String| AudisError::Forward(format!("{}: {}", self.url, why));
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let mut body = vec![];
+        for e in events {
+            let ts = rfc3339(timestamp(&e.id).unwrap_or(now));
+            let action = serde_json::json!({
+                "index": {
+                    "_index": format!("{}-{}", self.index, ts[..10].replace('-', ".")),
+                    "_id": e.id,
+                }
+            });
+            let mut doc = serde_json::to_value(e).map_err(codec)?;
+            doc["data"] = serde_json::json!(String::from_utf8_lossy(&e.data));
+            doc["@timestamp"] = serde_json::json!(ts);
+
+            serde_json::to_writer(&mut body, &action).map_err(codec)?;
+            body.push(b'\n');
+            serde_json::to_writer(&mut body, &doc).map_err(codec)?;
+            body.push(b'\n');
+        }
+
+        let mut req = attohttpc::post(&self.url)
+            .timeout(self.timeout)
+            .header("Content-Type", "application/x-ndjson");
+        match &self.auth {
+            Some(Auth::Basic(username, password)) => req = req.basic_auth(username, Some(password)),
+            Some(Auth::ApiKey(key)) => req = req.header("Authorization", format!("ApiKey {}", key)),
+            None => (),
+        }
+        let resp = req.bytes(body).send().map_err(|e| failed(e.to_string()))?;
+        if !resp.is_success() {
+            return Err(failed(format!("responded with {}", resp.status())));
+        }
+
+        // a 200 OK can still hide per-event failures
+        let resp = resp.bytes().map_err(|e| failed(e.to_string()))?;
+        let resp: serde_json::Value = serde_json::from_slice(&resp).map_err(codec)?;
+        if resp["errors"] == true {
+            let item = resp["items"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|item| &item["index"])
+                .find(|item| !item["error"].is_null());
+            return Err(failed(match item {
+                Some(item) => format!(
+                    "failed to index event {}: {}",
+                    item["_id"].as_str().unwrap_or("?"),
+                    item["error"]["reason"].as_str().unwrap_or("unknown error")
+                ),
+                None => "failed to index events".to_string(),
+            }));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+use rdkafka::ClientContext;
+
+use super::Sink;
+use crate::{AudisError, AudisResult, Event};
+
+/// A sink that publishes events to a Kafka topic.
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// use audis::forward::{Forwarder, Kafka};
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .forward(Forwarder::new(
+///             Kafka::new("kafka1:9092,kafka2:9092", "audit").set("compression.type", "zstd"),
+///         ));
+/// }
+/// ```
+///
+/// Each event is published as its own message, keyed by its
+/// first subject (so that the events of a subject stay in order,
+/// on one partition), with the event (as JSON) as the value, and
+/// its ID in an `audis-id` header.
+///
+/// The producer is idempotent, and waits for every in-sync
+/// replica to acknowledge each message, so the broker won't
+/// duplicate messages on its own account; but a failed batch is
+/// published again in its entirety, so consumers that need
+/// exactly-once semantics should deduplicate by event ID.
+///
+/// The producer is created when the first batch is delivered;
+/// configuration errors surface as failed deliveries.
+///
+/// This requires the `kafka` feature, and builds librdkafka.
+pub struct Kafka {
+    topic: String,
+    config: ClientConfig,
+    timeout: Duration,
+    producer: Option<BaseProducer<Deliveries>>,
+}
+
+impl Kafka {
+    /// Publish events to `topic`, via the (comma-separated)
+    /// bootstrap `brokers`, giving up on batches that haven't been
+    /// acknowledged within 30 seconds.
+    pub fn new(brokers: &str, topic: &str) -> Kafka {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .set("acks", "all");
+        Kafka {
+            topic: topic.to_string(),
+            config,
+            timeout: Duration::from_secs(30),
+            producer: None,
+        }
+    }
+
+    /// Set a librdkafka producer configuration property (i.e.
+    /// `security.protocol`, or `sasl.username`).
+    pub fn set(mut self, key: &str, value: &str) -> Kafka {
+        self.config.set(key, value);
+        self
+    }
+
+    /// Give up on batches that haven't been acknowledged within
+    /// `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Kafka {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Sink for Kafka {
+    fn deliver(&mut self, events: &[Event]) -> AudisResult<()> {
+        let topic = &self.topic;
+        let failed = |why: String| AudisError::Forward(format!("kafka topic {}: {}", topic, why));
+        if self.producer.is_none() {
+            let producer = self
+                .config
+                .create_with_context(Deliveries::default())
+                .map_err(|e| failed(e.to_string()))?;
+            self.producer = Some(producer);
+        }
+        let producer = self.producer.as_ref().unwrap();
+
+        for e in events {
+            let value = serde_json::to_vec(e).map_err(|e| AudisError::Codec(e.to_string()))?;
+            let mut record = BaseRecord::to(topic)
+                .key(e.subjects.first().map(String::as_str).unwrap_or(""))
+                .payload(&value)
+                .headers(OwnedHeaders::new().insert(Header {
+                    key: "audis-id",
+                    value: Some(&e.id),
+                }));
+            loop {
+                match producer.send(record) {
+                    Ok(()) => break,
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), r)) => {
+                        producer.poll(Duration::from_millis(100));
+                        record = r;
+                    }
+                    Err((e, _)) => return Err(failed(e.to_string())),
+                }
+            }
+        }
+
+        let flushed = producer.flush(self.timeout);
+        let failure = producer.context().failure.lock().unwrap().take();
+        match (flushed, failure) {
+            (_, Some(why)) => Err(failed(why)),
+            (Err(e), None) => Err(failed(e.to_string())),
+            (Ok(()), None) => Ok(()),
+        }
+    }
+}
+
+// Keeps track of messages that couldn't be delivered.
+#[derive(Default)]
+struct Deliveries {
+    failure: Mutex<Option<String>>,
+}
+
+impl ClientContext for Deliveries {}
+
+impl ProducerContext for Deliveries {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+        if let Err((e, _)) = result {
+            self.failure.lock().unwrap().get_or_insert(e.to_string());
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Forwarding of events to other systems, as they are logged.
+//!
+//! A `Forwarder` pairs a `Sink` (somewhere to deliver events to)
+//! with a background thread, which batches up events as they are
+//! logged, and delivers them, retrying failed deliveries with
+//! exponential backoff.  Forwarders are attached to a `Client`
+//! via `Client::forward()`:
+//!
+//! ```rust,no_run
+//! extern crate audis;
+//! use audis::forward::Forwarder;
+//!
+//! fn main() {
+//!     let client = audis::Client::connect("redis://127.0.0.1:6379")
+//!         .unwrap()
+//!         .forward(
+//!             Forwarder::new(|events: &[audis::Event]| {
+//!                 println!("logged {} event(s)", events.len());
+//!                 Ok(())
+//!             })
+//!             .subjects("user:*"),
+//!         );
+//!
+//!     // ... events logged against user:* subjects are now
+//!     //     printed out, as well as logged ...
+//! }
+//! ```
+//!
+//! Forwarding is best-effort: events are queued in memory, and
+//! are dropped (with an error, via the `log` crate) if the queue
+//! fills up, or if every attempt to deliver them fails.  Since a
+//! failed batch is delivered again in its entirety, sinks should
+//! deduplicate by event ID, where they can.
+//!
+//! Events are forwarded as they were logged, i.e. after any
+//! interceptors and pseudonymization, but before compression or
+//! encryption; only the subjects the event was actually logged
+//! against (see `Client::dedup()`) are included.
+//!
+
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use crate::backend::glob;
+use crate::{AudisResult, Client, Event};
+
+mod syslog;
+pub use self::syslog::Syslog;
+
+#[cfg(feature = "elasticsearch")]
+mod elasticsearch;
+#[cfg(feature = "elasticsearch")]
+pub use self::elasticsearch::Elasticsearch;
+
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "kafka")]
+pub use self::kafka::Kafka;
+
+#[cfg(feature = "nats")]
+mod nats;
+#[cfg(feature = "nats")]
+pub use self::nats::Nats;
+
+#[cfg(feature = "splunk")]
+mod splunk;
+#[cfg(feature = "splunk")]
+pub use self::splunk::Splunk;
+
+#[cfg(feature = "webhook")]
+mod webhook;
+#[cfg(feature = "webhook")]
+pub use self::webhook::Webhook;
+
+/// Somewhere that events can be forwarded to.
+///
+/// Closures of the form `FnMut(&[Event]) -> AudisResult<()>`
+/// are sinks too.
+pub trait Sink: Send {
+    /// Deliver a batch of events, in the order they were logged.
+    ///
+    /// If this fails, the whole batch will be delivered again,
+    /// after a short wait, until the forwarder runs out of
+    /// retries.
+    fn deliver(&mut self, events: &[Event]) -> AudisResult<()>;
+}
+
+impl<F> Sink for F
+where
+    F: FnMut(&[Event]) -> AudisResult<()> + Send,
+{
+    fn deliver(&mut self, events: &[Event]) -> AudisResult<()> {
+        self(events)
+    }
+}
+
+/// A sink, along with how (and which) events should be
+/// forwarded to it.
+pub struct Forwarder {
+    sink: Box<dyn Sink>,
+    patterns: Vec<String>,
+    batch: usize,
+    queue: usize,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Forwarder {
+    /// Forward every event to `sink`, in batches of up to 100,
+    /// retrying failed deliveries up to 5 times, starting 100ms
+    /// after the first failure.  Up to 10,000 events can be
+    /// queued up for delivery.
+    pub fn new<S: Sink + 'static>(sink: S) -> Forwarder {
+        Forwarder {
+            sink: Box::new(sink),
+            patterns: vec![],
+            batch: 100,
+            queue: 10_000,
+            retries: 5,
+            backoff: Duration::from_millis(100),
+        }
+    }
+
+    /// Only forward events logged against at least one subject
+    /// that matches the glob `pattern` (i.e. `user:*`).  This
+    /// can be given more than once.
+    pub fn subjects(mut self, pattern: &str) -> Forwarder {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Deliver up to `n` events at a time.
+    pub fn batch(mut self, n: usize) -> Forwarder {
+        self.batch = n.max(1);
+        self
+    }
+
+    /// Queue up to `n` events for delivery, before dropping
+    /// them.
+    pub fn queue(mut self, n: usize) -> Forwarder {
+        self.queue = n.max(1);
+        self
+    }
+
+    /// Retry each failed delivery up to `n` times.
+    pub fn retries(mut self, n: u32) -> Forwarder {
+        self.retries = n;
+        self
+    }
+
+    /// Wait `backoff` before retrying a failed delivery, twice
+    /// as long before the retry after that, and so on.
+    pub fn backoff(mut self, backoff: Duration) -> Forwarder {
+        self.backoff = backoff;
+        self
+    }
+
+    // Start delivering events from a background thread, which
+    // runs until every client sharing this forwarder is gone.
+    fn start(self) -> Forwarding {
+        let (tx, rx) = sync_channel(self.queue);
+        let Forwarder {
+            mut sink,
+            patterns,
+            batch,
+            retries,
+            backoff,
+            ..
+        } = self;
+        spawn(move || deliver(&mut *sink, rx, batch, retries, backoff));
+        Forwarding { patterns, tx }
+    }
+}
+
+// A running forwarder, as seen by the clients feeding it.
+pub(crate) struct Forwarding {
+    patterns: Vec<String>,
+    tx: SyncSender<Event>,
+}
+
+impl Forwarding {
+    // Queue an event for delivery, as logged against `subjects`,
+    // if it's of any interest.
+    fn offer(&self, e: &Event, subjects: &[&String]) {
+        if !self.patterns.is_empty()
+            && !subjects.iter().any(|s| {
+                self.patterns
+                    .iter()
+                    .any(|p| glob(p.as_bytes(), s.as_bytes()))
+            })
+        {
+            return;
+        }
+
+        let mut e = e.clone();
+        e.subjects = subjects.iter().map(|s| s.to_string()).collect();
+        match self.tx.try_send(e) {
+            Ok(()) => (),
+            Err(TrySendError::Full(e)) => {
+                log::error!(target: "audis", "forwarding queue is full; dropping event {}", e.id)
+            }
+            Err(TrySendError::Disconnected(e)) => {
+                log::error!(target: "audis", "forwarder has stopped; dropping event {}", e.id)
+            }
+        }
+    }
+}
+
+fn deliver(
+    sink: &mut dyn Sink,
+    rx: Receiver<Event>,
+    batch: usize,
+    retries: u32,
+    backoff: Duration,
+) {
+    while let Ok(first) = rx.recv() {
+        let mut events = vec![first];
+        while events.len() < batch {
+            match rx.try_recv() {
+                Ok(e) => events.push(e),
+                Err(_) => break,
+            }
+        }
+
+        let mut wait = backoff;
+        for attempt in 0..=retries {
+            match sink.deliver(&events) {
+                Ok(()) => break,
+                Err(err) if attempt < retries => {
+                    log::warn!(target: "audis", "failed to forward {} event(s) (will retry): {}", events.len(), err);
+                    sleep(wait);
+                    wait *= 2;
+                }
+                Err(err) => {
+                    log::error!(target: "audis", "failed to forward {} event(s): {}", events.len(), err)
+                }
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Forward every event logged through this client (or any
+    /// client made from it, i.e. via `background()`) from now on,
+    /// according to `f`; see `audis::forward`.
+    ///
+    /// Any number of forwarders can be attached to a client;
+    /// each one delivers events on its own.
+    pub fn forward(mut self, f: Forwarder) -> Client {
+        self.forwarders.push(Arc::new(f.start()));
+        self
+    }
+
+    // Hand a freshly logged event (as logged against `subjects`)
+    // to every forwarder interested in it.
+    pub(crate) fn forwarded(&self, e: &Event, subjects: &[&String]) {
+        for f in &self.forwarders {
+            f.offer(e, subjects);
+        }
+    }
+}
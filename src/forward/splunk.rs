@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use super::Sink;
+use crate::backend::glob;
+use crate::export::timestamp;
+use crate::{AudisError, AudisResult, Event};
+
+/// A sink that sends batches of events to a Splunk HTTP Event
+/// Collector (HEC).
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// use audis::forward::{Forwarder, Splunk};
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .forward(
+///             Forwarder::new(
+///                 Splunk::new("https://splunk.example.com:8088", "0a1b2c3d-...")
+///                     .index("audit")
+///                     .index_for("payments:*", "pci_audit"),
+///             )
+///             .batch(500),
+///         );
+/// }
+/// ```
+///
+/// Each event is sent (as JSON, with payloads that are JSON
+/// themselves embedded as such, so that Splunk can extract
+/// fields from them) with its ULID timestamp, if it has one.
+/// Batching, retries and backoff are up to the `Forwarder`; any
+/// response other than a 2xx (i.e. a 503, when the collector is
+/// busy) counts as a failed delivery.
+///
+/// This requires the `splunk` feature.
+pub struct Splunk {
+    url: String,
+    token: String,
+    index: Option<String>,
+    indexes: Vec<(String, String)>,
+    host: Option<String>,
+    source: Option<String>,
+    sourcetype: String,
+    timeout: Duration,
+}
+
+impl Splunk {
+    /// Send events to the collector at `url` (i.e.
+    /// `https://splunk.example.com:8088`), authenticating with
+    /// the HEC `token`.  Events are sent to the token's default
+    /// index, with a sourcetype of `audis`, giving up on requests
+    /// that take longer than 30 seconds.
+    pub fn new(url: &str, token: &str) -> Splunk {
+        Splunk {
+            url: format!("{}/services/collector/event", url.trim_end_matches('/')),
+            token: token.to_string(),
+            index: None,
+            indexes: vec![],
+            host: None,
+            source: None,
+            sourcetype: "audis".to_string(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Send events to `index`, rather than the token's default.
+    pub fn index(mut self, index: &str) -> Splunk {
+        self.index = Some(index.to_string());
+        self
+    }
+
+    /// Send events logged against any subject matching the glob
+    /// `pattern` (i.e. `payments:*`) to `index`.  This can be
+    /// given more than once; the first matching pattern wins.
+    pub fn index_for(mut self, pattern: &str, index: &str) -> Splunk {
+        self.indexes.push((pattern.to_string(), index.to_string()));
+        self
+    }
+
+    /// Tag events with a host, rather than letting Splunk decide.
+    pub fn host(mut self, host: &str) -> Splunk {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    /// Tag events with a source, rather than letting Splunk
+    /// decide.
+    pub fn source(mut self, source: &str) -> Splunk {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    /// Tag events with a different sourcetype.
+    pub fn sourcetype(mut self, sourcetype: &str) -> Splunk {
+        self.sourcetype = sourcetype.to_string();
+        self
+    }
+
+    /// Give up on requests that take longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Splunk {
+        self.timeout = timeout;
+        self
+    }
+
+    // The index an event should go to, if not the default.
+    fn index_of(&self, e: &Event) -> Option<&String> {
+        self.indexes
+            .iter()
+            .find(|(p, _)| e.subjects.iter().any(|s| glob(p.as_bytes(), s.as_bytes())))
+            .map(|(_, index)| index)
+            .or(self.index.as_ref())
+    }
+}
+
+impl Sink for Splunk {
+    fn deliver(&mut self, events: &[Event]) -> AudisResult<()> {
+        let codec = |e: serde_json::Error| AudisError::Codec(e.to_string());
+        let failed = |why: String| AudisError::Forward(format!("{}: {}", self.url, why));
+
+        // HEC takes any number of events, back to back
+        let mut body = vec![];
+        for e in events {
+            let mut event = serde_json::to_value(e).map_err(codec)?;
+            if let Ok(data) = serde_json::from_slice::<serde_json::Value>(&e.data) {
+                event["data"] = data;
+            }
+            let mut envelope = serde_json::json!({
+                "sourcetype": self.sourcetype,
+                "event": event,
+            });
+            if let Some(ms) = timestamp(&e.id) {
+                envelope["time"] = serde_json::json!(ms as f64 / 1000.0);
+            }
+            if let Some(index) = self.index_of(e) {
+                envelope["index"] = serde_json::json!(index);
+            }
+            if let Some(host) = &self.host {
+                envelope["host"] = serde_json::json!(host);
+            }
+            if let Some(source) = &self.source {
+                envelope["source"] = serde_json::json!(source);
+            }
+            serde_json::to_writer(&mut body, &envelope).map_err(codec)?;
+        }
+
+        let resp = attohttpc::post(&self.url)
+            .timeout(self.timeout)
+            .header("Authorization", format!("Splunk {}", self.token))
+            .header("Content-Type", "application/json")
+            .bytes(body)
+            .send()
+            .map_err(|e| failed(e.to_string()))?;
+        if !resp.is_success() {
+            let status = resp.status();
+            let why = resp.text().unwrap_or_default();
+            return Err(failed(format!("responded with {}: {}", status, why.trim())));
+        }
+        Ok(())
+    }
+}
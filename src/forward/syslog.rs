@@ -0,0 +1,132 @@
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use super::Sink;
+use crate::export::{self, Format};
+use crate::{AudisError, AudisResult, Event};
+
+/// A sink that sends events to a syslog collector, over UDP or
+/// TCP, as RFC 5424 messages (see `audis::export::Syslog`).
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// use audis::export;
+/// use audis::forward::{Forwarder, Syslog};
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .forward(Forwarder::new(
+///             Syslog::tcp("logs.example.com:601").format(export::Syslog::new("billing")),
+///         ));
+/// }
+/// ```
+///
+/// Over UDP, each event is sent as its own datagram.  Over TCP,
+/// messages are framed by octet counting (RFC 6587), and the
+/// connection is made when the first batch is delivered, and
+/// made again after any failure.  A batch that fails part way
+/// through is sent again in its entirety, so collectors may see
+/// some events more than once.
+pub struct Syslog {
+    to: Transport,
+    format: Box<dyn Format>,
+}
+
+enum Transport {
+    Udp(UdpSocket),
+    Tcp(String, Duration, Option<TcpStream>),
+}
+
+impl Syslog {
+    /// Send events to `addr` (i.e. `127.0.0.1:514`) over UDP.
+    pub fn udp(addr: &str) -> AudisResult<Syslog> {
+        let failed = |why: String| AudisError::Forward(format!("{}: {}", addr, why));
+        let to = addr
+            .to_socket_addrs()
+            .map_err(|e| failed(e.to_string()))?
+            .next()
+            .ok_or_else(|| failed("no such address".to_string()))?;
+        let from = if to.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(from).map_err(|e| failed(e.to_string()))?;
+        socket.connect(to).map_err(|e| failed(e.to_string()))?;
+        Ok(Syslog::new(Transport::Udp(socket)))
+    }
+
+    /// Send events to `addr` (i.e. `127.0.0.1:601`) over TCP,
+    /// giving up on writes that take longer than 30 seconds.
+    pub fn tcp(addr: &str) -> Syslog {
+        Syslog::new(Transport::Tcp(
+            addr.to_string(),
+            Duration::from_secs(30),
+            None,
+        ))
+    }
+
+    fn new(to: Transport) -> Syslog {
+        Syslog {
+            to,
+            format: Box::new(export::Syslog::new("audis")),
+        }
+    }
+
+    /// Render events with `format`, rather than as syslog
+    /// messages from an app named `audis`.
+    pub fn format<F: Format + 'static>(mut self, format: F) -> Syslog {
+        self.format = Box::new(format);
+        self
+    }
+
+    /// Give up on TCP connections and writes that take longer
+    /// than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Syslog {
+        if let Transport::Tcp(_, t, _) = &mut self.to {
+            *t = timeout;
+        }
+        self
+    }
+}
+
+impl Sink for Syslog {
+    fn deliver(&mut self, events: &[Event]) -> AudisResult<()> {
+        match &mut self.to {
+            Transport::Udp(socket) => {
+                for e in events {
+                    socket
+                        .send(self.format.render(e).as_bytes())
+                        .map_err(|e| AudisError::Forward(e.to_string()))?;
+                }
+                Ok(())
+            }
+            Transport::Tcp(addr, timeout, conn) => {
+                let failed = |why: String| AudisError::Forward(format!("{}: {}", addr, why));
+                if conn.is_none() {
+                    let to = addr
+                        .to_socket_addrs()
+                        .map_err(|e| failed(e.to_string()))?
+                        .next()
+                        .ok_or_else(|| failed("no such address".to_string()))?;
+                    let stream = TcpStream::connect_timeout(&to, *timeout)
+                        .map_err(|e| failed(e.to_string()))?;
+                    stream
+                        .set_write_timeout(Some(*timeout))
+                        .map_err(|e| failed(e.to_string()))?;
+                    *conn = Some(stream);
+                }
+
+                let mut frames = vec![];
+                for e in events {
+                    let msg = self.format.render(e);
+                    frames.extend_from_slice(format!("{} {}", msg.len(), msg).as_bytes());
+                }
+                let stream = conn.as_mut().unwrap();
+                if let Err(e) = stream.write_all(&frames).and_then(|_| stream.flush()) {
+                    *conn = None;
+                    return Err(failed(e.to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use attohttpc::header::{HeaderName, HeaderValue};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::Sink;
+use crate::{AudisError, AudisResult, Event};
+
+/// A sink that POSTs batches of events, as a JSON array, to a
+/// webhook.
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// use audis::forward::{Forwarder, Webhook};
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .forward(
+///             Forwarder::new(Webhook::new("https://hooks.example.com/audit").sign(b"s3cr3t"))
+///                 .subjects("user:*"),
+///         );
+/// }
+/// ```
+///
+/// If a secret is given, each request carries an
+/// `X-Audis-Signature` header, holding `sha256=` and the
+/// hex-encoded HMAC-SHA256 of the request body (keyed by the
+/// secret), so that the receiving end can check where it came
+/// from.  Any response other than a 2xx counts as a failed
+/// delivery.  To deliver to more than one webhook, give each
+/// its own forwarder.
+///
+/// This requires the `webhook` feature.
+pub struct Webhook {
+    url: String,
+    secret: Option<Vec<u8>>,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl Webhook {
+    /// Deliver events to `url`, giving up on requests that take
+    /// longer than 30 seconds.
+    pub fn new(url: &str) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            secret: None,
+            headers: vec![],
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Sign each request body with `secret`.
+    pub fn sign(mut self, secret: &[u8]) -> Webhook {
+        self.secret = Some(secret.to_vec());
+        self
+    }
+
+    /// Send an extra header (i.e. `Authorization`) with each
+    /// request.
+    pub fn header(mut self, name: &str, value: &str) -> Webhook {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Give up on requests that take longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Webhook {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Sink for Webhook {
+    fn deliver(&mut self, events: &[Event]) -> AudisResult<()> {
+        let body = serde_json::to_vec(events).map_err(|e| AudisError::Codec(e.to_string()))?;
+        let failed = |why: String| AudisError::Forward(format!("{}: {}", self.url, why));
+
+        let mut req = attohttpc::post(&self.url)
+            .timeout(self.timeout)
+            .header("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            let bad = |e: String| AudisError::Invalid(format!("header {}: {}", name, e));
+            req = req.header(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| bad(e.to_string()))?,
+                HeaderValue::from_str(value).map_err(|e| bad(e.to_string()))?,
+            );
+        }
+        if let Some(secret) = &self.secret {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+                .map_err(|e| AudisError::Invalid(e.to_string()))?;
+            mac.update(&body);
+            let sig: String = mac
+                .finalize()
+                .into_bytes()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            req = req.header("X-Audis-Signature", format!("sha256={}", sig));
+        }
+
+        let resp = req.bytes(body).send().map_err(|e| failed(e.to_string()))?;
+        if !resp.is_success() {
+            return Err(failed(format!("responded with {}", resp.status())));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{AudisResult, Client};
+
+/// The findings of a consistency check of the audit log, as
+/// reported by `Client::fsck()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fsck {
+    /// Events whose reference counts don't match the number of
+    /// subjects that actually reference them, as (ID, recorded
+    /// count, actual count) triples.
+    pub miscounted: Vec<(String, i64, i64)>,
+
+    /// References from subjects to events whose payloads are
+    /// missing, as (subject, ID) pairs.  These make `retrieve()`
+    /// fail with `AudisError::NotFound`.
+    pub dangling: Vec<(String, String)>,
+
+    /// Events that no subject references, and so can never be
+    /// retrieved.
+    pub orphans: Vec<String>,
+
+    /// Keys that don't belong to any event, subject, or other
+    /// audis bookkeeping.  These are never removed.
+    pub unknown: Vec<String>,
+
+    /// Whether or not the problems found (except for unknown
+    /// keys) have been repaired.
+    pub repaired: bool,
+}
+
+impl Fsck {
+    /// Returns true if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.miscounted.is_empty()
+            && self.dangling.is_empty()
+            && self.orphans.is_empty()
+            && self.unknown.is_empty()
+    }
+}
+
+impl Client {
+    /// Check the audit log for consistency, and (if `repair` is
+    /// set) fix whatever problems are found.
+    ///
+    /// Every subject is walked to count the references to each
+    /// event, and those counts are compared against the recorded
+    /// reference counts.  Repairs correct miscounted references,
+    /// remove dangling references from subjects, and delete
+    /// orphaned events.  Unknown keys are reported, but never
+    /// touched.
+    ///
+    /// Like `stats()`, this walks the entire keyspace, and should
+    /// not be run concurrently with anything that modifies the
+    /// audit log, lest it mistake an event being logged for an
+    /// orphan.
+    ///
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn fsck(&self, repair: bool) -> AudisResult<Fsck> {
+        self.instrument("fsck", || {
+            let mut report = self.check_consistency()?;
+            if repair {
+                for (id, _, actual) in &report.miscounted {
+                    self.set_refcount(id, *actual)?;
+                }
+                for (subject, id) in &report.dangling {
+                    self.query::<()>(redis::cmd("LREM").arg(subject).arg(0).arg(id))?;
+                    self.unstamp(subject, &[id.to_string()])?;
+                    self.del(id)?;
+                }
+                for id in &report.orphans {
+                    self.del(id)?;
+                }
+                let mut removed: Vec<String> = report.orphans.clone();
+                removed.extend(report.dangling.iter().map(|(_, id)| id.to_string()));
+                self.record("fsck", "*", &removed)?;
+                report.repaired = true;
+            }
+            Ok(report)
+        })
+    }
+
+    /// Delete every orphaned event (see `fsck()`) from the audit
+    /// log, returning how many were deleted.
+    ///
+    /// This also sweeps the IDs of events that no longer exist
+    /// out of every view (see `Client::view()`), which are
+    /// otherwise never pruned.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn gc(&self) -> AudisResult<u64> {
+        self.instrument("gc", || {
+            let orphans = self.check_consistency()?.orphans;
+            for id in &orphans {
+                self.del(id)?;
+            }
+            if !orphans.is_empty() {
+                self.record("gc", "*", &orphans)?;
+            }
+            self.sweep_views()?;
+            Ok(orphans.len() as u64)
+        })
+    }
+
+    fn check_consistency(&self) -> AudisResult<Fsck> {
+        let mut report = Fsck::default();
+
+        let subjects: HashSet<String> = self.smembers("subjects")?.into_iter().collect();
+        let mut refs: HashMap<String, i64> = HashMap::new();
+        let mut lists = vec![];
+        for s in &subjects {
+            self.cancelled()?;
+            for id in self.lrange(s, "0", "-1")? {
+                *refs.entry(id.to_string()).or_insert(0) += 1;
+                lists.push((s.to_string(), id));
+            }
+        }
+
+        let keys = self.scan("SCAN", None, "*")?;
+        let mut events: Vec<String> = keys
+            .iter()
+            .filter_map(|k| k.strip_prefix("audit:"))
+            .filter(|k| !crate::PARALLEL.iter().any(|p| k.ends_with(p)))
+            .map(String::from)
+            .collect();
+        events.sort();
+        let known: HashSet<&str> = events.iter().map(String::as_str).collect();
+
+        for chunk in events.chunks(1000) {
+            self.cancelled()?;
+            let recorded = self.refcounts(chunk)?;
+            for (id, recorded) in chunk.iter().zip(recorded) {
+                let actual = refs.get(id).copied().unwrap_or(0);
+                if actual == 0 {
+                    report.orphans.push(id.to_string());
+                } else if recorded.unwrap_or(0) != actual {
+                    report
+                        .miscounted
+                        .push((id.to_string(), recorded.unwrap_or(0), actual));
+                }
+            }
+        }
+
+        for (s, id) in lists {
+            if !known.contains(id.as_str()) {
+                report.dangling.push((s, id));
+            }
+        }
+
+        for k in keys {
+            let ours = match k.strip_prefix("audit:") {
+                Some(rest) => crate::PARALLEL.iter().all(|p| match rest.strip_suffix(p) {
+                    Some(id) => known.contains(id) || refs.contains_key(id),
+                    None => true,
+                }),
+                None => k.starts_with("audis:") || k == "subjects" || subjects.contains(&k),
+            };
+            if !ours {
+                report.unknown.push(k);
+            }
+        }
+        report.unknown.sort();
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+use crate::{AudisResult, Client};
+
+/// A point-in-time diagnostic report on an audit log backend.
+///
+/// This is cheap enough to compute that it can be wired into
+/// readiness probes; none of the figures require scanning the
+/// keyspace.
+#[derive(Debug, Clone)]
+pub struct Health {
+    /// How long a round trip (`PING`) to the backend took.
+    pub latency: Duration,
+
+    /// The version of Redis the backend is running, if the
+    /// backend is a Redis instance.
+    pub server_version: Option<String>,
+
+    /// The keying structure version recorded in the backend,
+    /// if any.  See `audis::SCHEMA_VERSION`.
+    pub schema_version: Option<u32>,
+
+    /// How many keys, in total, exist in the backend.  Since
+    /// an audit log has its Redis instance all to itself, this
+    /// approximates the number of audis keys.
+    pub keys: u64,
+
+    /// How many subjects are known to the audit log.
+    pub subjects: u64,
+
+    /// How many bytes of memory the backend is using, if it
+    /// reports such things.
+    pub memory: Option<u64>,
+}
+
+impl Client {
+    /// Check the health of the audit log backend.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn health(&self) -> AudisResult<Health> {
+        self.instrument("health", || {
+            let start = Instant::now();
+            self.ping()?;
+            let latency = start.elapsed();
+
+            let info: redis::InfoDict = self.query(&mut redis::cmd("INFO"))?;
+            Ok(Health {
+                latency,
+                server_version: info.get("redis_version"),
+                schema_version: self.query(redis::cmd("GET").arg("audis:schema"))?,
+                keys: self.query(&mut redis::cmd("DBSIZE"))?,
+                subjects: self.query(redis::cmd("SCARD").arg("subjects"))?,
+                memory: info.get("used_memory"),
+            })
+        })
+    }
+}
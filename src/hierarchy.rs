@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ids::timestamp;
+use crate::{AudisError, AudisResult, Client, Event, Operation, SubjectOrder};
+
+/// The known subjects, arranged into a hierarchy by splitting
+/// their names on `:`, as returned by `Client::subjects_tree()`.
+///
+/// Each node is keyed by its part of the name, so `user:42` is
+/// the `42` child of the `user` child of the root.  Nodes that
+/// are only groupings of other subjects (i.e. `user`, if
+/// nothing was ever logged against `user` itself) are not
+/// subjects in their own right.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubjectTree {
+    /// Whether events have been logged against this node's
+    /// subject itself.
+    pub subject: bool,
+
+    /// How many events are logged against this node's subject
+    /// itself.
+    pub events: u64,
+
+    /// The subjects beneath this one, by the next part of their
+    /// names.
+    pub children: BTreeMap<String, SubjectTree>,
+}
+
+impl SubjectTree {
+    /// Look up the node for the subject (or grouping) `name`.
+    pub fn get(&self, name: &str) -> Option<&SubjectTree> {
+        name.split(':')
+            .try_fold(self, |node, part| node.children.get(part))
+    }
+
+    /// Count the events logged against this node's subject, and
+    /// every subject beneath it.  Events logged against more than
+    /// one of them are counted more than once.
+    pub fn total(&self) -> u64 {
+        self.events + self.children.values().map(SubjectTree::total).sum::<u64>()
+    }
+}
+
+impl Client {
+    /// Retrieve the events logged against a subject, and every
+    /// subject beneath it in the hierarchy of `:`-separated names
+    /// (i.e. `user`, `user:42` and `user:42:sessions` all fall
+    /// under `user`), merged into one chronological list.
+    ///
+    /// Each subject's events are kept in the order they were
+    /// logged, and interleaved with the others' by the timestamps
+    /// of their IDs (see `ids::timestamp()`); events without a
+    /// timestamp stay put behind their subject's previous event.
+    /// Events logged against more than one of the subjects are
+    /// only retrieved once, with their `subjects` listing which
+    /// of them they were found in (as stored; see
+    /// `subjects_matching()`).  Subjects that the client's access
+    /// policy (if any) does not allow retrieving are left out, and
+    /// pseudonymized subject names (see `pseudonymize()`) have no
+    /// hierarchy to speak of.  Errors are otherwise the same as
+    /// for `retrieve()`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_subtree(&self, subject: &str) -> AudisResult<Vec<Event>> {
+        self.instrument("retrieve_subtree", || {
+            let top = self.subject(subject).into_owned();
+            // escaping is applied to names as a whole, so the
+            // children's prefix has to be escaped as one.
+            let prefix = self.subject(&format!("{}:", subject)).into_owned();
+            let mut subjects: Vec<String> = self
+                .scan(
+                    "SSCAN",
+                    Some("subjects"),
+                    &format!("{}*", glob_escape(&prefix)),
+                )?
+                .into_iter()
+                .filter(|s| s.starts_with(&prefix))
+                .collect();
+            subjects.push(top);
+            subjects.retain(|s| self.allows_stored(Operation::Retrieve, s));
+            subjects.sort();
+            subjects.dedup();
+
+            let mut found: Vec<((u64, usize, usize), Event)> = vec![];
+            let mut seen: HashMap<String, usize> = HashMap::new();
+            for (n, s) in subjects.iter().enumerate() {
+                self.cancelled()?;
+                let ids = self.snapshot(s, || {
+                    let mut events = vec![];
+                    for id in self.lrange(s, "0", "-1")? {
+                        self.cancelled()?;
+                        if let Some(&i) = seen.get(&id) {
+                            events.push(Err(i));
+                            continue;
+                        }
+                        match self.fetch(&id, Some(s))? {
+                            Some(e) => {
+                                #[cfg(feature = "crypto")]
+                                self.check_seal(&e)?;
+                                events.push(Ok(e));
+                            }
+                            None => return Err(AudisError::NotFound(id)),
+                        }
+                    }
+                    Ok(events)
+                })?;
+
+                let mut at = 0;
+                for (pos, e) in ids.into_iter().enumerate() {
+                    match e {
+                        Ok(mut e) => {
+                            at = timestamp(&e.id).unwrap_or(at);
+                            e.subjects = vec![s.to_string()];
+                            seen.insert(e.id.to_string(), found.len());
+                            found.push(((at, n, pos), e));
+                        }
+                        Err(i) => {
+                            let e = &mut found[i].1;
+                            at = timestamp(&e.id).unwrap_or(at);
+                            e.subjects.push(s.to_string());
+                        }
+                    }
+                }
+            }
+
+            found.sort_by_key(|(key, _)| *key);
+            Ok(found.into_iter().map(|(_, e)| e).collect())
+        })
+    }
+
+    /// Arrange the known subjects into a hierarchy, by splitting
+    /// their names on `:`, along with how many events each has.
+    ///
+    /// Subject names are as stored (see `subjects_matching()`),
+    /// and those that the client's access policy (if any) does
+    /// not allow retrieving are left out.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn subjects_tree(&self) -> AudisResult<SubjectTree> {
+        self.instrument("subjects_tree", || {
+            let mut root = SubjectTree::default();
+            for (s, n) in self.subject_counts("*", SubjectOrder::Name)? {
+                let node = s.split(':').fold(&mut root, |node, part| {
+                    node.children.entry(part.to_string()).or_default()
+                });
+                node.subject = true;
+                node.events = n;
+            }
+            Ok(root)
+        })
+    }
+}
+
+// Escape the characters that are special in Redis-style globs.
+pub(crate) fn glob_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,281 @@
+//! Event IDs that sort in the order they were generated, and the
+//! timestamps they carry.
+//!
+//! With the `id-gen` feature, this provides three generators of
+//! such IDs, behind the `IdGenerator` trait: `Ulid` (the
+//! default, used by `EventBuilder::build()` when no ID is given),
+//! `Ksuid`, and `UuidV7`:
+//!
+//! ```rust
+//! extern crate audis;
+//! # #[cfg(feature = "id-gen")]
+//! use audis::ids::{IdGenerator, UuidV7};
+//!
+//! # #[cfg(not(feature = "id-gen"))]
+//! # fn main() {}
+//! # #[cfg(feature = "id-gen")]
+//! fn main() {
+//!     let ids = UuidV7::new();
+//!     let e = audis::Event::builder()
+//!         .id_from(&ids)
+//!         .data("{\"some\":\"data\"}")
+//!         .subject("user:42")
+//!         .build()
+//!         .unwrap();
+//!
+//!     assert!(audis::ids::timestamp(&e.id).is_some());
+//!     assert!(ids.generate() > e.id);
+//! }
+//! ```
+//!
+//! Each generator is monotonic: IDs generated within the same
+//! tick of its clock (a millisecond, or a second for KSUIDs)
+//! count up from the first, rather than being entirely random,
+//! so they still sort in the order they were generated.  That
+//! only holds for IDs from the same generator, though; IDs
+//! generated elsewhere (in another process, say) only sort by
+//! their timestamps.
+//!
+//! Whichever way they were generated, audis reads the timestamps
+//! back out of these IDs (see `timestamp()`) to tell when events
+//! were logged, i.e. for `Client::retrieve_between()`.
+//!
+
+#[cfg(feature = "id-gen")]
+use std::sync::{Mutex, OnceLock};
+
+#[cfg(feature = "id-gen")]
+use crate::timeline::now;
+
+// Crockford's base 32, as used by ULIDs.
+const CROCKFORD: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+// The base 62 digits used by KSUIDs, in ASCII order.
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// The KSUID epoch (2014-05-13T16:53:20Z), in seconds since the
+// Unix epoch.
+const KSUID_EPOCH: u64 = 1_400_000_000;
+
+/// A source of event IDs that sort (lexically) in the order
+/// they were generated.
+pub trait IdGenerator: Send + Sync {
+    /// Generate a new ID.
+    fn generate(&self) -> String;
+}
+
+/// Generates ULIDs (i.e. `01ARZ3NDEKTSV4RRFFQ69G5FAV`): a
+/// millisecond timestamp, and 80 random bits, in 26 characters
+/// of Crockford's base 32.
+#[cfg(feature = "id-gen")]
+#[derive(Default)]
+pub struct Ulid {
+    last: Mutex<ulid::Generator>,
+}
+
+#[cfg(feature = "id-gen")]
+impl Ulid {
+    /// Make a new ULID generator.
+    pub fn new() -> Ulid {
+        Ulid::default()
+    }
+}
+
+#[cfg(feature = "id-gen")]
+impl IdGenerator for Ulid {
+    fn generate(&self) -> String {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        // only a few trillion IDs in one millisecond run out of
+        // room to count up in.
+        match last.generate() {
+            Ok(id) => id.to_string(),
+            Err(_) => ulid::Ulid::new().to_string(),
+        }
+    }
+}
+
+/// Generates KSUIDs (i.e. `0ujtsYcgvSTl8PAuAdqWYSMnLOv`): a
+/// timestamp, in seconds, and 128 random bits, in 27 characters
+/// of base 62.
+#[cfg(feature = "id-gen")]
+#[derive(Debug, Default)]
+pub struct Ksuid {
+    last: Mutex<(u64, u128)>,
+}
+
+#[cfg(feature = "id-gen")]
+impl Ksuid {
+    /// Make a new KSUID generator.
+    pub fn new() -> Ksuid {
+        Ksuid::default()
+    }
+}
+
+#[cfg(feature = "id-gen")]
+impl IdGenerator for Ksuid {
+    fn generate(&self) -> String {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let secs = (now() / 1000).saturating_sub(KSUID_EPOCH);
+        *last = match *last {
+            (t, r) if t >= secs && r < u128::MAX => (t, r + 1),
+            (t, _) => (secs.max(t + 1), random() << 48 ^ random()),
+        };
+
+        let (t, r) = *last;
+        let mut bytes = (t as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&r.to_be_bytes());
+        base62(&bytes)
+    }
+}
+
+/// Generates version 7 UUIDs (i.e.
+/// `01890a5d-ac96-774b-bcce-b302099a8057`): a millisecond
+/// timestamp, and 74 random bits, as per RFC 9562.
+#[cfg(feature = "id-gen")]
+#[derive(Debug, Default)]
+pub struct UuidV7 {
+    last: Mutex<(u64, u128)>,
+}
+
+#[cfg(feature = "id-gen")]
+impl UuidV7 {
+    /// Make a new UUIDv7 generator.
+    pub fn new() -> UuidV7 {
+        UuidV7::default()
+    }
+}
+
+#[cfg(feature = "id-gen")]
+impl IdGenerator for UuidV7 {
+    fn generate(&self) -> String {
+        const RAND: u128 = (1 << 74) - 1;
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let ms = now();
+        *last = match *last {
+            (t, r) if t >= ms && r < RAND => (t, r + 1),
+            (t, _) => (ms.max(t + 1), random() & RAND),
+        };
+
+        let (t, r) = *last;
+        let uuid = (t as u128 & 0xffff_ffff_ffff) << 80
+            | 0x7 << 76
+            | (r >> 62) << 64
+            | 0b10 << 62
+            | (r & ((1 << 62) - 1));
+        let hex = format!("{:032x}", uuid);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+/// The timestamp (in milliseconds since the epoch) of an event
+/// ID, if it is a ULID, a KSUID (to the second), or a version 7
+/// UUID.
+///
+/// Any 27-character string of letters and digits that decodes
+/// to a small enough number looks like a KSUID, so other IDs of
+/// that shape can be mistaken for one.
+pub fn timestamp(id: &str) -> Option<u64> {
+    match id.len() {
+        26 => ulid_timestamp(id),
+        27 => ksuid_timestamp(id),
+        36 => uuid_timestamp(id),
+        _ => None,
+    }
+}
+
+// Generate the next ID from the default (ULID) generator.
+#[cfg(feature = "id-gen")]
+pub(crate) fn generate() -> String {
+    static DEFAULT: OnceLock<Ulid> = OnceLock::new();
+    DEFAULT.get_or_init(Ulid::new).generate()
+}
+
+fn ulid_timestamp(id: &str) -> Option<u64> {
+    if !id.is_ascii() {
+        return None;
+    }
+    let mut ms: u64 = 0;
+    for c in id[..10].bytes() {
+        let v = CROCKFORD
+            .iter()
+            .position(|&d| d == c.to_ascii_uppercase())?;
+        ms = (ms << 5) | v as u64;
+    }
+    if ms >> 48 != 0
+        || !id[10..]
+            .bytes()
+            .all(|c| CROCKFORD.contains(&c.to_ascii_uppercase()))
+    {
+        return None;
+    }
+    Some(ms)
+}
+
+fn ksuid_timestamp(id: &str) -> Option<u64> {
+    // 20 bytes (as 5 big-endian u32 limbs), from 27 digits
+    let mut n = [0u64; 5];
+    for c in id.bytes() {
+        let mut carry = BASE62.iter().position(|&d| d == c)? as u64;
+        for limb in n.iter_mut().rev() {
+            let v = *limb * 62 + carry;
+            *limb = v & 0xffff_ffff;
+            carry = v >> 32;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some((n[0] + KSUID_EPOCH) * 1000)
+}
+
+fn uuid_timestamp(id: &str) -> Option<u64> {
+    let b = id.as_bytes();
+    if [8, 13, 18, 23].iter().any(|&i| b[i] != b'-') || b[14] != b'7' {
+        return None;
+    }
+    let hex: String = id.split('-').collect();
+    if hex.len() != 32 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    if !matches!(hex.as_bytes()[16], b'8' | b'9' | b'a' | b'b' | b'A' | b'B') {
+        return None;
+    }
+    u64::from_str_radix(&hex[..12], 16).ok()
+}
+
+// 80 random bits, courtesy of the ulid crate.
+#[cfg(feature = "id-gen")]
+fn random() -> u128 {
+    ulid::Ulid::new().random()
+}
+
+// Encode (20) bytes as (27) base 62 digits, zero-padded.
+#[cfg(feature = "id-gen")]
+fn base62(bytes: &[u8]) -> String {
+    let mut n: Vec<u32> = bytes
+        .chunks(4)
+        .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+        .collect();
+    let mut digits = vec![];
+    while n.iter().any(|&limb| limb != 0) {
+        let mut rem = 0u64;
+        for limb in n.iter_mut() {
+            let v = (rem << 32) | *limb as u64;
+            *limb = (v / 62) as u32;
+            rem = v % 62;
+        }
+        digits.push(BASE62[rem as usize]);
+    }
+    while digits.len() < 27 {
+        digits.push(b'0');
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("base 62 digits are ASCII")
+}
@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+use crate::{AudisResult, Client, Event};
+
+/// A hook on the write path, run against every event before
+/// it is logged.
+///
+/// Interceptors can rewrite events (to enrich or redact them),
+/// drop them (to sample them), or reject them outright (to
+/// validate them).  Any number of interceptors can be stacked
+/// onto a `Client`, via `with_interceptor()`; each one sees the
+/// event as the previous one left it.
+///
+/// Closures of the form `Fn(Event) -> AudisResult<Option<Event>>`
+/// are interceptors too.
+pub trait Interceptor: Send + Sync {
+    /// Inspect an event that is about to be logged.
+    ///
+    /// Return `Ok(Some(e))` to pass the (possibly modified)
+    /// event along, `Ok(None)` to quietly drop it, or an error
+    /// to fail the call to `log()` with that error.
+    fn intercept(&self, e: Event) -> AudisResult<Option<Event>>;
+}
+
+impl<F> Interceptor for F
+where
+    F: Fn(Event) -> AudisResult<Option<Event>> + Send + Sync,
+{
+    fn intercept(&self, e: Event) -> AudisResult<Option<Event>> {
+        self(e)
+    }
+}
+
+impl Client {
+    /// Add an interceptor to the end of this client's write
+    /// path.  Interceptors run in the order they were added,
+    /// before any size limits or validators are applied.
+    pub fn with_interceptor(mut self, i: Box<dyn Interceptor>) -> Client {
+        self.interceptors.push(i.into());
+        self
+    }
+
+    // Run an event through every interceptor, in order,
+    // returning None if any of them dropped it.
+    pub(crate) fn intercept<'a>(&self, e: &'a Event) -> AudisResult<Option<Cow<'a, Event>>> {
+        let mut e = Cow::Borrowed(e);
+        for i in &self.interceptors {
+            match i.intercept(e.into_owned())? {
+                Some(next) => e = Cow::Owned(next),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(e))
+    }
+}
+
+// How often interceptors that keep track of subjects in memory
+// (like the RateLimiter) sweep out those that have gone idle.
+pub(crate) const SWEEP: Duration = Duration::from_secs(60);
+
+// Whether it is time for such an interceptor to sweep again,
+// having last done so at `swept` (if ever), noting that it is
+// doing so now if it is.
+pub(crate) fn sweeping(swept: &mut Option<Instant>, now: Instant) -> bool {
+    match swept {
+        Some(at) if now.duration_since(*at) < SWEEP => false,
+        _ => {
+            *swept = Some(now);
+            true
+        }
+    }
+}
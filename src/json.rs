@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{Map, Value};
+
+use crate::layout::wrongtype;
+use crate::{AudisError, AudisResult, Client, Event, Stored};
+
+// An event's JSON document (if it has one), metadata and trail.
+type Document = (
+    Option<String>,
+    BTreeMap<String, String>,
+    BTreeMap<String, String>,
+);
+
+impl Client {
+    /// Store JSON payloads as RedisJSON documents (via
+    /// `JSON.SET`), if the RedisJSON module is loaded, so that
+    /// `retrieve_fields()` can pick fields out of them on the
+    /// server side.  Without RedisJSON, this does nothing.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+    ///         .unwrap()
+    ///         .json_payloads();
+    ///
+    ///     for e in client.retrieve_fields("user:42", &["actor", "action"]).unwrap() {
+    ///         println!("{}: {}", e.id, String::from_utf8_lossy(&e.data));
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// JSON payloads are normalized (to compact JSON) before they
+    /// are logged, since RedisJSON doesn't keep the original text
+    /// around; that's what `retrieve()` returns, and what
+    /// signatures and hash chains cover.  Payloads that aren't
+    /// JSON, or that end up compressed or encrypted, are stored
+    /// as usual.  Events stored either way can be retrieved by any
+    /// client, but only clients built with the `json` feature can
+    /// read RedisJSON documents.
+    ///
+    /// This requires the `json` feature.
+    pub fn json_payloads(mut self) -> Client {
+        let modules: Vec<HashMap<String, String>> = self
+            .query(redis::cmd("MODULE").arg("LIST"))
+            .unwrap_or_default();
+        self.json = modules.iter().any(|m| {
+            m.get("name")
+                .is_some_and(|n| n.eq_ignore_ascii_case("rejson"))
+        });
+        self
+    }
+
+    /// Retrieve the events for the given subject, like
+    /// `retrieve()` does, but with only the named top-level
+    /// `fields` of each (JSON) payload, as a JSON object.
+    ///
+    /// For events stored as RedisJSON documents (see
+    /// `json_payloads()`), the fields are picked out by Redis,
+    /// and the rest of the payload never leaves the server.
+    /// Other events are retrieved in full, and then picked apart.
+    /// Fields an event doesn't have are left out, as are all of
+    /// the fields of payloads that aren't JSON objects.
+    ///
+    /// Since partial payloads can't be checked against their
+    /// signatures (see `audis::crypto`), they aren't; use
+    /// `retrieve()` for that.
+    ///
+    /// This requires the `json` feature.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_fields(&self, log: &str, fields: &[&str]) -> AudisResult<Vec<Event>> {
+        self.instrument("retrieve_fields", || {
+            let log = self.readable(log)?;
+            let log = log.as_str();
+            let mut events = vec![];
+            for id in self.lrange(log, "0", "-1")? {
+                self.cancelled()?;
+                match self.fetch_fields(&id, log, fields)? {
+                    Some(e) => events.push(e),
+                    None => return Err(AudisError::NotFound(id)),
+                }
+            }
+            Ok(events)
+        })
+    }
+
+    // Normalize the payload of an event that is about to be
+    // logged, if it is going to be stored as a JSON document.
+    pub(crate) fn canonical<'a>(&self, e: &'a Event) -> Cow<'a, Event> {
+        match serde_json::from_slice::<Value>(&e.data) {
+            Ok(v) if self.json => {
+                let mut e = e.clone();
+                e.data = v.to_string().into_bytes();
+                Cow::Owned(e)
+            }
+            _ => Cow::Borrowed(e),
+        }
+    }
+
+    // Store a (normalized) payload as a JSON document, unless
+    // it's already there.  Returns None if the payload can't be
+    // stored as JSON.
+    pub(crate) fn set_json(&self, e: &Event, data: &[u8]) -> AudisResult<Option<bool>> {
+        if !self.json || data != e.data.as_slice() || serde_json::from_slice::<Value>(data).is_err()
+        {
+            return Ok(None);
+        }
+        let set: Option<String> = self.query(
+            redis::cmd("JSON.SET")
+                .arg(id!(e.id))
+                .arg("$")
+                .arg(data)
+                .arg("NX"),
+        )?;
+        Ok(Some(set.is_some()))
+    }
+
+    // Fetch an event stored as a JSON document, for `fetch()`,
+    // normalizing the payload.
+    pub(crate) fn fetch_json(&self, id: &str) -> AudisResult<Stored> {
+        let (doc, meta, trail): Document = self.pipeline(
+            redis::pipe()
+                .cmd("JSON.GET")
+                .arg(id!(id))
+                .cmd("HGETALL")
+                .arg(idmeta!(id))
+                .cmd("HGETALL")
+                .arg(idtrail!(id)),
+        )?;
+        let data = match doc {
+            Some(doc) => Some(normalize(id, &doc)?.to_string().into_bytes()),
+            None => None,
+        };
+        Ok((data, meta, trail))
+    }
+
+    // Fetch some of the fields of an event's payload, as seen
+    // from `subject`.
+    fn fetch_fields(&self, id: &str, subject: &str, fields: &[&str]) -> AudisResult<Option<Event>> {
+        if self.json {
+            let paths: Vec<String> = fields
+                .iter()
+                .map(|f| format!("$[{}]", Value::from(*f)))
+                .collect();
+            let got: AudisResult<Document> = self.pipeline(
+                redis::pipe()
+                    .cmd("JSON.GET")
+                    .arg(id!(id))
+                    .arg(&paths[..])
+                    .cmd("HGETALL")
+                    .arg(idmeta!(id))
+                    .cmd("HGETALL")
+                    .arg(idtrail!(id)),
+            );
+            match got {
+                Err(AudisError::Backend(e)) if wrongtype(&e) => (),
+                Err(e) => return Err(e),
+                Ok((None, _, _)) => return Ok(None),
+                Ok((Some(doc), meta, mut trail)) => {
+                    // with one path, RedisJSON replies with just
+                    // the matches for it; with more, by path.
+                    let doc = normalize(id, &doc)?;
+                    let mut picked = Map::new();
+                    for (f, path) in fields.iter().zip(&paths) {
+                        let found = if paths.len() == 1 {
+                            &doc
+                        } else {
+                            &doc[path.as_str()]
+                        };
+                        if let Some(v) = found.get(0) {
+                            picked.insert(f.to_string(), v.clone());
+                        }
+                    }
+                    return Ok(Some(Event {
+                        id: id.to_string(),
+                        data: Value::Object(picked).to_string().into_bytes(),
+                        subjects: vec![],
+                        meta,
+                        correlation_id: trail.remove("correlation"),
+                        parent_id: trail.remove("parent"),
+                    }));
+                }
+            }
+        }
+
+        Ok(self.fetch(id, Some(subject))?.map(|mut e| {
+            let picked: Map<String, Value> = match serde_json::from_slice(&e.data) {
+                Ok(Value::Object(mut doc)) => fields
+                    .iter()
+                    .filter_map(|f| doc.remove(*f).map(|v| (f.to_string(), v)))
+                    .collect(),
+                _ => Map::new(),
+            };
+            e.data = Value::Object(picked).to_string().into_bytes();
+            e
+        }))
+    }
+}
+
+fn normalize(id: &str, doc: &str) -> AudisResult<Value> {
+    serde_json::from_str(doc)
+        .map_err(|err| AudisError::Codec(format!("event {}: bad JSON document: {}", id, err)))
+}
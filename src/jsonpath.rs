@@ -0,0 +1,55 @@
+// A tiny subset of JSONPath, for picking fields out of event
+// payloads: `$`, followed by any number of `.key` and `[index]`
+// steps.
+
+use crate::{AudisError, AudisResult};
+
+pub(crate) enum Step {
+    Key(String),
+    Index(usize),
+}
+
+pub(crate) fn parse(path: &str) -> AudisResult<Vec<Step>> {
+    let bad = || AudisError::Invalid(format!("malformed JSON path '{}'", path));
+
+    let mut rest = path.strip_prefix('$').ok_or_else(bad)?;
+    let mut steps = vec![];
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            if end == 0 {
+                return Err(bad());
+            }
+            steps.push(Step::Key(r[..end].to_string()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').ok_or_else(bad)?;
+            steps.push(Step::Index(r[..end].parse().map_err(|_| bad())?));
+            rest = &r[end + 1..];
+        } else {
+            return Err(bad());
+        }
+    }
+    Ok(steps)
+}
+
+#[cfg(any(feature = "routing", feature = "search"))]
+pub(crate) fn lookup<'a>(
+    doc: &'a serde_json::Value,
+    path: &[Step],
+) -> Option<&'a serde_json::Value> {
+    path.iter().try_fold(doc, |v, step| match step {
+        Step::Key(k) => v.get(k),
+        Step::Index(i) => v.get(i),
+    })
+}
+#[cfg(feature = "redact")]
+pub(crate) fn lookup_mut<'a>(
+    doc: &'a mut serde_json::Value,
+    path: &[Step],
+) -> Option<&'a mut serde_json::Value> {
+    path.iter().try_fold(doc, |v, step| match step {
+        Step::Key(k) => v.get_mut(k),
+        Step::Index(i) => v.get_mut(i),
+    })
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::time::Instant;
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::{Event, Sender};
+
+/// A `tracing_subscriber` layer that turns tracing events (and
+/// spans) marked with an `audit = true` field into audit events,
+/// so that teams can standardize on the `tracing` macros, and
+/// still get audit-grade records:
+///
+/// ```rust,no_run
+/// extern crate audis;
+/// extern crate tracing;
+/// extern crate tracing_subscriber;
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+///     let (tx, thread) = client.background(0).unwrap();
+///     let subscriber = tracing_subscriber::registry()
+///         .with(audis::AuditLayer::new(tx).subject_key("user"));
+///     tracing::subscriber::set_global_default(subscriber).unwrap();
+///
+///     // logged against user:42
+///     tracing::info!(audit = true, user = 42, amount = 100, "refunded");
+/// }
+/// ```
+///
+/// Events are handed to a `background()` logging thread, with
+/// a ULID for an ID, and the fields (other than `audit`) as a
+/// JSON object for a payload; the message, if any, is the
+/// `message` field.  Audited spans are logged when they close,
+/// with the fields they were opened with (or recorded later),
+/// their name as `span`, and how long they were open for, in
+/// milliseconds, as `elapsed_ms`.
+///
+/// Fields whose names have been registered via `subject_key()`
+/// pick subjects, i.e. `user:42`, as does the value of any
+/// `subject` field; events with no subjects are logged against
+/// `audit`.  The level and target are kept as `severity` and
+/// `target` metadata.
+///
+/// This requires the `layer` feature.
+pub struct AuditLayer {
+    tx: Sender,
+    keys: Vec<String>,
+    subject: String,
+}
+
+impl AuditLayer {
+    /// Log audited events via `tx`.
+    pub fn new(tx: Sender) -> AuditLayer {
+        AuditLayer {
+            tx,
+            keys: vec![],
+            subject: "audit".to_string(),
+        }
+    }
+
+    /// Log events with a `key` field against the `key:value`
+    /// subject.  This can be given more than once.
+    pub fn subject_key(mut self, key: &str) -> AuditLayer {
+        self.keys.push(key.to_string());
+        self
+    }
+
+    /// Log events that don't pick any subjects against `subject`,
+    /// rather than `audit`.
+    pub fn default_subject(mut self, subject: &str) -> AuditLayer {
+        self.subject = subject.to_string();
+        self
+    }
+
+    fn emit(&self, fields: Map<String, Value>, meta: &Metadata<'_>) {
+        let mut subjects = vec![];
+        for (k, v) in &fields {
+            let v = match v {
+                Value::String(s) => s.to_string(),
+                v => v.to_string(),
+            };
+            if k == "subject" {
+                subjects.push(v);
+            } else if self.keys.iter().any(|key| key == k) {
+                subjects.push(format!("{}:{}", k, v));
+            }
+        }
+        if subjects.is_empty() {
+            subjects.push(self.subject.to_string());
+        }
+
+        let e = subjects
+            .into_iter()
+            .fold(Event::builder(), |e, s| e.subject(s))
+            .data(Value::Object(fields).to_string())
+            .meta(
+                "severity",
+                match *meta.level() {
+                    Level::ERROR => "err",
+                    Level::WARN => "warning",
+                    Level::INFO => "info",
+                    Level::DEBUG | Level::TRACE => "debug",
+                },
+            )
+            .meta("target", meta.target())
+            .build();
+        match e {
+            Ok(e) => {
+                if self.tx.send(e).is_err() {
+                    log::error!(target: "audis", "background logger has stopped; dropping audited event");
+                }
+            }
+            Err(err) => log::error!(target: "audis", "failed to log audited event: {}", err),
+        }
+    }
+}
+
+// The fields of an event (or span), as collected so far.
+#[derive(Default)]
+struct Fields {
+    audit: bool,
+    map: Map<String, Value>,
+}
+
+impl Visit for Fields {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == "audit" {
+            self.audit = value;
+        } else {
+            self.map.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.map.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.map.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.map.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.map.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.map
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+// Kept in the extensions of audited spans, until they close.
+struct Audited {
+    fields: Fields,
+    opened: Instant,
+}
+
+impl<S> Layer<S> for AuditLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        if fields.audit {
+            self.emit(fields.map, event.metadata());
+        }
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let (true, Some(span)) = (fields.audit, ctx.span(id)) {
+            span.extensions_mut().insert(Audited {
+                fields,
+                opened: Instant::now(),
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(audited) = span.extensions_mut().get_mut::<Audited>() {
+                values.record(&mut audited.fields);
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            let audited = span.extensions_mut().remove::<Audited>();
+            if let Some(Audited { mut fields, opened }) = audited {
+                fields.map.insert("span".to_string(), span.name().into());
+                fields.map.insert(
+                    "elapsed_ms".to_string(),
+                    (opened.elapsed().as_millis() as u64).into(),
+                );
+                self.emit(fields.map, span.metadata());
+            }
+        }
+    }
+}
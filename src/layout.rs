@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::timeline::logged_at;
+use crate::{AudisError, AudisResult, Client, Event, Stored};
+
+/// How each event is laid out in Redis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// The payload in the `audit:$id` string, with the reference
+    /// count, metadata and trail in their own `audit:$id:ref`,
+    /// `audit:$id:meta` and `audit:$id:trail` keys.  This is the
+    /// original layout, and the default.
+    Keys,
+
+    /// The payload (as `data`), reference count (`ref`), time
+    /// logged (`ts`, in milliseconds since the epoch), trail
+    /// (`correlation` and `parent`) and metadata (as `meta:$key`)
+    /// all in the one `audit:$id` hash, which saves memory, and
+    /// keeps the keyspace tidier.
+    Hash,
+}
+
+impl Client {
+    /// Lay out newly-logged events in Redis according to `layout`.
+    ///
+    /// Events are read (and pruned) in whichever layout they were
+    /// written in, so events logged under different layouts can
+    /// be mixed in the same audit log; see `migrate()` for moving
+    /// existing events from one layout to the other.  Payloads
+    /// are never stored as RedisJSON documents (see
+    /// `json_payloads()`) in the `Hash` layout.
+    ///
+    pub fn layout(mut self, layout: Layout) -> Client {
+        self.layout = layout;
+        self
+    }
+
+    /// Rewrite every event that isn't already laid out according
+    /// to `to`, returning how many were rewritten.
+    ///
+    /// Each event is rewritten atomically, but like `fsck()`, this
+    /// walks the entire keyspace, and should not be run while
+    /// anything else is pruning the audit log.  Events moved into
+    /// the `Hash` layout only get a `ts` if their IDs are
+    /// timestamped (i.e. ULIDs; see `ids::timestamp()`), since the
+    /// `Keys` layout doesn't record when events were logged.
+    /// Payloads stored as RedisJSON documents are left alone.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn migrate(&self, to: Layout) -> AudisResult<u64> {
+        self.instrument("migrate", || {
+            let mut n = 0;
+            for key in self.scan("SCAN", None, &id!("*"))? {
+                let id = match key.strip_prefix("audit:") {
+                    Some(id) if !crate::PARALLEL.iter().any(|p| id.ends_with(p)) => id,
+                    _ => continue,
+                };
+                let (data, meta, trail) = match (to, self.kind(id)?.as_str()) {
+                    (Layout::Hash, "string") => self.load_keys(id)?,
+                    (Layout::Keys, "hash") => self.load_hash(id)?,
+                    _ => continue,
+                };
+                let data = match data {
+                    Some(data) => data,
+                    None => continue,
+                };
+                let refs = self.refcounts(&[id.to_string()])?[0].unwrap_or(0);
+
+                let mut pipe = redis::pipe();
+                pipe.atomic()
+                    .cmd("DEL")
+                    .arg(id!(id))
+                    .arg(idref!(id))
+                    .arg(idmeta!(id))
+                    .arg(idtrail!(id))
+                    .ignore();
+                match to {
+                    Layout::Hash => {
+                        let mut hset = hashed(id, &data, &meta, &trail);
+                        if let Some(ts) = crate::export::timestamp(id) {
+                            hset.arg("ts").arg(ts);
+                        }
+                        hset.arg("ref").arg(refs);
+                        pipe.add_command(hset).ignore();
+                    }
+                    Layout::Keys => {
+                        pipe.cmd("SET").arg(id!(id)).arg(data).ignore();
+                        pipe.cmd("SET").arg(idref!(id)).arg(refs).ignore();
+                        for (key, fields) in &[(idmeta!(id), meta), (idtrail!(id), trail)] {
+                            if !fields.is_empty() {
+                                pipe.cmd("HSET").arg(key);
+                                for (k, v) in fields {
+                                    pipe.arg(k).arg(v);
+                                }
+                                pipe.ignore();
+                            }
+                        }
+                    }
+                }
+                self.pipeline::<()>(&pipe)?;
+                n += 1;
+            }
+            Ok(n)
+        })
+    }
+
+    // Write a newly-logged event's (encoded) payload, along with
+    // its metadata and trail, unless it already exists.  Returns
+    // whether or not it was written.
+    pub(crate) fn put(&self, e: &Event, data: &[u8]) -> AudisResult<bool> {
+        if self.layout == Layout::Hash {
+            // an event in another layout is just as much of a
+            // duplicate
+            let fresh: bool =
+                match self.query(redis::cmd("HSETNX").arg(id!(e.id)).arg("data").arg(data)) {
+                    Err(AudisError::Backend(err)) if wrongtype(&err) => false,
+                    fresh => fresh?,
+                };
+            if fresh {
+                let mut trail = BTreeMap::new();
+                if let Some(cid) = &e.correlation_id {
+                    trail.insert("correlation".to_string(), cid.to_string());
+                }
+                if let Some(parent) = &e.parent_id {
+                    trail.insert("parent".to_string(), parent.to_string());
+                }
+                let mut hset = hashed(&e.id, data, &e.meta, &trail);
+                self.query::<()>(hset.arg("ts").arg(logged_at(&e.id)))?;
+            }
+            return Ok(fresh);
+        }
+
+        #[cfg(feature = "json")]
+        let fresh = match self.set_json(e, data)? {
+            Some(fresh) => fresh,
+            None => self.setnx(&id!(e.id), data)?,
+        };
+        #[cfg(not(feature = "json"))]
+        let fresh = self.setnx(&id!(e.id), data)?;
+        if fresh && !e.meta.is_empty() {
+            let mut hset = redis::cmd("HSET");
+            hset.arg(idmeta!(e.id));
+            for (k, v) in &e.meta {
+                hset.arg(k).arg(v);
+            }
+            self.query::<()>(&mut hset)?;
+        }
+        if fresh && (e.correlation_id.is_some() || e.parent_id.is_some()) {
+            let mut hset = redis::cmd("HSET");
+            hset.arg(idtrail!(e.id));
+            if let Some(cid) = &e.correlation_id {
+                hset.arg("correlation").arg(cid);
+            }
+            if let Some(parent) = &e.parent_id {
+                hset.arg("parent").arg(parent);
+            }
+            self.query::<()>(&mut hset)?;
+        }
+        Ok(fresh)
+    }
+
+    // Look up an event's payload, metadata and trail, in
+    // whichever layout it was written.
+    pub(crate) fn load(&self, id: &str) -> AudisResult<Stored> {
+        let stored = match self.layout {
+            Layout::Keys => self.load_keys(id),
+            Layout::Hash => self.load_hash(id),
+        };
+        match stored {
+            Err(AudisError::Backend(e)) if wrongtype(&e) => match self.kind(id)?.as_str() {
+                "hash" => self.load_hash(id),
+                "string" => self.load_keys(id),
+                #[cfg(feature = "json")]
+                _ => self.fetch_json(id),
+                #[cfg(not(feature = "json"))]
+                _ => Err(AudisError::Backend(e)),
+            },
+            stored => stored,
+        }
+    }
+
+    // Adjust an event's reference count by `by`, returning the
+    // new count.
+    pub(crate) fn refer(&self, id: &str, by: i64) -> AudisResult<i64> {
+        match self.layout {
+            Layout::Hash => {
+                match self.query(redis::cmd("HINCRBY").arg(id!(id)).arg("ref").arg(by)) {
+                    Err(AudisError::Backend(e)) if wrongtype(&e) => {
+                        self.query(redis::cmd("INCRBY").arg(idref!(id)).arg(by))
+                    }
+                    n => n,
+                }
+            }
+            Layout::Keys => {
+                let n: i64 = self.query(redis::cmd("INCRBY").arg(idref!(id)).arg(by))?;
+                // a count that goes negative didn't exist, which
+                // it doesn't for events in the Hash layout.
+                if n < 0 && self.kind(id)? == "hash" {
+                    self.query::<()>(redis::cmd("DEL").arg(idref!(id)))?;
+                    return self.query(redis::cmd("HINCRBY").arg(id!(id)).arg("ref").arg(by));
+                }
+                Ok(n)
+            }
+        }
+    }
+
+    // Look up the reference counts of a batch of events.
+    pub(crate) fn refcounts(&self, ids: &[String]) -> AudisResult<Vec<Option<i64>>> {
+        let mut pipe = redis::pipe();
+        for id in ids {
+            pipe.cmd("TYPE").arg(id!(id));
+        }
+        let kinds: Vec<String> = self.pipeline(&pipe)?;
+
+        let mut pipe = redis::pipe();
+        for (id, kind) in ids.iter().zip(kinds) {
+            if kind == "hash" {
+                pipe.cmd("HGET").arg(id!(id)).arg("ref");
+            } else {
+                pipe.cmd("GET").arg(idref!(id));
+            }
+        }
+        self.pipeline(&pipe)
+    }
+
+    // Overwrite an event's reference count.
+    pub(crate) fn set_refcount(&self, id: &str, n: i64) -> AudisResult<()> {
+        if self.kind(id)? == "hash" {
+            self.query(redis::cmd("HSET").arg(id!(id)).arg("ref").arg(n))
+        } else {
+            self.query(redis::cmd("SET").arg(idref!(id)).arg(n))
+        }
+    }
+
+    // Overwrite an event's (encoded) payload.
+    pub(crate) fn set_payload(&self, id: &str, data: &[u8]) -> AudisResult<()> {
+        if self.kind(id)? == "hash" {
+            self.query(redis::cmd("HSET").arg(id!(id)).arg("data").arg(data))
+        } else {
+            self.query(redis::cmd("SET").arg(id!(id)).arg(data))
+        }
+    }
+
+    // Bump a numeric metadata field of an event, wherever the
+    // layout it was stored in (not the client's) keeps it.
+    pub(crate) fn bump(&self, id: &str, key: &str) -> AudisResult<()> {
+        if self.kind(id)? == "hash" {
+            self.query(
+                redis::cmd("HINCRBY")
+                    .arg(id!(id))
+                    .arg(format!("meta:{}", key))
+                    .arg(1),
+            )
+        } else {
+            self.query(redis::cmd("HINCRBY").arg(idmeta!(id)).arg(key).arg(1))
+        }
+    }
+
+    fn load_keys(&self, id: &str) -> AudisResult<Stored> {
+        self.pipeline(
+            redis::pipe()
+                .cmd("GET")
+                .arg(id!(id))
+                .cmd("HGETALL")
+                .arg(idmeta!(id))
+                .cmd("HGETALL")
+                .arg(idtrail!(id)),
+        )
+    }
+
+    fn load_hash(&self, id: &str) -> AudisResult<Stored> {
+        let mut fields: HashMap<String, Vec<u8>> =
+            self.query(redis::cmd("HGETALL").arg(id!(id)))?;
+        let data = fields.remove("data");
+        let (mut meta, mut trail) = (BTreeMap::new(), BTreeMap::new());
+        for (k, v) in fields {
+            let v = String::from_utf8_lossy(&v).into_owned();
+            if let Some(k) = k.strip_prefix("meta:") {
+                meta.insert(k.to_string(), v);
+            } else if k == "correlation" || k == "parent" {
+                trail.insert(k, v);
+            }
+        }
+        Ok((data, meta, trail))
+    }
+
+    // The Redis type of an event's `audit:$id` key.
+    fn kind(&self, id: &str) -> AudisResult<String> {
+        self.query(redis::cmd("TYPE").arg(id!(id)))
+    }
+}
+
+// An HSET of an event in the Hash layout, less its `ts` and
+// `ref` fields.
+fn hashed(
+    id: &str,
+    data: &[u8],
+    meta: &BTreeMap<String, String>,
+    trail: &BTreeMap<String, String>,
+) -> redis::Cmd {
+    let mut hset = redis::cmd("HSET");
+    hset.arg(id!(id)).arg("data").arg(data);
+    for (k, v) in meta {
+        hset.arg(format!("meta:{}", k)).arg(v);
+    }
+    for (k, v) in trail {
+        hset.arg(k).arg(v);
+    }
+    hset
+}
+
+// Whether a command failed because a key held the wrong type
+// of value (i.e. a hash, where a string was expected).
+pub(crate) fn wrongtype(e: &redis::RedisError) -> bool {
+    e.extension_error_code() == Some("WRONGTYPE") || e.to_string().contains("WRONGTYPE")
+}
@@ -21,11 +21,12 @@
 //!
 //!     client.log(&audis::Event{
 //!         id: "foo1".to_string(),
-//!         data: "{\"some\":\"data\"}".to_string(),
+//!         data: "{\"some\":\"data\"}".into(),
 //!         subjects: vec![
 //!             "system".to_string(),
 //!             "user:42".to_string(),
 //!         ],
+//!         ..Default::default()
 //!     }).unwrap();
 //!
 //!     // ... etc ...
@@ -45,7 +46,7 @@
 //!     for subject in &client.subjects().unwrap() {
 //!         println!("## {} ######################", subject);
 //!         for event in &client.retrieve(subject).unwrap() {
-//!             println!("  {}", event.data);
+//!             println!("  {}", String::from_utf8_lossy(&event.data));
 //!         }
 //!         println!("");
 //!     }
@@ -74,11 +75,12 @@
 //!
 //!     tx.send(audis::Event{
 //!         id: "foo1".to_string(),
-//!         data: "{\"some\":\"data\"}".to_string(),
+//!         data: "{\"some\":\"data\"}".into(),
 //!         subjects: vec![
 //!             "system".to_string(),
 //!             "user:42".to_string(),
 //!         ],
+//!         ..Default::default()
 //!     }).unwrap();
 //!
 //!     // ... etc ...
@@ -87,6 +89,27 @@
 //! }
 //! ```
 //!
+//! ## Embedded Storage
+//!
+//! For CLI tools and edge agents that can't depend on a Redis
+//! instance, audis ships an embedded backend that keeps the
+//! audit log in a local, append-only journal file.  Just use a
+//! `file:` URL instead of a `redis:` one:
+//!
+//! ```rust,no_run
+//! extern crate audis;
+//!
+//! fn main() {
+//!     let client = audis::Client::connect("file:/var/lib/audit.aof").unwrap();
+//!
+//!     // ... use it just like you would a Redis-backed client ...
+//! }
+//! ```
+//!
+//! The journal is written in the Redis protocol, the same way
+//! Redis writes its own append-only files, so subject indexing
+//! works exactly the same as it does in Redis proper.
+//!
 //! ## Implementation Details
 //!
 //! Audis uses four (4) types of objects A) the events
@@ -108,16 +131,77 @@
 //! referencing the given event.  For example, `audit:ae2:ref`
 //! is the reference count key for `audit:ae2`.
 //!
+//! Payloads larger than the threshold given to
+//! `Client::compress()` are compressed before they are stored,
+//! behind a short header (`\0AZ\x01`, plus a byte identifying
+//! the algorithm) that lets `retrieve()` decompress them again.
+//!
+//! Events that carry structured metadata (actor, source IP,
+//! request ID, etc.) have it stored in a Redis Hash, under
+//! another parallel key that appends `:meta`; for example,
+//! `audit:ae2:meta`.
+//!
+//! Likewise, events with a correlation ID or a parent event ID
+//! keep them in a Hash under a key ending in `:trail`.  Each
+//! correlation ID also gets a list of the events that carry it,
+//! in insertion order, under `audis:trail:$correlation_id`,
+//! which events leave as they are deleted.
+//!
+//! Clients can instead keep each event's payload, reference
+//! count, metadata and trail (and the time it was logged) as
+//! fields of a single Redis Hash, under `audit:$id`, via
+//! `Client::layout(Layout::Hash)`.  Events are read in either
+//! layout, and `Client::migrate()` moves them between the two.
+//!
+//! Clients with hash chaining turned on (see `Client::chain()`)
+//! record, for each subject, the previous event's hash, the
+//! event's own hash, and the version of the scheme that hashed
+//! it, in a Hash under a key ending in `:chain`.
+//! The latest hash for each subject lives in
+//! `audis:chain:$subject`.
+//!
+//! Clients that number events (see `Client::sequenced()`) keep
+//! each event's sequence number in each of its subjects in a
+//! Hash under a key ending in `:seq`, handing them out from the
+//! `audis:seq:$subject` counters.
+//!
 //! Each subject in the audit log maintains its own list of
 //! event IDs that are relevant to it.  These lists are stored
 //! under keys derived from the subject itself.  Callers are
 //! strongly urged to ensure that subject names are as unique
-//! as they need to be for analysis.
+//! as they need to be for analysis.  Names that would collide
+//! with the other keys described here are refused, unless the
+//! client escapes them (see `Client::escape_subjects()`).
+//!
+//! Clients that pseudonymize subjects (see
+//! `Client::pseudonymize()`) key these lists (and everything
+//! else named after a subject) by the hex-encoded HMAC-SHA256
+//! of the subject name instead, so that the subject names
+//! themselves never reach Redis.
 //!
 //! Finally, a single Redis Set, called `subjects`, exists to
 //! track the complete set of known subject strings.  This
 //! facilitates discovery of the different subsets of the audit
-//! log.
+//! log.  When each subject was last logged against is kept in
+//! the `audis:touched` sorted set, for `Client::expire_idle()`.
+//!
+//! Alongside all of this, the `audis:schema` key records the
+//! version of this keying structure that the audit log was
+//! written with, so that future versions of audis can tell
+//! when a migration is in order.
+//!
+//! Events logged by clients with a TTL (see
+//! `Client::expire_after()`) are expired by Redis itself, and
+//! tracked until then in the `audis:expiring` sorted set, with
+//! the subjects (and trail) referencing each in a Set under
+//! `audis:expiring:$id`, so that `Client::reap()` can clean
+//! up after them.
+//!
+//! Clients that audit changes (see `Client::audit_changes()`)
+//! log an event against the reserved `__audis__` subject for
+//! every destructive operation they carry out.  These events
+//! are numbered by the `audis:changes` counter, and get IDs
+//! like `__audis__:42`.
 //!
 //! Here is some pseudocode for the insertion logic of the
 //! `LOG(e)` operation, where `e` is an object:
@@ -199,16 +283,20 @@
 //!             break
 //! ```
 //!
-//! Both of these operations suffer from massive problems
+//! Both of these operations suffered from massive problems
 //! when run concurrently with each other, or with other
-//! calls to themselves.  A future version of this library
-//! will correct this, by the judicious use of `LOCK()`/`UNLOCK()`
-//! primitives implemented inside of the same Redis database.
+//! calls to themselves.  They now hold a per-subject lock,
+//! `audis:lock:$s`, while they run (taken with `SET ... NX PX`,
+//! so that it expires if its holder dies), which retrievals
+//! can also take; see `Client::snapshot_reads()`.
 //!
 
-use redis;
-use std::sync::mpsc::{sync_channel, SyncSender};
-use std::thread::{spawn, JoinHandle};
+use std::borrow::Cow;
+#[cfg(feature = "tracing")]
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
 
 macro_rules! id {
     ($x:expr) => {
@@ -222,19 +310,326 @@ macro_rules! idref {
     };
 }
 
-pub type AudisResult<T> = redis::RedisResult<T>;
+macro_rules! idmeta {
+    ($x:expr) => {
+        format!("audit:{}:meta", $x)
+    };
+}
+
+macro_rules! idtrail {
+    ($x:expr) => {
+        format!("audit:{}:trail", $x)
+    };
+}
+
+macro_rules! idchain {
+    ($x:expr) => {
+        format!("audit:{}:chain", $x)
+    };
+}
+
+#[cfg_attr(not(feature = "crypto"), allow(unused_macros))]
+macro_rules! idsig {
+    ($x:expr) => {
+        format!("audit:{}:sig", $x)
+    };
+}
+
+macro_rules! idkeys {
+    ($x:expr) => {
+        format!("audit:{}:keys", $x)
+    };
+}
+
+macro_rules! idseq {
+    ($x:expr) => {
+        format!("audit:{}:seq", $x)
+    };
+}
+
+macro_rules! trail {
+    ($x:expr) => {
+        format!("audis:trail:{}", $x)
+    };
+}
+
+pub mod backend;
+use backend::Backend;
+
+mod error;
+pub use error::{AudisError, AudisResult};
+
+mod background;
+pub use background::{BackgroundStats, Sender};
+
+mod builder;
+pub use builder::EventBuilder;
+
+pub mod ids;
+
+#[cfg(feature = "serde")]
+mod payload;
+
+mod health;
+pub use health::Health;
+
+mod stats;
+pub use stats::Stats;
+
+mod fsck;
+pub use fsck::Fsck;
+
+mod compress;
+pub use compress::Compression;
+
+mod dedup;
+
+mod chain;
+pub use chain::ChainBreak;
+
+pub mod crypto;
+
+mod checkpoint;
+pub use checkpoint::Checkpoint;
+
+mod erase;
+
+mod subject;
+pub use subject::{SubjectOrder, SubjectScan};
+
+mod batch;
+
+mod hierarchy;
+pub use hierarchy::SubjectTree;
+
+mod alias;
+
+mod view;
+
+mod annotate;
+
+mod analytics;
+
+mod pseudonym;
+
+mod names;
+
+mod audit;
+pub use audit::{Actor, SYSTEM_SUBJECT};
+
+mod policy;
+pub use policy::Operation;
+
+mod follow;
+pub use follow::Follow;
+
+pub mod export;
+
+pub mod forward;
+
+pub mod context;
+
+mod intercept;
+pub use intercept::Interceptor;
+
+mod enrich;
+pub use enrich::Enricher;
+
+#[cfg(any(feature = "routing", feature = "redact", feature = "search"))]
+mod jsonpath;
+
+mod route;
+pub use route::Router;
+
+#[cfg(feature = "redact")]
+mod redact;
+#[cfg(feature = "redact")]
+pub use redact::Redactor;
+
+mod ratelimit;
+pub use ratelimit::{Overflow, RateLimiter};
+
+mod anomaly;
+pub use anomaly::{Anomaly, AnomalyDetector};
+
+mod sample;
+pub use sample::{Sampler, Sampling};
+
+#[cfg(feature = "schema")]
+mod schema;
+
+mod timeline;
+
+mod layout;
+pub use layout::Layout;
+
+mod expiry;
+
+mod sequence;
+pub use sequence::Completeness;
+
+mod outcome;
+pub use outcome::LogOutcome;
+
+mod trail;
+pub use trail::Trail;
+
+mod outbox;
+pub use outbox::Outbox;
+
+mod missing;
+pub use missing::{Missing, Retrieval};
+
+mod snapshot;
+
+mod cancel;
+pub use cancel::CancellationToken;
+
+mod connect;
+pub use connect::ClientBuilder;
+
+#[cfg(feature = "search")]
+mod search;
+
+#[cfg(feature = "json")]
+mod json;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "typed")]
+pub mod codec;
+#[cfg(feature = "typed")]
+mod typed;
+#[cfg(feature = "typed")]
+pub use typed::Record;
+
+#[cfg(feature = "appender")]
+mod appender;
+#[cfg(feature = "appender")]
+pub use appender::Appender;
+
+#[cfg(feature = "layer")]
+mod layer;
+#[cfg(feature = "layer")]
+pub use layer::AuditLayer;
+
+#[cfg(feature = "middleware")]
+pub mod middleware;
+
+#[cfg(feature = "async")]
+pub mod aio;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+// How many backend commands the current thread has issued,
+// for attributing command counts to tracing spans.
+#[cfg(feature = "tracing")]
+thread_local!(static COMMANDS: Cell<u64> = const { Cell::new(0) });
+
+/// The version of the keying structure that this library
+/// reads and writes, recorded in the `audis:schema` key.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// The suffixes of the keys kept alongside each `audit:$id`
+// event key.
+const PARALLEL: &[&str] = &[":ref", ":meta", ":trail", ":chain", ":sig", ":keys", ":seq"];
 
 /// A single Redis endpoint housing an audit log.
+///
+/// Clients are cheap to clone (everything but a handful of
+/// flags is behind an `Arc`), and can be shared between threads,
+/// so a service can stash one in its shared state and log from
+/// any handler.  Clones share the same backend (and so, with the
+/// `RedisBackend`, the same pool of connections).
+#[derive(Clone)]
 pub struct Client {
-    url: String,
-    redis: redis::Client,
+    backend: Arc<dyn Backend>,
+    compression: Option<(Compression, usize)>,
+    max_payload: Option<usize>,
+    validators: Vec<Arc<Validator>>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    dedup: Option<Duration>,
+    duplicates_ok: bool,
+    chained: bool,
+    keys: crypto::Keys,
+    checkpointer: Option<Arc<checkpoint::Checkpointer>>,
+    pseudonyms: Option<Vec<u8>>,
+    audit_changes: bool,
+    actor: Option<Actor>,
+    policy: Option<Arc<policy::Policy>>,
+    forwarders: Vec<Arc<forward::Forwarding>>,
+    timeline: bool,
+    layout: Layout,
+    ttl: Option<Duration>,
+    escape: bool,
+    sequenced: bool,
+    outboxes: Vec<String>,
+    views: Vec<view::View>,
+    snapshot: bool,
+    aliased: bool,
+    cancel: Option<CancellationToken>,
+    #[cfg(feature = "search")]
+    search: Vec<Arc<search::Field>>,
+    #[cfg(feature = "json")]
+    json: bool,
 }
 
+// An event's payload, metadata and trail, as stored.
+type Stored = (
+    Option<Vec<u8>>,
+    BTreeMap<String, String>,
+    BTreeMap<String, String>,
+);
+
+// A caller-supplied check, run against every event before
+// it is logged.
+type Validator = dyn Fn(&Event) -> Result<(), String> + Send + Sync;
+
 /// An event, suitable for logging in the audit log.
+///
+/// With the `serde` feature enabled, events can be serialized
+/// and deserialized, for shipping over the wire.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Event {
     pub id: String,
-    pub data: String,
+
+    /// The event payload.  Audis doesn't care what this is;
+    /// usually it's JSON, but binary formats (protobuf, etc.)
+    /// are stored just as faithfully.
+    #[cfg_attr(feature = "serde", serde(with = "payload"))]
+    pub data: Vec<u8>,
+
     pub subjects: Vec<String>,
+
+    /// Structured attributes of the event (actor, source IP,
+    /// request ID, severity, etc.), kept apart from the opaque
+    /// `data` payload so that they can be queried and exported
+    /// without having to understand the payload.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub meta: BTreeMap<String, String>,
+
+    /// The business transaction (or request, or workflow) that
+    /// this event is a part of.  All of the events sharing a
+    /// correlation ID can be retrieved, in order, via
+    /// `Client::retrieve_trail()`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub correlation_id: Option<String>,
+
+    /// The ID of the event that caused this one.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub parent_id: Option<String>,
 }
 
 impl Client {
@@ -248,109 +643,609 @@ impl Client {
     ///  - redis://localhost
     ///  - unix:/path/to/redis.sock
     ///
+    /// Additionally, `file:` URLs (i.e. `file:/var/lib/audit.aof`)
+    /// will use the embedded `FileBackend`, for situations where
+    /// a Redis instance isn't available.
+    ///
+    /// See `builder()` for configuring credentials, timeouts,
+    /// retries and the like, up front.
+    ///
     pub fn connect(url: &str) -> AudisResult<Client> {
+        Client::with_backend(backend::open(url)?)
+    }
+
+    /// Use an already-opened storage backend.
+    pub fn with_backend(backend: Box<dyn Backend>) -> AudisResult<Client> {
         let c = Client {
-            url: url.to_string(),
-            redis: redis::Client::open(url)?,
+            backend: Arc::from(backend),
+            compression: None,
+            max_payload: None,
+            validators: vec![],
+            interceptors: vec![],
+            dedup: None,
+            duplicates_ok: false,
+            chained: false,
+            keys: crypto::Keys::default(),
+            checkpointer: None,
+            pseudonyms: None,
+            audit_changes: false,
+            actor: None,
+            policy: None,
+            forwarders: vec![],
+            timeline: false,
+            layout: Layout::Keys,
+            ttl: None,
+            escape: false,
+            sequenced: false,
+            outboxes: vec![],
+            views: vec![],
+            snapshot: false,
+            aliased: false,
+            cancel: None,
+            #[cfg(feature = "search")]
+            search: vec![],
+            #[cfg(feature = "json")]
+            json: false,
         };
-        match c.ping() {
-            Ok(_) => Ok(c),
-            Err(e) => Err(e),
-        }
+        c.ping()?;
+        c.query::<()>(redis::cmd("SETNX").arg("audis:schema").arg(SCHEMA_VERSION))?;
+        Ok(c)
     }
 
-    /// Delegate event logging to a background thread.
+    /// Compress event payloads larger than `threshold` bytes,
+    /// using the given algorithm.
     ///
-    /// This function spins up a new thread, with a copy of the
-    /// audis Client object, and returns a channel for sending
-    /// new audis::Event objects to be logged, and the thread
-    /// JoinHandle for waiting on the thread to finish.
+    /// Compressed payloads carry a small header, and are
+    /// decompressed by `retrieve()` automatically, so events
+    /// logged with and without compression can be mixed freely
+    /// in the same audit log.  Payloads that don't shrink when
+    /// compressed are stored as-is.
     ///
-    /// The sending channel is buffered, and will have enough
-    /// space to keep `n` Event objects in memory.  If `n` is
-    /// passed as zero, a suitable default will be used instead.
-    ///
-    /// If the background thread encounters an error while trying
-    /// to log an Event to the Redis backend, it will print out
-    /// the error and attempt to recover.
+    #[cfg(any(feature = "zstd", feature = "gzip"))]
+    pub fn compress(mut self, algo: Compression, threshold: usize) -> Client {
+        self.compression = Some((algo, threshold));
+        self
+    }
+
+    /// Refuse to log events whose payload is larger than
+    /// `bytes` bytes, returning `AudisError::Invalid` instead.
     ///
-    /// To shut down the background thread, drop the returned
-    /// SyncSender<Event> object and then join the thread's
-    /// JoinHandle.
+    /// The limit applies to the payload as given, before any
+    /// compression.
     ///
-    pub fn background(&self, n: usize) -> AudisResult<(SyncSender<Event>, JoinHandle<()>)> {
-        let c = Client {
-            url: self.url.to_string(),
-            redis: redis::Client::open(self.url.as_str())?,
-        };
-        let (tx, rx) = sync_channel(if n == 0 { 100 } else { n });
-
-        let t = spawn(move || {
-            for e in rx {
-                match c.log(&e) {
-                    Err(err) => println!("audis failed to log event {}: {}", e.id, err),
-                    Ok(_) => (),
-                };
-            }
-        });
+    pub fn limit(mut self, bytes: usize) -> Client {
+        self.max_payload = Some(bytes);
+        self
+    }
 
-        Ok((tx, t))
+    /// Run every event through `check` before logging it.
+    ///
+    /// If `check` returns an error, the event is not logged,
+    /// and `log()` returns `AudisError::Invalid` with the error
+    /// message.  Multiple checks can be registered; they run
+    /// in the order they were added.
+    ///
+    pub fn validate<F>(mut self, check: F) -> Client
+    where
+        F: Fn(&Event) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validators.push(Arc::new(check));
+        self
     }
 
     /// Return the list of all known subjects.
+    ///
+    /// Subjects that the client's access policy (if any) does
+    /// not allow retrieving are left out.  This fetches them all
+    /// with a single `SMEMBERS`, which blocks Redis for as long
+    /// as it takes; see `scan_subjects()` for audit logs with
+    /// very many subjects.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
     pub fn subjects(&self) -> AudisResult<Vec<String>> {
-        self.smembers("subjects")
+        self.instrument("subjects", || {
+            let mut subjects = self.smembers("subjects")?;
+            subjects.retain(|s| self.allows_stored(Operation::Retrieve, s));
+            Ok(subjects)
+        })
     }
 
     /// Log an event to the audit log.
+    ///
+    /// If an event with the same ID has already been logged,
+    /// `AudisError::Duplicate` is returned (unless the client
+    /// `ignore_duplicates()`), and nothing is indexed.
+    /// Events with IDs or subject names that can't safely be used
+    /// as Redis keys (see `escape_subjects()`) are refused with
+    /// `AudisError::Invalid`.  If the event can't be indexed
+    /// against one of its subjects, it is still indexed against
+    /// the rest, and the first such error is returned; see
+    /// `log_outcome()` for finding out which subjects failed.
+    ///
+    /// Anything set in this thread's `audis::context` is
+    /// attached to the event before it is logged.  The event is
+    /// then run through any interceptors added via
+    /// `with_interceptor()`; if one of them drops the event,
+    /// nothing is logged, and `log()` still succeeds.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, e),
+            err,
+            fields(id = %e.id, subjects = e.subjects.len(), commands)
+        )
+    )]
     pub fn log(&self, e: &Event) -> AudisResult<&Client> {
-        self.setnx(&id!(e.id), &e.data)?;
-        for s in &e.subjects {
-            self.sadd("subjects", s)?.rpush(s, &e.id)?.incr(&e.id)?;
-        }
-        Ok(self)
+        self.instrument("log", || {
+            self.logged(e)?.ok()?;
+            Ok(self)
+        })
     }
 
     /// Retrieve the full list of events for the given subject.
+    ///
+    /// If the subject references an event whose data is missing,
+    /// `AudisError::NotFound` is returned, with its ID; see
+    /// `retrieve_range_with()` for skipping such events
+    /// instead.  If the client checks signatures (see
+    /// `audis::crypto`), and an event's doesn't match,
+    /// `AudisError::Tampered` is returned.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
     pub fn retrieve(&self, log: &str) -> AudisResult<Vec<Event>> {
-        let mut events: Vec<Event> = vec![];
-        for id in self.lrange(&log, "0", "-1")? {
-            events.push(Event {
-                id: String::from(&id),
-                data: self.get(&id!(id))?,
-                subjects: vec![],
-            })
-        }
+        self.instrument("retrieve", || self.events(log, 0, -1))
+    }
+
+    /// Retrieve part of the list of events for the given subject,
+    /// from index `start` to index `stop` (inclusive).
+    ///
+    /// Like the Redis `LRANGE` command, negative indices count
+    /// back from the end of the list, so `retrieve_range(s, -10,
+    /// -1)` retrieves the last ten events.  Errors are the same
+    /// as for `retrieve()`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_range(&self, log: &str, start: i64, stop: i64) -> AudisResult<Vec<Event>> {
+        self.instrument("retrieve_range", || self.events(log, start, stop))
+    }
+
+    /// Retrieve every event logged with the given correlation
+    /// ID, across all subjects, in the order they were logged.
+    ///
+    /// Events that have since been pruned from all of their
+    /// subjects (via `truncate()` or `purge()`) are skipped.  If
+    /// the client has an access policy, so are events it doesn't
+    /// allow retrieving from any of their subjects, which means
+    /// walking every subject (like `get_events()` does).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_trail(&self, correlation_id: &str) -> AudisResult<Vec<Event>> {
+        self.instrument("retrieve_trail", || {
+            let allowed = self.retrievable()?;
+            let mut events: Vec<Event> = vec![];
+            for id in self.lrange(&trail!(correlation_id), "0", "-1")? {
+                self.cancelled()?;
+                if allowed.as_ref().is_some_and(|a| !a.contains(&id)) {
+                    continue;
+                }
+                if let Some(e) = self.fetch(&id, None)? {
+                    #[cfg(feature = "crypto")]
+                    self.check_seal(&e)?;
+                    events.push(e);
+                }
+            }
+            Ok(events)
+        })
+    }
+
+    /// Count the events currently logged against a subject.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn count(&self, log: &str) -> AudisResult<u64> {
+        self.instrument("count", || self.llen(&self.readable(log)?))
+    }
 
-        Ok(events)
+    /// List the IDs of part of the list of events for the given
+    /// subject, from index `start` to index `stop` (inclusive),
+    /// without retrieving the events themselves.
+    ///
+    /// Indices work as they do for `retrieve_range()`.  The IDs
+    /// are listed whether or not their events are still around.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn event_ids(&self, log: &str, start: i64, stop: i64) -> AudisResult<Vec<String>> {
+        self.instrument("event_ids", || {
+            let log = self.readable(log)?;
+            self.lrange(&log, &start.to_string(), &stop.to_string())
+        })
+    }
+
+    /// Retrieve a single event, by ID, or None if there is no
+    /// such event.
+    ///
+    /// The subjects of the event are not filled in; see
+    /// `subjects_of()` for that.  If the client has an access
+    /// policy, it must allow retrieving at least one of the
+    /// subjects of the event, which means walking every subject
+    /// (like `subjects_of()` does).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_event(&self, id: &str) -> AudisResult<Option<Event>> {
+        self.instrument("retrieve_event", || {
+            if self.policy.is_some()
+                && !self
+                    .referrers(id)?
+                    .iter()
+                    .any(|s| self.allows_stored(Operation::Retrieve, s))
+            {
+                return Err(AudisError::Forbidden(format!("retrieve of event {}", id)));
+            }
+            let e = self.fetch(id, None)?;
+            #[cfg(feature = "crypto")]
+            if let Some(e) = &e {
+                self.check_seal(e)?;
+            }
+            Ok(e)
+        })
+    }
+
+    /// Find all of the subjects that an event is logged against.
+    ///
+    /// Audis doesn't keep an index from events to subjects, so
+    /// this walks every subject, and should not be called in any
+    /// hot paths.  Subjects that the client's access policy (if
+    /// any) does not allow retrieving are left out.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn subjects_of(&self, id: &str) -> AudisResult<Vec<String>> {
+        self.instrument("subjects_of", || {
+            let mut subjects = self.referrers(id)?;
+            subjects.retain(|s| self.allows_stored(Operation::Retrieve, s));
+            Ok(subjects)
+        })
+    }
+
+    /// Delete a single event, by ID, from every subject it is
+    /// logged against.
+    ///
+    /// Like `subjects_of()`, this walks every subject.  If the
+    /// client has an access policy, it must allow deleting from
+    /// all of them, or nothing is deleted.  Hash chains through
+    /// the event will no longer verify.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn delete(&self, id: &str) -> AudisResult<&Client> {
+        self.instrument("delete", || {
+            let subjects = self.referrers(id)?;
+            for s in &subjects {
+                self.allow_stored(Operation::Delete, s)?;
+            }
+            for s in &subjects {
+                self.locked(s, || {
+                    self.query::<()>(redis::cmd("LREM").arg(s).arg(0).arg(id))?;
+                    self.unstamp(s, &[id.to_string()])
+                })?;
+            }
+            self.del(id)?;
+            for s in &subjects {
+                self.record("delete", s, &[id.to_string()])?;
+            }
+            Ok(self)
+        })
     }
 
     /// Truncate a subject so that it only contains `n` Events.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
     pub fn truncate(&self, log: &str, n: u32) -> AudisResult<&Client> {
-        for id in self.lrange(&log, "0", &format!("-{}", n + 1))? {
-            self.lpop(&log)?.deref(&id)?;
-        }
-        Ok(self)
+        self.instrument("truncate", || {
+            self.allow(Operation::Truncate, log)?;
+            let log = self.subject(log);
+            let log = log.as_ref();
+            let removed = self.locked(log, || {
+                let removed = self.lrange(log, "0", &format!("-{}", n + 1))?;
+                for id in &removed {
+                    self.lpop(log)?.deref(id)?;
+                }
+                self.unstamp(log, &removed)?;
+                Ok(removed)
+            })?;
+            self.record("truncate", log, &removed)?;
+            Ok(self)
+        })
     }
 
     /// Delete the Event `last` and all prior events from a given subject.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
     pub fn purge(&self, log: &str, last: &str) -> AudisResult<&Client> {
-        for id in self.lrange(&log, "0", "-1")? {
-            self.lpop(&log)?.deref(&id)?;
-            if id == last {
+        self.instrument("purge", || {
+            self.allow(Operation::Purge, log)?;
+            let log = self.subject(log);
+            let log = log.as_ref();
+            let removed = self.locked(log, || self.purge_through(log, last))?;
+            self.record("purge", log, &removed)?;
+            Ok(self)
+        })
+    }
+
+    /// Delete the Event `last` and all prior events from a given
+    /// subject, like `purge()` does, handing them to `archive`
+    /// first, i.e. to write them out somewhere safe.
+    ///
+    /// The subject's lock (see `snapshot_reads()`) is held from
+    /// before the events are read until after they are removed,
+    /// so the events archived are exactly those removed, however
+    /// many other clients are pruning the subject at the same
+    /// time.  If `archive` fails, nothing is removed, and its
+    /// error is returned.  Events whose payloads have gone
+    /// missing are removed without being archived.
+    ///
+    /// If the client has an access policy, it must allow both
+    /// `Operation::Purge` and `Operation::Retrieve` on the subject.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, archive), err, fields(commands))
+    )]
+    pub fn purge_archiving<F>(&self, log: &str, last: &str, archive: F) -> AudisResult<&Client>
+    where
+        F: FnOnce(&[Event]) -> AudisResult<()>,
+    {
+        self.instrument("purge_archiving", || {
+            self.allow(Operation::Purge, log)?;
+            self.allow(Operation::Retrieve, log)?;
+            let log = self.subject(log);
+            let log = log.as_ref();
+            let removed = self.locked(log, || {
+                let mut events = vec![];
+                for id in self.lrange(log, "0", "-1")? {
+                    if let Some(e) = self.fetch(&id, Some(log))? {
+                        #[cfg(feature = "crypto")]
+                        self.check_seal(&e)?;
+                        events.push(e);
+                    }
+                    if id == last {
+                        break;
+                    }
+                }
+                archive(&events)?;
+                self.purge_through(log, last)
+            })?;
+            self.record("purge", log, &removed)?;
+            Ok(self)
+        })
+    }
+
+    // Delete the Event `last` and all prior events from a (stored)
+    // subject, whose lock the caller holds, returning their IDs
+    // for the caller to record once it has let go of the lock.
+    fn purge_through(&self, log: &str, last: &str) -> AudisResult<Vec<String>> {
+        let mut removed = vec![];
+        for id in self.lrange(log, "0", "-1")? {
+            self.lpop(log)?.deref(&id)?;
+            let done = id == last;
+            removed.push(id);
+            if done {
                 break;
             }
         }
+        self.unstamp(log, &removed)?;
+        Ok(removed)
+    }
 
-        Ok(self)
+    // Run an event past the naming rules, the payload size
+    // limit, and all of the registered validators.
+    fn check(&self, e: &Event) -> AudisResult<()> {
+        self.check_names(e)?;
+        if let Some(max) = self.max_payload {
+            if e.data.len() > max {
+                return Err(AudisError::Invalid(format!(
+                    "event {} payload is {} bytes (limit is {})",
+                    e.id,
+                    e.data.len(),
+                    max
+                )));
+            }
+        }
+        for v in &self.validators {
+            v(e).map_err(|why| AudisError::Invalid(format!("event {}: {}", e.id, why)))?;
+        }
+        Ok(())
+    }
+
+    // Find the subjects that reference an event.
+    fn referrers(&self, id: &str) -> AudisResult<Vec<String>> {
+        let mut found = vec![];
+        for s in self.smembers("subjects")? {
+            if self.lrange(&s, "0", "-1")?.iter().any(|i| i == id) {
+                found.push(s);
+            }
+        }
+        found.sort();
+        Ok(found)
+    }
+
+    // Look up a range of the events in a subject, for both
+    // `retrieve()` and `retrieve_range()`.
+    fn events(&self, log: &str, start: i64, stop: i64) -> AudisResult<Vec<Event>> {
+        Ok(self.retrieval(log, start, stop, Missing::Fail)?.events)
+    }
+
+    // Log an event, for `log()`, `log_sequenced()` and
+    // `log_outcome()`, reporting what became of it by (real)
+    // subject name.
+    fn logged(&self, e: &Event) -> AudisResult<LogOutcome> {
+        let e = context::attach(e);
+        let e = match self.intercept(&e)? {
+            Some(e) => e,
+            None => return Ok(LogOutcome::default()),
+        };
+        self.check(&e)?;
+        self.stored(e)
+    }
+
+    // Store an event that has already been intercepted and
+    // checked, and report what became of it by (real) subject
+    // name.
+    fn stored(&self, e: Cow<'_, Event>) -> AudisResult<LogOutcome> {
+        let names: Vec<String> = e.subjects.clone();
+        let e = self.pseudonymized(e);
+        let real: BTreeMap<&String, String> = e.subjects.iter().zip(names).collect();
+        let stored = self.store(&e)?;
+        let real = |s: String| real.get(&s).cloned().unwrap_or(s);
+        Ok(LogOutcome {
+            indexed: stored.indexed.into_iter().map(real).collect(),
+            failed: stored
+                .failed
+                .into_iter()
+                .map(|(s, err)| (real(s), err))
+                .collect(),
+            sequence: stored
+                .sequence
+                .into_iter()
+                .map(|(s, n)| (real(s), n))
+                .collect(),
+        })
+    }
+
+    // Write an event (that has already made it past the
+    // interceptors and validators) to the backend, and index it,
+    // reporting what became of it by (stored) subject name.
+    fn store(&self, e: &Event) -> AudisResult<LogOutcome> {
+        #[cfg(feature = "json")]
+        let e = &*self.canonical(e);
+        let (data, keys) = self.encode_payload(e)?;
+        if !self.put(e, &data)? {
+            if self.duplicates_ok {
+                return Ok(LogOutcome::default());
+            }
+            return Err(AudisError::Duplicate(e.id.to_string()));
+        }
+        if let Some(mut keys) = keys {
+            self.query::<()>(&mut keys)?;
+        }
+        #[cfg(feature = "crypto")]
+        self.seal(e)?;
+        let subjects = self.novel(e)?;
+        if subjects.is_empty() && !e.subjects.is_empty() {
+            self.del(&e.id)?;
+            return Ok(LogOutcome::default());
+        }
+        if let Some(cid) = &e.correlation_id {
+            self.rpush(&trail!(cid), &e.id)?;
+        }
+        let mut outcome = LogOutcome::default();
+        let mut indexed = vec![];
+        for s in subjects {
+            match self.index(s, e) {
+                Ok(n) => {
+                    if let Some(n) = n {
+                        outcome.sequence.insert(s.to_string(), n);
+                    }
+                    outcome.indexed.push(s.to_string());
+                    indexed.push(s);
+                }
+                Err(err) => outcome.failed.push((s.to_string(), err)),
+            }
+        }
+        #[cfg(feature = "search")]
+        self.index_fields(e, &indexed)?;
+        self.materialize(e, &indexed)?;
+        self.expire(e, &indexed)?;
+        self.forwarded(e, &indexed);
+        self.tick();
+        Ok(outcome)
+    }
+
+    // Index a (stored) event against one of its subjects,
+    // returning the sequence number it was given there, if any.
+    fn index(&self, s: &str, e: &Event) -> AudisResult<Option<u64>> {
+        self.sadd("subjects", s)?
+            .touch(s)?
+            .append(s, e)?
+            .stamp(s, &e.id)?
+            .refer(&e.id, 1)?;
+        self.enqueue(s, &e.id)?;
+        self.sequence(s, &e.id)
+    }
+
+    // Run a public operation, recording whatever instrumentation
+    // has been compiled in (i.e. the `metrics` and `tracing`
+    // features).  Spans are opened by the callers themselves,
+    // via `tracing::instrument`; here we just fill in the number
+    // of backend commands the operation needed.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn instrument<T, F>(&self, op: &'static str, f: F) -> AudisResult<T>
+    where
+        F: FnOnce() -> AudisResult<T>,
+    {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        #[cfg(feature = "tracing")]
+        let before = COMMANDS.with(|n| n.get());
+
+        let r = f();
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("commands", COMMANDS.with(|n| n.get()) - before);
+        #[cfg(feature = "metrics")]
+        metrics::observe(op, start.elapsed(), r.is_ok());
+        r
     }
 
     fn query<T: redis::FromRedisValue>(&self, cmd: &mut redis::Cmd) -> AudisResult<T> {
-        cmd.query(&mut self.redis.get_connection()?)
+        #[cfg(feature = "tracing")]
+        COMMANDS.with(|n| n.set(n.get() + 1));
+        Ok(cmd.query(&mut *self.backend.connection()?)?)
+    }
+
+    fn pipeline<T: redis::FromRedisValue>(&self, pipe: &redis::Pipeline) -> AudisResult<T> {
+        #[cfg(feature = "tracing")]
+        COMMANDS.with(|n| n.set(n.get() + 1));
+        Ok(pipe.query(&mut *self.backend.connection()?)?)
+    }
+
+    // Look up a single event (without its subjects), by ID,
+    // as seen from `subject` (if known).
+    fn fetch(&self, id: &str, subject: Option<&str>) -> AudisResult<Option<Event>> {
+        let (data, meta, mut trail) = self.load(id)?;
+        Ok(match data {
+            Some(data) => Some(Event {
+                data: self.decode_payload(id, subject, data)?,
+                id: id.to_string(),
+                subjects: vec![],
+                meta,
+                correlation_id: trail.remove("correlation"),
+                parent_id: trail.remove("parent"),
+            }),
+            None => None,
+        })
     }
 
     fn ping(&self) -> AudisResult<&Client> {
-        self.query(&mut redis::cmd("PING"))?;
+        self.query::<()>(&mut redis::cmd("PING"))?;
         Ok(self)
     }
 
@@ -358,59 +1253,99 @@ impl Client {
         self.query(redis::cmd("LRANGE").arg(key).arg(a).arg(b))
     }
 
-    fn smembers(&self, key: &str) -> AudisResult<Vec<String>> {
-        self.query(redis::cmd("SMEMBERS").arg(key))
+    fn llen(&self, key: &str) -> AudisResult<u64> {
+        self.query(redis::cmd("LLEN").arg(key))
     }
 
-    fn rpush(&self, log: &str, id: &str) -> AudisResult<&Client> {
-        self.query(redis::cmd("RPUSH").arg(log).arg(id))?;
-        Ok(self)
+    // Walk a SCAN-style cursor (SCAN, SSCAN, etc.) to completion,
+    // collecting everything that matches the glob `pattern`.
+    fn scan(&self, cmd: &str, key: Option<&str>, pattern: &str) -> AudisResult<Vec<String>> {
+        let mut all = vec![];
+        let mut cursor = 0u64;
+        loop {
+            self.cancelled()?;
+            let mut c = redis::cmd(cmd);
+            if let Some(key) = key {
+                c.arg(key);
+            }
+            let (next, batch): (u64, Vec<String>) = self.query(
+                c.arg(cursor)
+                    .arg("MATCH")
+                    .arg(pattern)
+                    .arg("COUNT")
+                    .arg(1000),
+            )?;
+            all.extend(batch);
+            if next == 0 {
+                return Ok(all);
+            }
+            cursor = next;
+        }
     }
 
-    fn lpop(&self, log: &str) -> AudisResult<&Client> {
-        self.query(redis::cmd("LPOP").arg(log))?;
-        Ok(self)
+    fn smembers(&self, key: &str) -> AudisResult<Vec<String>> {
+        self.query(redis::cmd("SMEMBERS").arg(key))
     }
 
-    fn decr(&self, key: &str) -> AudisResult<&Client> {
-        self.query(redis::cmd("DECR").arg(key))?;
+    fn rpush(&self, log: &str, id: &str) -> AudisResult<&Client> {
+        self.query::<()>(redis::cmd("RPUSH").arg(log).arg(id))?;
         Ok(self)
     }
 
-    fn incr(&self, key: &str) -> AudisResult<&Client> {
-        self.query(redis::cmd("INCR").arg(key))?;
+    fn lpop(&self, log: &str) -> AudisResult<&Client> {
+        self.query::<()>(redis::cmd("LPOP").arg(log))?;
         Ok(self)
     }
 
-    fn setnx(&self, key: &str, data: &str) -> AudisResult<&Client> {
-        let s: i32 = self.query(redis::cmd("SETNX").arg(key).arg(data))?;
-        if s == 1 {
-            Ok(self)
-        } else {
-            Err(redis::RedisError::from((
-                redis::ErrorKind::IoError,
-                "duplicate key detected",
-            )))
-        }
+    fn setnx(&self, key: &str, data: &[u8]) -> AudisResult<bool> {
+        self.query(redis::cmd("SETNX").arg(key).arg(data))
     }
 
     fn sadd(&self, key: &str, data: &str) -> AudisResult<&Client> {
-        self.query(redis::cmd("SADD").arg(key).arg(data))?;
+        self.query::<()>(redis::cmd("SADD").arg(key).arg(data))?;
         Ok(self)
     }
 
-    fn get(&self, key: &str) -> AudisResult<String> {
+    fn get(&self, key: &str) -> AudisResult<Option<String>> {
         self.query(redis::cmd("GET").arg(key))
     }
 
     fn del(&self, id: &str) -> AudisResult<&Client> {
-        self.query(redis::cmd("DEL").arg(id!(id)).arg(idref!(id)))?;
+        // the event goes from its trail along with everything else,
+        // lest the trail outlive all of its events.
+        let (kind, mut cid): (String, Option<String>) = self.pipeline(
+            redis::pipe()
+                .cmd("TYPE")
+                .arg(id!(id))
+                .cmd("HGET")
+                .arg(idtrail!(id))
+                .arg("correlation"),
+        )?;
+        if kind == "hash" {
+            cid = self.query(redis::cmd("HGET").arg(id!(id)).arg("correlation"))?;
+        }
+        if let Some(cid) = cid {
+            self.query::<()>(redis::cmd("LREM").arg(trail!(cid)).arg(0).arg(id))?;
+        }
+        self.query::<()>(
+            redis::cmd("DEL")
+                .arg(id!(id))
+                .arg(idref!(id))
+                .arg(idmeta!(id))
+                .arg(idtrail!(id))
+                .arg(idchain!(id))
+                .arg(idsig!(id))
+                .arg(idkeys!(id))
+                .arg(idseq!(id)),
+        )?;
+        #[cfg(feature = "search")]
+        self.query::<()>(redis::cmd("DEL").arg(format!("audis:search:{}", id)))?;
         Ok(self)
     }
 
     // Dereference (and possibly delete) an audit event.
     fn deref(&self, id: &str) -> AudisResult<&Client> {
-        if self.decr(&idref!(id))?.get(&idref!(id))? == "0" {
+        if self.refer(id, -1)? <= 0 {
             self.del(id)?;
         }
         Ok(self)
@@ -0,0 +1,106 @@
+//! Prometheus instrumentation, enabled by the `metrics` feature.
+//!
+//! Every public audit log operation (`log`, `retrieve`,
+//! `truncate` and `purge`) is timed, and event logging is
+//! tallied, regardless of whether it happens in the calling
+//! thread or in a `background()` thread.  The collectors live
+//! in a process-wide set; register them with whatever registry
+//! your service exposes to its scraper:
+//!
+//! ```rust,no_run
+//! extern crate audis;
+//! extern crate prometheus;
+//!
+//! fn main() {
+//!     let registry = prometheus::Registry::new();
+//!     audis::metrics::register(&registry).unwrap();
+//!
+//!     // ... serve registry.gather() on /metrics ...
+//! }
+//! ```
+//!
+//! The following metrics are provided:
+//!
+//!  - `audis_events_logged_total` - how many events have been
+//!    successfully logged.
+//!  - `audis_log_failures_total` - how many events have failed
+//!    to be logged, for any reason.
+//!  - `audis_operation_duration_seconds` - a histogram of how
+//!    long each operation took, labeled by `op`.
+//!  - `audis_background_queue_depth` - how many events are
+//!    waiting in `background()` buffers to be logged.
+//!
+
+use prometheus::{Histogram, HistogramOpts, HistogramVec, IntCounter, IntGauge, Registry};
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+struct Metrics {
+    events_logged: IntCounter,
+    log_failures: IntCounter,
+    duration: HistogramVec,
+    queue_depth: IntGauge,
+}
+
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| Metrics {
+        events_logged: IntCounter::new(
+            "audis_events_logged_total",
+            "Total number of audit events logged",
+        )
+        .unwrap(),
+        log_failures: IntCounter::new(
+            "audis_log_failures_total",
+            "Total number of audit events that failed to log",
+        )
+        .unwrap(),
+        duration: HistogramVec::new(
+            HistogramOpts::new(
+                "audis_operation_duration_seconds",
+                "Latency of audit log operations",
+            ),
+            &["op"],
+        )
+        .unwrap(),
+        queue_depth: IntGauge::new(
+            "audis_background_queue_depth",
+            "Number of audit events waiting to be logged by background threads",
+        )
+        .unwrap(),
+    })
+}
+
+/// Register all audis metrics with a Prometheus registry.
+pub fn register(registry: &Registry) -> prometheus::Result<()> {
+    let m = metrics();
+    registry.register(Box::new(m.events_logged.clone()))?;
+    registry.register(Box::new(m.log_failures.clone()))?;
+    registry.register(Box::new(m.duration.clone()))?;
+    registry.register(Box::new(m.queue_depth.clone()))?;
+    Ok(())
+}
+
+/// Retrieve the latency histogram for a single operation.
+pub fn duration(op: &str) -> Histogram {
+    metrics().duration.with_label_values(&[op])
+}
+
+// Record the outcome of a single client operation.
+pub(crate) fn observe(op: &str, took: Duration, ok: bool) {
+    let m = metrics();
+    duration(op).observe(took.as_secs_f64());
+    if op == "log" {
+        if ok {
+            m.events_logged.inc();
+        } else {
+            m.log_failures.inc();
+        }
+    }
+}
+
+// Track events entering (and leaving) background buffers.
+pub(crate) fn queued(n: i64) {
+    metrics().queue_depth.add(n);
+}
@@ -0,0 +1,181 @@
+//! Tower (and so axum, tonic, etc.) middleware for auditing HTTP
+//! requests.
+//!
+//! Adding a `RequestAudit` layer to a service logs one audit
+//! event per request, via a `background()` logging thread:
+//!
+//! ```rust,no_run
+//! extern crate audis;
+//! use audis::middleware::RequestAudit;
+//!
+//! fn main() {
+//!     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+//!     let (tx, thread) = client.background(0).unwrap();
+//!     let audit = RequestAudit::new(tx).subject(|req| {
+//!         req.headers
+//!             .get("x-user")
+//!             .and_then(|u| u.to_str().ok())
+//!             .map(|u| format!("user:{}", u))
+//!     });
+//!
+//!     // ... and then, with axum:
+//!     //
+//!     //   let app = Router::new()
+//!     //       .route("/", get(handler))
+//!     //       .layer(audit);
+//! }
+//! ```
+//!
+//! Each event's payload is a JSON object holding the request
+//! `method` and `path`, the response `status`, and the time taken
+//! to respond (up to the headers) in milliseconds, as
+//! `latency_ms`.  Requests that fail outright (rather than
+//! getting an error response) are logged with a null `status`,
+//! and the `error`.  Server errors (5xx) are logged with a
+//! `severity` of `err`, client errors (4xx) with `warning`, and
+//! everything else with `info`.
+//!
+//! Since services run on an async runtime, events are queued
+//! without waiting; if the background thread's buffer is full,
+//! the event is dropped, with an error (via the `log` crate).
+//!
+//! This requires the `middleware` feature.
+//!
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use http::request::Parts;
+use http::{Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{Event, Sender};
+
+// Picks the subject to log a request against.
+type Principal = dyn Fn(&Parts) -> Option<String> + Send + Sync;
+
+/// A tower layer that logs an audit event for each request.
+#[derive(Clone)]
+pub struct RequestAudit {
+    tx: Sender,
+    principal: Option<Arc<Principal>>,
+    subject: String,
+}
+
+impl RequestAudit {
+    /// Log an event for each request via `tx`, against the
+    /// `http` subject.
+    pub fn new(tx: Sender) -> RequestAudit {
+        RequestAudit {
+            tx,
+            principal: None,
+            subject: "http".to_string(),
+        }
+    }
+
+    /// Log each request against the subject picked by `f` (i.e.
+    /// from the authenticated principal, found in the request
+    /// headers or extensions), if it picks one.
+    pub fn subject<F>(mut self, f: F) -> RequestAudit
+    where
+        F: Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    {
+        self.principal = Some(Arc::new(f));
+        self
+    }
+
+    /// Log requests that `subject()` doesn't pick a subject for
+    /// against `subject`, rather than `http`.
+    pub fn default_subject(mut self, subject: &str) -> RequestAudit {
+        self.subject = subject.to_string();
+        self
+    }
+}
+
+impl<S> Layer<S> for RequestAudit {
+    type Service = RequestAuditService<S>;
+
+    fn layer(&self, inner: S) -> RequestAuditService<S> {
+        RequestAuditService {
+            inner,
+            audit: self.clone(),
+        }
+    }
+}
+
+/// A service wrapped by a `RequestAudit` layer.
+#[derive(Clone)]
+pub struct RequestAuditService<S> {
+    inner: S,
+    audit: RequestAudit,
+}
+
+impl<S, B, R> Service<Request<B>> for RequestAuditService<S>
+where
+    S: Service<Request<B>, Response = Response<R>>,
+    S::Future: Send + 'static,
+    S::Error: std::fmt::Display,
+{
+    type Response = Response<R>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<R>, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let (parts, body) = req.into_parts();
+        let subject = self
+            .audit
+            .principal
+            .as_ref()
+            .and_then(|f| f(&parts))
+            .unwrap_or_else(|| self.audit.subject.to_string());
+        let mut data = serde_json::json!({
+            "method": parts.method.as_str(),
+            "path": parts.uri.path(),
+        });
+
+        let tx = self.audit.tx.clone();
+        let started = Instant::now();
+        let response = self.inner.call(Request::from_parts(parts, body));
+        Box::pin(async move {
+            let response = response.await;
+            data["latency_ms"] = (started.elapsed().as_millis() as u64).into();
+            let severity = match &response {
+                Ok(r) => {
+                    data["status"] = r.status().as_u16().into();
+                    match r.status().as_u16() {
+                        500.. => "err",
+                        400..=499 => "warning",
+                        _ => "info",
+                    }
+                }
+                Err(e) => {
+                    data["status"] = serde_json::Value::Null;
+                    data["error"] = e.to_string().into();
+                    "err"
+                }
+            };
+
+            let e = Event::builder()
+                .subject(subject)
+                .data(data.to_string())
+                .meta("severity", severity)
+                .build();
+            let queued = match e {
+                Ok(e) => tx.try_send(e).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(why) = queued {
+                log::error!(target: "audis", "failed to log HTTP request: {}", why);
+            }
+            response
+        })
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{AudisError, AudisResult, Client, Event};
+
+/// What to do about events that are still listed in a subject,
+/// but whose payloads have gone missing (i.e. because they were
+/// pruned, or expired, part-way through a retrieval); see
+/// `Client::retrieve_range_with()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Missing {
+    /// Fail with `AudisError::NotFound`, naming the first event
+    /// that's missing, like `retrieve()` does.
+    Fail,
+
+    /// Skip missing events, reporting their IDs in
+    /// `Retrieval::missing`.
+    Skip,
+}
+
+/// The events retrieved by `Client::retrieve_range_with()`.
+#[derive(Debug, Default)]
+pub struct Retrieval {
+    /// The events that were retrieved, in order.
+    pub events: Vec<Event>,
+
+    /// The IDs of the events that are listed in the subject, but
+    /// whose payloads were missing, in order.
+    pub missing: Vec<String>,
+}
+
+impl Retrieval {
+    /// Returns true if every event listed in the subject was
+    /// retrieved.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl Client {
+    /// Retrieve part of the list of events for the given subject,
+    /// like `retrieve_range()` does, dealing with events whose
+    /// payloads have gone missing as per `missing`.
+    ///
+    /// A subject's events are listed before they are looked up,
+    /// so if they are pruned from the backend in between (by
+    /// another client, or because they expired; see
+    /// `Client::expire_after()`), the list will refer to events
+    /// that are no longer there.  Reporting tools that would
+    /// rather show what's left than nothing at all can skip
+    /// them, and flag the retrieval as incomplete.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use audis::Missing;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///     let r = client.retrieve_range_with("user:42", 0, -1, Missing::Skip).unwrap();
+    ///     for id in &r.missing {
+    ///         eprintln!("event {} has gone missing", id);
+    ///     }
+    /// }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_range_with(
+        &self,
+        log: &str,
+        start: i64,
+        stop: i64,
+        missing: Missing,
+    ) -> AudisResult<Retrieval> {
+        self.instrument("retrieve_range_with", || {
+            self.retrieval(log, start, stop, missing)
+        })
+    }
+
+    pub(crate) fn retrieval(
+        &self,
+        log: &str,
+        start: i64,
+        stop: i64,
+        missing: Missing,
+    ) -> AudisResult<Retrieval> {
+        let log = self.readable(log)?;
+        let log = log.as_str();
+        self.snapshot(log, || {
+            let mut r = Retrieval::default();
+            for id in self.lrange(log, &start.to_string(), &stop.to_string())? {
+                self.cancelled()?;
+                match self.fetch(&id, Some(log))? {
+                    Some(e) => {
+                        #[cfg(feature = "crypto")]
+                        self.check_seal(&e)?;
+                        r.events.push(e)
+                    }
+                    None if missing == Missing::Skip => r.missing.push(id),
+                    None => return Err(AudisError::NotFound(id)),
+                }
+            }
+            Ok(r)
+        })
+    }
+}
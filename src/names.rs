@@ -0,0 +1,128 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use crate::{AudisError, AudisResult, Client, Event, PARALLEL, SYSTEM_SUBJECT};
+
+impl Client {
+    /// Escape subject names that can't be stored as they are,
+    /// rather than refusing to log events against them.
+    ///
+    /// Subject names double as Redis keys, so `log()` normally
+    /// rejects (with `AudisError::Invalid`) those that are empty,
+    /// contain control characters, or would collide with audis'
+    /// own keys: `subjects`, and anything starting with `audit:`
+    /// or `audis:`.  With escaping, control characters (and `%`
+    /// itself) are percent-encoded instead, as is the first
+    /// character of a colliding name, so `audit:x` is stored as
+    /// `%61udit:x`; `retrieve()` and friends escape the names
+    /// they are given the same way.  Empty names, and the
+    /// reserved `__audis__` subject, are still rejected.
+    ///
+    /// Event IDs are never escaped, since they are handed back
+    /// to callers as-is; malformed IDs are always rejected.
+    ///
+    pub fn escape_subjects(mut self) -> Client {
+        self.escape = true;
+        self
+    }
+
+    // Make sure that an event's ID and subjects can be used in
+    // (or as) Redis keys, without clobbering anything else.
+    pub(crate) fn check_names(&self, e: &Event) -> AudisResult<()> {
+        let bad = |why: String| Err(AudisError::Invalid(why));
+        if e.id.is_empty() {
+            return bad("event ID is empty".to_string());
+        }
+        if e.id.chars().any(char::is_control) {
+            return bad(format!("event ID {:?} contains control characters", e.id));
+        }
+        if let Some(p) = PARALLEL.iter().find(|p| e.id.ends_with(*p)) {
+            return bad(format!(
+                "event ID {:?} ends in {}, which is reserved",
+                e.id, p
+            ));
+        }
+        if e.id.starts_with(&format!("{}:", SYSTEM_SUBJECT)) {
+            return bad(format!(
+                "event ID {:?} starts with {}:, which is reserved",
+                e.id, SYSTEM_SUBJECT
+            ));
+        }
+
+        for s in &e.subjects {
+            let why = if s.is_empty() {
+                "is empty"
+            } else if s == SYSTEM_SUBJECT {
+                "is reserved"
+            } else {
+                match reserved(&self.subject(s)) {
+                    Some(why) => why,
+                    None => continue,
+                }
+            };
+            return bad(format!("event {}: subject {:?} {}", e.id, s, why));
+        }
+        Ok(())
+    }
+}
+
+// Why a (stored) subject name can't be used, if it can't.
+fn reserved(s: &str) -> Option<&'static str> {
+    if s.chars().any(char::is_control) {
+        Some("contains control characters")
+    } else if collides(s) {
+        Some("collides with audis' own keys")
+    } else {
+        None
+    }
+}
+
+fn collides(s: &str) -> bool {
+    s == "subjects" || s.starts_with("audit:") || s.starts_with("audis:")
+}
+
+// Percent-encode the parts of a subject name that can't be
+// stored as they are (see `Client::escape_subjects()`).
+pub(crate) fn escape(s: &str) -> Cow<'_, str> {
+    let collides = collides(s);
+    if !collides && !s.chars().any(|c| c == '%' || c.is_control()) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + 2);
+    for (i, c) in s.chars().enumerate() {
+        if c == '%' || c.is_control() || (i == 0 && collides) {
+            for b in c.to_string().bytes() {
+                write!(escaped, "%{:02X}", b).expect("writing to a String can't fail");
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+// Undo `escape()`, which always percent-encodes `%` itself, so
+// that every `%XX` in a stored name is an escape.
+pub(crate) fn unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('%') {
+        return Cow::Borrowed(s);
+    }
+    let (b, mut raw) = (s.as_bytes(), Vec::with_capacity(s.len()));
+    let mut i = 0;
+    while i < b.len() {
+        let hex = b
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(c) if b[i] == b'%' => {
+                raw.push(c);
+                i += 3;
+            }
+            _ => {
+                raw.push(b[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&raw).into_owned())
+}
@@ -0,0 +1,307 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::backend::glob;
+use crate::{AudisError, AudisResult, Client, Event, Operation};
+
+// The queue of event IDs waiting to be processed, per subject.
+macro_rules! queue {
+    ($s:expr) => {
+        format!("audis:outbox:queue:{}", $s)
+    };
+}
+
+// The workers that have claimed events from a subject's queue.
+macro_rules! workers {
+    ($s:expr) => {
+        format!("audis:outbox:workers:{}", $s)
+    };
+}
+
+// The events a worker has claimed, but not yet acknowledged.
+macro_rules! pending {
+    ($w:expr, $s:expr) => {
+        format!("audis:outbox:pending:{}:{}", $w, $s)
+    };
+}
+
+// A worker's lease on its claimed events, which expires if it
+// stops claiming more.
+macro_rules! lease {
+    ($w:expr, $s:expr) => {
+        format!("audis:outbox:lease:{}:{}", $w, $s)
+    };
+}
+
+/// One worker's view of a subject's outbox; see
+/// `Client::outbox()`.
+pub struct Outbox<'a> {
+    client: &'a Client,
+    subject: String,
+    worker: String,
+    lease: Duration,
+}
+
+impl Client {
+    /// Queue up the IDs of events logged against subjects
+    /// matching `pattern` (a Redis-style glob, matched against
+    /// subject names as stored), for workers to process.
+    ///
+    /// This is the reliable-consumer pattern, for forwarding
+    /// events on to other systems (or otherwise acting on them)
+    /// exactly once they are logged: each matching subject gets
+    /// a queue (in `audis:outbox:queue:$subject`), which workers
+    /// claim batches of events from, and then acknowledge once
+    /// they're done with them; see `outbox_worker()`.  The
+    /// subjects themselves are left as they are.  Only events
+    /// logged by clients with a matching outbox are queued.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+    ///         .unwrap()
+    ///         .outbox("billing:*");
+    ///
+    ///     let outbox = client
+    ///         .outbox_worker("billing:invoices", "worker-1", Duration::from_secs(60))
+    ///         .unwrap();
+    ///     loop {
+    ///         let n = outbox
+    ///             .process(100, |e| {
+    ///                 println!("forwarding {}", e.id);
+    ///                 Ok::<(), String>(())
+    ///             })
+    ///             .unwrap();
+    ///         if n == 0 {
+    ///             std::thread::sleep(Duration::from_secs(1));
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    pub fn outbox(mut self, pattern: &str) -> Client {
+        self.outboxes.push(pattern.to_string());
+        self
+    }
+
+    /// Work through a subject's outbox (see `outbox()`), as
+    /// `worker`, which must be unique among the workers sharing
+    /// the outbox.
+    ///
+    /// Each claim renews the worker's lease on the events it has
+    /// claimed for `lease`.  If it stops claiming events for
+    /// longer than that (i.e. because it died), whatever it still
+    /// hasn't acknowledged is put back at the end of the queue,
+    /// for the other workers to claim; `lease` should be long
+    /// enough to process a batch of events in.  Events are thus
+    /// processed at least once, but not necessarily in order.
+    pub fn outbox_worker(
+        &self,
+        subject: &str,
+        worker: &str,
+        lease: Duration,
+    ) -> AudisResult<Outbox<'_>> {
+        self.allow(Operation::Retrieve, subject)?;
+        if worker.is_empty() || worker.contains(':') || worker.chars().any(char::is_control) {
+            return Err(AudisError::Invalid(format!(
+                "outbox worker name {:?} must not be empty, or contain colons or control characters",
+                worker
+            )));
+        }
+        Ok(Outbox {
+            client: self,
+            subject: self.subject(subject).into_owned(),
+            worker: worker.to_string(),
+            lease,
+        })
+    }
+
+    // Queue an event up in a (stored) subject's outbox, if it
+    // has one.
+    pub(crate) fn enqueue(&self, subject: &str, id: &str) -> AudisResult<()> {
+        if self
+            .outboxes
+            .iter()
+            .any(|p| glob(p.as_bytes(), subject.as_bytes()))
+        {
+            self.query::<()>(redis::cmd("LPUSH").arg(queue!(subject)).arg(id))?;
+        }
+        Ok(())
+    }
+}
+
+impl Outbox<'_> {
+    /// Claim up to `n` of the oldest events in the queue, moving
+    /// them to this worker's pending list, where they stay until
+    /// they are acknowledged (see `ack()`).
+    ///
+    /// Claimed events that have since been removed from the
+    /// audit log are acknowledged, and left out.  Before
+    /// claiming anything, the events left pending by workers
+    /// whose leases have expired are put back in the queue.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(subject = %self.subject, worker = %self.worker, commands))
+    )]
+    pub fn claim(&self, n: usize) -> AudisResult<Vec<Event>> {
+        let c = self.client;
+        c.instrument("outbox_claim", || {
+            self.requeue()?;
+            let lease = self.lease.as_millis().max(1) as u64;
+            c.pipeline::<()>(
+                redis::pipe()
+                    .cmd("SET")
+                    .arg(lease!(self.worker, self.subject))
+                    .arg(1)
+                    .arg("PX")
+                    .arg(lease)
+                    .ignore()
+                    .cmd("SADD")
+                    .arg(workers!(self.subject))
+                    .arg(&self.worker)
+                    .ignore(),
+            )?;
+
+            let mut events = vec![];
+            for _ in 0..n {
+                let id: Option<String> = c.query(
+                    redis::cmd("RPOPLPUSH")
+                        .arg(queue!(self.subject))
+                        .arg(pending!(self.worker, self.subject)),
+                )?;
+                let id = match id {
+                    Some(id) => id,
+                    None => break,
+                };
+                match c.fetch(&id, Some(&self.subject))? {
+                    Some(e) => {
+                        #[cfg(feature = "crypto")]
+                        c.check_seal(&e)?;
+                        events.push(e);
+                    }
+                    None => self.acked(&id)?,
+                }
+            }
+            Ok(events)
+        })
+    }
+
+    /// Acknowledge that a claimed event has been processed,
+    /// removing it from this worker's pending list for good.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(subject = %self.subject, worker = %self.worker, commands))
+    )]
+    pub fn ack(&self, id: &str) -> AudisResult<()> {
+        self.client.instrument("outbox_ack", || self.acked(id))
+    }
+
+    /// Give up on processing a claimed event (for now), putting
+    /// it back at the front of the queue, to be claimed again.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(subject = %self.subject, worker = %self.worker, commands))
+    )]
+    pub fn nack(&self, id: &str) -> AudisResult<()> {
+        let c = self.client;
+        c.instrument("outbox_nack", || {
+            let removed: u64 = c.query(
+                redis::cmd("LREM")
+                    .arg(pending!(self.worker, self.subject))
+                    .arg(0)
+                    .arg(id),
+            )?;
+            if removed > 0 {
+                c.query::<()>(redis::cmd("RPUSH").arg(queue!(self.subject)).arg(id))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// List the IDs of the events this worker has claimed, but
+    /// not yet acknowledged, oldest first.  A worker that starts
+    /// back up (under the same name) before its lease expires
+    /// should finish these off before claiming any more.
+    pub fn pending(&self) -> AudisResult<Vec<String>> {
+        let c = self.client;
+        let mut ids = c.lrange(&pending!(self.worker, self.subject), "0", "-1")?;
+        ids.reverse();
+        Ok(ids)
+    }
+
+    /// Count the events still waiting in the queue.
+    pub fn queued(&self) -> AudisResult<u64> {
+        self.client.llen(&queue!(self.subject))
+    }
+
+    /// Claim up to `n` events (see `claim()`), and run `f` on
+    /// each, in order, acknowledging those it succeeds on, and
+    /// putting those it fails on back in the queue (see
+    /// `nack()`).  Returns how many events were processed.
+    ///
+    /// Failures are reported through the `log` crate, at the
+    /// `warn` level, under the `audis` target.
+    pub fn process<F, E>(&self, n: usize, mut f: F) -> AudisResult<usize>
+    where
+        F: FnMut(&Event) -> Result<(), E>,
+        E: fmt::Display,
+    {
+        let mut processed = 0;
+        for e in self.claim(n)? {
+            match f(&e) {
+                Ok(()) => {
+                    self.ack(&e.id)?;
+                    processed += 1;
+                }
+                Err(err) => {
+                    log::warn!(target: "audis", "failed to process event {} from the {} outbox: {}", e.id, self.subject, err);
+                    self.nack(&e.id)?;
+                }
+            }
+        }
+        Ok(processed)
+    }
+
+    // Put the events left pending by workers whose leases have
+    // expired back in the queue (one at a time, so that workers
+    // racing to do the same can't duplicate them), returning how
+    // many there were.
+    fn requeue(&self) -> AudisResult<u64> {
+        let c = self.client;
+        let mut n = 0;
+        for worker in c.smembers(&workers!(self.subject))? {
+            if worker == self.worker {
+                continue;
+            }
+            let alive: bool = c.query(redis::cmd("EXISTS").arg(lease!(worker, self.subject)))?;
+            if alive {
+                continue;
+            }
+            loop {
+                let id: Option<String> = c.query(
+                    redis::cmd("RPOPLPUSH")
+                        .arg(pending!(worker, self.subject))
+                        .arg(queue!(self.subject)),
+                )?;
+                match id {
+                    Some(_) => n += 1,
+                    None => break,
+                }
+            }
+            c.query::<()>(redis::cmd("SREM").arg(workers!(self.subject)).arg(&worker))?;
+        }
+        Ok(n)
+    }
+
+    fn acked(&self, id: &str) -> AudisResult<()> {
+        self.client.query::<()>(
+            redis::cmd("LREM")
+                .arg(pending!(self.worker, self.subject))
+                .arg(0)
+                .arg(id),
+        )
+    }
+}
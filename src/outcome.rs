@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use crate::{AudisError, AudisResult, Client, Event};
+
+/// What became of an event logged via `Client::log_outcome()`,
+/// subject by subject.
+///
+/// Subjects that the event wasn't logged against because it was
+/// a duplicate there (see `Client::dedup()`) are in neither list.
+#[derive(Debug, Default)]
+pub struct LogOutcome {
+    /// The subjects the event was indexed against, in order.
+    pub indexed: Vec<String>,
+
+    /// The subjects the event couldn't be indexed against, and
+    /// why, in order.  The event may still have made it into
+    /// some of these (i.e. into the subject itself, but not its
+    /// time index), so they should be checked (see
+    /// `Client::fsck()`) before logging it again.
+    pub failed: Vec<(String, AudisError)>,
+
+    /// The sequence numbers the event was given (see
+    /// `Client::sequenced()`), by subject.
+    pub sequence: BTreeMap<String, u64>,
+}
+
+impl LogOutcome {
+    /// Returns true if the event was indexed against every
+    /// subject it was logged against.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    // Fail with the first subject's error, if any failed.
+    pub(crate) fn ok(mut self) -> AudisResult<LogOutcome> {
+        if self.failed.is_empty() {
+            Ok(self)
+        } else {
+            Err(self.failed.remove(0).1)
+        }
+    }
+}
+
+impl Client {
+    /// Log an event, like `log()` does, but carry on indexing it
+    /// against the rest of its subjects if any of them fail,
+    /// and report which ones it was (and wasn't) indexed against.
+    ///
+    /// This is for recovery tooling that needs to know exactly
+    /// what to repair when logging an event against many
+    /// subjects only partially succeeds.  Failures before the
+    /// event is indexed against any subjects (i.e. because it's
+    /// a duplicate, or invalid, or the backend couldn't be
+    /// reached at all) are returned as errors, just like for
+    /// `log()`, which otherwise fails with the first subject's
+    /// error.  If an interceptor drops the event, the outcome is
+    /// empty.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///     let e = audis::Event::builder()
+    ///         .id("foo1")
+    ///         .data("{\"ok\":true}")
+    ///         .subjects(vec!["system", "user:42", "acct:7"])
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     let outcome = client.log_outcome(&e).unwrap();
+    ///     for (subject, err) in &outcome.failed {
+    ///         eprintln!("{} wasn't indexed against {}: {}", e.id, subject, err);
+    ///     }
+    /// }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, e),
+            err,
+            fields(id = %e.id, subjects = e.subjects.len(), commands)
+        )
+    )]
+    pub fn log_outcome(&self, e: &Event) -> AudisResult<LogOutcome> {
+        self.instrument("log_outcome", || self.logged(e))
+    }
+}
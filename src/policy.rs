@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::names::unescape;
+use crate::{AudisError, AudisResult, Client, SYSTEM_SUBJECT};
+
+/// The operations on a subject that an access policy (see
+/// `Client::authorize()`) is consulted about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Reading the events of a subject, via `retrieve()` (or
+    /// `retrieve_event()`), or finding out that it exists, via
+    /// `subjects()` (or `subjects_of()`).
+    Retrieve,
+
+    /// Walking the hash chain of a subject, via `verify()`, or
+    /// its sequence numbers, via `verify_completeness()`.
+    Verify,
+
+    /// Removing old events from a subject, via `truncate()`.
+    Truncate,
+
+    /// Removing old events from a subject, via `purge()` (or
+    /// `purge_before()`).
+    Purge,
+
+    /// Removing a subject entirely, via `erase_subject()`.
+    Erase,
+
+    /// Destroying the data key of a subject, via
+    /// `shred_subject_key()`.
+    Shred,
+
+    /// Removing a single event from a subject, via `delete()`.
+    Delete,
+
+    /// Removing a subject, via `remove_subject()`.
+    Remove,
+
+    /// Renaming a subject, via `rename_subject()`.  This is
+    /// checked against both the old and the new names.
+    Rename,
+
+    /// Merging one subject into another, via `merge_subjects()`.
+    /// This is checked against both subjects.
+    Merge,
+
+    /// Making (or removing) another name for a subject, via
+    /// `alias_subject()`.  This is checked against both names.
+    Alias,
+
+    /// Annotating a subject, via `annotate_subject()` (or
+    /// `unannotate_subject()`).
+    Annotate,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Retrieve => "retrieve",
+            Operation::Verify => "verify",
+            Operation::Truncate => "truncate",
+            Operation::Purge => "purge",
+            Operation::Erase => "erase",
+            Operation::Shred => "shred",
+            Operation::Delete => "delete",
+            Operation::Remove => "remove",
+            Operation::Rename => "rename",
+            Operation::Merge => "merge",
+            Operation::Alias => "alias",
+            Operation::Annotate => "annotate",
+        })
+    }
+}
+
+// A caller-supplied access policy, deciding which operations
+// can be carried out on which subjects.
+pub(crate) type Policy = dyn Fn(&Operation, &str) -> bool + Send + Sync;
+
+impl Client {
+    /// Consult `policy` before reading from (or destroying
+    /// parts of) any subject, so that i.e. a service embedding
+    /// audis can keep one tenant from seeing another's audit
+    /// log:
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use audis::Operation;
+    ///
+    /// fn main() {
+    ///     let tenant = "tenant:A:";
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+    ///         .unwrap()
+    ///         .authorize(move |op, subject| {
+    ///             *op == Operation::Retrieve && subject.starts_with(tenant)
+    ///         });
+    ///
+    ///     // fails with AudisError::Forbidden
+    ///     client.retrieve("tenant:B:users").unwrap_err();
+    /// }
+    /// ```
+    ///
+    /// If `policy` returns false, the operation fails with
+    /// `AudisError::Forbidden`, and nothing is done.  Subjects
+    /// that can't be retrieved are also left out of the list
+    /// returned by `subjects()`.
+    ///
+    /// The policy is always given real subject names.  Where
+    /// audis only knows the name a subject is stored under (i.e.
+    /// when it walks the subjects, for `subjects()`,
+    /// `subjects_of()`, `retrieve_event()`, `delete()` and the
+    /// like), escaped names (see `escape_subjects()`) are
+    /// unescaped first.  Pseudonyms (see `pseudonymize()`) can't
+    /// be turned back into real names, so with a policy, the
+    /// subjects found that way are never allowed: they are left
+    /// out, or the operation is forbidden.
+    ///
+    pub fn authorize<F>(mut self, policy: F) -> Client
+    where
+        F: Fn(&Operation, &str) -> bool + Send + Sync + 'static,
+    {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    // Check that the access policy (if any) allows `op` on
+    // `subject`.
+    pub(crate) fn allow(&self, op: Operation, subject: &str) -> AudisResult<()> {
+        if self.allows(op, subject) {
+            Ok(())
+        } else {
+            Err(AudisError::Forbidden(format!("{} of {}", op, subject)))
+        }
+    }
+
+    pub(crate) fn allows(&self, op: Operation, subject: &str) -> bool {
+        match &self.policy {
+            Some(policy) => policy(&op, subject),
+            None => true,
+        }
+    }
+
+    // Check that the access policy (if any) allows `op` on a
+    // subject, given the name it is stored under.
+    pub(crate) fn allow_stored(&self, op: Operation, stored: &str) -> AudisResult<()> {
+        if self.allows_stored(op, stored) {
+            Ok(())
+        } else {
+            Err(AudisError::Forbidden(format!("{} of {}", op, stored)))
+        }
+    }
+
+    pub(crate) fn allows_stored(&self, op: Operation, stored: &str) -> bool {
+        match &self.policy {
+            None => true,
+            Some(_) if stored == SYSTEM_SUBJECT => self.allows(op, stored),
+            Some(_) if self.pseudonyms.is_some() => false,
+            Some(_) if self.escape => self.allows(op, &unescape(stored)),
+            Some(_) => self.allows(op, stored),
+        }
+    }
+
+    // Find the IDs of every event in the subjects the access
+    // policy allows retrieving, or None if there is no policy.
+    pub(crate) fn retrievable(&self) -> AudisResult<Option<HashSet<String>>> {
+        if self.policy.is_none() {
+            return Ok(None);
+        }
+        let mut allowed = HashSet::new();
+        for s in self.smembers("subjects")? {
+            self.cancelled()?;
+            if self.allows_stored(Operation::Retrieve, &s) {
+                allowed.extend(self.lrange(&s, "0", "-1")?);
+            }
+        }
+        Ok(Some(allowed))
+    }
+}
@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{Client, Event, SYSTEM_SUBJECT};
+
+impl Client {
+    /// Replace subject names with keyed hashes (HMAC-SHA256,
+    /// under `key`) before they are used as Redis keys, or added
+    /// to the `subjects` set.
+    ///
+    /// Anyone with access to Redis can still see which events
+    /// were logged together, but not who (or what) they were
+    /// about.  Callers that know the real subject name (and
+    /// hold the key) can still read it back, since `retrieve()`,
+    /// `truncate()`, `purge()`, `verify()` and `erase_subject()`
+    /// all take real subject names, and hash them the same way.
+    /// `subjects()` returns the hashes.  The reserved
+    /// `__audis__` subject is never hashed.
+    ///
+    /// Once subjects have been logged under a key, that key must
+    /// not change; events logged under a different key (or none
+    /// at all) are indexed under different subjects entirely.
+    ///
+    pub fn pseudonymize(mut self, key: &[u8]) -> Client {
+        self.pseudonyms = Some(key.to_vec());
+        self
+    }
+
+    /// Return the name that `subject` is stored under in Redis,
+    /// i.e. the one that `subjects()` will report for it.
+    ///
+    /// Without `pseudonymize()` (or `escape_subjects()`), this is
+    /// just `subject`.
+    pub fn pseudonym(&self, subject: &str) -> String {
+        self.subject(subject).into_owned()
+    }
+
+    // Map a real subject name onto the one it is stored under.
+    pub(crate) fn subject<'a>(&self, subject: &'a str) -> Cow<'a, str> {
+        match &self.pseudonyms {
+            Some(key) if subject != SYSTEM_SUBJECT => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+                mac.update(subject.as_bytes());
+                Cow::Owned(
+                    mac.finalize()
+                        .into_bytes()
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect(),
+                )
+            }
+            _ if self.escape && subject != SYSTEM_SUBJECT => crate::names::escape(subject),
+            _ => Cow::Borrowed(subject),
+        }
+    }
+
+    // Swap all of an event's subjects for their pseudonyms.
+    pub(crate) fn pseudonymized<'a>(&self, e: Cow<'a, Event>) -> Cow<'a, Event> {
+        if self.pseudonyms.is_none() && !self.escape {
+            return e;
+        }
+        let mut e = e.into_owned();
+        e.subjects = e.subjects.iter().map(|s| self.pseudonym(s)).collect();
+        Cow::Owned(e)
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::glob;
+use crate::intercept::sweeping;
+use crate::{AudisResult, Event, Interceptor};
+
+/// What to do with events that exceed a subject's rate limit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Don't log the event against the subject at all.
+    Drop,
+
+    /// Don't log the event against the subject, but count it;
+    /// the next event that makes it through will carry the
+    /// count in its `rate_limited:$subject` metadata field.
+    Coalesce,
+
+    /// Log the event against this (overflow) subject instead.
+    Divert(String),
+}
+
+/// An interceptor that limits how quickly events can be
+/// logged against each subject, so that one runaway component
+/// can't flood its subjects (and the backend) with events.
+///
+/// ```rust,no_run
+/// extern crate audis;
+///
+/// use audis::Overflow;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .with_interceptor(Box::new(
+///             audis::RateLimiter::new()
+///                 .limit("debug:*", 10.0, 100, Overflow::Drop)
+///                 .limit("*", 1000.0, 5000, Overflow::Divert("overflow".to_string())),
+///         ));
+/// }
+/// ```
+///
+/// Every subject gets its own token bucket, which holds (at
+/// most) `burst` tokens, and refills at `rate` tokens per
+/// second; logging an event against the subject spends one
+/// token.  Subjects are matched against each limit's glob
+/// pattern in the order the limits were added; the first
+/// match wins, and subjects that match no limit are never
+/// limited.  An event that ends up with none of its subjects
+/// is not logged; one logged without any is passed along as is.
+///
+/// Buckets live in process memory, so each process (and each
+/// RateLimiter) enforces its limits separately.  Those that
+/// have refilled completely (and have no coalesced events to
+/// report) are forgotten every so often, since a new bucket
+/// would be no different.
+#[derive(Default)]
+pub struct RateLimiter {
+    limits: Vec<Limit>,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    by_subject: HashMap<String, Bucket>,
+    swept: Option<Instant>,
+}
+
+struct Limit {
+    pattern: String,
+    rate: f64,
+    burst: f64,
+    overflow: Overflow,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    suppressed: u64,
+
+    // when the bucket will be full again, if ever.
+    full: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a RateLimiter that doesn't limit anything.
+    pub fn new() -> RateLimiter {
+        RateLimiter::default()
+    }
+
+    /// Limit subjects matching the glob `pattern` to `rate`
+    /// events per second, with bursts of up to `burst` events.
+    pub fn limit(
+        mut self,
+        pattern: &str,
+        rate: f64,
+        burst: u32,
+        overflow: Overflow,
+    ) -> RateLimiter {
+        self.limits.push(Limit {
+            pattern: pattern.to_string(),
+            rate,
+            burst: burst as f64,
+            overflow,
+        });
+        self
+    }
+}
+
+impl Interceptor for RateLimiter {
+    fn intercept(&self, mut e: Event) -> AudisResult<Option<Event>> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let buckets = &mut *buckets;
+        if sweeping(&mut buckets.swept, now) {
+            buckets
+                .by_subject
+                .retain(|_, b| b.suppressed > 0 || b.full.is_none_or(|full| full > now));
+        }
+
+        let had_subjects = !e.subjects.is_empty();
+        let mut subjects = vec![];
+        for s in e.subjects.drain(..) {
+            let limit = match self
+                .limits
+                .iter()
+                .find(|l| glob(l.pattern.as_bytes(), s.as_bytes()))
+            {
+                Some(limit) => limit,
+                None => {
+                    subjects.push(s);
+                    continue;
+                }
+            };
+
+            let b = buckets.by_subject.entry(s.to_string()).or_insert(Bucket {
+                tokens: limit.burst,
+                refilled: now,
+                suppressed: 0,
+                full: Some(now),
+            });
+            let elapsed = now.duration_since(b.refilled).as_secs_f64();
+            b.tokens = (b.tokens + elapsed * limit.rate).min(limit.burst);
+            b.refilled = now;
+
+            if b.tokens >= 1.0 {
+                b.tokens -= 1.0;
+                b.full = Duration::try_from_secs_f64((limit.burst - b.tokens) / limit.rate)
+                    .ok()
+                    .and_then(|d| now.checked_add(d));
+                if b.suppressed > 0 {
+                    e.meta
+                        .insert(format!("rate_limited:{}", s), b.suppressed.to_string());
+                    b.suppressed = 0;
+                }
+                subjects.push(s);
+                continue;
+            }
+
+            match &limit.overflow {
+                Overflow::Drop => (),
+                Overflow::Coalesce => b.suppressed += 1,
+                Overflow::Divert(to) => {
+                    if !subjects.contains(to) {
+                        subjects.push(to.to_string());
+                    }
+                }
+            }
+        }
+
+        if subjects.is_empty() && had_subjects {
+            return Ok(None);
+        }
+        e.subjects = subjects;
+        Ok(Some(e))
+    }
+}
@@ -0,0 +1,88 @@
+use crate::jsonpath::{self, Step};
+use crate::{AudisError, AudisResult, Event, Interceptor};
+
+/// An interceptor that masks sensitive fields of JSON payloads
+/// before they are logged, so that the values never make it
+/// into the backend at all.
+///
+/// ```rust,no_run
+/// extern crate audis;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .with_interceptor(Box::new(
+///             audis::Redactor::new()
+///                 .field("$.password")
+///                 .unwrap()
+///                 .field("$.user.ssn")
+///                 .unwrap(),
+///         ));
+/// }
+/// ```
+///
+/// Masked values are replaced with the string `[REDACTED]`
+/// (or whatever is given to `mask()`), and the paths of every
+/// field that was masked are listed, comma-separated, in the
+/// event's `redacted` metadata field.  Payloads that aren't
+/// JSON, or that don't have any of the fields, pass through
+/// untouched.
+///
+/// This requires the `redact` feature.
+pub struct Redactor {
+    fields: Vec<(String, Vec<Step>)>,
+    mask: String,
+}
+
+impl Default for Redactor {
+    fn default() -> Redactor {
+        Redactor {
+            fields: vec![],
+            mask: "[REDACTED]".to_string(),
+        }
+    }
+}
+
+impl Redactor {
+    /// Create a Redactor that doesn't mask anything.
+    pub fn new() -> Redactor {
+        Redactor::default()
+    }
+
+    /// Mask the field at `path` (i.e. `$.password`, or
+    /// `$.cards[0].number`).  Malformed paths are rejected
+    /// with `AudisError::Invalid`.
+    pub fn field(mut self, path: &str) -> AudisResult<Redactor> {
+        self.fields.push((path.to_string(), jsonpath::parse(path)?));
+        Ok(self)
+    }
+
+    /// Replace masked values with `mask`, instead of the
+    /// default `[REDACTED]`.
+    pub fn mask(mut self, mask: &str) -> Redactor {
+        self.mask = mask.to_string();
+        self
+    }
+}
+
+impl Interceptor for Redactor {
+    fn intercept(&self, mut e: Event) -> AudisResult<Option<Event>> {
+        let mut doc: serde_json::Value = match serde_json::from_slice(&e.data) {
+            Ok(doc) => doc,
+            Err(_) => return Ok(Some(e)),
+        };
+
+        let mut redacted = vec![];
+        for (name, path) in &self.fields {
+            if let Some(v) = jsonpath::lookup_mut(&mut doc, path) {
+                *v = serde_json::Value::String(self.mask.to_string());
+                redacted.push(name.as_str());
+            }
+        }
+        if !redacted.is_empty() {
+            e.data = serde_json::to_vec(&doc).map_err(|err| AudisError::Codec(err.to_string()))?;
+            e.meta.insert("redacted".to_string(), redacted.join(","));
+        }
+        Ok(Some(e))
+    }
+}
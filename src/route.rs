@@ -0,0 +1,144 @@
+#[cfg(feature = "routing")]
+use crate::jsonpath::{self, Step};
+use crate::{AudisResult, Event, Interceptor};
+
+/// An interceptor that derives additional subjects for events
+/// from their contents, so that every producer doesn't have to
+/// duplicate the same indexing logic.
+///
+/// ```rust,no_run
+/// extern crate audis;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .with_interceptor(Box::new(
+///             audis::Router::new()
+///                 .meta("tenant", "tenant:{}")
+///                 .rule(|e| {
+///                     if e.data.len() > 4096 {
+///                         vec!["big-events".to_string()]
+///                     } else {
+///                         vec![]
+///                     }
+///                 }),
+///         ));
+///
+///     // ... events with a `tenant` metadata field now also
+///     //     get logged against tenant:$tenant ...
+/// }
+/// ```
+///
+/// In each template, `{}` is replaced by the extracted value.
+/// Derived subjects that an event already has are not added
+/// a second time.
+#[derive(Default)]
+pub struct Router {
+    rules: Vec<Rule>,
+}
+
+enum Rule {
+    Meta(String, String),
+    #[cfg(feature = "routing")]
+    Json(Vec<Step>, String),
+    Custom(Box<Deriver>),
+}
+
+type Deriver = dyn Fn(&Event) -> Vec<String> + Send + Sync;
+
+impl Router {
+    /// Create a Router with no rules.
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Derive a subject from the value of the `key` metadata
+    /// field, if the event has one.
+    pub fn meta(mut self, key: &str, template: &str) -> Router {
+        self.rules
+            .push(Rule::Meta(key.to_string(), template.to_string()));
+        self
+    }
+
+    /// Derive subjects from the value at `path` in the event
+    /// payload, parsed as JSON.
+    ///
+    /// Paths are written as `$.actor`, `$.target.user` or
+    /// `$.users[0]`.  String values are used verbatim; numbers
+    /// and booleans are formatted as JSON would; arrays yield
+    /// one subject per (scalar) element.  If the payload isn't
+    /// JSON, or the path doesn't lead anywhere, no subjects are
+    /// derived.  Malformed paths are rejected straight away,
+    /// with `AudisError::Invalid`.
+    ///
+    /// This requires the `routing` feature.
+    #[cfg(feature = "routing")]
+    pub fn json(mut self, path: &str, template: &str) -> AudisResult<Router> {
+        self.rules
+            .push(Rule::Json(jsonpath::parse(path)?, template.to_string()));
+        Ok(self)
+    }
+
+    /// Derive subjects with arbitrary code.
+    pub fn rule<F>(mut self, f: F) -> Router
+    where
+        F: Fn(&Event) -> Vec<String> + Send + Sync + 'static,
+    {
+        self.rules.push(Rule::Custom(Box::new(f)));
+        self
+    }
+
+    // Compute all of the subjects our rules derive for `e`.
+    fn derive(&self, e: &Event) -> Vec<String> {
+        #[cfg(feature = "routing")]
+        let mut json: Option<Option<serde_json::Value>> = None;
+
+        let mut subjects = vec![];
+        for rule in &self.rules {
+            match rule {
+                Rule::Meta(key, template) => {
+                    if let Some(v) = e.meta.get(key) {
+                        subjects.push(template.replace("{}", v));
+                    }
+                }
+                #[cfg(feature = "routing")]
+                Rule::Json(path, template) => {
+                    let doc = json.get_or_insert_with(|| serde_json::from_slice(&e.data).ok());
+                    if let Some(v) = doc.as_ref().and_then(|doc| jsonpath::lookup(doc, path)) {
+                        for v in scalars(v) {
+                            subjects.push(template.replace("{}", &v));
+                        }
+                    }
+                }
+                Rule::Custom(f) => subjects.extend(f(e)),
+            }
+        }
+        subjects
+    }
+}
+
+impl Interceptor for Router {
+    fn intercept(&self, mut e: Event) -> AudisResult<Option<Event>> {
+        for s in self.derive(&e) {
+            if !e.subjects.contains(&s) {
+                e.subjects.push(s);
+            }
+        }
+        Ok(Some(e))
+    }
+}
+
+#[cfg(feature = "routing")]
+fn scalars(v: &serde_json::Value) -> Vec<String> {
+    use serde_json::Value;
+    match v {
+        Value::String(s) => vec![s.to_string()],
+        Value::Number(_) | Value::Bool(_) => vec![v.to_string()],
+        Value::Array(a) => a
+            .iter()
+            .filter(|v| !v.is_array())
+            .flat_map(scalars)
+            .collect(),
+        Value::Null | Value::Object(_) => vec![],
+    }
+}
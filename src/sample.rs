@@ -0,0 +1,231 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::glob;
+use crate::intercept::{sweeping, SWEEP};
+use crate::{AudisResult, Event, Interceptor};
+
+/// How much of a subject's event stream to keep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    /// Keep every `n`th event, starting with the first.
+    OneIn(u64),
+
+    /// Keep each event with probability `p` (0.0 to 1.0).
+    Probability(f64),
+}
+
+/// An interceptor that logs only a sample of the events for
+/// high-volume subjects, for debug-grade streams that are too
+/// chatty to keep in full.
+///
+/// ```rust,no_run
+/// extern crate audis;
+///
+/// use audis::Sampling;
+///
+/// fn main() {
+///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+///         .unwrap()
+///         .with_interceptor(Box::new(
+///             audis::Sampler::new()
+///                 .sample("debug:*", Sampling::OneIn(100))
+///                 .sample("trace:*", Sampling::Probability(0.01)),
+///         ));
+/// }
+/// ```
+///
+/// Subjects are matched against each pattern in the order
+/// they were added; the first match wins, and subjects that
+/// match no pattern are never sampled.  Events sampled out of
+/// every one of their subjects are not logged at all; those
+/// logged without any subjects are passed along as they are.
+///
+/// Each event that is kept records how many events were
+/// suppressed for each sampled subject since the last one was
+/// kept, in its `sampled:$subject` metadata field, so that
+/// the true volume can be reconstructed from the audit log.
+/// The counts for subjects that go quiet can be handed off
+/// periodically instead, via `flush_every()`.  Subjects sampled with `Sampling::OneIn` that go idle for a
+/// while are forgotten, and start over with the next event.
+#[derive(Default)]
+pub struct Sampler {
+    rules: Vec<(String, Sampling)>,
+    state: Arc<Mutex<State>>,
+    flush: Option<Arc<Flush>>,
+}
+
+type Flush = dyn Fn(&str, u64) + Send + Sync;
+
+#[derive(Default)]
+struct State {
+    rng: u64,
+    seen: HashMap<String, (u64, Instant)>,
+    suppressed: HashMap<String, u64>,
+    total: HashMap<String, u64>,
+    swept: Option<Instant>,
+}
+
+impl Sampler {
+    /// Create a Sampler that keeps everything.
+    pub fn new() -> Sampler {
+        Sampler::default()
+    }
+
+    /// Sample subjects matching the glob `pattern`.
+    pub fn sample(mut self, pattern: &str, how: Sampling) -> Sampler {
+        self.rules.push((pattern.to_string(), how));
+        self
+    }
+
+    /// Hand the counts of suppressed events that haven't been
+    /// recorded on a kept event yet to `hook`, subject by
+    /// subject, every `every` (of at least a millisecond), and
+    /// once more when the Sampler is dropped, so that the counts
+    /// for subjects that go quiet aren't lost.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// use std::time::Duration;
+    /// use audis::Sampling;
+    ///
+    /// fn main() {
+    ///     let plain = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///     let tally = plain.clone();
+    ///     let client = plain.with_interceptor(Box::new(
+    ///         audis::Sampler::new()
+    ///             .sample("debug:*", Sampling::OneIn(100))
+    ///             .flush_every(Duration::from_secs(60), move |subject, n| {
+    ///                 let e = audis::Event::builder()
+    ///                     .data(format!("{} event(s) sampled out", n))
+    ///                     .subject(subject)
+    ///                     .meta(format!("sampled:{}", subject), n.to_string())
+    ///                     .build()
+    ///                     .unwrap();
+    ///                 tally.log(&e).ok();
+    ///             }),
+    ///     ));
+    /// }
+    /// ```
+    ///
+    /// Each suppressed event is counted once, either on the next
+    /// event kept for its subject, or by the hook, whichever
+    /// comes first.  The hook is called from a background thread
+    /// (or whichever thread drops the Sampler), and shouldn't log
+    /// through the Sampler itself, lest it sample its own counts.
+    pub fn flush_every<F>(mut self, every: Duration, hook: F) -> Sampler
+    where
+        F: Fn(&str, u64) + Send + Sync + 'static,
+    {
+        let every = every.max(Duration::from_millis(1));
+        let hook: Arc<Flush> = Arc::new(hook);
+        let (state, flush) = (Arc::downgrade(&self.state), hook.clone());
+        std::thread::spawn(move || loop {
+            std::thread::sleep(every);
+            match state.upgrade() {
+                Some(state) => flush_to(&state, &*flush),
+                None => return,
+            }
+        });
+        self.flush = Some(hook);
+        self
+    }
+
+    /// How many events have been suppressed for each sampled
+    /// subject since the last call (or since the Sampler was
+    /// created), resetting the counts.
+    pub fn suppressed(&self) -> HashMap<String, u64> {
+        std::mem::take(&mut self.state.lock().unwrap().total)
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        if let Some(hook) = &self.flush {
+            flush_to(&self.state, &**hook);
+        }
+    }
+}
+
+// Hand the counts of suppressed events not yet recorded on a
+// kept event to a hook, without holding the lock while it runs.
+fn flush_to(state: &Mutex<State>, hook: &Flush) {
+    let pending = std::mem::take(&mut state.lock().unwrap().suppressed);
+    let mut pending: Vec<(String, u64)> = pending.into_iter().collect();
+    pending.sort();
+    for (s, n) in pending {
+        hook(&s, n);
+    }
+}
+
+impl State {
+    // xorshift64*, seeded randomly; plenty good enough for
+    // deciding which debug events to keep.
+    fn random(&mut self) -> f64 {
+        if self.rng == 0 {
+            self.rng = RandomState::new().build_hasher().finish() | 1;
+        }
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Interceptor for Sampler {
+    fn intercept(&self, mut e: Event) -> AudisResult<Option<Event>> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if sweeping(&mut state.swept, now) {
+            state
+                .seen
+                .retain(|_, (_, at)| now.duration_since(*at) < SWEEP);
+        }
+
+        let had_subjects = !e.subjects.is_empty();
+        let mut subjects = vec![];
+        for s in e.subjects.drain(..) {
+            let how = match self
+                .rules
+                .iter()
+                .find(|(p, _)| glob(p.as_bytes(), s.as_bytes()))
+            {
+                Some((_, how)) => *how,
+                None => {
+                    subjects.push(s);
+                    continue;
+                }
+            };
+
+            let keep = match how {
+                Sampling::OneIn(n) => {
+                    let (seen, at) = state.seen.entry(s.to_string()).or_insert((0, now));
+                    *seen += 1;
+                    *at = now;
+                    n <= 1 || (*seen - 1).is_multiple_of(n)
+                }
+                Sampling::Probability(p) => state.random() < p,
+            };
+
+            if keep {
+                if let Some(n) = state.suppressed.remove(&s) {
+                    e.meta.insert(format!("sampled:{}", s), n.to_string());
+                }
+                subjects.push(s);
+            } else {
+                *state.suppressed.entry(s.to_string()).or_insert(0) += 1;
+                *state.total.entry(s).or_insert(0) += 1;
+            }
+        }
+
+        if subjects.is_empty() && had_subjects {
+            return Ok(None);
+        }
+        e.subjects = subjects;
+        Ok(Some(e))
+    }
+}
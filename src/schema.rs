@@ -0,0 +1,37 @@
+//! JSON Schema validation of event payloads, enabled by the
+//! `schema` feature.
+
+use crate::backend::glob;
+use crate::{AudisError, AudisResult, Client};
+
+impl Client {
+    /// Validate the payloads of events logged against subjects
+    /// matching the glob `pattern` (as understood by Redis'
+    /// `SCAN ... MATCH`) against a JSON Schema.
+    ///
+    /// Use a `pattern` of `*` to validate every event.  Events
+    /// whose payloads are not JSON, or fail to validate, are
+    /// rejected with `AudisError::Invalid`.  If the schema is
+    /// itself invalid, that is reported straight away.
+    ///
+    pub fn schema(self, pattern: &str, schema: &serde_json::Value) -> AudisResult<Client> {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|e| AudisError::Invalid(format!("bad schema for '{}': {}", pattern, e)))?;
+        let pattern = pattern.to_string();
+
+        Ok(self.validate(move |e| {
+            if !e
+                .subjects
+                .iter()
+                .any(|s| glob(pattern.as_bytes(), s.as_bytes()))
+            {
+                return Ok(());
+            }
+            let v: serde_json::Value = serde_json::from_slice(&e.data)
+                .map_err(|err| format!("payload is not JSON: {}", err))?;
+            validator
+                .validate(&v)
+                .map_err(|err| format!("payload does not match schema for '{}': {}", pattern, err))
+        }))
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use crate::jsonpath::{self, Step};
+use crate::{AudisError, AudisResult, Client, Event, Operation};
+
+// The RediSearch index, and the prefix of the hashes it covers.
+const INDEX: &str = "audis:search";
+const PREFIX: &str = "audis:search:";
+
+// How many results to ask RediSearch for at a time.
+const PAGE: usize = 1000;
+
+// A payload field to index, by name, and where to find it.
+pub(crate) struct Field {
+    name: String,
+    path: Vec<Step>,
+}
+
+impl Client {
+    /// Index the value at `path` in each event's payload (parsed
+    /// as JSON) as the RediSearch TEXT field `name`, so that
+    /// `search()` can find events by it.  This can be given more
+    /// than once; malformed paths are rejected with
+    /// `AudisError::Invalid`.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+    ///         .unwrap()
+    ///         .searchable("invoice", "$.invoice.id")
+    ///         .unwrap()
+    ///         .searchable("actor", "$.actor")
+    ///         .unwrap();
+    ///
+    ///     for e in client.search("@invoice:1234").unwrap() {
+    ///         println!("{}", e.id);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// As each event is logged, the values of its indexed fields
+    /// (and its subjects, as the TAG field `subjects`) are written
+    /// to the `audis:search:$id` hash, which RediSearch picks up.
+    /// Those values are stored in the clear, even if payloads are
+    /// encrypted (see `audis::crypto`), so index with care.
+    ///
+    /// This requires the `search` feature, and the RediSearch
+    /// module.
+    pub fn searchable(mut self, name: &str, path: &str) -> AudisResult<Client> {
+        self.search.push(Arc::new(Field {
+            name: name.to_string(),
+            path: jsonpath::parse(path)?,
+        }));
+        Ok(self)
+    }
+
+    /// Find the events that match a RediSearch `query` (i.e.
+    /// `@invoice:1234`, or `@subjects:{user\:42} @actor:alice`),
+    /// across all subjects, best matches first.
+    ///
+    /// The `audis:search` index is created the first time it is
+    /// needed, covering whatever fields this client has been told
+    /// are `searchable()`; events logged before then are indexed
+    /// too.  To index more fields later, drop the index (with
+    /// `FT.DROPINDEX audis:search`) and let it be created again.
+    ///
+    /// Like `retrieve_event()`, the subjects of the events are not
+    /// filled in, and events that the client's access policy (if
+    /// any) doesn't allow retrieving from any of their subjects
+    /// are left out.  Without RediSearch, this fails with
+    /// `AudisError::Backend`.
+    ///
+    /// This requires the `search` feature.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn search(&self, query: &str) -> AudisResult<Vec<Event>> {
+        self.instrument("search", || {
+            let mut events = vec![];
+            let mut offset = 0;
+            loop {
+                let found = self.search_page(query, offset)?;
+                let (total, ids) = match found.split_first() {
+                    Some((total, ids)) => (total.parse::<usize>().unwrap_or(0), ids),
+                    None => (0, &found[..0]),
+                };
+                for doc in ids {
+                    let id = doc.strip_prefix(PREFIX).unwrap_or(doc);
+                    if self.policy.is_some() {
+                        let subjects: Option<String> =
+                            self.query(redis::cmd("HGET").arg(doc).arg("subjects"))?;
+                        if !subjects
+                            .unwrap_or_default()
+                            .split(',')
+                            .any(|s| self.allows_stored(Operation::Retrieve, s))
+                        {
+                            continue;
+                        }
+                    }
+                    if let Some(e) = self.fetch(id, None)? {
+                        #[cfg(feature = "crypto")]
+                        self.check_seal(&e)?;
+                        events.push(e);
+                    }
+                }
+                offset += ids.len();
+                if ids.is_empty() || offset >= total {
+                    return Ok(events);
+                }
+            }
+        })
+    }
+
+    // Run one page of a search, creating the index if it isn't
+    // there yet.
+    fn search_page(&self, query: &str, offset: usize) -> AudisResult<Vec<String>> {
+        let mut cmd = redis::cmd("FT.SEARCH");
+        cmd.arg(INDEX)
+            .arg(query)
+            .arg("NOCONTENT")
+            .arg("LIMIT")
+            .arg(offset)
+            .arg(PAGE);
+        match self.query(&mut cmd) {
+            Err(AudisError::Backend(e)) if missing(&e) => {
+                let mut create = redis::cmd("FT.CREATE");
+                create
+                    .arg(INDEX)
+                    .arg("ON")
+                    .arg("HASH")
+                    .arg("PREFIX")
+                    .arg(1)
+                    .arg(PREFIX)
+                    .arg("SCHEMA")
+                    .arg("subjects")
+                    .arg("TAG")
+                    .arg("SEPARATOR")
+                    .arg(",");
+                for f in &self.search {
+                    create.arg(&f.name).arg("TEXT");
+                }
+                self.query::<()>(&mut create)?;
+                self.query(&mut cmd)
+            }
+            r => r,
+        }
+    }
+
+    // Write the searchable fields of an event (as it is being
+    // logged against `subjects`) to its search hash.
+    pub(crate) fn index_fields(&self, e: &Event, subjects: &[&String]) -> AudisResult<()> {
+        if self.search.is_empty() {
+            return Ok(());
+        }
+        let doc: Option<serde_json::Value> = serde_json::from_slice(&e.data).ok();
+        let mut hset = redis::cmd("HSET");
+        hset.arg(format!("{}{}", PREFIX, e.id)).arg("subjects").arg(
+            subjects
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        for f in &self.search {
+            match doc.as_ref().and_then(|d| jsonpath::lookup(d, &f.path)) {
+                Some(serde_json::Value::String(s)) => hset.arg(&f.name).arg(s),
+                Some(v) => hset.arg(&f.name).arg(v.to_string()),
+                None => continue,
+            };
+        }
+        self.query::<()>(&mut hset)
+    }
+
+    // Rewrite the search hash of an event whose payload has just
+    // been redacted, because `subject` is being erased; fields
+    // taken from the original payload go, whether or not this
+    // client knows how to index the redacted one.
+    pub(crate) fn unindex(&self, e: &Event, subject: &str) -> AudisResult<()> {
+        let doc = format!("{}{}", PREFIX, e.id);
+        let subjects: Option<String> = self.query(redis::cmd("HGET").arg(&doc).arg("subjects"))?;
+        let subjects: Vec<String> = match subjects {
+            Some(subjects) => subjects
+                .split(',')
+                .filter(|s| *s != subject)
+                .map(String::from)
+                .collect(),
+            None => return Ok(()),
+        };
+        self.query::<()>(redis::cmd("DEL").arg(&doc))?;
+        if self.search.is_empty() {
+            self.query(
+                redis::cmd("HSET")
+                    .arg(&doc)
+                    .arg("subjects")
+                    .arg(subjects.join(",")),
+            )
+        } else {
+            self.index_fields(e, &subjects.iter().collect::<Vec<_>>())
+        }
+    }
+}
+
+// Whether RediSearch failed because the index doesn't exist
+// (yet); its wording varies between versions.
+fn missing(e: &redis::RedisError) -> bool {
+    let why = e.to_string().to_lowercase();
+    why.contains("unknown index") || why.contains("no such index")
+}
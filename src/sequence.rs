@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use crate::{AudisResult, Client, Event, Operation};
+
+// The counter that hands out a subject's sequence numbers.
+macro_rules! counter {
+    ($s:expr) => {
+        format!("audis:seq:{}", $s)
+    };
+}
+
+/// What `Client::verify_completeness()` found out about a
+/// subject.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completeness {
+    /// The last sequence number handed out in the subject (or
+    /// zero, if none ever were).
+    pub last: u64,
+
+    /// The (inclusive) ranges of sequence numbers, up to `last`,
+    /// that none of the events still in the subject have, in
+    /// order.  These events were either removed (i.e. by
+    /// `truncate()`), or never made it into the subject at all.
+    pub missing: Vec<(u64, u64)>,
+
+    /// The IDs of events in the subject without sequence numbers
+    /// (i.e. those logged by clients that aren't `sequenced()`),
+    /// which can't be accounted for either way.
+    pub unnumbered: Vec<String>,
+}
+
+impl Completeness {
+    /// Returns true if no sequence numbers are missing, and
+    /// every event in the subject has one.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.unnumbered.is_empty()
+    }
+}
+
+impl Client {
+    // Drop the counter of a (stored) subject, because it is being
+    // erased.
+    pub(crate) fn drop_counter(&self, subject: &str) -> AudisResult<()> {
+        self.query(redis::cmd("DEL").arg(counter!(subject)))
+    }
+
+    /// Number each event logged against a subject, in the order
+    /// they were logged, so that consumers can tell if any have
+    /// gone missing.
+    ///
+    /// Each subject's numbers are handed out by Redis (via `INCR`
+    /// on `audis:seq:$subject`), starting from 1, and recorded
+    /// alongside each event, in a Hash (of subject to number)
+    /// under a key ending in `:seq`.  The counters outlive the
+    /// subjects themselves, so a subject that is removed and then
+    /// logged against again picks up where it left off.  Only
+    /// events logged (or moved between subjects) by sequenced
+    /// clients are numbered.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+    ///         .unwrap()
+    ///         .sequenced();
+    ///
+    ///     let e = audis::Event::builder()
+    ///         .data("{\"ok\":true}")
+    ///         .subject("user:42")
+    ///         .build()
+    ///         .unwrap();
+    ///     for (subject, n) in client.log_sequenced(&e).unwrap() {
+    ///         println!("{} is event #{} of {}", e.id, n, subject);
+    ///     }
+    /// }
+    /// ```
+    ///
+    pub fn sequenced(mut self) -> Client {
+        self.sequenced = true;
+        self
+    }
+
+    /// Log an event, like `log()` does, returning the sequence
+    /// number (see `sequenced()`) it was given in each of its
+    /// subjects.
+    ///
+    /// Subjects that the event wasn't logged against (because it
+    /// was a duplicate there; see `dedup()`) are left out, as is
+    /// everything if the client isn't sequenced, or an
+    /// interceptor dropped the event.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, e),
+            err,
+            fields(id = %e.id, subjects = e.subjects.len(), commands)
+        )
+    )]
+    pub fn log_sequenced(&self, e: &Event) -> AudisResult<BTreeMap<String, u64>> {
+        self.instrument("log_sequenced", || Ok(self.logged(e)?.ok()?.sequence))
+    }
+
+    /// List the IDs of the events logged against a subject, in
+    /// order, along with their sequence numbers (see
+    /// `sequenced()`) in that subject, if they have them.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn sequence_numbers(&self, log: &str) -> AudisResult<Vec<(String, Option<u64>)>> {
+        self.instrument("sequence_numbers", || {
+            self.allow(Operation::Retrieve, log)?;
+            self.numbered(&self.subject(log))
+        })
+    }
+
+    /// Check that none of the events numbered in a subject (see
+    /// `sequenced()`) have gone missing since, and report the
+    /// sequence numbers that have.
+    ///
+    /// A complete subject still has every event numbered from 1
+    /// to the last number handed out.  Events removed on purpose
+    /// (i.e. by `truncate()` or `purge()`) count as missing, too;
+    /// see `audit_changes()` for keeping track of those.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn verify_completeness(&self, log: &str) -> AudisResult<Completeness> {
+        self.instrument("verify_completeness", || {
+            self.allow(Operation::Verify, log)?;
+            let log = self.subject(log);
+            let log = log.as_ref();
+
+            let mut report = Completeness::default();
+            let mut seen = vec![];
+            for (id, n) in self.numbered(log)? {
+                match n {
+                    Some(n) => seen.push(n),
+                    None => report.unnumbered.push(id),
+                }
+            }
+            seen.sort_unstable();
+            seen.dedup();
+
+            let last: Option<u64> = self.query(redis::cmd("GET").arg(counter!(log)))?;
+            report.last = last.unwrap_or(0);
+            let mut next = 1;
+            for n in seen.into_iter().chain(Some(report.last + 1)) {
+                if n > next {
+                    report.missing.push((next, n - 1));
+                }
+                next = next.max(n + 1);
+            }
+            Ok(report)
+        })
+    }
+
+    // Look up the sequence numbers of every event in a subject
+    // (by its stored name).
+    fn numbered(&self, log: &str) -> AudisResult<Vec<(String, Option<u64>)>> {
+        let ids = self.lrange(log, "0", "-1")?;
+        let mut numbered = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(1000) {
+            let mut pipe = redis::pipe();
+            for id in chunk {
+                pipe.cmd("HGET").arg(idseq!(id)).arg(log);
+            }
+            let n: Vec<Option<u64>> = self.pipeline(&pipe)?;
+            numbered.extend(chunk.iter().cloned().zip(n));
+        }
+        Ok(numbered)
+    }
+
+    // Give an event the next sequence number in a subject, if
+    // we hand them out.
+    pub(crate) fn sequence(&self, subject: &str, id: &str) -> AudisResult<Option<u64>> {
+        if !self.sequenced {
+            return Ok(None);
+        }
+        let n: u64 = self.query(redis::cmd("INCR").arg(counter!(subject)))?;
+        self.query::<()>(redis::cmd("HSET").arg(idseq!(id)).arg(subject).arg(n))?;
+        Ok(Some(n))
+    }
+}
@@ -0,0 +1,132 @@
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{AudisError, AudisResult, Client};
+
+// The lock held on a (stored) subject while it is pruned, or
+// read from with `snapshot_reads()`.
+macro_rules! lock {
+    ($s:expr) => {
+        format!("audis:lock:{}", $s)
+    };
+}
+
+// How long a subject lock is held for, at most, so that a client
+// that dies while holding one doesn't wedge the subject.
+const LOCK_TTL: Duration = Duration::from_secs(30);
+
+// How long to wait on a subject lock before giving up.
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+
+impl Client {
+    /// Take a subject's lock while retrieving its events, so
+    /// that they are read as they were at a single point in time.
+    ///
+    /// Pruning a subject (via `truncate()`, `purge()`,
+    /// `purge_before()`, `delete_subject()`, `expire_idle()`,
+    /// `delete()`, `reap()` or `erase_subject()`) removes its
+    /// events one at a time, and a retrieval that runs at the
+    /// same time can see some of them, but not all (or their
+    /// IDs, but not their payloads; see `retrieve_range_with()`).
+    /// Pruning always takes the subject's lock (in `audis:lock:$subject`); with snapshot
+    /// reads, so does every retrieval of a subject's events, so
+    /// that a report generated while a retention job is running
+    /// sees the subject either before it was pruned, or after.
+    ///
+    /// Logging doesn't take the lock, since new events are only
+    /// ever appended.  Locks are held for at most 30 seconds,
+    /// in case their holder dies; waiting more than 10 seconds
+    /// for one fails with `AudisError::Locked`.
+    pub fn snapshot_reads(mut self) -> Client {
+        self.snapshot = true;
+        self
+    }
+
+    // Run `f` while holding the lock on a (stored) subject.  The
+    // lock is only released if we still hold it, i.e. if `f`
+    // didn't take so long that it expired, and someone else
+    // took it in the meantime.  Locks aren't re-entrant, so `f`
+    // must not log (or `record()`) anything against the subject.
+    pub(crate) fn locked<T, F>(&self, subject: &str, f: F) -> AudisResult<T>
+    where
+        F: FnOnce() -> AudisResult<T>,
+    {
+        let (key, token) = (lock!(subject), token());
+        let (started, mut backoff) = (Instant::now(), Duration::from_millis(5));
+        loop {
+            let set: Option<String> = self.query(
+                redis::cmd("SET")
+                    .arg(&key)
+                    .arg(&token)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(LOCK_TTL.as_millis() as u64),
+            )?;
+            if set.is_some() {
+                break;
+            }
+            if started.elapsed() >= LOCK_WAIT {
+                return Err(AudisError::Locked(subject.to_string()));
+            }
+            sleep(backoff);
+            backoff = (backoff * 2).min(Duration::from_millis(100));
+        }
+
+        let r = f();
+        self.release(&key, &token)?;
+        r
+    }
+
+    // Release a lock, if we still hold it.  The check and the
+    // delete happen on one connection, under WATCH, so that a
+    // lock that expires (and is taken by someone else) between
+    // the two is left alone: the MULTI / EXEC simply fails.
+    fn release(&self, key: &str, token: &str) -> AudisResult<()> {
+        #[cfg(feature = "tracing")]
+        crate::COMMANDS.with(|n| n.set(n.get() + 3));
+        let mut con = self.backend.connection()?;
+        redis::cmd("WATCH").arg(key).query::<()>(&mut *con)?;
+        let held: Option<String> = redis::cmd("GET").arg(key).query(&mut *con)?;
+        if held.as_deref() == Some(token) {
+            redis::pipe()
+                .atomic()
+                .cmd("DEL")
+                .arg(key)
+                .query::<()>(&mut *con)?;
+        } else {
+            redis::cmd("UNWATCH").query::<()>(&mut *con)?;
+        }
+        Ok(())
+    }
+
+    // Run `f` while holding the lock on a (stored) subject, if
+    // the client does snapshot reads.
+    pub(crate) fn snapshot<T, F>(&self, subject: &str, f: F) -> AudisResult<T>
+    where
+        F: FnOnce() -> AudisResult<T>,
+    {
+        if self.snapshot {
+            self.locked(subject, f)
+        } else {
+            f()
+        }
+    }
+}
+
+// A token identifying this particular hold on a lock, unique
+// across processes (and hosts, almost certainly).
+fn token() -> String {
+    static HOLDS: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!(
+        "{}.{}.{}",
+        process::id(),
+        nanos,
+        HOLDS.fetch_add(1, Ordering::Relaxed)
+    )
+}
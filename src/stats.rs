@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use crate::ids::timestamp;
+use crate::{AudisResult, Client};
+
+// How long a day is, in milliseconds.
+const DAY: u64 = 86_400_000;
+
+/// Capacity planning figures for an entire audit log.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    /// How many events are stored in the audit log.
+    pub events: u64,
+
+    /// How many subjects are known to the audit log.
+    pub subjects: u64,
+
+    /// How many events are no longer referenced by any subject
+    /// (i.e. their reference counts are zero, or missing), and
+    /// so can never be retrieved.
+    pub orphans: u64,
+
+    /// The largest subjects, by number of events, in
+    /// descending order.
+    pub by_count: Vec<(String, u64)>,
+
+    /// The largest subjects, by the number of bytes their
+    /// event lists occupy (per `MEMORY USAGE`), in descending
+    /// order.  This does not include the event data itself,
+    /// since that is shared between subjects.
+    pub by_memory: Vec<(String, u64)>,
+
+    /// How many events were logged per (UTC) day, keyed by the
+    /// start of each day, in milliseconds since the epoch, going
+    /// by the timestamps of their IDs (see `ids::timestamp()`).
+    /// Events whose IDs don't have one are left out, and days
+    /// without any events are too; see `histogram()` for other
+    /// buckets, one subject at a time.
+    pub by_day: BTreeMap<u64, u64>,
+}
+
+impl Client {
+    /// Gather statistics about the audit log as a whole,
+    /// reporting on (at most) the `top` largest subjects.
+    ///
+    /// Unlike `health()`, this walks the entire keyspace, and
+    /// touches every subject, so it should not be called in
+    /// any hot paths.
+    ///
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn stats(&self, top: usize) -> AudisResult<Stats> {
+        self.instrument("stats", || {
+            let ids: Vec<String> = self
+                .scan("SCAN", None, &id!("*"))?
+                .into_iter()
+                .filter(|k| !crate::PARALLEL.iter().any(|p| k.ends_with(p)))
+                .map(|k| k["audit:".len()..].to_string())
+                .collect();
+            let events = ids.len() as u64;
+
+            let mut by_day = BTreeMap::new();
+            for t in ids.iter().filter_map(|id| timestamp(id)) {
+                *by_day.entry(t - t % DAY).or_insert(0) += 1;
+            }
+
+            let mut orphans = 0;
+            for chunk in ids.chunks(1000) {
+                self.cancelled()?;
+                let refs = self.refcounts(chunk)?;
+                orphans += refs.iter().filter(|r| r.unwrap_or(0) < 1).count() as u64;
+            }
+
+            let mut by_count = vec![];
+            let mut by_memory = vec![];
+            for s in self.subjects()? {
+                self.cancelled()?;
+                by_count.push((s.to_string(), self.llen(&s)?));
+                let mem: Option<u64> = self.query(redis::cmd("MEMORY").arg("USAGE").arg(&s))?;
+                by_memory.push((s, mem.unwrap_or(0)));
+            }
+            let subjects = by_count.len() as u64;
+
+            by_count.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            by_count.truncate(top);
+            by_memory.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            by_memory.truncate(top);
+
+            Ok(Stats {
+                events,
+                subjects,
+                orphans,
+                by_count,
+                by_memory,
+                by_day,
+            })
+        })
+    }
+}
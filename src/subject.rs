@@ -0,0 +1,388 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::timeline::now;
+use crate::{AudisError, AudisResult, Client, Event, Operation, SYSTEM_SUBJECT};
+
+/// An iterator over the known subjects, fetched from Redis a
+/// page at a time; see `Client::scan_subjects()`.
+pub struct SubjectScan<'a> {
+    client: &'a Client,
+    pattern: String,
+    count: usize,
+    cursor: Option<u64>,
+    queue: VecDeque<String>,
+}
+
+/// How `Client::subject_counts()` sorts the subjects it lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubjectOrder {
+    /// By name, alphabetically.
+    Name,
+
+    /// By how many events they have, largest first (and then by
+    /// name).
+    Count,
+}
+
+// The sorted set of subjects, scored by when something was last
+// logged against (or moved into) them.
+const TOUCHED: &str = "audis:touched";
+
+impl Client {
+    /// List the known subjects whose names match the glob
+    /// `pattern` (i.e. `user:*`), using `SSCAN`.
+    ///
+    /// Subjects that the client's access policy (if any) does
+    /// not allow retrieving are left out.  Pseudonymized subject
+    /// names (see `pseudonymize()`) can't usefully be matched.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn subjects_matching(&self, pattern: &str) -> AudisResult<Vec<String>> {
+        self.instrument("subjects_matching", || {
+            let mut subjects = self.scan("SSCAN", Some("subjects"), pattern)?;
+            subjects.retain(|s| self.allows_stored(Operation::Retrieve, s));
+            subjects.sort();
+            subjects.dedup();
+            Ok(subjects)
+        })
+    }
+
+    /// List the known subjects whose names match the glob
+    /// `pattern` (like `subjects_matching()` does), along with
+    /// how many events each has, sorted by `order`.
+    ///
+    /// The counts are fetched with pipelined `LLEN`s, a thousand
+    /// subjects at a time, rather than with a `count()` per
+    /// subject.  Subjects that the client's access policy (if
+    /// any) does not allow retrieving are left out.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn subject_counts(
+        &self,
+        pattern: &str,
+        order: SubjectOrder,
+    ) -> AudisResult<Vec<(String, u64)>> {
+        self.instrument("subject_counts", || {
+            let mut subjects = self.scan("SSCAN", Some("subjects"), pattern)?;
+            subjects.retain(|s| self.allows_stored(Operation::Retrieve, s));
+            subjects.sort();
+            subjects.dedup();
+
+            let mut counts = Vec::with_capacity(subjects.len());
+            for chunk in subjects.chunks(1000) {
+                let mut pipe = redis::pipe();
+                for s in chunk {
+                    pipe.cmd("LLEN").arg(s);
+                }
+                let n: Vec<u64> = self.pipeline(&pipe)?;
+                counts.extend(chunk.iter().cloned().zip(n));
+            }
+            if order == SubjectOrder::Count {
+                counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            }
+            Ok(counts)
+        })
+    }
+
+    /// Iterate over the known subjects whose names match the glob
+    /// `pattern` (i.e. `user:*`, or `*` for all of them), fetching
+    /// them with `SSCAN`, about `count` at a time.
+    ///
+    /// Unlike `subjects()` (and `subjects_matching()`), this
+    /// never holds more than a page of subjects in memory, nor
+    /// blocks Redis for longer than it takes to fetch one, which
+    /// matters once there are millions of them.  As with `SSCAN`
+    /// itself, subjects are yielded in no particular order, and
+    /// may be yielded more than once; those added or removed
+    /// while iterating may or may not turn up.  Subjects that the
+    /// client's access policy (if any) does not allow retrieving
+    /// are left out.  Iteration stops after the first error.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///
+    ///     for s in client.scan_subjects("session:*", 1000) {
+    ///         println!("{}", s.unwrap());
+    ///     }
+    /// }
+    /// ```
+    ///
+    pub fn scan_subjects(&self, pattern: &str, count: usize) -> SubjectScan<'_> {
+        SubjectScan {
+            client: self,
+            pattern: pattern.to_string(),
+            count: count.max(1),
+            cursor: Some(0),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Remove a subject from the audit log, dereferencing (and
+    /// possibly deleting) each of its events.
+    ///
+    /// Unlike `erase_subject()`, the payloads of events shared
+    /// with other subjects are left alone.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn remove_subject(&self, subject: &str) -> AudisResult<&Client> {
+        self.instrument("remove_subject", || {
+            self.allow(Operation::Remove, subject)?;
+            self.drop_subject("remove", &self.subject(subject))
+        })
+    }
+
+    /// Remove every subject that nothing has been logged against
+    /// (or moved into) for at least `idle`, like
+    /// `remove_subject()` does, returning the names of the
+    /// subjects removed.
+    ///
+    /// Before each subject is removed, its events are handed to
+    /// `archive`, along with the name of the subject; if that
+    /// fails, the subject is left alone, and so are the rest.
+    /// Pass `|_, _| Ok(())` to skip archiving.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///     let month = Duration::from_secs(30 * 86400);
+    ///     client
+    ///         .expire_idle(month, |subject, events| {
+    ///             println!("expiring {} ({} event(s))", subject, events.len());
+    ///             Ok(())
+    ///         })
+    ///         .unwrap();
+    /// }
+    /// ```
+    ///
+    /// When each subject was last touched is kept in the
+    /// `audis:touched` sorted set, so subjects that haven't been
+    /// touched since that started (i.e. by an older audis) are
+    /// never considered idle.  Subject names are as stored (see
+    /// `pseudonym()`); the reserved `__audis__` subject, and
+    /// those that the client's access policy (if any) does not
+    /// allow removing, are left alone.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, archive), err, fields(commands))
+    )]
+    pub fn expire_idle<F>(&self, idle: Duration, mut archive: F) -> AudisResult<Vec<String>>
+    where
+        F: FnMut(&str, &[Event]) -> AudisResult<()>,
+    {
+        self.instrument("expire_idle", || {
+            let cutoff = now().saturating_sub(idle.as_millis() as u64);
+            let idle: Vec<String> = self.query(
+                redis::cmd("ZRANGEBYSCORE")
+                    .arg(TOUCHED)
+                    .arg("-inf")
+                    .arg(cutoff),
+            )?;
+
+            let mut expired = vec![];
+            for s in idle {
+                if s == SYSTEM_SUBJECT || !self.allows_stored(Operation::Remove, &s) {
+                    continue;
+                }
+                let mut events = vec![];
+                for id in self.lrange(&s, "0", "-1")? {
+                    if let Some(e) = self.fetch(&id, Some(&s))? {
+                        events.push(e);
+                    }
+                }
+                archive(&s, &events)?;
+
+                // something may have been logged in the meantime
+                let touched: Option<f64> = self.query(redis::cmd("ZSCORE").arg(TOUCHED).arg(&s))?;
+                if touched.is_some_and(|t| t > cutoff as f64) {
+                    continue;
+                }
+                self.drop_subject("expire", &s)?;
+                expired.push(s);
+            }
+            Ok(expired)
+        })
+    }
+
+    /// Rename a subject, keeping all of its events.
+    ///
+    /// If a subject named `to` already exists, this fails with
+    /// `AudisError::Invalid`; see `merge_subjects()` instead.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn rename_subject(&self, from: &str, to: &str) -> AudisResult<&Client> {
+        self.instrument("rename_subject", || {
+            self.allow(Operation::Rename, from)?;
+            self.allow(Operation::Rename, to)?;
+            let (from, to) = (self.subject(from), self.subject(to));
+
+            let known = self.smembers("subjects")?;
+            if known.iter().any(|s| *s == to) || self.llen(&to)? > 0 {
+                return Err(AudisError::Invalid(format!(
+                    "cannot rename {} to {}: {} already exists",
+                    from, to, to
+                )));
+            }
+            let moved = self.move_events(&from, &to)?;
+            self.move_notes(&from, &to)?;
+            self.record_with("rename", &from, &moved, &[("into", &to)])?;
+            Ok(self)
+        })
+    }
+
+    /// Merge one subject into another, appending the events of
+    /// `from` that aren't already in `into` to the end of it, and
+    /// then removing `from`.
+    ///
+    /// Since audis doesn't keep timestamps, the merged subject is
+    /// not re-sorted.  If the client chains events, the moved
+    /// events are chained onto the end of `into`, so that it
+    /// still verifies.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn merge_subjects(&self, from: &str, into: &str) -> AudisResult<&Client> {
+        self.instrument("merge_subjects", || {
+            self.allow(Operation::Merge, from)?;
+            self.allow(Operation::Merge, into)?;
+            let (from, into) = (self.subject(from), self.subject(into));
+            if from == into {
+                return Err(AudisError::Invalid(format!(
+                    "cannot merge {} into itself",
+                    from
+                )));
+            }
+            let moved = self.move_events(&from, &into)?;
+            self.record_with("merge", &from, &moved, &[("into", &into)])?;
+            Ok(self)
+        })
+    }
+
+    // Move every event from one subject to the end of another,
+    // unless it's already there, and then remove the first.
+    fn move_events(&self, from: &str, to: &str) -> AudisResult<Vec<String>> {
+        let already = self.lrange(to, "0", "-1")?;
+        let ids = self.lrange(from, "0", "-1")?;
+        for id in &ids {
+            if already.contains(id) {
+                self.unlink(from, id)?.deref(id)?;
+                continue;
+            }
+            match self.fetch(id, Some(from))? {
+                Some(e) => {
+                    self.rewrap(id, from, to)?;
+                    self.unlink(from, id)?
+                        .append(to, &e)?
+                        .stamp(to, id)?
+                        .expiring_in(id, to)?;
+                    self.sequence(to, id)?;
+                }
+                None => {
+                    self.deref(id)?;
+                }
+            }
+        }
+        if !ids.is_empty() {
+            self.sadd("subjects", to)?.touch(to)?;
+        }
+        self.forget(from)?;
+        self.realias(from, to)?;
+        Ok(ids)
+    }
+
+    // Remove a subject (by its stored name), dereferencing its
+    // events, and recording it as `op`.
+    fn drop_subject(&self, op: &str, subject: &str) -> AudisResult<&Client> {
+        let removed = self.locked(subject, || {
+            let removed = self.lrange(subject, "0", "-1")?;
+            for id in &removed {
+                self.unlink(subject, id)?.deref(id)?;
+            }
+            self.forget(subject)?;
+            Ok(removed)
+        })?;
+        self.record(op, subject, &removed)?;
+        Ok(self)
+    }
+
+    // Drop a subject's link in the hash chain of an event, its
+    // copy of the event's data key, and its sequence number.
+    pub(crate) fn unlink(&self, subject: &str, id: &str) -> AudisResult<&Client> {
+        self.query::<()>(redis::cmd("HDEL").arg(idchain!(id)).arg(subject))?;
+        self.query::<()>(redis::cmd("HDEL").arg(idkeys!(id)).arg(subject))?;
+        self.query::<()>(redis::cmd("HDEL").arg(idseq!(id)).arg(subject))?;
+        Ok(self)
+    }
+
+    // Note that something was just logged against a subject.
+    pub(crate) fn touch(&self, subject: &str) -> AudisResult<&Client> {
+        self.query::<()>(redis::cmd("ZADD").arg(TOUCHED).arg(now()).arg(subject))?;
+        Ok(self)
+    }
+
+    // Get rid of a subject's index, chain head, data key, time
+    // index and last-touched time.
+    pub(crate) fn forget(&self, subject: &str) -> AudisResult<&Client> {
+        self.query::<()>(
+            redis::cmd("DEL")
+                .arg(subject)
+                .arg(format!("audis:chain:{}", subject))
+                .arg(format!("audis:keys:{}", subject))
+                .arg(format!("audis:time:{}", subject)),
+        )?;
+        self.query::<()>(redis::cmd("SREM").arg("subjects").arg(subject))?;
+        self.query::<()>(redis::cmd("ZREM").arg(TOUCHED).arg(subject))?;
+        Ok(self)
+    }
+}
+
+impl Iterator for SubjectScan<'_> {
+    type Item = AudisResult<String>;
+
+    fn next(&mut self) -> Option<AudisResult<String>> {
+        let c = self.client;
+        loop {
+            if let Some(s) = self.queue.pop_front() {
+                return Some(Ok(s));
+            }
+            let cursor = self.cursor?;
+            let page: AudisResult<(u64, Vec<String>)> = c.query(
+                redis::cmd("SSCAN")
+                    .arg("subjects")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&self.pattern)
+                    .arg("COUNT")
+                    .arg(self.count),
+            );
+            let (next, batch) = match page {
+                Ok(page) => page,
+                Err(e) => {
+                    self.cursor = None;
+                    return Some(Err(e));
+                }
+            };
+            self.cursor = if next == 0 { None } else { Some(next) };
+            self.queue.extend(
+                batch
+                    .into_iter()
+                    .filter(|s| c.allows(Operation::Retrieve, s)),
+            );
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::export::timestamp;
+use crate::{AudisError, AudisResult, Client, Event, Operation};
+
+// The sorted set of a subject's event IDs, scored by time.
+macro_rules! timeline {
+    ($s:expr) => {
+        format!("audis:time:{}", $s)
+    };
+}
+
+impl Client {
+    /// Keep a time index for each subject, alongside its list of
+    /// events, so that `retrieve_between()` and `purge_before()`
+    /// can look events up by time on the server side, rather than
+    /// retrieving (and sifting through) every event in the
+    /// subject.
+    ///
+    /// The index for each subject is a sorted set, in the
+    /// `audis:time:$subject` key, scoring each event by the
+    /// timestamp of its ID, if it has one (see `ids::timestamp()`),
+    /// or else by the time
+    /// it was logged, in milliseconds since the epoch.  Only
+    /// events logged (or moved between subjects) by clients that
+    /// keep time indexes are indexed.
+    ///
+    pub fn time_indexed(mut self) -> Client {
+        self.timeline = true;
+        self
+    }
+
+    /// Retrieve the events for the given subject that were logged
+    /// at or after `since`, but before `until` (both in
+    /// milliseconds since the epoch), oldest first.
+    ///
+    /// With `time_indexed()`, this is a single `ZRANGEBYSCORE`.
+    /// Without, every event in the subject is retrieved, and only
+    /// those with timestamped IDs (i.e. ULIDs) are kept.
+    /// Errors are the same as for `retrieve()`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_between(&self, log: &str, since: u64, until: u64) -> AudisResult<Vec<Event>> {
+        self.instrument("retrieve_between", || {
+            if !self.timeline {
+                let mut events = self.events(log, 0, -1)?;
+                events.retain(|e| timestamp(&e.id).is_some_and(|t| t >= since && t < until));
+                events.sort_by_key(|e| timestamp(&e.id));
+                return Ok(events);
+            }
+
+            let log = self.readable(log)?;
+            let log = log.as_str();
+            let mut events = vec![];
+            for id in self.between(log, &since.to_string(), &format!("({}", until))? {
+                self.cancelled()?;
+                match self.fetch(&id, Some(log))? {
+                    Some(e) => {
+                        #[cfg(feature = "crypto")]
+                        self.check_seal(&e)?;
+                        events.push(e)
+                    }
+                    None => return Err(AudisError::NotFound(id)),
+                }
+            }
+            Ok(events)
+        })
+    }
+
+    /// Delete all of the events logged against a subject before
+    /// `before` (in milliseconds since the epoch), like `purge()`
+    /// does.
+    ///
+    /// With `time_indexed()`, the events are found with a single
+    /// `ZRANGEBYSCORE`.  Without, every event ID in the subject is
+    /// checked, and only those that are timestamped (i.e. ULIDs)
+    /// can be purged.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn purge_before(&self, log: &str, before: u64) -> AudisResult<&Client> {
+        self.instrument("purge_before", || {
+            self.allow(Operation::Purge, log)?;
+            let log = self.subject(log);
+            let log = log.as_ref();
+            let removed = self.locked(log, || {
+                let removed: Vec<String> = if self.timeline {
+                    self.between(log, "-inf", &format!("({}", before))?
+                } else {
+                    self.lrange(log, "0", "-1")?
+                        .into_iter()
+                        .filter(|id| timestamp(id).is_some_and(|t| t < before))
+                        .collect()
+                };
+                for id in &removed {
+                    self.query::<()>(redis::cmd("LREM").arg(log).arg(0).arg(id))?;
+                    self.deref(id)?;
+                }
+                self.unstamp(log, &removed)?;
+                Ok(removed)
+            })?;
+            self.record("purge", log, &removed)?;
+            Ok(self)
+        })
+    }
+
+    // Add an event to a subject's time index, if we keep them.
+    pub(crate) fn stamp(&self, subject: &str, id: &str) -> AudisResult<&Client> {
+        if self.timeline {
+            self.query::<()>(
+                redis::cmd("ZADD")
+                    .arg(timeline!(subject))
+                    .arg(logged_at(id))
+                    .arg(id),
+            )?;
+        }
+        Ok(self)
+    }
+
+    // Drop events from a subject's time index.  This is done
+    // whether or not we keep time indexes, since other clients
+    // might.
+    pub(crate) fn unstamp(&self, subject: &str, ids: &[String]) -> AudisResult<&Client> {
+        if !ids.is_empty() {
+            self.query::<()>(redis::cmd("ZREM").arg(timeline!(subject)).arg(ids))?;
+        }
+        Ok(self)
+    }
+
+    // Get the times of every event in a subject's time index.
+    pub(crate) fn stamps(&self, subject: &str) -> AudisResult<Vec<u64>> {
+        let scored: Vec<(String, f64)> = self.query(
+            redis::cmd("ZRANGEBYSCORE")
+                .arg(timeline!(subject))
+                .arg("-inf")
+                .arg("+inf")
+                .arg("WITHSCORES"),
+        )?;
+        Ok(scored.into_iter().map(|(_, t)| t as u64).collect())
+    }
+
+    fn between(&self, subject: &str, min: &str, max: &str) -> AudisResult<Vec<String>> {
+        self.query(
+            redis::cmd("ZRANGEBYSCORE")
+                .arg(timeline!(subject))
+                .arg(min)
+                .arg(max),
+        )
+    }
+}
+
+// When an event was logged, in milliseconds since the epoch:
+// the timestamp of its ID, if it has one, or else now.
+pub(crate) fn logged_at(id: &str) -> u64 {
+    timestamp(id).unwrap_or_else(now)
+}
+
+// The current time, in milliseconds since the epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
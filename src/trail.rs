@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{context, AudisError, AudisResult, Client, Event, LogOutcome};
+
+/// A group of related events, all sharing a correlation ID, to
+/// be logged together; see `Client::begin_trail()`.
+///
+/// Events added to a trail aren't logged until it is committed;
+/// dropping a trail without committing it discards them.
+pub struct Trail<'a> {
+    client: &'a Client,
+    correlation_id: String,
+    meta: BTreeMap<String, String>,
+    events: Vec<Event>,
+}
+
+impl Client {
+    /// Start a group of related events, all sharing the given
+    /// correlation ID (and any metadata set on the trail), to be
+    /// logged together once they have all been added.
+    ///
+    /// This is meant for request handlers that log start, step
+    /// and end events for each request, and would rather log
+    /// none of them than some:
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379").unwrap();
+    ///
+    ///     let mut trail = client.begin_trail("req-1");
+    ///     trail.meta("actor", "jhunt");
+    ///     for step in &["started", "charged card", "finished"] {
+    ///         trail.add(
+    ///             audis::Event::builder()
+    ///                 .data(*step)
+    ///                 .subject("order:42")
+    ///                 .build()
+    ///                 .unwrap(),
+    ///         );
+    ///     }
+    ///     trail.commit().unwrap();
+    /// }
+    /// ```
+    pub fn begin_trail(&self, correlation_id: &str) -> Trail<'_> {
+        Trail {
+            client: self,
+            correlation_id: correlation_id.to_string(),
+            meta: BTreeMap::new(),
+            events: vec![],
+        }
+    }
+}
+
+impl Trail<'_> {
+    /// The correlation ID shared by every event in the trail.
+    pub fn correlation_id(&self) -> &str {
+        &self.correlation_id
+    }
+
+    /// Attach a metadata attribute to every event in the trail
+    /// (that doesn't already have one by that name).
+    pub fn meta<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> &mut Self {
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add another event to the trail.  Its correlation ID (if
+    /// unset) is filled in when the trail is committed.
+    pub fn add(&mut self, e: Event) -> &mut Self {
+        self.events.push(e);
+        self
+    }
+
+    /// Log every event in the trail, in the order they were
+    /// added, reporting what became of each (see
+    /// `Client::log_outcome()`).
+    ///
+    /// Every event is run through the client's interceptors and
+    /// checked (see `Client::validate()`), and its ID looked up
+    /// (in one pipelined round trip), before any are logged, so
+    /// an invalid event, one with a different correlation ID, or
+    /// a duplicate (of an event already logged, or of another in
+    /// the trail) keeps the whole trail from being logged, with
+    /// `AudisError::Duplicate` for the latter.  If the client
+    /// `ignore_duplicates()`, duplicates are skipped instead, and
+    /// left out of the outcomes.  Failures writing
+    /// to the backend part-way through can still leave only some
+    /// of the events logged, though: events that can't be indexed
+    /// against some of their subjects are reported as such, and
+    /// any other failure (including a duplicate logged by someone
+    /// else in the meantime) is returned as an error, leaving the
+    /// rest of the trail unlogged.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self),
+            err,
+            fields(correlation_id = %self.correlation_id, events = self.events.len(), commands)
+        )
+    )]
+    pub fn commit(self) -> AudisResult<Vec<LogOutcome>> {
+        let c = self.client;
+        c.instrument("commit_trail", || {
+            let mut ready = Vec::with_capacity(self.events.len());
+            for mut e in self.events {
+                match &e.correlation_id {
+                    Some(cid) if *cid != self.correlation_id => {
+                        return Err(AudisError::Invalid(format!(
+                            "event {} is part of trail {}, not {}",
+                            e.id, cid, self.correlation_id
+                        )));
+                    }
+                    Some(_) => (),
+                    None => e.correlation_id = Some(self.correlation_id.to_string()),
+                }
+                for (k, v) in &self.meta {
+                    e.meta.entry(k.to_string()).or_insert_with(|| v.to_string());
+                }
+
+                let e = context::attach_owned(e);
+                if let Some(e) = c.intercept(&e)? {
+                    c.check(&e)?;
+                    ready.push(e.into_owned());
+                }
+            }
+
+            if ready.is_empty() {
+                return Ok(vec![]);
+            }
+            let mut pipe = redis::pipe();
+            for e in &ready {
+                pipe.cmd("EXISTS").arg(id!(e.id));
+            }
+            let exists: Vec<bool> = c.pipeline(&pipe)?;
+            let mut seen = HashSet::new();
+            let mut fresh = Vec::with_capacity(ready.len());
+            for (e, exists) in ready.into_iter().zip(exists) {
+                if exists || !seen.insert(e.id.to_string()) {
+                    if c.duplicates_ok {
+                        continue;
+                    }
+                    return Err(AudisError::Duplicate(e.id));
+                }
+                fresh.push(e);
+            }
+            let ready = fresh;
+
+            let mut outcomes = Vec::with_capacity(ready.len());
+            for e in ready {
+                outcomes.push(c.stored(Cow::Owned(e))?);
+            }
+            Ok(outcomes)
+        })
+    }
+}
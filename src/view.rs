@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use crate::backend::glob;
+use crate::{AudisResult, Client, Event, Operation};
+
+// The IDs of the events in a view, in the order they were logged.
+macro_rules! view {
+    ($v:expr) => {
+        format!("audis:view:{}", $v)
+    };
+}
+
+type Predicate = dyn Fn(&Event) -> bool + Send + Sync;
+
+// A view, and which events belong in it.
+#[derive(Clone)]
+pub(crate) struct View {
+    name: String,
+    filter: Filter,
+}
+
+#[derive(Clone)]
+enum Filter {
+    Subjects(String),
+    Custom(Arc<Predicate>),
+}
+
+impl Client {
+    /// Maintain a view named `name` of every event logged against
+    /// a subject matching the glob `pattern` (as stored; see
+    /// `subjects_matching()`), so that i.e. a dashboard showing
+    /// the latest activity of every `tenant:*` can retrieve it in
+    /// one go, via `retrieve_view()`, instead of retrieving every
+    /// tenant's events and merging them.
+    ///
+    /// Views are kept (as lists of event IDs, in
+    /// `audis:view:$name`) by the clients that log events, as
+    /// they log them: a view only has the events logged since the
+    /// client logging them was configured with it, and every
+    /// client logging events that belong in a view has to be.  An
+    /// event is only added to a view once, however many of its
+    /// subjects match, or however many times the view is
+    /// configured (i.e. with different patterns, or with
+    /// `view_where()`).
+    pub fn view(mut self, name: &str, pattern: &str) -> Client {
+        self.views.push(View {
+            name: name.to_string(),
+            filter: Filter::Subjects(pattern.to_string()),
+        });
+        self
+    }
+
+    /// Maintain a view named `name` of every event for which
+    /// `predicate` holds, like `view()` does for subject patterns.
+    ///
+    /// ```rust,no_run
+    /// extern crate audis;
+    ///
+    /// fn main() {
+    ///     let client = audis::Client::connect("redis://127.0.0.1:6379")
+    ///         .unwrap()
+    ///         .view_where("logins", |e| e.meta.get("action").map(String::as_str) == Some("login"));
+    ///
+    ///     // ... log events, and then, elsewhere ...
+    ///     for e in client.retrieve_view("logins").unwrap() {
+    ///         println!("{}", e.id);
+    ///     }
+    /// }
+    /// ```
+    pub fn view_where<F>(mut self, name: &str, predicate: F) -> Client
+    where
+        F: Fn(&Event) -> bool + Send + Sync + 'static,
+    {
+        self.views.push(View {
+            name: name.to_string(),
+            filter: Filter::Custom(Arc::new(predicate)),
+        });
+        self
+    }
+
+    /// Retrieve every event in a view (see `view()`), in the order
+    /// they were logged.
+    ///
+    /// The events in a view are still logged against (and pruned
+    /// from) their subjects as usual; those that have since been
+    /// pruned from every one of them are left out (and are removed
+    /// from the view altogether by `gc()`).  Any client can
+    /// retrieve a view, whether or not it maintains it; its
+    /// subjects are not filled in.  If the client has an access
+    /// policy, it must allow retrieving the name of the view.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_view(&self, name: &str) -> AudisResult<Vec<Event>> {
+        self.instrument("retrieve_view", || self.view_events(name, 0, -1))
+    }
+
+    /// Retrieve part of a view, like `retrieve_range()` does for
+    /// subjects; see `retrieve_view()`.
+    ///
+    /// Indices are into the view's list of events, so a range may
+    /// come back short if some of them have been pruned.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn retrieve_view_range(
+        &self,
+        name: &str,
+        start: i64,
+        stop: i64,
+    ) -> AudisResult<Vec<Event>> {
+        self.instrument("retrieve_view_range", || {
+            self.view_events(name, start, stop)
+        })
+    }
+
+    /// Count the events in a view, including any that have since
+    /// been pruned from their subjects, but not yet swept out of
+    /// it by `gc()`; see `retrieve_view()`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self), err, fields(commands))
+    )]
+    pub fn count_view(&self, name: &str) -> AudisResult<u64> {
+        self.instrument("count_view", || {
+            self.allow(Operation::Retrieve, name)?;
+            self.llen(&view!(name))
+        })
+    }
+
+    fn view_events(&self, name: &str, start: i64, stop: i64) -> AudisResult<Vec<Event>> {
+        self.allow(Operation::Retrieve, name)?;
+        let mut events = vec![];
+        for id in self.lrange(&view!(name), &start.to_string(), &stop.to_string())? {
+            self.cancelled()?;
+            if let Some(e) = self.fetch(&id, None)? {
+                #[cfg(feature = "crypto")]
+                self.check_seal(&e)?;
+                events.push(e);
+            }
+        }
+        Ok(events)
+    }
+
+    // Remove the IDs of events that no longer exist (because they
+    // have been pruned from every subject) from every view, as
+    // part of `gc()`.
+    pub(crate) fn sweep_views(&self) -> AudisResult<()> {
+        for view in self.scan("SCAN", None, &view!("*"))? {
+            let ids = self.lrange(&view, "0", "-1")?;
+            for chunk in ids.chunks(1000) {
+                self.cancelled()?;
+                let mut pipe = redis::pipe();
+                for id in chunk {
+                    pipe.cmd("EXISTS").arg(id!(id));
+                }
+                let exists: Vec<bool> = self.pipeline(&pipe)?;
+                for (id, _) in chunk.iter().zip(exists).filter(|(_, exists)| !exists) {
+                    self.query::<()>(redis::cmd("LREM").arg(&view).arg(0).arg(id))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Add a (just stored) event to the views it belongs in, given
+    // the (stored) subjects it was indexed against.
+    pub(crate) fn materialize(&self, e: &Event, subjects: &[&String]) -> AudisResult<()> {
+        let mut names: Vec<&str> = self
+            .views
+            .iter()
+            .filter(|v| match &v.filter {
+                Filter::Subjects(pattern) => subjects
+                    .iter()
+                    .any(|s| glob(pattern.as_bytes(), s.as_bytes())),
+                Filter::Custom(predicate) => predicate(e),
+            })
+            .map(|v| v.name.as_str())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        for name in names {
+            self.rpush(&view!(name), &e.id)?;
+        }
+        Ok(())
+    }
+}